@@ -0,0 +1,237 @@
+//! The new device's side of device linking (see [`lib::crypto::device_link`]),
+//! and the relay through which both devices exchange the linking messages.
+//!
+//! The existing device's side is in [`crate::manager::device_link`].
+use std::time::Duration;
+
+use crate::{
+    manager::{account::Profile, listener::ListenerMessage, WEBSOCKET_MANAGER},
+    net::RequestError,
+};
+use anyhow::{bail, Context, Result};
+use lib::{
+    api::{
+        group::{DeliveryStamp, SendMessageRequest},
+        messages::{ChatServiceMessage, Message, UnauthRequest},
+        proto::{
+            DeviceLinkChain, DeviceLinkDeviceCertificate, DeviceLinkProvisioning, ProstMessage,
+        },
+        server::Server,
+    },
+    crypto::{
+        certificates::{
+            ed25519::{Ed25519CertificateChainSecret, Ed25519DeviceCert},
+            hybrid::{HybridCertificateChainSecret, HybridDeviceCert},
+            rotation::AccountKeyShare,
+            SerializedAccountCertificate, SerializedChain, SerializedDeviceCertificate,
+        },
+        device_link::{DeviceLinkCode, DeviceLinkStep},
+    },
+    identifiers::{DeviceId, LicksIdentifier},
+};
+use tokio::{sync::mpsc, time::timeout};
+
+/// How long a device waits for the other one to send the next
+/// linking message, which can involve the user typing the code.
+pub const DEVICE_LINK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Encrypts `message` with the link code and sends it to the address of `step`.
+pub async fn send_link_message(
+    server: &Server,
+    code: &DeviceLinkCode,
+    step: DeviceLinkStep,
+    message: &[u8],
+) -> Result<()> {
+    let resp = WEBSOCKET_MANAGER
+        .request_unauth(
+            server,
+            UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
+                blinded_address_proof: code.address(step).create_proof(code.encrypt(step, message)),
+                ephemeral: false,
+            })),
+        )
+        .await?;
+
+    match resp {
+        Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(_))) => Ok(()),
+        other => Err(RequestError::from_response(other)).context("Link message did not deliver"),
+    }
+}
+
+/// Waits for the message of `step`, whether it was sent before or after we
+/// started waiting, and decrypts it. Messages that can't be decrypted with
+/// the link code are ignored.
+pub async fn receive_link_message(
+    server: &Server,
+    code: &DeviceLinkCode,
+    step: DeviceLinkStep,
+) -> Result<Vec<u8>> {
+    let address = code.address(step).to_public();
+
+    let (live_tx, live_rx) = mpsc::channel::<ListenerMessage>(16);
+    let listener_id = WEBSOCKET_MANAGER
+        .start_listen(server, address, live_tx)
+        .await?;
+
+    let result = timeout(
+        DEVICE_LINK_TIMEOUT,
+        next_link_message(server, code, step, live_rx),
+    )
+    .await
+    .context("Timed out waiting for the other device");
+    let _ = WEBSOCKET_MANAGER.stop_listen(server, listener_id).await;
+
+    result?
+}
+
+/// Returns the first message of `step` that can be decrypted, either from the
+/// queue of its address or from `live_rx`.
+async fn next_link_message(
+    server: &Server,
+    code: &DeviceLinkCode,
+    step: DeviceLinkStep,
+    mut live_rx: mpsc::Receiver<ListenerMessage>,
+) -> Result<Vec<u8>> {
+    let (queue_tx, mut queue_rx) = mpsc::channel::<ListenerMessage>(16);
+    let retrieval = WEBSOCKET_MANAGER.retrieve_queue(
+        server,
+        code.address(step).to_public(),
+        DeliveryStamp::EARLIEST,
+        queue_tx,
+    );
+    tokio::pin!(retrieval);
+    let mut retrieved = false;
+
+    loop {
+        let (delivery_stamp, ciphertext) = tokio::select! {
+            result = &mut retrieval, if !retrieved => {
+                result?;
+                retrieved = true;
+                continue;
+            }
+            Some(message) = queue_rx.recv() => message,
+            message = live_rx.recv() => match message {
+                Some(message) => message,
+                None => bail!("The connection closed while waiting for the other device"),
+            },
+        };
+
+        match code.decrypt(step, &ciphertext) {
+            Ok(message) => return Ok(message),
+            Err(err) => log::warn!("Ignoring link message {delivery_stamp:?}: {err:?}"),
+        }
+    }
+}
+
+/// Sends our device certificate to the existing device, and waits for it to send
+/// back our chain, signed by the account key, and the account key itself.
+async fn exchange_device_cert(
+    server: &Server,
+    code: &DeviceLinkCode,
+    device_cert: SerializedDeviceCertificate,
+) -> Result<(SerializedChain, AccountKeyShare)> {
+    let device_cert = DeviceLinkDeviceCertificate {
+        device_certificate: Some(device_cert.into()),
+    };
+    send_link_message(
+        server,
+        code,
+        DeviceLinkStep::DeviceCertificate,
+        &device_cert.encode_to_vec(),
+    )
+    .await?;
+
+    let chain = DeviceLinkChain::decode(
+        receive_link_message(server, code, DeviceLinkStep::Chain)
+            .await?
+            .as_slice(),
+    )
+    .context("Failed to decode our chain")?;
+
+    Ok((
+        chain
+            .chain
+            .context("The existing device didn't send our chain")?
+            .try_into()?,
+        chain
+            .account_key
+            .context("The existing device didn't send the account key")?
+            .try_into()?,
+    ))
+}
+
+/// Waits for an existing device of the account to provision us, generates our
+/// device key and has the existing device sign it, then registers our chain with
+/// the server. Returns our profile along with the number of key packages the
+/// existing device expects back.
+pub async fn link_device(server: &Server, code: &DeviceLinkCode) -> Result<(Profile, u32)> {
+    let provisioning = DeviceLinkProvisioning::decode(
+        receive_link_message(server, code, DeviceLinkStep::Provisioning)
+            .await?
+            .as_slice(),
+    )
+    .context("Failed to decode the provisioning message")?;
+
+    let account_cert: SerializedAccountCertificate = provisioning
+        .account_certificate
+        .context("The provisioning message doesn't contain the account certificate")?
+        .try_into()?;
+
+    // Our device secret never leaves this device: only its certificate is signed
+    let device_id = DeviceId::generate_id();
+    let profile = match account_cert {
+        SerializedAccountCertificate::Ed25519(_) => {
+            let (device_cert, device_secret) = Ed25519DeviceCert::generate(device_id);
+            let (chain, share) = exchange_device_cert(
+                server,
+                code,
+                SerializedDeviceCertificate::Ed25519(Box::new(device_cert)),
+            )
+            .await?;
+            let SerializedChain::Ed25519Chain(chain) = chain else {
+                bail!("The existing device sent a chain of another signature scheme");
+            };
+
+            Profile::V1(Ed25519CertificateChainSecret::from_linked_chain(
+                chain,
+                device_secret,
+                &share,
+            )?)
+        }
+        SerializedAccountCertificate::Hybrid(_) => {
+            let (device_cert, device_secret) = HybridDeviceCert::generate(device_id);
+            let (chain, share) = exchange_device_cert(
+                server,
+                code,
+                SerializedDeviceCertificate::Hybrid(Box::new(device_cert)),
+            )
+            .await?;
+            let SerializedChain::HybridChain(chain) = chain else {
+                bail!("The existing device sent a chain of another signature scheme");
+            };
+
+            Profile::V2(HybridCertificateChainSecret::from_linked_chain(
+                chain,
+                device_secret,
+                &share,
+            )?)
+        }
+    };
+
+    if profile.get_server() != server {
+        bail!("The linked account belongs to another server");
+    }
+
+    let req = UnauthRequest::RegisterDevice(profile.mls_credential_public().chain);
+
+    match WEBSOCKET_MANAGER.request_unauth(server, req).await? {
+        Message::Ok => {
+            log::info!("Registered the linked device");
+            Ok((profile, provisioning.key_package_count))
+        }
+        other => {
+            log::error!("Device registration failed, received this response: {other:?}");
+            Err(RequestError::from_response(other)).context("Registering the linked device failed")
+        }
+    }
+}
@@ -1,3 +1,4 @@
+pub mod link;
 pub mod register;
 
 pub use lib::identifiers::*;
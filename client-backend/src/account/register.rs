@@ -1,5 +1,8 @@
-use crate::manager::{account::Profile, WEBSOCKET_MANAGER};
-use anyhow::{bail, Context, Result};
+use crate::{
+    manager::{account::Profile, WEBSOCKET_MANAGER},
+    net::RequestError,
+};
+use anyhow::{Context, Result};
 use lib::{
     api::{
         messages::{Message, UnauthRequest},
@@ -8,7 +11,7 @@ use lib::{
     },
     crypto::{
         certificates::{
-            ed25519::{Ed25519AccountCert, Ed25519CertificateChainSecret, Ed25519DeviceCert},
+            hybrid::{HybridAccountCert, HybridCertificateChainSecret, HybridDeviceCert},
             CertificateChainSecret,
         },
         usernames::UsernameHash,
@@ -17,28 +20,28 @@ use lib::{
 };
 
 pub async fn create_account(server: &Server, username_hash: UsernameHash) -> Result<Profile> {
-    let (account_public, mut account_secret) = Ed25519AccountCert::generate_keys();
+    let (account_public, account_secret) = HybridAccountCert::generate_keys();
 
     // Stage 1 request
     let req = UnauthRequest::Registration(RegistrationService::Stage1(
-        Stage1Message::HereIsMyAccountPublicKey(account_public.to_bytes().to_vec()),
+        Stage1Message::HereIsMyAccountPublicKey(account_public.to_bytes()),
     ));
 
     let assigned_account_id = match WEBSOCKET_MANAGER.request_unauth(server, req).await? {
         Message::Unauth(UnauthRequest::Registration(RegistrationService::Stage1(
             Stage1Message::HereIsYourAccountId(account_id),
-        ))) => Some(account_id),
+        ))) => account_id,
         other => {
             log::error!("Stage 1 failed, received an unexpected response: {other:?}");
-            None
+            return Err(RequestError::from_response(other))
+                .context("Registration's first stage failed");
         }
-    }
-    .context("Registration's first stage failed")?;
+    };
 
     // Create account certificate
-    let account_cert = Ed25519AccountCert::complete(
+    let account_cert = HybridAccountCert::complete(
         account_public,
-        &mut account_secret,
+        &account_secret,
         server.to_owned(),
         assigned_account_id,
     );
@@ -54,20 +57,17 @@ pub async fn create_account(server: &Server, username_hash: UsernameHash) -> Res
         }
         other => {
             log::error!("Stage 2 failed, received this response: {other:?}");
-            bail!("Registration failed at stage 2.");
+            return Err(RequestError::from_response(other))
+                .context("Registration failed at stage 2");
         }
     }
 
     // Stage 3: Generate device certificate, create certificate chain, send it to server
     let device_id = DeviceId::generate_id();
-    let (device_cert, device_secret) = Ed25519DeviceCert::generate(device_id);
+    let (device_cert, device_secret) = HybridDeviceCert::generate(device_id);
 
-    let certificate_chain_secret = Ed25519CertificateChainSecret::new(
-        account_cert,
-        account_secret,
-        device_cert,
-        device_secret,
-    );
+    let certificate_chain_secret =
+        HybridCertificateChainSecret::new(account_cert, account_secret, device_cert, device_secret);
 
     let certificate_chain_public = certificate_chain_secret.serialized();
 
@@ -79,13 +79,13 @@ pub async fn create_account(server: &Server, username_hash: UsernameHash) -> Res
     match WEBSOCKET_MANAGER.request_unauth(server, req).await? {
         Message::Ok => {
             log::info!("Stage 3 registration success");
-            let profile: Profile = Profile::V1(certificate_chain_secret);
+            let profile: Profile = Profile::V2(certificate_chain_secret);
 
             Ok(profile)
         }
         other => {
             log::error!("Stage 3 failed, received this response: {other:?}");
-            bail!("Registration failed at stage 3: {other:?}.");
+            Err(RequestError::from_response(other)).context("Registration failed at stage 3")
         }
     }
 }
@@ -1,6 +1,8 @@
-use std::{io::ErrorKind, ops::Deref, sync::Arc};
+use std::{io::ErrorKind, ops::Deref, path::PathBuf, sync::Arc};
 
+use anyhow::bail;
 use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use lib::crypto::device_link::DeviceLinkCode;
 
 use crate::manager::{
     listener::ListenerManager,
@@ -12,7 +14,7 @@ use crate::manager::{
 /// Client is meant to handle different profiles. UI frontend keeps track
 /// of the [`ProfileManager`] it's on, but also at highest level should start Client.
 pub struct Client {
-    pub listener_manager: ListenerManager,
+    pub listener_manager: Arc<ListenerManager>,
 
     // String is the profile name.
     pub profile_managers: scc::HashMap<String, Arc<ProfileManager>>,
@@ -30,7 +32,7 @@ impl Client {
 
     pub fn new_with_notif(tx: UnboundedSender<Notification>) -> Self {
         Self {
-            listener_manager: ListenerManager::default(),
+            listener_manager: Arc::new(ListenerManager::default()),
             profile_managers: scc::HashMap::new(),
             notification_manager: NotificationSender::new(tx).into(),
         }
@@ -63,6 +65,18 @@ impl Client {
             return Ok(profile.get().clone());
         }
 
+        let profile_manager: Arc<ProfileManager> =
+            ProfileManager::initialize(Some(Self::profile_folder(profile_name))).await?;
+
+        self.profile_managers
+            .insert(profile_name.to_owned(), profile_manager.clone())
+            .expect("adds to hashmap");
+
+        Ok(profile_manager)
+    }
+
+    /// Returns the folder where the data of a profile is stored, creating it if needed.
+    fn profile_folder(profile_name: &str) -> PathBuf {
         let mut licks_folder_path =
             dirs::data_local_dir().expect("Operating system returns data local folder");
         licks_folder_path.push("licks");
@@ -77,14 +91,7 @@ impl Client {
             }
         }
 
-        let profile_manager: Arc<ProfileManager> =
-            ProfileManager::initialize(Some(licks_folder_path)).await?;
-
-        self.profile_managers
-            .insert(profile_name.to_owned(), profile_manager.clone())
-            .expect("adds to hashmap");
-
-        Ok(profile_manager)
+        licks_folder_path
     }
 
     pub fn add_profile(&self, profile_name: String, profile_manager: Arc<ProfileManager>) {
@@ -108,6 +115,14 @@ impl Client {
             profile_manager,
         };
 
+        client_profile.listen_to_all_groups().await?;
+        client_profile.listen_to_invites().await;
+        if let Err(err) = client_profile.update_pending_identities().await {
+            log::warn!("Couldn't update our signing identity in our groups: {err:?}");
+        }
+        client_profile.retry_outbox();
+        client_profile.sync_history();
+
         // TODO: Move this logic to front-end
         // Also make sure this doesn't run if the account is already registered
         // when launching app (this isn't the case for now, but....)
@@ -129,6 +144,8 @@ impl Client {
             profile_manager,
         };
 
+        client_profile.listen_to_invites().await;
+
         // TODO: Move this logic to front-end
         // Also make sure this doesn't run if the account is already registered
         // when launching app (this isn't the case for now, but....)
@@ -136,6 +153,55 @@ impl Client {
 
         Ok(client_profile)
     }
+
+    /// Creates a profile for an account we already have on another device,
+    /// which must link us with `code` (see [`ClientProfile::link_new_device`]).
+    /// Unlike [`Client::get_profile`], this doesn't create a self group: we are
+    /// added to the one of the account instead.
+    pub async fn link_profile<'a>(
+        &'a self,
+        profile_name: &str,
+        code: &DeviceLinkCode,
+    ) -> anyhow::Result<ClientProfile<'a>> {
+        self.link_profile_at(profile_name, Some(Self::profile_folder(profile_name)), code)
+            .await
+    }
+
+    /// Same as [`Client::link_profile`] except it keeps the profile in memory instead.
+    pub async fn link_in_memory_profile<'a>(
+        &'a self,
+        profile_name: &str,
+        code: &DeviceLinkCode,
+    ) -> anyhow::Result<ClientProfile<'a>> {
+        self.link_profile_at(profile_name, None, code).await
+    }
+
+    async fn link_profile_at<'a>(
+        &'a self,
+        profile_name: &str,
+        root_data_folder: Option<PathBuf>,
+        code: &DeviceLinkCode,
+    ) -> anyhow::Result<ClientProfile<'a>> {
+        if self.profile_managers.contains(profile_name) {
+            bail!("A profile with this name is already loaded");
+        }
+
+        let (profile_manager, key_package_count) =
+            ProfileManager::initialize_from_link(root_data_folder, code).await?;
+        self.add_profile(profile_name.to_owned(), profile_manager.clone());
+
+        let client_profile = ClientProfile {
+            client: self,
+            profile_manager,
+        };
+
+        client_profile.listen_to_invites().await;
+        client_profile
+            .finish_device_link(code, key_package_count)
+            .await?;
+
+        Ok(client_profile)
+    }
 }
 
 /// An instance of Client+ProfileManager which is what is ultimately used
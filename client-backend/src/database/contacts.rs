@@ -1,11 +1,18 @@
 use lib::{
     crypto::usernames::Username,
-    identifiers::{AccountId, LicksIdentifier},
+    identifiers::{AccountId, LicksIdentifier, Uuid},
 };
 use rusqlite::params;
 
 use super::{Database, DatabaseError};
 
+pub struct DatabaseContact {
+    pub account_id: AccountId,
+    pub username: Option<String>,
+    pub profile_name: Option<String>,
+    pub profile_description: Option<String>,
+}
+
 impl Database {
     pub fn add_new_contact(
         &self,
@@ -31,4 +38,27 @@ impl Database {
 
         Ok(())
     }
+
+    pub fn get_contacts(&self) -> Result<Vec<DatabaseContact>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "SELECT account_id, username, profile_name, profile_description FROM contacts",
+        )?;
+
+        let rows = statement.query_map((), |row| {
+            Ok(DatabaseContact {
+                account_id: row.get::<_, Uuid>(0)?.into(),
+                username: row.get(1)?,
+                profile_name: row.get(2)?,
+                profile_description: row.get(3)?,
+            })
+        })?;
+
+        let mut contacts = Vec::new();
+        for row in rows {
+            contacts.push(row?);
+        }
+
+        Ok(contacts)
+    }
 }
@@ -0,0 +1,302 @@
+//! Edits and deletions of messages (see [`Content::Edit`] and [`Content::Delete`]).
+//!
+//! They are stored as they are received, since they may arrive before the message
+//! they target, and applied to that message whenever we have both. A message can
+//! only be edited by its sender: edits are matched to the messages of the account
+//! that sent them.
+//!
+//! [`Content::Edit`]: crate::messages::Content::Edit
+//! [`Content::Delete`]: crate::messages::Content::Delete
+use lib::{
+    api::group::DeliveryStamp,
+    identifiers::{AccountId, GroupIdentifier, LicksIdentifier, Uuid},
+};
+use rusqlite::{params, OptionalExtension};
+
+use crate::messages::{MessageKind, MessageReference};
+
+use super::{Database, DatabaseError};
+
+impl Database {
+    /// Stores an edit of the message that `target` references, or its deletion
+    /// if `body` is `None`, and applies it if we have that message.
+    pub(super) fn add_message_edit(
+        &self,
+        group: &GroupIdentifier,
+        target: &MessageReference,
+        edit_stamp: &DeliveryStamp,
+        body: Option<String>,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "INSERT OR IGNORE INTO message_edits (group_id, account_id, target_timestamp,
+                edit_timestamp, plaintext_content, deleted)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                group.to_bytes(),
+                target.sender_account_id.as_uuid(),
+                target.client_stamp.to_vec(),
+                edit_stamp.to_vec(),
+                body,
+                body.is_none(),
+            ],
+        )?;
+
+        self.apply_message_edits(group, target)
+    }
+
+    /// Brings the message that `target` references up to date with the edits
+    /// and deletions we received for it. Does nothing if we don't have it.
+    ///
+    /// The reactions to deleted messages are removed as well.
+    pub(super) fn apply_message_edits(
+        &self,
+        group: &GroupIdentifier,
+        target: &MessageReference,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.get_connection();
+        let group_id = group.to_bytes();
+        let account_id = target.sender_account_id.as_uuid();
+        let target_timestamp = target.client_stamp.to_vec();
+
+        let deleted: bool = connection.query_row(
+            "SELECT EXISTS(SELECT 1 FROM message_edits
+                WHERE group_id = ?1 AND account_id = ?2 AND target_timestamp = ?3 AND deleted = 1)",
+            params![group_id, account_id, target_timestamp],
+            |row| row.get(0),
+        )?;
+
+        if deleted {
+            // Nothing of a deleted message is kept, including its previous versions
+            connection.execute(
+                "UPDATE messages
+                    SET message_kind = ?4, plaintext_content = NULL, original_content = NULL
+                    WHERE group_id = ?1 AND account_id = ?2 AND client_timestamp = ?3",
+                params![
+                    group_id,
+                    account_id,
+                    target_timestamp,
+                    u8::from(MessageKind::Deleted)
+                ],
+            )?;
+            connection.execute(
+                "UPDATE message_edits SET plaintext_content = NULL
+                    WHERE group_id = ?1 AND account_id = ?2 AND target_timestamp = ?3",
+                params![group_id, account_id, target_timestamp],
+            )?;
+            drop(connection);
+
+            return self.delete_reactions(group, target);
+        }
+
+        let latest_body: Option<String> = connection
+            .query_row(
+                "SELECT plaintext_content FROM message_edits
+                    WHERE group_id = ?1 AND account_id = ?2 AND target_timestamp = ?3
+                    ORDER BY edit_timestamp DESC
+                    LIMIT 1",
+                params![group_id, account_id, target_timestamp],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        if let Some(body) = latest_body {
+            connection.execute(
+                "UPDATE messages
+                    SET original_content = COALESCE(original_content, plaintext_content),
+                        plaintext_content = ?4
+                    WHERE group_id = ?1 AND account_id = ?2 AND client_timestamp = ?3",
+                params![group_id, account_id, target_timestamp, body],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns every version of the message that `reference` points to, oldest
+    /// first. Empty if we don't have it or if it was deleted.
+    pub fn get_edit_history(
+        &self,
+        group: GroupIdentifier,
+        reference: &MessageReference,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let connection = self.get_connection();
+        let group_id = group.to_bytes();
+        let account_id = reference.sender_account_id.as_uuid();
+        let client_timestamp = reference.client_stamp.to_vec();
+
+        let Some((original_content, plaintext_content)) = connection
+            .query_row(
+                "SELECT original_content, plaintext_content FROM messages
+                    WHERE group_id = ?1 AND account_id = ?2 AND client_timestamp = ?3",
+                params![group_id, account_id, client_timestamp],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                    ))
+                },
+            )
+            .optional()?
+        else {
+            return Ok(Vec::new());
+        };
+
+        let Some(original_content) = original_content else {
+            return Ok(plaintext_content.into_iter().collect());
+        };
+
+        let mut statement = connection.prepare(
+            "SELECT plaintext_content FROM message_edits
+                WHERE group_id = ?1 AND account_id = ?2 AND target_timestamp = ?3
+                    AND plaintext_content IS NOT NULL
+                ORDER BY edit_timestamp ASC",
+        )?;
+        let rows = statement.query_map(params![group_id, account_id, client_timestamp], |row| {
+            row.get::<_, String>(0)
+        })?;
+
+        let mut versions = vec![original_content];
+        for row in rows {
+            versions.push(row?);
+        }
+
+        Ok(versions)
+    }
+
+    /// Returns the edits of the message that `reference` points to with their
+    /// stamps, oldest first. Deletions have no body, and neither do the edits
+    /// of deleted messages, which are left out.
+    pub fn get_message_edits(
+        &self,
+        group: GroupIdentifier,
+        reference: &MessageReference,
+    ) -> Result<Vec<(DeliveryStamp, Option<String>)>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "SELECT edit_timestamp, plaintext_content FROM message_edits
+                WHERE group_id = ?1 AND account_id = ?2 AND target_timestamp = ?3
+                    AND (deleted = 1 OR plaintext_content IS NOT NULL)
+                ORDER BY edit_timestamp ASC",
+        )?;
+        let rows = statement.query_map(
+            params![
+                group.to_bytes(),
+                reference.sender_account_id.as_uuid(),
+                reference.client_stamp.to_vec(),
+            ],
+            |row| Ok((row.get::<_, Uuid>(0)?, row.get::<_, Option<String>>(1)?)),
+        )?;
+
+        let mut edits = Vec::new();
+        for row in rows {
+            let (edit_timestamp, body) = row?;
+            let edit_stamp = DeliveryStamp::try_from(edit_timestamp)
+                .map_err(|()| DatabaseError::CorruptedData)?;
+
+            edits.push((edit_stamp, body));
+        }
+
+        Ok(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::messages::Content;
+
+    use super::*;
+
+    #[test]
+    pub fn edits_before_their_target() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+        let target = MessageReference {
+            sender_account_id: AccountId::generate_id(),
+            client_stamp: DeliveryStamp::generate(),
+        };
+
+        db.add_message(
+            Content::Edit {
+                target: target.client_stamp,
+                body: "Second".to_string(),
+            },
+            target.sender_account_id,
+            Some(&DeliveryStamp::generate()),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding an edit works");
+        db.add_message(
+            Content::Edit {
+                target: target.client_stamp,
+                body: "Edited by someone else".to_string(),
+            },
+            AccountId::generate_id(),
+            Some(&DeliveryStamp::generate()),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding an edit works");
+        db.add_message(
+            Content::plain_text("First".to_string()),
+            target.sender_account_id,
+            Some(&target.client_stamp),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding a message works");
+
+        let message = db
+            .get_message_by_reference(group_id, &target)
+            .expect("Looking up messages works")
+            .expect("The message was stored");
+        assert_eq!(
+            message.message,
+            Content::plain_text("Second".to_string()),
+            "The edit is applied once its target arrives, and only its sender can edit it"
+        );
+        assert!(message.edited, "The message is marked as edited");
+        assert_eq!(
+            db.get_message_edits(group_id, &target)
+                .map(|edits| edits.into_iter().map(|(_, body)| body).collect::<Vec<_>>()),
+            Ok(vec![Some("Second".to_string())]),
+            "Only the edits of the sender are kept for the message"
+        );
+        assert_eq!(
+            db.get_edit_history(group_id, &target),
+            Ok(vec!["First".to_string(), "Second".to_string()]),
+            "The previous version is kept"
+        );
+
+        db.add_message(
+            Content::Delete {
+                target: target.client_stamp,
+            },
+            target.sender_account_id,
+            Some(&DeliveryStamp::generate()),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding a deletion works");
+        assert_eq!(
+            db.get_message_by_reference(group_id, &target)
+                .expect("Looking up messages works")
+                .map(|message| message.message),
+            Some(Content::Delete {
+                target: target.client_stamp
+            }),
+            "The deleted message is a tombstone"
+        );
+        assert_eq!(
+            db.get_edit_history(group_id, &target),
+            Ok(Vec::new()),
+            "No version of a deleted message is kept"
+        );
+        assert_eq!(
+            db.get_message_edits(group_id, &target)
+                .map(|edits| edits.into_iter().map(|(_, body)| body).collect::<Vec<_>>()),
+            Ok(vec![None]),
+            "Only the deletion of a deleted message is kept"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Secrets of the invite links we created (see [`lib::crypto::group_link`]).
+//!
+//! Only the device that created a link knows its secret, so it is the one
+//! that keeps the link up to date after every commit, and uploads it again
+//! when it starts so that the server doesn't expire it.
+use lib::{crypto::group_link::GroupLinkSecret, identifiers::GroupIdentifier};
+use rusqlite::{params, OptionalExtension};
+
+use super::{Database, DatabaseError};
+
+impl Database {
+    /// Saves the secret of the invite link of a group, replacing the previous one.
+    pub fn set_group_link_secret(
+        &self,
+        group_id: &GroupIdentifier,
+        link_secret: &GroupLinkSecret,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "INSERT OR REPLACE INTO group_links (group_id, link_secret) VALUES (?1, ?2)",
+            params![group_id.to_bytes(), link_secret.to_bytes()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the secret of the invite link of a group, or `None` if we
+    /// didn't create one.
+    pub fn get_group_link_secret(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<Option<GroupLinkSecret>, DatabaseError> {
+        let bytes: Option<Vec<u8>> = self
+            .get_connection()
+            .query_row(
+                "SELECT link_secret FROM group_links WHERE group_id = ?",
+                params![group_id.to_bytes()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        bytes
+            .map(|bytes| {
+                Ok(GroupLinkSecret::from_bytes(
+                    bytes.try_into().map_err(|_| DatabaseError::CorruptedData)?,
+                ))
+            })
+            .transpose()
+    }
+
+    /// Returns the groups whose invite link we created.
+    pub fn get_groups_with_link_secret(&self) -> Result<Vec<GroupIdentifier>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare("SELECT group_id FROM group_links")?;
+
+        let rows = statement.query_map((), |row| row.get::<_, Vec<u8>>(0))?;
+
+        let mut group_ids = Vec::new();
+        for row in rows {
+            group_ids.push(
+                GroupIdentifier::try_from(row?.as_slice())
+                    .map_err(|_| DatabaseError::CorruptedData)?,
+            );
+        }
+
+        Ok(group_ids)
+    }
+
+    pub fn delete_group_link_secret(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "DELETE FROM group_links WHERE group_id = ?",
+            params![group_id.to_bytes()],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::identifiers::LicksIdentifier;
+
+    use super::*;
+
+    #[test]
+    pub fn group_link_secret_is_replaced() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+
+        assert_eq!(
+            db.get_group_link_secret(&group_id),
+            Ok(None),
+            "No link was created yet"
+        );
+
+        let first_secret = GroupLinkSecret::generate();
+        db.set_group_link_secret(&group_id, &first_secret)
+            .expect("Saving the link secret works");
+        assert_eq!(
+            db.get_group_link_secret(&group_id),
+            Ok(Some(first_secret)),
+            "Link secret is stored correctly"
+        );
+
+        let second_secret = GroupLinkSecret::generate();
+        db.set_group_link_secret(&group_id, &second_secret)
+            .expect("Saving the link secret works");
+        assert_eq!(
+            db.get_group_link_secret(&group_id),
+            Ok(Some(second_secret)),
+            "Rotating the link replaces its secret"
+        );
+
+        assert_eq!(
+            db.get_groups_with_link_secret(),
+            Ok(vec![group_id]),
+            "The group should be listed once"
+        );
+
+        db.delete_group_link_secret(&group_id)
+            .expect("Deleting the link secret works");
+        assert_eq!(
+            db.get_group_link_secret(&group_id),
+            Ok(None),
+            "Link secret was deleted"
+        );
+        assert_eq!(
+            db.get_groups_with_link_secret(),
+            Ok(Vec::new()),
+            "The group shouldn't be listed anymore"
+        );
+    }
+}
@@ -7,6 +7,8 @@ use rusqlite::{params, ToSql};
 use super::{Database, DatabaseError};
 
 impl Database {
+    /// Saves the information of a group at a given epoch, replacing the
+    /// one we had for that epoch.
     #[allow(clippy::needless_pass_by_value)]
     pub fn add_group_info(
         &self,
@@ -17,7 +19,7 @@ impl Database {
         blinded_address: BlindedAddressSecret,
     ) -> Result<(), DatabaseError> {
         self.get_connection().execute(
-            "INSERT INTO group_info 
+            "INSERT OR REPLACE INTO group_info 
                     (group_id, epoch_id, group_name, group_description, blinded_address) 
                     VALUES (?, ?, ?, ?, ?)",
             params![
@@ -67,6 +69,32 @@ impl Database {
 
         Ok(BlindedAddressSecret::from_bytes(bytes))
     }
+
+    /// Replaces the name and description of a group, for every epoch we know of.
+    /// Does nothing if we don't know the group.
+    pub fn set_group_metadata(
+        &self,
+        group_id: GroupIdentifier,
+        group_name: &str,
+        group_description: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "UPDATE group_info SET group_name = ?, group_description = ? WHERE group_id = ?",
+            params![group_name, group_description, group_id.to_bytes()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Deletes the information of every epoch of a group.
+    pub fn delete_group_info(&self, group_id: &GroupIdentifier) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "DELETE FROM group_info WHERE group_id = ?",
+            params![group_id.to_bytes()],
+        )?;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
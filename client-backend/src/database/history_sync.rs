@@ -0,0 +1,148 @@
+//! Progress of the history transfers to our newly linked devices
+//! (see [`crate::manager::history_sync`]), so that they can be resumed.
+use lib::identifiers::{DeviceId, LicksIdentifier};
+use rusqlite::params;
+
+use super::{Database, DatabaseError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistorySyncState {
+    pub device_id: DeviceId,
+    /// The id of the last message that was sent, or 0 if none was.
+    pub last_message_id: u64,
+    /// Messages received after the transfer started aren't sent: the
+    /// new device receives them itself.
+    pub up_to_message_id: u64,
+}
+
+impl Database {
+    /// Starts a history transfer to `device_id`, covering every message we have
+    /// now. Does nothing if a transfer to that device was already started.
+    pub fn start_history_sync(&self, device_id: &DeviceId) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "INSERT OR IGNORE INTO history_sync (device_id, last_message_id, up_to_message_id)
+                SELECT ?1, 0, COALESCE(MAX(id), 0) FROM messages",
+            params![device_id.to_bytes()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the history transfers that aren't done yet.
+    pub fn get_history_syncs(&self) -> Result<Vec<HistorySyncState>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection
+            .prepare("SELECT device_id, last_message_id, up_to_message_id FROM history_sync")?;
+
+        let rows = statement.query_map((), |row| {
+            Ok((
+                row.get::<_, Vec<u8>>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
+        })?;
+
+        let mut states = Vec::new();
+        for row in rows {
+            let (device_id, last_message_id, up_to_message_id) = row?;
+
+            states.push(HistorySyncState {
+                device_id: DeviceId::try_from(device_id.as_slice())
+                    .map_err(|_| DatabaseError::CorruptedData)?,
+                last_message_id,
+                up_to_message_id,
+            });
+        }
+
+        Ok(states)
+    }
+
+    pub fn set_history_sync_progress(
+        &self,
+        device_id: &DeviceId,
+        last_message_id: u64,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "UPDATE history_sync SET last_message_id = ?2 WHERE device_id = ?1",
+            params![device_id.to_bytes(), last_message_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Forgets a history transfer, once it is done.
+    pub fn delete_history_sync(&self, device_id: &DeviceId) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "DELETE FROM history_sync WHERE device_id = ?",
+            params![device_id.to_bytes()],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::{
+        api::group::DeliveryStamp,
+        identifiers::{AccountId, GroupIdentifier},
+    };
+
+    use crate::messages::Content;
+
+    use super::*;
+
+    #[test]
+    pub fn history_sync_progress() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+        let device_id = DeviceId::generate_id();
+
+        db.add_message(
+            Content::plain_text("Before the link".to_string()),
+            AccountId::generate_id(),
+            Some(&DeliveryStamp::generate()),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding a message works");
+
+        db.start_history_sync(&device_id)
+            .expect("Starting the transfer works");
+        let state = HistorySyncState {
+            device_id,
+            last_message_id: 0,
+            up_to_message_id: 1,
+        };
+        assert_eq!(
+            db.get_history_syncs(),
+            Ok(vec![state]),
+            "The transfer covers the messages we had when it started"
+        );
+
+        db.add_message(
+            Content::plain_text("After the link".to_string()),
+            AccountId::generate_id(),
+            Some(&DeliveryStamp::generate()),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding a message works");
+        db.start_history_sync(&device_id)
+            .expect("Starting the transfer again works");
+        db.set_history_sync_progress(&device_id, 1)
+            .expect("Saving the progress works");
+        assert_eq!(
+            db.get_history_syncs(),
+            Ok(vec![HistorySyncState {
+                last_message_id: 1,
+                ..state
+            }]),
+            "Starting a transfer twice doesn't restart it"
+        );
+
+        db.delete_history_sync(&device_id)
+            .expect("Deleting the transfer works");
+        assert_eq!(db.get_history_syncs(), Ok(vec![]), "The transfer is done");
+    }
+}
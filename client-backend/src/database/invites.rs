@@ -0,0 +1,254 @@
+//! Invitations (MLS Welcome messages) delivered to our invite inbox.
+//!
+//! Invites are identified by the [`DeliveryStamp`] the server gave them, which
+//! also tells us from where to retrieve the inbox after being offline.
+use lib::{
+    api::group::DeliveryStamp,
+    crypto::{blinded_address::BlindedAddressSecret, rng::random_bytes},
+};
+use rusqlite::{params, OptionalExtension};
+
+use super::{Database, DatabaseError};
+
+/// Whether we answered an invite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InviteStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+impl From<InviteStatus> for u8 {
+    fn from(value: InviteStatus) -> Self {
+        match value {
+            InviteStatus::Pending => 1,
+            InviteStatus::Accepted => 2,
+            InviteStatus::Declined => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for InviteStatus {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(InviteStatus::Pending),
+            2 => Ok(InviteStatus::Accepted),
+            3 => Ok(InviteStatus::Declined),
+            x => Err(x),
+        }
+    }
+}
+
+impl Database {
+    /// Adds a new invite, as [`InviteStatus::Pending`]. Returns
+    /// [`DatabaseError::AlreadyExists`] if we already received it.
+    pub fn add_invite(
+        &self,
+        delivery_stamp: &DeliveryStamp,
+        welcome: &[u8],
+    ) -> Result<(), DatabaseError> {
+        let inserted = self.get_connection().execute(
+            "INSERT OR IGNORE INTO invites (delivery_stamp, welcome, status) VALUES (?1, ?2, ?3)",
+            params![
+                delivery_stamp.to_vec(),
+                welcome,
+                u8::from(InviteStatus::Pending)
+            ],
+        )?;
+
+        if inserted == 0 {
+            return Err(DatabaseError::AlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the Welcome message of a pending invite.
+    pub fn get_pending_invite(
+        &self,
+        delivery_stamp: &DeliveryStamp,
+    ) -> Result<Vec<u8>, DatabaseError> {
+        Ok(self.get_connection().query_row(
+            "SELECT welcome FROM invites WHERE delivery_stamp = ? AND status = ?",
+            params![delivery_stamp.to_vec(), u8::from(InviteStatus::Pending)],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Returns the invites we didn't answer yet, oldest first.
+    pub fn get_pending_invites(&self) -> Result<Vec<DeliveryStamp>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "SELECT delivery_stamp FROM invites WHERE status = ? ORDER BY delivery_stamp ASC",
+        )?;
+
+        let rows = statement.query_map(params![u8::from(InviteStatus::Pending)], |row| {
+            row.get::<_, Vec<u8>>(0)
+        })?;
+
+        let mut invites = Vec::new();
+        for row in rows {
+            invites.push(
+                DeliveryStamp::try_from(row?.as_slice())
+                    .map_err(|()| DatabaseError::CorruptedData)?,
+            );
+        }
+
+        Ok(invites)
+    }
+
+    /// Answers a pending invite. Returns `false` if it wasn't pending anymore.
+    pub fn set_invite_status(
+        &self,
+        delivery_stamp: &DeliveryStamp,
+        status: InviteStatus,
+    ) -> Result<bool, DatabaseError> {
+        let updated = self.get_connection().execute(
+            "UPDATE invites SET status = ?1 WHERE delivery_stamp = ?2 AND status = ?3",
+            params![
+                u8::from(status),
+                delivery_stamp.to_vec(),
+                u8::from(InviteStatus::Pending)
+            ],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    /// Returns the [`DeliveryStamp`] of the last invite we received, or `None`
+    /// if we never received any.
+    pub fn get_last_invite_stamp(&self) -> Result<Option<DeliveryStamp>, DatabaseError> {
+        // Stamps are big-endian Uuid v7s, so comparing their bytes
+        // compares their timestamps.
+        let bytes: Option<Vec<u8>> = self
+            .get_connection()
+            .query_row("SELECT MAX(delivery_stamp) FROM invites", (), |row| {
+                row.get(0)
+            })
+            .optional()?
+            .flatten();
+
+        bytes
+            .map(|bytes| {
+                DeliveryStamp::try_from(bytes.as_slice()).map_err(|()| DatabaseError::CorruptedData)
+            })
+            .transpose()
+    }
+
+    /// Returns the secret of our invite inbox, generating it the first time.
+    pub fn get_invite_inbox_secret(&self) -> Result<[u8; 32], DatabaseError> {
+        let connection = self.get_connection();
+
+        let secret = connection
+            .query_row("SELECT secret FROM invite_inbox", (), |row| row.get(0))
+            .optional()?;
+        if let Some(secret) = secret {
+            return Ok(secret);
+        }
+
+        let secret = BlindedAddressSecret::from_group_secret(&random_bytes::<32>()).to_bytes();
+        connection.execute(
+            "INSERT INTO invite_inbox (secret) VALUES (?1)",
+            params![secret],
+        )?;
+
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn invite_status_changes() {
+        let db = Database::in_memory().expect("in-memory db starts");
+
+        assert_eq!(
+            db.get_last_invite_stamp(),
+            Ok(None),
+            "No invite was received yet"
+        );
+
+        let (first_stamp, second_stamp) = {
+            let (a, b) = (DeliveryStamp::generate(), DeliveryStamp::generate());
+            (a.min(b), a.max(b))
+        };
+
+        assert_eq!(
+            db.add_invite(&first_stamp, b"first welcome"),
+            Ok(()),
+            "Adding an invite works"
+        );
+        assert_eq!(
+            db.add_invite(&first_stamp, b"first welcome"),
+            Err(DatabaseError::AlreadyExists),
+            "The delivery stamp identifies the invite"
+        );
+        assert_eq!(
+            db.add_invite(&second_stamp, b"second welcome"),
+            Ok(()),
+            "Adding an invite works"
+        );
+
+        assert_eq!(
+            db.get_pending_invites(),
+            Ok(vec![first_stamp, second_stamp]),
+            "Both invites are pending"
+        );
+        assert_eq!(
+            db.get_pending_invite(&first_stamp),
+            Ok(b"first welcome".to_vec()),
+            "Welcome is stored correctly"
+        );
+
+        assert_eq!(
+            db.set_invite_status(&second_stamp, InviteStatus::Accepted),
+            Ok(true),
+            "Pending invite can be accepted"
+        );
+        assert_eq!(
+            db.set_invite_status(&second_stamp, InviteStatus::Declined),
+            Ok(false),
+            "Invite was already accepted"
+        );
+        assert!(
+            db.get_pending_invite(&second_stamp).is_err(),
+            "Invite isn't pending anymore"
+        );
+
+        assert_eq!(
+            db.get_pending_invites(),
+            Ok(vec![first_stamp]),
+            "Only the first invite is pending"
+        );
+        assert_eq!(
+            db.get_last_invite_stamp(),
+            Ok(Some(second_stamp)),
+            "Answered invites still count"
+        );
+    }
+
+    #[test]
+    pub fn invite_inbox_secret_is_kept() {
+        let db = Database::in_memory().expect("in-memory db starts");
+
+        let secret = db
+            .get_invite_inbox_secret()
+            .expect("The secret is generated");
+        assert_eq!(
+            db.get_invite_inbox_secret(),
+            Ok(secret),
+            "The same secret is returned afterwards"
+        );
+
+        let other_db = Database::in_memory().expect("in-memory db starts");
+        assert_ne!(
+            other_db.get_invite_inbox_secret(),
+            Ok(secret),
+            "Every device has its own secret"
+        );
+    }
+}
@@ -2,11 +2,11 @@ use lib::{
     api::group::DeliveryStamp,
     identifiers::{AccountId, GroupIdentifier, LicksIdentifier, Uuid},
 };
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension, Row};
 
-use crate::messages::{Content, MessageKind};
+use crate::messages::{Content, MessageKind, MessageReference};
 
-use super::{Database, DatabaseError};
+use super::{outbox::OutboxStatus, Database, DatabaseError};
 
 pub struct DatabaseMessage {
     pub database_id: u64,
@@ -14,8 +14,17 @@ pub struct DatabaseMessage {
     pub account_id: AccountId,
     pub server_delivery_stamp: DeliveryStamp,
     pub our_delivery_stamp: DeliveryStamp,
+    /// `None` for messages stored before client stamps were.
+    pub client_stamp: Option<DeliveryStamp>,
     pub message_kind: MessageKind,
+    /// Deleted messages are tombstones: their content is the
+    /// [`Content::Delete`] that removed them.
     pub message: Content,
+    /// See [`Database::get_edit_history`] for the previous versions.
+    pub edited: bool,
+    /// The status of the messages we sent (see [`crate::database::outbox`]),
+    /// `None` for the messages we received.
+    pub outbox_status: Option<OutboxStatus>,
 }
 
 #[derive(Clone, Copy)]
@@ -24,52 +33,229 @@ pub enum Direction {
     After,
 }
 
-/// (`id`, `account_id`, `server_timestamp`, `received_timestamp`, `message_kind`, `plaintext_content`)
-type MessageSqlRow = (u64, Uuid, Uuid, Uuid, u8, String);
+/// (`id`, `account_id`, `server_timestamp`, `received_timestamp`, `message_kind`, `plaintext_content`,
+/// `client_timestamp`, `reply_account_id`, `reply_message_timestamp`, `original_content IS NOT NULL`,
+/// outbox `status`)
+type MessageSqlRow = (
+    u64,
+    Uuid,
+    Uuid,
+    Uuid,
+    u8,
+    Option<String>,
+    Option<Uuid>,
+    Option<Uuid>,
+    Option<Uuid>,
+    bool,
+    Option<u8>,
+);
 
 impl Database {
     pub fn add_message(
         &self,
         content: Content,
         sender_account_id: AccountId,
+        client_stamp: Option<&DeliveryStamp>,
         server_delivery_timestamp: &DeliveryStamp,
         group: &GroupIdentifier,
     ) -> Result<(), DatabaseError> {
-        match content {
-            Content::BasicText { body } => {
-                // TODO: Use our DeliveryId as the key
-                let query = "
-                    INSERT INTO messages (id, group_id, 
-                        account_id, server_timestamp, 
-                        received_timestamp, message_kind, 
-                        plaintext_content, reply_message_timestamp)
-                    VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, NULL)
-                ";
-
-                let group_id = group.to_bytes();
-                let account_id = sender_account_id.as_uuid();
-                let server_timestamp = server_delivery_timestamp.as_bytes();
-                let received_timestamp = DeliveryStamp::generate().to_vec();
-                let message_kind: u8 = MessageKind::PlainText.into();
-                let plaintext_content = body;
-
-                self.get_connection().execute(
-                    query,
-                    params![
-                        group_id,
-                        account_id,
-                        server_timestamp,
-                        received_timestamp,
-                        message_kind,
-                        plaintext_content,
-                    ],
-                )?;
+        let (message_kind, plaintext_content, parent) = match content {
+            Content::BasicText { body } => (MessageKind::PlainText, body, None),
+            Content::Reply { body, parent } => (MessageKind::Reply, body, Some(parent)),
+            // History batches are applied when received, not stored
+            Content::HistorySync(_) => return Ok(()),
+            // Typing indicators are ephemeral
+            Content::Typing(_) => return Ok(()),
+            // Account keys are adopted when received, not stored
+            Content::AccountKey(_) => return Ok(()),
+            Content::Edit { target, body } => {
+                return self.add_message_edit(
+                    group,
+                    &MessageReference {
+                        sender_account_id,
+                        client_stamp: target,
+                    },
+                    client_stamp.unwrap_or(server_delivery_timestamp),
+                    Some(body),
+                );
+            }
+            Content::Delete { target } => {
+                return self.add_message_edit(
+                    group,
+                    &MessageReference {
+                        sender_account_id,
+                        client_stamp: target,
+                    },
+                    client_stamp.unwrap_or(server_delivery_timestamp),
+                    None,
+                );
+            }
+            Content::Reaction {
+                target,
+                emoji,
+                remove,
+            } => {
+                return self.set_reaction(group, &target, sender_account_id, &emoji, remove);
+            }
+            Content::Receipt { kind, messages } => {
+                return self.add_receipts(group, sender_account_id, kind, &messages);
             }
         };
 
+        // TODO: Use our DeliveryId as the key
+        let query = "
+            INSERT INTO messages (id, group_id, 
+                account_id, server_timestamp, 
+                received_timestamp, message_kind, 
+                plaintext_content, reply_message_timestamp,
+                client_timestamp, reply_account_id)
+            VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        ";
+
+        let group_id = group.to_bytes();
+        let account_id = sender_account_id.as_uuid();
+        let server_timestamp = server_delivery_timestamp.as_bytes();
+        let received_timestamp = DeliveryStamp::generate().to_vec();
+        let message_kind: u8 = message_kind.into();
+        let client_timestamp = client_stamp.map(|stamp| stamp.to_vec());
+        let reply_account_id = parent.map(|parent| parent.sender_account_id.as_uuid());
+        let reply_message_timestamp = parent.map(|parent| parent.client_stamp.to_vec());
+
+        self.get_connection().execute(
+            query,
+            params![
+                group_id,
+                account_id,
+                server_timestamp,
+                received_timestamp,
+                message_kind,
+                plaintext_content,
+                reply_message_timestamp,
+                client_timestamp,
+                reply_account_id,
+            ],
+        )?;
+
+        // Edits and deletions may have arrived before the message
+        if let Some(client_stamp) = client_stamp {
+            self.apply_message_edits(
+                group,
+                &MessageReference {
+                    sender_account_id,
+                    client_stamp: *client_stamp,
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the server stamp of a message we sent, once the server delivered it.
+    /// Until then, the message is stored with its `client_stamp` instead.
+    pub fn set_message_server_stamp(
+        &self,
+        group: &GroupIdentifier,
+        reference: &MessageReference,
+        server_delivery_timestamp: &DeliveryStamp,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "UPDATE messages SET server_timestamp = ?1
+                WHERE group_id = ?2 AND account_id = ?3 AND client_timestamp = ?4",
+            params![
+                server_delivery_timestamp.as_bytes(),
+                group.to_bytes(),
+                reference.sender_account_id.as_uuid(),
+                reference.client_stamp.to_vec()
+            ],
+        )?;
+
         Ok(())
     }
 
+    /// Adds a message received from another of our devices (see
+    /// [`crate::manager::history_sync`]). Returns `false` if we already had it,
+    /// which is the case for messages we also received ourselves.
+    pub fn add_synced_message(
+        &self,
+        content: Content,
+        sender_account_id: AccountId,
+        client_stamp: Option<&DeliveryStamp>,
+        server_delivery_timestamp: &DeliveryStamp,
+        group: &GroupIdentifier,
+    ) -> Result<bool, DatabaseError> {
+        let already_exists: bool = self.get_connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM messages WHERE group_id = ? AND server_timestamp = ?)",
+            params![group.to_bytes(), server_delivery_timestamp.as_bytes()],
+            |row| row.get(0),
+        )?;
+
+        if already_exists {
+            return Ok(false);
+        }
+
+        self.add_message(
+            content,
+            sender_account_id,
+            client_stamp,
+            server_delivery_timestamp,
+            group,
+        )?;
+
+        Ok(true)
+    }
+
+    /// Returns the messages of every group whose id is in `(after_id, up_to_id]`,
+    /// oldest first.
+    pub fn get_messages_to_sync(
+        &self,
+        after_id: u64,
+        up_to_id: u64,
+        count: usize,
+    ) -> Result<Vec<DatabaseMessage>, DatabaseError> {
+        let query = "
+            SELECT id,
+                account_id, server_timestamp,
+                received_timestamp, message_kind,
+                plaintext_content, client_timestamp,
+                reply_account_id, reply_message_timestamp,
+                original_content IS NOT NULL,
+                (SELECT status FROM outbox
+                    WHERE outbox.client_stamp = messages.client_timestamp
+                    AND outbox.group_id = messages.group_id),
+                group_id
+                FROM messages
+                WHERE id > ? AND id <= ?
+                ORDER BY id ASC
+                LIMIT ?
+        ";
+
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(query)?;
+        let rows = statement.query_map(params![after_id, up_to_id, count], |row| {
+            Ok((Self::read_message_row(row)?, row.get::<_, Vec<u8>>(11)?))
+        })?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            let (row, group_id) = row?;
+            let group_id = GroupIdentifier::try_from(group_id.as_slice())
+                .map_err(|_| DatabaseError::CorruptedData)?;
+
+            messages.push(Self::convert_values_to_message(row, group_id)?);
+        }
+
+        Ok(messages)
+    }
+
+    /// Returns how many messages of every group have an id up to `id`.
+    pub fn count_messages_up_to(&self, id: u64) -> Result<u64, DatabaseError> {
+        Ok(self.get_connection().query_row(
+            "SELECT COUNT(*) FROM messages WHERE id <= ?",
+            params![id],
+            |row| row.get(0),
+        )?)
+    }
+
     pub fn get_last_message(
         &self,
         group: GroupIdentifier,
@@ -78,7 +264,12 @@ impl Database {
             SELECT id, 
                 account_id, server_timestamp, 
                 received_timestamp, message_kind, 
-                plaintext_content
+                plaintext_content, client_timestamp,
+                reply_account_id, reply_message_timestamp,
+                original_content IS NOT NULL,
+                (SELECT status FROM outbox
+                    WHERE outbox.client_stamp = messages.client_timestamp
+                    AND outbox.group_id = messages.group_id)
                 FROM messages
                 WHERE group_id = ?
                 ORDER BY id DESC
@@ -87,22 +278,52 @@ impl Database {
 
         let group_id = group.to_bytes();
 
-        let row: MessageSqlRow =
+        let row =
             self.get_connection()
-                .query_row(query, params![group_id], |row| {
-                    Ok((
-                        row.get(0)?,
-                        row.get(1)?,
-                        row.get(2)?,
-                        row.get(3)?,
-                        row.get(4)?,
-                        row.get(5)?,
-                    ))
-                })?;
+                .query_row(query, params![group_id], Self::read_message_row)?;
 
         Self::convert_values_to_message(row, group)
     }
 
+    /// Returns the message of `group` that `reference` points to, or `None` if
+    /// we don't have it (yet).
+    pub fn get_message_by_reference(
+        &self,
+        group: GroupIdentifier,
+        reference: &MessageReference,
+    ) -> Result<Option<DatabaseMessage>, DatabaseError> {
+        let query = "
+            SELECT id, 
+                account_id, server_timestamp, 
+                received_timestamp, message_kind, 
+                plaintext_content, client_timestamp,
+                reply_account_id, reply_message_timestamp,
+                original_content IS NOT NULL,
+                (SELECT status FROM outbox
+                    WHERE outbox.client_stamp = messages.client_timestamp
+                    AND outbox.group_id = messages.group_id)
+                FROM messages
+                WHERE group_id = ? AND account_id = ? AND client_timestamp = ?
+                LIMIT 1
+        ";
+
+        let row = self
+            .get_connection()
+            .query_row(
+                query,
+                params![
+                    group.to_bytes(),
+                    reference.sender_account_id.as_uuid(),
+                    reference.client_stamp.to_vec()
+                ],
+                Self::read_message_row,
+            )
+            .optional()?;
+
+        row.map(|row| Self::convert_values_to_message(row, group))
+            .transpose()
+    }
+
     pub const MAX_MESSAGE_ID: u64 = i64::MAX as u64;
 
     pub fn get_many_messages(
@@ -116,7 +337,12 @@ impl Database {
             SELECT id, 
                 account_id, server_timestamp, 
                 received_timestamp, message_kind, 
-                plaintext_content
+                plaintext_content, client_timestamp,
+                reply_account_id, reply_message_timestamp,
+                original_content IS NOT NULL,
+                (SELECT status FROM outbox
+                    WHERE outbox.client_stamp = messages.client_timestamp
+                    AND outbox.group_id = messages.group_id)
                 FROM messages
                 WHERE group_id = ? AND id < ?
                 ORDER BY id DESC
@@ -127,7 +353,12 @@ impl Database {
             SELECT id, 
                 account_id, server_timestamp, 
                 received_timestamp, message_kind, 
-                plaintext_content
+                plaintext_content, client_timestamp,
+                reply_account_id, reply_message_timestamp,
+                original_content IS NOT NULL,
+                (SELECT status FROM outbox
+                    WHERE outbox.client_stamp = messages.client_timestamp
+                    AND outbox.group_id = messages.group_id)
                 FROM messages
                 WHERE group_id = ? AND id > ? 
                 ORDER BY id ASC
@@ -139,16 +370,8 @@ impl Database {
         };
         let connection = self.get_connection();
         let mut statement = connection.prepare(query)?;
-        let rows = statement.query_map(params![group.to_bytes(), id, count], |row| {
-            Ok((
-                row.get::<_, u64>(0)?,
-                row.get::<_, Uuid>(1)?,
-                row.get::<_, Uuid>(2)?,
-                row.get::<_, Uuid>(3)?,
-                row.get::<_, u8>(4)?,
-                row.get::<_, String>(5)?,
-            ))
-        })?;
+        let rows =
+            statement.query_map(params![group.to_bytes(), id, count], Self::read_message_row)?;
 
         let mut fin = Vec::new();
         for row in rows {
@@ -162,14 +385,70 @@ impl Database {
         Ok(fin)
     }
 
+    fn read_message_row(row: &Row) -> rusqlite::Result<MessageSqlRow> {
+        Ok((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+            row.get(7)?,
+            row.get(8)?,
+            row.get(9)?,
+            row.get(10)?,
+        ))
+    }
+
     fn convert_values_to_message(
         row: MessageSqlRow,
         group_id: GroupIdentifier,
     ) -> Result<DatabaseMessage, DatabaseError> {
-        let (id, account_id, server_timestamp, received_timestamp, message_kind, plaintext_content) =
-            row;
+        let (
+            id,
+            account_id,
+            server_timestamp,
+            received_timestamp,
+            message_kind,
+            plaintext_content,
+            client_timestamp,
+            reply_account_id,
+            reply_message_timestamp,
+            edited,
+            outbox_status,
+        ) = row;
         let message_kind =
             MessageKind::try_from(message_kind).map_err(|_| DatabaseError::CorruptedData)?;
+        let client_stamp = client_timestamp
+            .map(DeliveryStamp::try_from)
+            .transpose()
+            .map_err(|()| DatabaseError::CorruptedData)?;
+        let message = match message_kind {
+            MessageKind::PlainText => Content::BasicText {
+                body: plaintext_content.ok_or(DatabaseError::CorruptedData)?,
+            },
+            MessageKind::Reply => {
+                let (Some(reply_account_id), Some(reply_message_timestamp)) =
+                    (reply_account_id, reply_message_timestamp)
+                else {
+                    return Err(DatabaseError::CorruptedData);
+                };
+
+                Content::Reply {
+                    body: plaintext_content.ok_or(DatabaseError::CorruptedData)?,
+                    parent: MessageReference {
+                        sender_account_id: reply_account_id.into(),
+                        client_stamp: DeliveryStamp::try_from(reply_message_timestamp)
+                            .map_err(|()| DatabaseError::CorruptedData)?,
+                    },
+                }
+            }
+            MessageKind::Deleted => Content::Delete {
+                target: client_stamp.ok_or(DatabaseError::CorruptedData)?,
+            },
+        };
+
         Ok(DatabaseMessage {
             database_id: id,
             group_id,
@@ -178,12 +457,73 @@ impl Database {
                 .map_err(|()| DatabaseError::CorruptedData)?,
             our_delivery_stamp: DeliveryStamp::try_from(received_timestamp)
                 .map_err(|()| DatabaseError::CorruptedData)?,
+            client_stamp,
             message_kind,
-            message: match message_kind {
-                MessageKind::PlainText => Content::BasicText {
-                    body: plaintext_content,
-                },
-            },
+            message,
+            edited,
+            outbox_status: outbox_status
+                .map(OutboxStatus::try_from)
+                .transpose()
+                .map_err(|_| DatabaseError::CorruptedData)?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn reply_references_parent() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+        let parent = MessageReference {
+            sender_account_id: AccountId::generate_id(),
+            client_stamp: DeliveryStamp::generate(),
+        };
+
+        db.add_message(
+            Content::reply("Replying early".to_string(), parent),
+            AccountId::generate_id(),
+            Some(&DeliveryStamp::generate()),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding a reply works");
+        assert_eq!(
+            db.get_last_message(group_id)
+                .expect("The reply was stored")
+                .message,
+            Content::reply("Replying early".to_string(), parent),
+            "The reply keeps its parent"
+        );
+        assert!(
+            db.get_message_by_reference(group_id, &parent)
+                .expect("Looking up messages works")
+                .is_none(),
+            "The parent hasn't arrived yet"
+        );
+
+        db.add_message(
+            Content::plain_text("The parent".to_string()),
+            parent.sender_account_id,
+            Some(&parent.client_stamp),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding a message works");
+        assert_eq!(
+            db.get_message_by_reference(group_id, &parent)
+                .expect("Looking up messages works")
+                .map(|message| message.message),
+            Some(Content::plain_text("The parent".to_string())),
+            "The parent can be found once it arrives"
+        );
+        assert!(
+            db.get_message_by_reference(GroupIdentifier::generate_id(), &parent)
+                .expect("Looking up messages works")
+                .is_none(),
+            "References are scoped to their group"
+        );
+    }
+}
@@ -9,10 +9,19 @@ use mls_rs_provider_sqlite::{connection_strategy::ConnectionStrategy, SqLiteData
 use rusqlite::Connection;
 
 pub mod contacts;
+pub mod edits;
+pub mod group_links;
 pub mod groups;
+pub mod history_sync;
+pub mod invites;
 pub mod messages;
+pub mod outbox;
 pub mod profile;
+pub mod reactions;
+pub mod receipts;
 pub mod schemas;
+pub mod settings;
+pub mod sync;
 
 #[derive(Debug, PartialEq, thiserror::Error)]
 pub enum DatabaseError {
@@ -0,0 +1,361 @@
+//! Messages we encrypted but that the server didn't acknowledge yet.
+//!
+//! Every message goes through the outbox before being sent, and is stored in our
+//! own messages at the same time. Its `client_stamp` identifies it, and it is
+//! claimed by the attempt sending it, so that it is never sent twice at once.
+use lib::{
+    api::{
+        group::DeliveryStamp,
+        proto::{self, ProstMessage},
+    },
+    crypto::blinded_address::BlindedAddressSecret,
+    identifiers::{GroupIdentifier, LicksIdentifier},
+};
+use rusqlite::params;
+
+use crate::messages::Content;
+
+use super::{Database, DatabaseError};
+
+/// Where a message of the outbox is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxStatus {
+    /// Not acknowledged by the server yet, we will try sending it again.
+    Pending,
+    /// Being sent right now, see [`Database::claim_outbox_message`].
+    Sending,
+    /// Delivered to the server.
+    Sent,
+    /// The server refused the message, or we gave up sending it.
+    Failed,
+}
+
+impl From<OutboxStatus> for u8 {
+    fn from(value: OutboxStatus) -> Self {
+        match value {
+            OutboxStatus::Pending => 1,
+            OutboxStatus::Sent => 2,
+            OutboxStatus::Failed => 3,
+            OutboxStatus::Sending => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for OutboxStatus {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(OutboxStatus::Pending),
+            2 => Ok(OutboxStatus::Sent),
+            3 => Ok(OutboxStatus::Failed),
+            4 => Ok(OutboxStatus::Sending),
+            x => Err(x),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OutboxMessage {
+    pub client_stamp: DeliveryStamp,
+    pub group_id: GroupIdentifier,
+    /// The blinded address of the epoch the message was encrypted in.
+    pub blinded_address: BlindedAddressSecret,
+    pub mls_message: Vec<u8>,
+    pub content: Content,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+}
+
+/// (`client_stamp`, `group_id`, `blinded_address`, `mls_message`, `content`, `status`, `attempts`)
+type OutboxSqlRow = (Vec<u8>, Vec<u8>, [u8; 32], Vec<u8>, Vec<u8>, u8, u32);
+
+impl Database {
+    /// Adds a new message to the outbox, as [`OutboxStatus::Pending`].
+    /// Returns [`DatabaseError::AlreadyExists`] if a message with the
+    /// same `client_stamp` is already in it.
+    pub fn add_outbox_message(
+        &self,
+        client_stamp: &DeliveryStamp,
+        group_id: &GroupIdentifier,
+        blinded_address: BlindedAddressSecret,
+        mls_message: &[u8],
+        content: Content,
+    ) -> Result<(), DatabaseError> {
+        let inserted = self.get_connection().execute(
+            "INSERT OR IGNORE INTO outbox
+                (client_stamp, group_id, blinded_address, mls_message, content, status, attempts)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0)",
+            params![
+                client_stamp.to_vec(),
+                group_id.to_bytes(),
+                blinded_address.to_bytes(),
+                mls_message,
+                proto::Content::from(content).encode_to_vec(),
+                u8::from(OutboxStatus::Pending),
+            ],
+        )?;
+
+        if inserted == 0 {
+            return Err(DatabaseError::AlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the messages that still have to be sent, oldest first.
+    pub fn get_pending_outbox_messages(&self) -> Result<Vec<OutboxMessage>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "SELECT client_stamp, group_id, blinded_address, mls_message, content, status, attempts
+                FROM outbox
+                WHERE status = ?
+                ORDER BY client_stamp ASC",
+        )?;
+
+        let rows = statement.query_map(params![u8::from(OutboxStatus::Pending)], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get(6)?,
+            ))
+        })?;
+
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(Self::convert_values_to_outbox_message(row?)?);
+        }
+
+        Ok(messages)
+    }
+
+    pub fn get_outbox_status(
+        &self,
+        client_stamp: &DeliveryStamp,
+    ) -> Result<OutboxStatus, DatabaseError> {
+        let status: u8 = self.get_connection().query_row(
+            "SELECT status FROM outbox WHERE client_stamp = ?",
+            params![client_stamp.to_vec()],
+            |row| row.get(0),
+        )?;
+
+        OutboxStatus::try_from(status).map_err(|_| DatabaseError::CorruptedData)
+    }
+
+    /// Claims a pending message before sending it, so that it is never sent by
+    /// two attempts at once. Returns `false` if the message wasn't pending, e.g.
+    /// because another attempt is already sending it.
+    pub fn claim_outbox_message(
+        &self,
+        client_stamp: &DeliveryStamp,
+    ) -> Result<bool, DatabaseError> {
+        let updated = self.get_connection().execute(
+            "UPDATE outbox SET status = ?1 WHERE client_stamp = ?2 AND status = ?3",
+            params![
+                u8::from(OutboxStatus::Sending),
+                client_stamp.to_vec(),
+                u8::from(OutboxStatus::Pending)
+            ],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    /// Changes the status of a message we claimed (see [`Self::claim_outbox_message`])
+    /// once we tried sending it. Setting it back to [`OutboxStatus::Pending`] releases
+    /// the claim. Returns `false` if the message wasn't claimed.
+    pub fn set_outbox_status(
+        &self,
+        client_stamp: &DeliveryStamp,
+        status: OutboxStatus,
+    ) -> Result<bool, DatabaseError> {
+        let updated = self.get_connection().execute(
+            "UPDATE outbox SET status = ?1 WHERE client_stamp = ?2 AND status = ?3",
+            params![
+                u8::from(status),
+                client_stamp.to_vec(),
+                u8::from(OutboxStatus::Sending)
+            ],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    /// Makes the messages that were being sent when we stopped pending again.
+    pub fn release_outbox_claims(&self) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "UPDATE outbox SET status = ?1 WHERE status = ?2",
+            params![
+                u8::from(OutboxStatus::Pending),
+                u8::from(OutboxStatus::Sending)
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Counts one more failed attempt to send a message, and returns
+    /// how many attempts failed so far.
+    pub fn increment_outbox_attempts(
+        &self,
+        client_stamp: &DeliveryStamp,
+    ) -> Result<u32, DatabaseError> {
+        Ok(self.get_connection().query_row(
+            "UPDATE outbox SET attempts = attempts + 1 WHERE client_stamp = ? RETURNING attempts",
+            params![client_stamp.to_vec()],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn convert_values_to_outbox_message(row: OutboxSqlRow) -> Result<OutboxMessage, DatabaseError> {
+        let (client_stamp, group_id, blinded_address, mls_message, content, status, attempts) = row;
+
+        let content = proto::Content::decode(content.as_slice())
+            .map_err(|_| DatabaseError::CorruptedData)?
+            .try_into()
+            .map_err(|_| DatabaseError::CorruptedData)?;
+
+        Ok(OutboxMessage {
+            client_stamp: DeliveryStamp::try_from(client_stamp.as_slice())
+                .map_err(|()| DatabaseError::CorruptedData)?,
+            group_id: GroupIdentifier::try_from(group_id.as_slice())
+                .map_err(|_| DatabaseError::CorruptedData)?,
+            blinded_address: BlindedAddressSecret::from_bytes(blinded_address),
+            mls_message,
+            content,
+            status: OutboxStatus::try_from(status).map_err(|_| DatabaseError::CorruptedData)?,
+            attempts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::crypto::rng::random_bytes;
+
+    use super::*;
+
+    #[test]
+    pub fn outbox_status_changes() {
+        let db = Database::in_memory().expect("in-memory db starts");
+
+        let client_stamp = DeliveryStamp::generate();
+        let group_id = GroupIdentifier::generate_id();
+        let blinded_address_bytes =
+            BlindedAddressSecret::from_group_secret(&random_bytes::<16>()).to_bytes();
+        let content = Content::plain_text("Hello".to_string());
+
+        assert_eq!(
+            db.add_outbox_message(
+                &client_stamp,
+                &group_id,
+                BlindedAddressSecret::from_bytes(blinded_address_bytes),
+                b"mls message",
+                content.clone()
+            ),
+            Ok(()),
+            "Adding a message to the outbox works"
+        );
+
+        assert_eq!(
+            db.add_outbox_message(
+                &client_stamp,
+                &group_id,
+                BlindedAddressSecret::from_bytes(blinded_address_bytes),
+                b"mls message",
+                content.clone()
+            ),
+            Err(DatabaseError::AlreadyExists),
+            "The client stamp identifies the message"
+        );
+
+        let pending = db
+            .get_pending_outbox_messages()
+            .expect("pending messages are read");
+        assert_eq!(pending.len(), 1, "The message is pending");
+        assert_eq!(
+            pending[0].client_stamp, client_stamp,
+            "Stamp is stored correctly"
+        );
+        assert_eq!(pending[0].group_id, group_id, "Group is stored correctly");
+        assert_eq!(
+            pending[0].blinded_address,
+            BlindedAddressSecret::from_bytes(blinded_address_bytes),
+            "Blinded address is stored correctly"
+        );
+        assert_eq!(
+            pending[0].mls_message,
+            b"mls message".to_vec(),
+            "MLS message is stored correctly"
+        );
+        assert_eq!(pending[0].content, content, "Content is stored correctly");
+        assert_eq!(pending[0].attempts, 0, "Nothing was attempted yet");
+
+        assert_eq!(
+            db.increment_outbox_attempts(&client_stamp),
+            Ok(1),
+            "Attempts are counted"
+        );
+
+        assert_eq!(
+            db.set_outbox_status(&client_stamp, OutboxStatus::Sent),
+            Ok(false),
+            "A message has to be claimed before its status changes"
+        );
+        assert_eq!(
+            db.claim_outbox_message(&client_stamp),
+            Ok(true),
+            "A pending message can be claimed"
+        );
+        assert_eq!(
+            db.claim_outbox_message(&client_stamp),
+            Ok(false),
+            "A message can only be claimed by one attempt"
+        );
+        assert!(
+            db.get_pending_outbox_messages()
+                .expect("pending messages are read")
+                .is_empty(),
+            "Claimed messages are not pending"
+        );
+
+        db.release_outbox_claims()
+            .expect("Claims of a previous run are released");
+        assert_eq!(
+            db.get_outbox_status(&client_stamp),
+            Ok(OutboxStatus::Pending),
+            "Released messages are pending again"
+        );
+        assert_eq!(
+            db.claim_outbox_message(&client_stamp),
+            Ok(true),
+            "A released message can be claimed again"
+        );
+
+        assert_eq!(
+            db.set_outbox_status(&client_stamp, OutboxStatus::Sent),
+            Ok(true),
+            "A claimed message can be marked as sent"
+        );
+        assert_eq!(
+            db.set_outbox_status(&client_stamp, OutboxStatus::Sent),
+            Ok(false),
+            "A message that was already sent isn't claimed anymore"
+        );
+        assert_eq!(
+            db.get_outbox_status(&client_stamp),
+            Ok(OutboxStatus::Sent),
+            "Status was changed"
+        );
+        assert!(
+            db.get_pending_outbox_messages()
+                .expect("pending messages are read")
+                .is_empty(),
+            "Sent messages are not pending"
+        );
+    }
+}
@@ -1,9 +1,14 @@
+use lib::identifiers::{GroupIdentifier, LicksIdentifier};
 use rusqlite::params;
 
 use crate::manager::account::Profile;
 
 use super::{Database, DatabaseError};
 
+/// Set while our renewed chain isn't registered with the server, see
+/// [`crate::manager::ProfileManager::register_renewed_chain`].
+const CHAIN_REGISTRATION_PENDING: &str = "chain_registration_pending";
+
 impl Database {
     pub fn set_profile(&self, profile: &Profile) -> Result<(), DatabaseError> {
         self.get_connection().execute(
@@ -17,6 +22,81 @@ impl Database {
         Ok(())
     }
 
+    /// Replaces the stored profile, e.g. after renewing its certificates.
+    pub fn update_profile(&self, profile: &Profile) -> Result<(), DatabaseError> {
+        self.get_connection()
+            .execute("UPDATE profile SET data = ?1", params![profile.to_bytes()])?;
+
+        Ok(())
+    }
+
+    /// Replaces the stored profile with one using new keys, and marks every group
+    /// of `group_ids` as needing an update of our leaf, at once: if we stop in
+    /// between, the updates are still done with the new profile when we restart.
+    pub fn replace_profile(
+        &self,
+        profile: &Profile,
+        group_ids: &[GroupIdentifier],
+    ) -> Result<(), DatabaseError> {
+        let mut connection = self.get_connection();
+        let transaction = connection.transaction()?;
+
+        transaction.execute("UPDATE profile SET data = ?1", params![profile.to_bytes()])?;
+        for group_id in group_ids {
+            transaction.execute(
+                "INSERT OR IGNORE INTO pending_identity_updates (group_id) VALUES (?1)",
+                params![group_id.to_bytes()],
+            )?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Returns the groups where our leaf wasn't updated to our current profile yet.
+    pub fn get_pending_identity_updates(&self) -> Result<Vec<GroupIdentifier>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare("SELECT group_id FROM pending_identity_updates")?;
+
+        let rows = statement.query_map((), |row| row.get::<_, Vec<u8>>(0))?;
+
+        let mut group_ids = Vec::new();
+        for row in rows {
+            group_ids.push(
+                GroupIdentifier::try_from(row?.as_slice())
+                    .map_err(|_| DatabaseError::CorruptedData)?,
+            );
+        }
+
+        Ok(group_ids)
+    }
+
+    pub fn is_identity_update_pending(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<bool, DatabaseError> {
+        Ok(self.get_connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM pending_identity_updates WHERE group_id = ?1)",
+            params![group_id.to_bytes()],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Forgets the pending update of a group, once it is done or
+    /// if we aren't in that group anymore.
+    pub fn delete_pending_identity_update(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "DELETE FROM pending_identity_updates WHERE group_id = ?1",
+            params![group_id.to_bytes()],
+        )?;
+
+        Ok(())
+    }
+
     pub fn get_profile(&self) -> Result<Profile, DatabaseError> {
         let profile_bytes: Vec<u8> =
             self.get_connection()
@@ -27,4 +107,67 @@ impl Database {
 
         Ok(profile)
     }
+
+    pub fn is_chain_registration_pending(&self) -> Result<bool, DatabaseError> {
+        Ok(self.get_setting(CHAIN_REGISTRATION_PENDING)? == Some(1))
+    }
+
+    pub fn set_chain_registration_pending(&self, pending: bool) -> Result<(), DatabaseError> {
+        self.set_setting(CHAIN_REGISTRATION_PENDING, pending.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::{
+        api::server::Server,
+        crypto::certificates::ed25519::{
+            Ed25519AccountCert, Ed25519CertificateChainSecret, Ed25519DeviceCert,
+        },
+        identifiers::{AccountId, DeviceId},
+    };
+
+    use super::*;
+
+    #[test]
+    fn replacing_the_profile_marks_groups_as_pending() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), AccountId::generate_id());
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let profile = Profile::V1(Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        ));
+        db.set_profile(&profile).expect("Saving the profile works");
+
+        let rotated_profile = profile.rotate_device_key();
+        let group_ids = [
+            GroupIdentifier::generate_id(),
+            GroupIdentifier::generate_id(),
+        ];
+        db.replace_profile(&rotated_profile, &group_ids)
+            .expect("Replacing the profile works");
+
+        assert_eq!(
+            db.get_profile(),
+            Ok(rotated_profile),
+            "The new profile should be saved"
+        );
+        assert_eq!(
+            db.is_identity_update_pending(&group_ids[0]),
+            Ok(true),
+            "Every group should be pending"
+        );
+
+        db.delete_pending_identity_update(&group_ids[0])
+            .expect("Deleting a pending update works");
+        assert_eq!(
+            db.get_pending_identity_updates(),
+            Ok(vec![group_ids[1]]),
+            "Only the group that wasn't updated should be left"
+        );
+    }
 }
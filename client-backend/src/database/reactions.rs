@@ -0,0 +1,229 @@
+//! Reactions to messages (see [`Content::Reaction`]). They may arrive before the
+//! message they react to, and aren't messages themselves: they don't change the
+//! last message of a group.
+//!
+//! [`Content::Reaction`]: crate::messages::Content::Reaction
+use lib::identifiers::{AccountId, GroupIdentifier, LicksIdentifier, Uuid};
+use rusqlite::params;
+
+use crate::messages::MessageReference;
+
+use super::{Database, DatabaseError};
+
+/// The reactions to a message with the same emoji.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseReaction {
+    pub emoji: String,
+    pub count: u64,
+    /// `true` if the account passed to [`Database::get_reactions`] is
+    /// one of the reacting accounts.
+    pub includes_account: bool,
+}
+
+impl Database {
+    /// Sets the reaction of `account_id` to the message that `target` references,
+    /// replacing the previous one. Removes it instead if `remove` is `true`,
+    /// unless it was already replaced by another emoji.
+    pub fn set_reaction(
+        &self,
+        group: &GroupIdentifier,
+        target: &MessageReference,
+        account_id: AccountId,
+        emoji: &str,
+        remove: bool,
+    ) -> Result<(), DatabaseError> {
+        let query = if remove {
+            "DELETE FROM reactions
+                WHERE group_id = ?1 AND target_account_id = ?2 AND target_timestamp = ?3
+                    AND account_id = ?4 AND emoji = ?5"
+        } else {
+            "INSERT OR REPLACE INTO reactions (group_id, target_account_id, target_timestamp,
+                account_id, emoji)
+                VALUES (?1, ?2, ?3, ?4, ?5)"
+        };
+
+        self.get_connection().execute(
+            query,
+            params![
+                group.to_bytes(),
+                target.sender_account_id.as_uuid(),
+                target.client_stamp.to_vec(),
+                account_id.as_uuid(),
+                emoji,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the reactions to the message that `target` references, grouped by
+    /// emoji, the most common first.
+    pub fn get_reactions(
+        &self,
+        group: GroupIdentifier,
+        target: &MessageReference,
+        account_id: AccountId,
+    ) -> Result<Vec<DatabaseReaction>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "SELECT emoji, COUNT(*), MAX(account_id = ?4) FROM reactions
+                WHERE group_id = ?1 AND target_account_id = ?2 AND target_timestamp = ?3
+                GROUP BY emoji
+                ORDER BY COUNT(*) DESC, emoji ASC",
+        )?;
+
+        let rows = statement.query_map(
+            params![
+                group.to_bytes(),
+                target.sender_account_id.as_uuid(),
+                target.client_stamp.to_vec(),
+                account_id.as_uuid(),
+            ],
+            |row| {
+                Ok(DatabaseReaction {
+                    emoji: row.get(0)?,
+                    count: row.get(1)?,
+                    includes_account: row.get(2)?,
+                })
+            },
+        )?;
+
+        let mut reactions = Vec::new();
+        for row in rows {
+            reactions.push(row?);
+        }
+
+        Ok(reactions)
+    }
+
+    /// Returns the reaction of every account to the message that `target`
+    /// references.
+    pub fn get_reactions_by_account(
+        &self,
+        group: GroupIdentifier,
+        target: &MessageReference,
+    ) -> Result<Vec<(AccountId, String)>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "SELECT account_id, emoji FROM reactions
+                WHERE group_id = ?1 AND target_account_id = ?2 AND target_timestamp = ?3",
+        )?;
+
+        let rows = statement.query_map(
+            params![
+                group.to_bytes(),
+                target.sender_account_id.as_uuid(),
+                target.client_stamp.to_vec(),
+            ],
+            |row| Ok((row.get::<_, Uuid>(0)?.into(), row.get(1)?)),
+        )?;
+
+        let mut reactions = Vec::new();
+        for row in rows {
+            reactions.push(row?);
+        }
+
+        Ok(reactions)
+    }
+
+    /// Removes every reaction to the message that `target` references.
+    pub(super) fn delete_reactions(
+        &self,
+        group: &GroupIdentifier,
+        target: &MessageReference,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "DELETE FROM reactions
+                WHERE group_id = ?1 AND target_account_id = ?2 AND target_timestamp = ?3",
+            params![
+                group.to_bytes(),
+                target.sender_account_id.as_uuid(),
+                target.client_stamp.to_vec(),
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::api::group::DeliveryStamp;
+
+    use crate::messages::Content;
+
+    use super::*;
+
+    #[test]
+    pub fn reactions_per_account() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+        let alice = AccountId::generate_id();
+        let bob = AccountId::generate_id();
+        let target = MessageReference {
+            sender_account_id: alice,
+            client_stamp: DeliveryStamp::generate(),
+        };
+
+        db.add_message(
+            Content::plain_text("Hi".to_string()),
+            alice,
+            Some(&target.client_stamp),
+            &DeliveryStamp::generate(),
+            &group_id,
+        )
+        .expect("Adding a message works");
+
+        for (account_id, emoji) in [(alice, "👋"), (bob, "🎉"), (bob, "👋")] {
+            db.add_message(
+                Content::Reaction {
+                    target,
+                    emoji: emoji.to_string(),
+                    remove: false,
+                },
+                account_id,
+                Some(&DeliveryStamp::generate()),
+                &DeliveryStamp::generate(),
+                &group_id,
+            )
+            .expect("Adding a reaction works");
+        }
+
+        assert_eq!(
+            db.get_reactions(group_id, &target, bob),
+            Ok(vec![DatabaseReaction {
+                emoji: "👋".to_string(),
+                count: 2,
+                includes_account: true,
+            }]),
+            "Each account has one reaction, which replaces the previous one"
+        );
+        assert_eq!(
+            db.get_reactions_by_account(group_id, &target)
+                .map(|reactions| reactions.len()),
+            Ok(2),
+            "Alice and Bob both reacted"
+        );
+        assert_eq!(
+            db.get_last_message(group_id)
+                .expect("The message was stored")
+                .message,
+            Content::plain_text("Hi".to_string()),
+            "Reactions aren't messages"
+        );
+
+        db.set_reaction(&group_id, &target, bob, "🎉", true)
+            .expect("Removing a reaction works");
+        db.set_reaction(&group_id, &target, alice, "👋", true)
+            .expect("Removing a reaction works");
+        assert_eq!(
+            db.get_reactions(group_id, &target, alice),
+            Ok(vec![DatabaseReaction {
+                emoji: "👋".to_string(),
+                count: 1,
+                includes_account: false,
+            }]),
+            "Only the current reaction of an account can be removed"
+        );
+    }
+}
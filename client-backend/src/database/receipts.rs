@@ -0,0 +1,169 @@
+//! Delivery and read receipts (see [`Content::Receipt`]), stored per message and
+//! per recipient account.
+//!
+//! [`Content::Receipt`]: crate::messages::Content::Receipt
+use lib::identifiers::{AccountId, GroupIdentifier, LicksIdentifier, Uuid};
+use rusqlite::{params, OptionalExtension};
+
+use crate::messages::{MessageReference, ReceiptKind};
+
+use super::{Database, DatabaseError};
+
+impl Database {
+    /// Saves that `account_id` received or read `messages`. A message that was
+    /// read stays read.
+    pub fn add_receipts(
+        &self,
+        group: &GroupIdentifier,
+        account_id: AccountId,
+        kind: ReceiptKind,
+        messages: &[MessageReference],
+    ) -> Result<(), DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "INSERT INTO receipts (group_id, target_account_id, target_timestamp, account_id, status)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (group_id, target_account_id, target_timestamp, account_id)
+                DO UPDATE SET status = MAX(status, excluded.status)",
+        )?;
+
+        for message in messages {
+            statement.execute(params![
+                group.to_bytes(),
+                message.sender_account_id.as_uuid(),
+                message.client_stamp.to_vec(),
+                account_id.as_uuid(),
+                u8::from(kind),
+            ])?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns how many recipients of the message that `target` references
+    /// received it (including those who read it), and how many read it.
+    pub fn get_receipt_counts(
+        &self,
+        group: GroupIdentifier,
+        target: &MessageReference,
+    ) -> Result<(u64, u64), DatabaseError> {
+        Ok(self.get_connection().query_row(
+            "SELECT COUNT(*), COALESCE(SUM(status = ?4), 0) FROM receipts
+                WHERE group_id = ?1 AND target_account_id = ?2 AND target_timestamp = ?3
+                    AND account_id != target_account_id",
+            params![
+                group.to_bytes(),
+                target.sender_account_id.as_uuid(),
+                target.client_stamp.to_vec(),
+                u8::from(ReceiptKind::Read),
+            ],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?)
+    }
+
+    /// Returns the receipt of every account that received the message that
+    /// `target` references.
+    pub fn get_receipts(
+        &self,
+        group: GroupIdentifier,
+        target: &MessageReference,
+    ) -> Result<Vec<(AccountId, ReceiptKind)>, DatabaseError> {
+        let connection = self.get_connection();
+        let mut statement = connection.prepare(
+            "SELECT account_id, status FROM receipts
+                WHERE group_id = ?1 AND target_account_id = ?2 AND target_timestamp = ?3",
+        )?;
+
+        let rows = statement.query_map(
+            params![
+                group.to_bytes(),
+                target.sender_account_id.as_uuid(),
+                target.client_stamp.to_vec(),
+            ],
+            |row| Ok((row.get::<_, Uuid>(0)?, row.get::<_, u8>(1)?)),
+        )?;
+
+        let mut receipts = Vec::new();
+        for row in rows {
+            let (account_id, status) = row?;
+            let kind = ReceiptKind::try_from(status).map_err(|_| DatabaseError::CorruptedData)?;
+
+            receipts.push((account_id.into(), kind));
+        }
+
+        Ok(receipts)
+    }
+
+    /// Returns the receipt `account_id` sent for the message that `target`
+    /// references, if any.
+    pub fn get_receipt(
+        &self,
+        group: GroupIdentifier,
+        target: &MessageReference,
+        account_id: AccountId,
+    ) -> Result<Option<ReceiptKind>, DatabaseError> {
+        let status: Option<u8> = self
+            .get_connection()
+            .query_row(
+                "SELECT status FROM receipts
+                    WHERE group_id = ?1 AND target_account_id = ?2 AND target_timestamp = ?3
+                        AND account_id = ?4",
+                params![
+                    group.to_bytes(),
+                    target.sender_account_id.as_uuid(),
+                    target.client_stamp.to_vec(),
+                    account_id.as_uuid(),
+                ],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        status
+            .map(ReceiptKind::try_from)
+            .transpose()
+            .map_err(|_| DatabaseError::CorruptedData)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::api::group::DeliveryStamp;
+
+    use super::*;
+
+    #[test]
+    pub fn receipts_per_recipient() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+        let bob = AccountId::generate_id();
+        let charlie = AccountId::generate_id();
+        let target = MessageReference {
+            sender_account_id: AccountId::generate_id(),
+            client_stamp: DeliveryStamp::generate(),
+        };
+
+        db.add_receipts(&group_id, bob, ReceiptKind::Read, &[target])
+            .expect("Adding receipts works");
+        db.add_receipts(&group_id, bob, ReceiptKind::Delivered, &[target])
+            .expect("Adding receipts works");
+        db.add_receipts(&group_id, charlie, ReceiptKind::Delivered, &[target])
+            .expect("Adding receipts works");
+
+        assert_eq!(
+            db.get_receipt_counts(group_id, &target),
+            Ok((2, 1)),
+            "Both received the message and Bob read it"
+        );
+        assert_eq!(
+            db.get_receipt(group_id, &target, bob),
+            Ok(Some(ReceiptKind::Read)),
+            "A message that was read stays read"
+        );
+        assert_eq!(
+            db.get_receipts(group_id, &target)
+                .map(|receipts| receipts.len()),
+            Ok(2),
+            "Bob and Charlie both sent a receipt"
+        );
+    }
+}
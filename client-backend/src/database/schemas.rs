@@ -13,7 +13,7 @@ use super::{Database, DatabaseError};
 /// The latest version of the database. It's just an
 /// integer increasing by one every time we add
 /// a new schema.
-pub const LATEST_DATABASE_VERSION: usize = 2;
+pub const LATEST_DATABASE_VERSION: usize = 14;
 
 pub const SCHEMAS: [&str; LATEST_DATABASE_VERSION] = [
     "
@@ -88,6 +88,128 @@ pub const SCHEMAS: [&str; LATEST_DATABASE_VERSION] = [
     ) WITHOUT ROWID;
     PRAGMA user_version = 1;
     ",
+    // The last message we processed for each epoch of each group,
+    // used to catch up on messages we missed while offline.
+    "
+    CREATE TABLE sync_state(
+        group_id                    BLOB,
+        epoch_id                    INTEGER,
+        last_delivery_stamp         BLOB        NOT NULL,
+        PRIMARY KEY (group_id, epoch_id)
+    );
+    ",
+    // Messages are stored encrypted in the outbox before being sent,
+    // so that they can be sent again if the request fails.
+    "
+    CREATE TABLE outbox(
+        client_stamp                BLOB        PRIMARY KEY,
+        group_id                    BLOB        NOT NULL,
+        blinded_address             BLOB        NOT NULL,
+        mls_message                 BLOB        NOT NULL,
+        content                     BLOB        NOT NULL,
+        status                      INTEGER     NOT NULL,
+        attempts                    INTEGER     NOT NULL
+    );
+    ",
+    // Welcomes delivered to our invite inbox. They are kept once accepted or
+    // declined, so that we know from where to retrieve the inbox.
+    "
+    CREATE TABLE invites(
+        delivery_stamp              BLOB        PRIMARY KEY,
+        welcome                     BLOB        NOT NULL,
+        status                      INTEGER     NOT NULL
+    );
+    ",
+    // Secrets of the invite links we created, which we keep up to date.
+    "
+    CREATE TABLE group_links(
+        group_id                    BLOB        PRIMARY KEY,
+        link_secret                 BLOB        NOT NULL
+    );
+    ",
+    // History transfers to our newly linked devices that aren't done yet.
+    // Messages up to `up_to_message_id` are sent, after `last_message_id`.
+    "
+    CREATE TABLE history_sync(
+        device_id                   BLOB        PRIMARY KEY,
+        last_message_id             INTEGER     NOT NULL,
+        up_to_message_id            INTEGER     NOT NULL
+    );
+    ",
+    // Messages are identified by their sender and client timestamp, which
+    // replies use to reference their parent (stored in `reply_message_timestamp`).
+    "
+    ALTER TABLE messages ADD COLUMN client_timestamp BLOB;
+    ALTER TABLE messages ADD COLUMN reply_account_id BLOB;
+    CREATE INDEX messages_by_reference ON messages(group_id, account_id, client_timestamp);
+    ",
+    // Edits and deletions, which may arrive before the message they target.
+    // The content of edited messages is replaced, and their first version
+    // is kept in `original_content`.
+    "
+    ALTER TABLE messages ADD COLUMN original_content TEXT;
+    CREATE TABLE message_edits(
+        group_id                    BLOB        NOT NULL,
+        account_id                  BLOB        NOT NULL,
+        target_timestamp            BLOB        NOT NULL,
+        edit_timestamp              BLOB        NOT NULL,
+        plaintext_content           TEXT,
+        deleted                     INTEGER     NOT NULL,
+        PRIMARY KEY (group_id, account_id, edit_timestamp)
+    );
+    ",
+    // Reactions to messages, one per reacting account and message.
+    // They aren't messages themselves, so they are kept separately.
+    "
+    CREATE TABLE reactions(
+        group_id                    BLOB        NOT NULL,
+        target_account_id           BLOB        NOT NULL,
+        target_timestamp            BLOB        NOT NULL,
+        account_id                  BLOB        NOT NULL,
+        emoji                       TEXT        NOT NULL,
+        PRIMARY KEY (group_id, target_account_id, target_timestamp, account_id)
+    );
+    ",
+    // Delivery and read receipts of the messages we sent, one per recipient
+    // account, and of the messages we received. Also our own settings.
+    "
+    CREATE TABLE receipts(
+        group_id                    BLOB        NOT NULL,
+        target_account_id           BLOB        NOT NULL,
+        target_timestamp            BLOB        NOT NULL,
+        account_id                  BLOB        NOT NULL,
+        status                      INTEGER     NOT NULL,
+        PRIMARY KEY (group_id, target_account_id, target_timestamp, account_id)
+    );
+    CREATE TABLE settings(
+        key                         TEXT        PRIMARY KEY,
+        value                       INTEGER     NOT NULL
+    );
+    ",
+    // The groups where our leaf still has our previous signing identity,
+    // after our profile was replaced (e.g. when rotating our device key).
+    "
+    CREATE TABLE pending_identity_updates(
+        group_id                    BLOB        PRIMARY KEY
+    );
+    ",
+    // The secret of our invite inbox, which we publish in our key packages.
+    "
+    CREATE TABLE invite_inbox(
+        secret                      BLOB        PRIMARY KEY
+    );
+    ",
+    // The messages processed in every epoch of every group. Live messages can
+    // arrive out of order, so `sync_state` only tells from where to retrieve
+    // the queue, and this tells which of its messages to skip.
+    "
+    CREATE TABLE processed_messages(
+        group_id                    BLOB        NOT NULL,
+        epoch_id                    INTEGER     NOT NULL,
+        delivery_stamp              BLOB        NOT NULL,
+        PRIMARY KEY (group_id, epoch_id, delivery_stamp)
+    );
+    ",
 ];
 
 /// If needed, execute the new schemas to upgrade
@@ -0,0 +1,38 @@
+//! The settings of our profile, which are only stored locally.
+use rusqlite::{params, OptionalExtension};
+
+use super::{Database, DatabaseError};
+
+/// Whether we tell the senders of the messages we read (see
+/// [`crate::manager::receipts`]). Enabled by default.
+const READ_RECEIPTS: &str = "read_receipts";
+
+impl Database {
+    pub(super) fn get_setting(&self, key: &str) -> Result<Option<i64>, DatabaseError> {
+        Ok(self
+            .get_connection()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub(super) fn set_setting(&self, key: &str, value: i64) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_read_receipts_enabled(&self) -> Result<bool, DatabaseError> {
+        Ok(self.get_setting(READ_RECEIPTS)? != Some(0))
+    }
+
+    pub fn set_read_receipts_enabled(&self, enabled: bool) -> Result<(), DatabaseError> {
+        self.set_setting(READ_RECEIPTS, enabled.into())
+    }
+}
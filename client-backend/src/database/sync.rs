@@ -0,0 +1,208 @@
+//! Keeps track of the messages processed in every epoch of every group, so that
+//! we know from where to retrieve the queue after being offline, and which of
+//! the retrieved messages we already processed.
+//!
+//! The queue is retrieved from the last stamp before which every message was
+//! processed. Messages delivered live can arrive out of order or fail to be
+//! processed, so they are remembered one by one instead.
+use lib::{
+    api::group::DeliveryStamp,
+    identifiers::{GroupIdentifier, LicksIdentifier},
+};
+use rusqlite::{params, OptionalExtension};
+
+use super::{Database, DatabaseError};
+
+impl Database {
+    /// Returns the [`DeliveryStamp`] from which to retrieve the queue of the given
+    /// epoch of a group, or `None` if we haven't processed any message in it yet.
+    /// Every message before it was processed.
+    pub fn get_last_delivery_stamp(
+        &self,
+        group_id: &GroupIdentifier,
+        epoch: u64,
+    ) -> Result<Option<DeliveryStamp>, DatabaseError> {
+        let bytes: Option<Vec<u8>> = self
+            .get_connection()
+            .query_row(
+                "SELECT last_delivery_stamp FROM sync_state WHERE group_id = ? AND epoch_id = ?",
+                params![group_id.to_bytes(), epoch],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        bytes
+            .map(|bytes| {
+                DeliveryStamp::try_from(bytes.as_slice()).map_err(|()| DatabaseError::CorruptedData)
+            })
+            .transpose()
+    }
+
+    /// Moves the stamp from which to retrieve the queue forward to `delivery_stamp`.
+    /// Stamps older than the one already stored are ignored.
+    pub fn set_last_delivery_stamp(
+        &self,
+        group_id: &GroupIdentifier,
+        epoch: u64,
+        delivery_stamp: &DeliveryStamp,
+    ) -> Result<(), DatabaseError> {
+        // Stamps are big-endian Uuid v7s, so comparing their bytes
+        // compares their timestamps.
+        self.get_connection().execute(
+            "INSERT INTO sync_state (group_id, epoch_id, last_delivery_stamp)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (group_id, epoch_id) DO UPDATE
+                SET last_delivery_stamp = excluded.last_delivery_stamp
+                WHERE excluded.last_delivery_stamp > sync_state.last_delivery_stamp",
+            params![group_id.to_bytes(), epoch, delivery_stamp.to_vec()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Moves the stamp from which to retrieve the queue back to `delivery_stamp`,
+    /// so that the message with that stamp is retrieved again. Does nothing if
+    /// the queue is retrieved from its start anyway.
+    pub fn rewind_last_delivery_stamp(
+        &self,
+        group_id: &GroupIdentifier,
+        epoch: u64,
+        delivery_stamp: &DeliveryStamp,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "UPDATE sync_state SET last_delivery_stamp = ?3
+                WHERE group_id = ?1 AND epoch_id = ?2 AND last_delivery_stamp > ?3",
+            params![group_id.to_bytes(), epoch, delivery_stamp.to_vec()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns whether we processed the message with the given [`DeliveryStamp`].
+    pub fn is_message_processed(
+        &self,
+        group_id: &GroupIdentifier,
+        epoch: u64,
+        delivery_stamp: &DeliveryStamp,
+    ) -> Result<bool, DatabaseError> {
+        Ok(self.get_connection().query_row(
+            "SELECT EXISTS(SELECT 1 FROM processed_messages
+                WHERE group_id = ? AND epoch_id = ? AND delivery_stamp = ?)",
+            params![group_id.to_bytes(), epoch, delivery_stamp.to_vec()],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Remembers that we processed the message with the given [`DeliveryStamp`].
+    pub fn set_message_processed(
+        &self,
+        group_id: &GroupIdentifier,
+        epoch: u64,
+        delivery_stamp: &DeliveryStamp,
+    ) -> Result<(), DatabaseError> {
+        self.get_connection().execute(
+            "INSERT OR IGNORE INTO processed_messages (group_id, epoch_id, delivery_stamp)
+                VALUES (?, ?, ?)",
+            params![group_id.to_bytes(), epoch, delivery_stamp.to_vec()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Forgets the processed messages of every epoch of a group.
+    pub fn delete_sync_state(&self, group_id: &GroupIdentifier) -> Result<(), DatabaseError> {
+        let connection = self.get_connection();
+        connection.execute(
+            "DELETE FROM sync_state WHERE group_id = ?",
+            params![group_id.to_bytes()],
+        )?;
+        connection.execute(
+            "DELETE FROM processed_messages WHERE group_id = ?",
+            params![group_id.to_bytes()],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn roundtrip_last_delivery_stamp() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+
+        assert_eq!(
+            db.get_last_delivery_stamp(&group_id, 1),
+            Ok(None),
+            "Nothing was processed yet"
+        );
+
+        let older = DeliveryStamp::generate();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let newer = DeliveryStamp::generate();
+
+        db.set_last_delivery_stamp(&group_id, 1, &newer)
+            .expect("stamp is stored");
+        db.set_last_delivery_stamp(&group_id, 1, &older)
+            .expect("older stamp is ignored without error");
+
+        assert_eq!(
+            db.get_last_delivery_stamp(&group_id, 1),
+            Ok(Some(newer)),
+            "The newest stamp is kept"
+        );
+        assert_eq!(
+            db.get_last_delivery_stamp(&group_id, 2),
+            Ok(None),
+            "Stamps are stored per epoch"
+        );
+
+        db.rewind_last_delivery_stamp(&group_id, 1, &older)
+            .expect("stamp is rewound");
+        assert_eq!(
+            db.get_last_delivery_stamp(&group_id, 1),
+            Ok(Some(older)),
+            "The stamp can be moved back to retrieve a message again"
+        );
+    }
+
+    #[test]
+    pub fn processed_messages() {
+        let db = Database::in_memory().expect("in-memory db starts");
+        let group_id = GroupIdentifier::generate_id();
+        let stamp = DeliveryStamp::generate();
+
+        assert_eq!(
+            db.is_message_processed(&group_id, 1, &stamp),
+            Ok(false),
+            "Nothing was processed yet"
+        );
+
+        db.set_message_processed(&group_id, 1, &stamp)
+            .expect("stamp is stored");
+        db.set_message_processed(&group_id, 1, &stamp)
+            .expect("a stamp can be stored twice");
+
+        assert_eq!(
+            db.is_message_processed(&group_id, 1, &stamp),
+            Ok(true),
+            "The message was processed"
+        );
+        assert_eq!(
+            db.is_message_processed(&group_id, 2, &stamp),
+            Ok(false),
+            "Processed messages are stored per epoch"
+        );
+
+        db.delete_sync_state(&group_id)
+            .expect("the sync state is deleted");
+        assert_eq!(
+            db.is_message_processed(&group_id, 1, &stamp),
+            Ok(false),
+            "The processed messages of a deleted group are forgotten"
+        );
+    }
+}
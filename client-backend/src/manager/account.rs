@@ -1,27 +1,42 @@
-use std::hash::Hash;
+use std::{hash::Hash, time::Duration};
 
-use anyhow::bail;
+use anyhow::Context;
 use lib::{
     api::{
-        messages::{Message, UnauthRequest},
+        messages::{AuthRequest, Message, UnauthRequest},
+        proto,
         server::Server,
     },
     crypto::{
+        blinded_address::BlindedAddressSecret,
         certificates::{
-            ed25519::Ed25519CertificateChainSecret, CertificateChain, CertificateChainSecret,
+            ed25519::Ed25519CertificateChainSecret,
+            hybrid::HybridCertificateChainSecret,
+            rotation::{AccountKeyRotation, AccountKeyShare},
+            Certificate, CertificateChain, CertificateChainSecret, CertificateError,
+            SerializedChain, SerializedDeviceCertificate, Validity,
         },
         challenge::{AuthChallenge, AuthChallengeResponse},
     },
     error::ProtoError,
-    identifiers::{AccountId, DeviceId},
+    identifiers::{AccountId, DeviceId, GroupIdentifier},
 };
 
 pub use lib::crypto::usernames::{Username, UsernameHash};
 
 use super::{ProfileManager, WEBSOCKET_MANAGER};
-use crate::mls::credentials::LicksMlsCredential;
+use crate::{
+    client::ClientProfile, messages::Content, mls::credentials::LicksMlsCredential,
+    net::RequestError,
+};
 use mls_rs::identity::SigningIdentity;
 
+/// Device certificates are renewed when they expire in less than this.
+pub const DEVICE_CERT_RENEWAL_MARGIN: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Account certificates are renewed when they expire in less than this.
+pub const ACCOUNT_CERT_RENEWAL_MARGIN: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
 /// `Profile` (meaning `CertificateChainSecret`) holds:
 /// - `AccountId`
 /// - Server domain of account
@@ -30,12 +45,15 @@ use mls_rs::identity::SigningIdentity;
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Profile {
     V1(Ed25519CertificateChainSecret),
+    /// Hybrid Ed25519 + ML-DSA certificates
+    V2(HybridCertificateChainSecret),
 }
 
 impl Profile {
     pub fn get_server(&self) -> &Server {
         match self {
             Profile::V1(cert_secret) => cert_secret.public_chain.get_server(),
+            Profile::V2(cert_secret) => cert_secret.public_chain.get_server(),
         }
     }
 
@@ -43,6 +61,11 @@ impl Profile {
         match self {
             Profile::V1(cert_secret) => LicksMlsCredential {
                 chain: cert_secret.serialized(),
+                previous_account_signature: cert_secret.previous_account_signature.clone(),
+            },
+            Profile::V2(cert_secret) => LicksMlsCredential {
+                chain: cert_secret.serialized(),
+                previous_account_signature: cert_secret.previous_account_signature.clone(),
             },
         }
     }
@@ -50,12 +73,157 @@ impl Profile {
     pub fn get_account_id(&self) -> AccountId {
         match self {
             Profile::V1(cert_secret) => *cert_secret.public_chain.account_id(),
+            Profile::V2(cert_secret) => *cert_secret.public_chain.account_id(),
         }
     }
 
     pub fn get_device_id(&self) -> DeviceId {
         match self {
             Profile::V1(cert_secret) => *cert_secret.public_chain.device_id(),
+            Profile::V2(cert_secret) => *cert_secret.public_chain.device_id(),
+        }
+    }
+
+    fn device_cert_validity(&self) -> Validity {
+        match self {
+            Profile::V1(cert_secret) => cert_secret.public_chain.device_cert().validity(),
+            Profile::V2(cert_secret) => cert_secret.public_chain.device_cert().validity(),
+        }
+    }
+
+    fn account_cert_validity(&self) -> Validity {
+        match self {
+            Profile::V1(cert_secret) => cert_secret.public_chain.account_cert().validity(),
+            Profile::V2(cert_secret) => cert_secret.public_chain.account_cert().validity(),
+        }
+    }
+
+    /// Returns `true` if the device certificate expires in less than `duration`.
+    /// The account certificate is renewed separately, see
+    /// [`Self::account_cert_expires_within`].
+    pub fn device_cert_expires_within(&self, duration: Duration) -> bool {
+        self.device_cert_validity().expires_within(duration)
+    }
+
+    /// Returns `true` if the account certificate expires in less than `duration`.
+    pub fn account_cert_expires_within(&self, duration: Duration) -> bool {
+        self.account_cert_validity().expires_within(duration)
+    }
+
+    /// Renews the device certificate, keeping the same device key. The
+    /// profile must then be saved again.
+    pub fn renew_device_cert(&mut self) {
+        match self {
+            Profile::V1(cert_secret) => cert_secret.renew_device_cert(),
+            Profile::V2(cert_secret) => cert_secret.renew_device_cert(),
+        }
+    }
+
+    /// Renews the account certificate, keeping the same account key. The
+    /// profile must then be saved again.
+    pub fn renew_account_cert(&mut self) {
+        match self {
+            Profile::V1(cert_secret) => cert_secret.renew_account_cert(),
+            Profile::V2(cert_secret) => cert_secret.renew_account_cert(),
+        }
+    }
+
+    /// Renews the certificates that expire within [`ACCOUNT_CERT_RENEWAL_MARGIN`]
+    /// and [`DEVICE_CERT_RENEWAL_MARGIN`], including legacy certificates which
+    /// have no validity period. Returns `true` if our chain changed, in which
+    /// case the profile must be saved and the chain registered with the server
+    /// (see [`ProfileManager::register_renewed_chain`]).
+    pub fn renew_expiring_certs(&mut self) -> bool {
+        let renew_account_cert = self.account_cert_expires_within(ACCOUNT_CERT_RENEWAL_MARGIN);
+        let renew_device_cert = self.device_cert_expires_within(DEVICE_CERT_RENEWAL_MARGIN);
+
+        if renew_account_cert {
+            self.renew_account_cert();
+        }
+        if renew_device_cert {
+            self.renew_device_cert();
+        }
+
+        renew_account_cert || renew_device_cert
+    }
+
+    /// Replaces the account key with a new one, keeping the same `AccountId`, and
+    /// signs the device certificates of `registered_chains` again with it. Returns
+    /// the rotated profile, which must only be used once the server accepted the
+    /// returned [`AccountKeyRotation`].
+    pub fn rotate_account_key(
+        &self,
+        registered_chains: &[SerializedChain],
+    ) -> (Self, AccountKeyRotation) {
+        match self {
+            Profile::V1(cert_secret) => {
+                let (rotated, rotation) = cert_secret.rotate_account_key(registered_chains);
+                (Profile::V1(rotated), rotation)
+            }
+            Profile::V2(cert_secret) => {
+                let (rotated, rotation) = cert_secret.rotate_account_key(registered_chains);
+                (Profile::V2(rotated), rotation)
+            }
+        }
+    }
+
+    /// Our account key, to share with our other devices after rotating it,
+    /// or with a device we link.
+    pub fn share_account_key(&self) -> AccountKeyShare {
+        match self {
+            Profile::V1(cert_secret) => cert_secret.share_account_key(),
+            Profile::V2(cert_secret) => cert_secret.share_account_key(),
+        }
+    }
+
+    /// Adopts the account key that another of our devices rotated, see
+    /// [`ProfileManager::receive_account_key`].
+    pub fn with_shared_account_key(
+        &self,
+        share: &AccountKeyShare,
+    ) -> Result<Self, CertificateError> {
+        match self {
+            Profile::V1(cert_secret) => cert_secret.with_shared_account_key(share).map(Profile::V1),
+            Profile::V2(cert_secret) => cert_secret.with_shared_account_key(share).map(Profile::V2),
+        }
+    }
+
+    /// Replaces the device key with a new one, for the same device. The
+    /// rotated profile's chain must then be registered with the server.
+    pub fn rotate_device_key(&self) -> Self {
+        match self {
+            Profile::V1(cert_secret) => Profile::V1(cert_secret.rotate_device_key()),
+            Profile::V2(cert_secret) => Profile::V2(cert_secret.rotate_device_key()),
+        }
+    }
+
+    /// Our account certificate, sent to a device we link so that its device
+    /// certificate uses the same signature scheme.
+    pub fn account_certificate(&self) -> proto::Certificate {
+        match self {
+            Profile::V1(cert_secret) => cert_secret.public_chain.account_cert().to_proto(),
+            Profile::V2(cert_secret) => cert_secret.public_chain.account_cert().to_proto(),
+        }
+    }
+
+    /// Signs the device certificate of a device we are linking to our account
+    /// (see [`ClientProfile::link_new_device`]), and returns its chain. Fails
+    /// unless the certificate is valid and uses the same signature scheme as
+    /// our account certificate.
+    pub fn sign_device_cert(
+        &self,
+        device_cert: SerializedDeviceCertificate,
+    ) -> Result<SerializedChain, CertificateError> {
+        device_cert.verify()?;
+
+        match (self, device_cert) {
+            (Profile::V1(cert_secret), SerializedDeviceCertificate::Ed25519(device_cert)) => {
+                Ok(cert_secret.sign_device_cert(*device_cert).serialize())
+            }
+            (Profile::V2(cert_secret), SerializedDeviceCertificate::Hybrid(device_cert)) => {
+                Ok(cert_secret.sign_device_cert(*device_cert).serialize())
+            }
+            _ => Err(CertificateError::InvalidData),
         }
     }
 
@@ -66,9 +234,14 @@ impl Profile {
         )
     }
 
-    pub fn get_auth_challenge_response(&self, challenge: AuthChallenge) -> AuthChallengeResponse {
+    pub fn get_auth_challenge_response(
+        &self,
+        challenge: AuthChallenge,
+        handshake_hash: &[u8],
+    ) -> AuthChallengeResponse {
         match self {
-            Profile::V1(cert_secret) => challenge.accept(cert_secret),
+            Profile::V1(cert_secret) => challenge.accept(cert_secret, handshake_hash),
+            Profile::V2(cert_secret) => challenge.accept(cert_secret, handshake_hash),
         }
     }
 
@@ -77,19 +250,24 @@ impl Profile {
             // `.as_bytes()` returns just the compressed secret key bytes
             // but mls-rs expects the full uncompressed representation, so use `.to_keypair_bytes()`
             Profile::V1(cert_secret) => cert_secret.device_secret.to_keypair_bytes(),
+            // MLS only uses the Ed25519 part of the key
+            Profile::V2(cert_secret) => cert_secret.device_secret.ed25519().to_keypair_bytes(),
         }
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
         match self {
             Profile::V1(cert_secret) => cert_secret.to_bytes(),
+            Profile::V2(cert_secret) => cert_secret.to_bytes(),
         }
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtoError> {
-        Ok(Profile::V1(Ed25519CertificateChainSecret::from_bytes(
-            bytes,
-        )?))
+        // Both secrets have the same protobuf layout, but parsing
+        // only succeeds if the chain uses the expected scheme
+        Ed25519CertificateChainSecret::from_bytes(bytes)
+            .map(Profile::V1)
+            .or_else(|_| HybridCertificateChainSecret::from_bytes(bytes).map(Profile::V2))
     }
 }
 
@@ -108,10 +286,321 @@ impl ProfileManager {
         match resp {
             Message::Unauth(UnauthRequest::HereIsAccount(account_id)) => Ok(Some(account_id)),
             Message::Unauth(UnauthRequest::NoAccount) => Ok(None),
-            err => {
-                log::error!("Unexpected response while looking up username: {err:?}");
-                bail!("Unexpected response while looking up username: {err:?}");
+            other => Err(RequestError::from_response(other)).context("Looking up username failed"),
+        }
+    }
+
+    /// Registers our chain with the server if it was renewed since it was last
+    /// registered (see [`Profile::renew_expiring_certs`]). The server keeps
+    /// accepting our previous certificates until they expire, so if this
+    /// fails, it is tried again by the next [`ProfileManager::initialize`].
+    pub async fn register_renewed_chain(&self) -> anyhow::Result<()> {
+        if !self.sqlite_database.is_chain_registration_pending()? {
+            return Ok(());
+        }
+
+        match WEBSOCKET_MANAGER
+            .request_auth(
+                self.get_profile(),
+                AuthRequest::UpdateDeviceChain(self.get_profile().mls_credential_public().chain),
+            )
+            .await?
+        {
+            Message::Ok => {
+                self.sqlite_database.set_chain_registration_pending(false)?;
+                Ok(())
+            }
+            other => Err(RequestError::from_response(other))
+                .context("Registering our renewed certificates failed"),
+        }
+    }
+
+    /// Rotates the account key (e.g. if the account secret leaked). The chains of
+    /// all our devices are signed again with the new key, and the server replaces
+    /// them all at once. The rotated profile is then used right away (see
+    /// [`ProfileManager::replace_profile`]), and an Update is committed in every
+    /// group, so that their members verify the new account certificate.
+    ///
+    /// Returns the new epoch and blinded address of every updated group, which
+    /// must then be listened to. The new account key must also be shared with
+    /// our other devices, see [`ClientProfile::rotate_account_key`].
+    pub async fn rotate_account_key_without_updating_listeners(
+        &self,
+    ) -> anyhow::Result<Vec<(GroupIdentifier, u64, BlindedAddressSecret)>> {
+        let registered_chains = match WEBSOCKET_MANAGER
+            .request_auth(self.get_profile(), AuthRequest::GetDeviceChains)
+            .await?
+        {
+            Message::Auth(AuthRequest::HereAreDeviceChains(chains)) => chains,
+            other => {
+                return Err(RequestError::from_response(other))
+                    .context("Getting the chains of our devices failed");
+            }
+        };
+
+        let (rotated_profile, rotation) = self.get_profile().rotate_account_key(&registered_chains);
+
+        match WEBSOCKET_MANAGER
+            .request_auth(self.get_profile(), AuthRequest::RotateAccountKey(rotation))
+            .await?
+        {
+            Message::Ok => {}
+            other => {
+                return Err(RequestError::from_response(other))
+                    .context("Rotating the account key failed");
+            }
+        }
+
+        self.replace_profile(rotated_profile)?;
+
+        self.update_pending_identities_without_updating_listeners()
+            .await
+    }
+
+    /// Adopts the account key that `sender_account_id` (another of our devices)
+    /// rotated and shared in the self group, see [`ClientProfile::rotate_account_key`].
+    /// Our leaf must then be updated in every group (see [`ProfileManager::replace_profile`]).
+    ///
+    /// The server already registered our chain signed by the new key, but our device
+    /// certificate may have been renewed since, so our chain is registered again.
+    /// Returns `false` if we already used that account key.
+    pub async fn receive_account_key(
+        &self,
+        sender_account_id: &AccountId,
+        share: &[u8],
+    ) -> anyhow::Result<bool> {
+        let profile = self.get_profile();
+        if sender_account_id != &profile.get_account_id() {
+            anyhow::bail!("Only our own devices can share our account key");
+        }
+
+        let share = AccountKeyShare::from_bytes(share)?;
+        if profile.share_account_key().account_secret == share.account_secret {
+            return Ok(false);
+        }
+
+        let rotated_profile = profile
+            .with_shared_account_key(&share)
+            .context("The shared account key isn't a successor of ours")?;
+
+        self.replace_profile(rotated_profile)?;
+        self.sqlite_database.set_chain_registration_pending(true)?;
+        if let Err(err) = self.register_renewed_chain().await {
+            log::warn!("Couldn't register our chain signed by the new account key: {err:?}");
+        }
+
+        Ok(true)
+    }
+
+    /// Replaces our device key with a new one (keeping the same `DeviceId`),
+    /// registers the new chain with the server, and uses the rotated profile
+    /// right away (see [`ProfileManager::replace_profile`]). An Update with the
+    /// new signing identity is then committed in every group.
+    ///
+    /// Returns the new epoch and blinded address of every updated group, which
+    /// must then be listened to. Groups where the update failed keep using the
+    /// previous key until [`ProfileManager::update_pending_identities_without_updating_listeners`]
+    /// succeeds for them.
+    pub async fn rotate_device_key_without_updating_listeners(
+        &self,
+    ) -> anyhow::Result<Vec<(GroupIdentifier, u64, BlindedAddressSecret)>> {
+        let rotated_profile = self.get_profile().rotate_device_key();
+
+        match WEBSOCKET_MANAGER
+            .request_auth(
+                self.get_profile(),
+                AuthRequest::UpdateDeviceChain(rotated_profile.mls_credential_public().chain),
+            )
+            .await?
+        {
+            Message::Ok => {}
+            other => {
+                return Err(RequestError::from_response(other))
+                    .context("Registering the new device key failed");
+            }
+        }
+
+        self.replace_profile(rotated_profile)?;
+
+        self.update_pending_identities_without_updating_listeners()
+            .await
+    }
+
+    /// Commits an Update with our current signing identity in every group where
+    /// it is still pending since our profile was replaced. Groups where it fails
+    /// stay pending, so that it is tried again later.
+    ///
+    /// Returns the new epoch and blinded address of every updated group, which
+    /// must then be listened to.
+    pub async fn update_pending_identities_without_updating_listeners(
+        &self,
+    ) -> anyhow::Result<Vec<(GroupIdentifier, u64, BlindedAddressSecret)>> {
+        let mut updated_groups = Vec::new();
+
+        for group_id in self.sqlite_database.get_pending_identity_updates()? {
+            match self.update_pending_identity(&group_id).await {
+                Ok(Some((epoch, blinded_address))) => {
+                    updated_groups.push((group_id, epoch, blinded_address));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    log::warn!(
+                        "Couldn't update our signing identity in group {group_id:?}: {err:?}"
+                    );
+                }
             }
         }
+
+        Ok(updated_groups)
+    }
+
+    /// Commits an Update with our current signing identity in `group_id` if it is
+    /// still pending (see [`ProfileManager::replace_profile`]). Returns the new
+    /// epoch of the group and its blinded address, or `None` if there was nothing
+    /// to update.
+    pub async fn update_pending_identity(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> anyhow::Result<Option<(u64, BlindedAddressSecret)>> {
+        let _guard = self.identity_update_lock.lock().await;
+
+        if !self.sqlite_database.is_identity_update_pending(group_id)? {
+            return Ok(None);
+        }
+
+        if !self.get_all_group_ids()?.contains(group_id) {
+            // We left the group since
+            self.sqlite_database
+                .delete_pending_identity_update(group_id)?;
+            return Ok(None);
+        }
+
+        let (epoch, blinded_address) = self
+            .update_signing_identity_without_updating_listener(group_id, &self.get_profile())
+            .await?;
+        self.sqlite_database
+            .delete_pending_identity_update(group_id)?;
+
+        Ok(Some((epoch, blinded_address)))
+    }
+}
+
+impl ClientProfile<'_> {
+    /// See [`ProfileManager::rotate_device_key_without_updating_listeners`].
+    /// This also listens to the new epoch of every updated group.
+    pub async fn rotate_device_key(&self) -> anyhow::Result<()> {
+        let updated_groups = self
+            .profile_manager
+            .rotate_device_key_without_updating_listeners()
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_to_updated_groups(
+                &self.profile_manager,
+                updated_groups,
+                &self.client.notification_manager,
+            )
+            .await
+    }
+
+    /// See [`ProfileManager::rotate_account_key_without_updating_listeners`].
+    /// This also listens to the new epoch of every updated group, and shares the
+    /// new account key with our other devices in the self group (see
+    /// [`ProfileManager::receive_account_key`]).
+    pub async fn rotate_account_key(&self) -> anyhow::Result<()> {
+        let updated_groups = self
+            .profile_manager
+            .rotate_account_key_without_updating_listeners()
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_to_updated_groups(
+                &self.profile_manager,
+                updated_groups,
+                &self.client.notification_manager,
+            )
+            .await?;
+
+        self.send_application_message(
+            &GroupIdentifier::self_id(),
+            Content::AccountKey(self.get_profile().share_account_key().to_bytes()),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// See [`ListenerManager::update_pending_identities`].
+    ///
+    /// [`ListenerManager::update_pending_identities`]: super::listener::ListenerManager::update_pending_identities
+    pub async fn update_pending_identities(&self) -> anyhow::Result<()> {
+        self.client
+            .listener_manager
+            .update_pending_identities(
+                self.profile_manager.clone(),
+                self.client.notification_manager.clone(),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::{
+        crypto::certificates::{
+            ed25519::{Ed25519AccountCert, Ed25519DeviceCert},
+            DEVICE_CERT_VALIDITY,
+        },
+        identifiers::LicksIdentifier,
+    };
+
+    use super::*;
+
+    #[test]
+    fn certificates_are_renewed_separately() {
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), AccountId::generate_id());
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let mut profile = Profile::V1(Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        ));
+
+        assert!(
+            !profile.renew_expiring_certs(),
+            "New certificates shouldn't be renewed"
+        );
+
+        let after_device_cert = DEVICE_CERT_VALIDITY + Duration::from_secs(24 * 60 * 60);
+        assert!(
+            profile.device_cert_expires_within(after_device_cert),
+            "The device certificate should expire on its own"
+        );
+        assert!(
+            !profile.account_cert_expires_within(after_device_cert),
+            "The account certificate should outlive the device certificate"
+        );
+
+        let account_key = profile
+            .mls_credential_public()
+            .chain
+            .account_pub_key_bytes();
+        profile.renew_account_cert();
+        assert_eq!(
+            profile
+                .mls_credential_public()
+                .chain
+                .account_pub_key_bytes(),
+            account_key,
+            "Renewing should keep the account key"
+        );
+        assert!(
+            profile.mls_credential_public().chain.verify().is_ok(),
+            "The renewed chain should be valid"
+        );
     }
 }
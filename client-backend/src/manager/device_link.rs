@@ -0,0 +1,193 @@
+//! Linking a new device to our account (see [`lib::crypto::device_link`]).
+//!
+//! The existing device signs the device certificate of the new device, whose
+//! device secret never leaves it, and adds it to all of its groups. Every group
+//! is a separate Add commit, so the new device sends one key package per group.
+use anyhow::{bail, Context};
+use lib::{
+    api::proto::{
+        DeviceLinkChain, DeviceLinkDeviceCertificate, DeviceLinkKeyPackages,
+        DeviceLinkProvisioning, DeviceLinkWelcomes, ProstMessage,
+    },
+    crypto::{
+        certificates::SerializedDeviceCertificate,
+        device_link::{DeviceLinkCode, DeviceLinkStep},
+    },
+    identifiers::{DeviceId, GroupIdentifier},
+};
+use mls_rs::{MlsMessage, WireFormat};
+
+use crate::{
+    account::link::{receive_link_message, send_link_message},
+    client::ClientProfile,
+    mls::credentials::LicksIdentityProvider,
+};
+
+use super::error::Result;
+
+impl ClientProfile<'_> {
+    /// Links the device that displays `code` to our account: signs its device
+    /// certificate and sends it back its chain along with the account key, then
+    /// adds its key packages to every group we are in, including the self group.
+    /// Returns the [`DeviceId`] of the new device.
+    ///
+    /// Groups where adding the new device fails are skipped. Our history is then
+    /// sent to the new device in the background (see [`Self::sync_history`]).
+    pub async fn link_new_device(&self, code: &str) -> Result<DeviceId> {
+        let code = DeviceLinkCode::from_code_string(code)?;
+        let server = self.get_server();
+
+        let profile = self.get_profile();
+        let group_ids = self.get_all_group_ids()?;
+
+        let provisioning = DeviceLinkProvisioning {
+            key_package_count: group_ids.len().try_into()?,
+            account_certificate: Some(profile.account_certificate()),
+        };
+        send_link_message(
+            server,
+            &code,
+            DeviceLinkStep::Provisioning,
+            &provisioning.encode_to_vec(),
+        )
+        .await?;
+
+        let device_cert: SerializedDeviceCertificate = DeviceLinkDeviceCertificate::decode(
+            receive_link_message(server, &code, DeviceLinkStep::DeviceCertificate)
+                .await?
+                .as_slice(),
+        )
+        .context("Failed to decode the device certificate of the new device")?
+        .device_certificate
+        .context("The new device didn't send its device certificate")?
+        .try_into()?;
+        let new_device_id = *device_cert.device_id();
+
+        let chain = DeviceLinkChain {
+            chain: Some(profile.sign_device_cert(device_cert)?.into()),
+            account_key: Some(profile.share_account_key().into()),
+        };
+        send_link_message(server, &code, DeviceLinkStep::Chain, &chain.encode_to_vec()).await?;
+
+        let key_packages = DeviceLinkKeyPackages::decode(
+            receive_link_message(server, &code, DeviceLinkStep::KeyPackages)
+                .await?
+                .as_slice(),
+        )
+        .context("Failed to decode the key packages of the new device")?
+        .key_packages;
+
+        if key_packages.len() != group_ids.len() {
+            bail!("The new device didn't send one key package per group");
+        }
+
+        let mut welcomes = Vec::new();
+        for (group_id, key_package) in group_ids.into_iter().zip(key_packages) {
+            let key_package = MlsMessage::from_bytes(&key_package)?;
+
+            // Only the device we provisioned can be added this way
+            let device_id = key_package
+                .clone()
+                .into_key_package()
+                .and_then(|key_package| {
+                    LicksIdentityProvider::resolve_to_licks_credential(
+                        key_package.signing_identity(),
+                    )
+                })
+                .map(|credential| *credential.chain.device_id());
+            if device_id != Some(new_device_id) {
+                bail!("The new device sent a key package of another device");
+            }
+
+            let (welcome, new_epoch, new_blinded_address) = match self
+                .add_key_package_without_updating_listener(&group_id, key_package)
+                .await
+            {
+                Ok(added) => added,
+                Err(err) => {
+                    log::warn!("Couldn't add the new device to group {group_id:?}: {err:?}");
+                    continue;
+                }
+            };
+
+            self.client
+                .listener_manager
+                .listen_new_epoch(
+                    self.profile_manager.clone(),
+                    group_id,
+                    new_epoch,
+                    new_blinded_address.to_public(),
+                )
+                .await?;
+
+            welcomes.push(welcome.to_bytes()?);
+        }
+
+        send_link_message(
+            server,
+            &code,
+            DeviceLinkStep::Welcomes,
+            &DeviceLinkWelcomes { welcomes }.encode_to_vec(),
+        )
+        .await?;
+
+        self.profile_manager.start_history_sync(&new_device_id)?;
+        self.sync_history();
+
+        Ok(new_device_id)
+    }
+
+    /// The new device's side of [`Self::link_new_device`], once its chain was
+    /// registered (see [`super::ProfileManager::initialize_from_link`]): uploads
+    /// key packages, sends `key_package_count` others to the existing device,
+    /// and joins the groups it was added to.
+    pub async fn finish_device_link(
+        &self,
+        code: &DeviceLinkCode,
+        key_package_count: u32,
+    ) -> Result<Vec<GroupIdentifier>> {
+        let server = self.get_server();
+
+        self.upload_new_key_packages(1).await?;
+
+        let mut key_packages = Vec::new();
+        for _ in 0..key_package_count {
+            key_packages.push(
+                self.mls_client()
+                    .generate_key_package_message()?
+                    .to_bytes()?,
+            );
+        }
+        send_link_message(
+            server,
+            code,
+            DeviceLinkStep::KeyPackages,
+            &DeviceLinkKeyPackages { key_packages }.encode_to_vec(),
+        )
+        .await?;
+
+        let welcomes = DeviceLinkWelcomes::decode(
+            receive_link_message(server, code, DeviceLinkStep::Welcomes)
+                .await?
+                .as_slice(),
+        )
+        .context("Failed to decode the Welcome messages")?
+        .welcomes;
+
+        let mut group_ids = Vec::new();
+        for welcome in welcomes {
+            let welcome = MlsMessage::from_bytes(&welcome)?;
+            if welcome.wire_format() != WireFormat::Welcome {
+                log::warn!("Ignoring a linked group that isn't a Welcome message");
+                continue;
+            }
+
+            match self.join_group_from_welcome_and_listen(&welcome).await {
+                Ok(group_id) => group_ids.push(group_id),
+                Err(err) => log::warn!("Couldn't join a linked group: {err:?}"),
+            }
+        }
+
+        Ok(group_ids)
+    }
+}
@@ -8,29 +8,43 @@
 //! directly with the mls-rs `Group` struct.
 //!
 //! The group manager also contains helper functions for user interfaces.
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use crate::{
     client::ClientProfile,
+    database::outbox::{OutboxMessage, OutboxStatus},
     messages::MlsApplicationMessage,
-    mls::credentials::{LicksIdentityProvider, LicksMlsCredential},
+    mls::{
+        credentials::{LicksIdentityProvider, LicksMlsCredential},
+        extensions::{ActiveGroupLink, GroupMetadata, GroupRoles, InviteInbox},
+        rules::LicksMlsRules,
+    },
+    net::RequestError,
     ui::GroupUi,
 };
 
-use super::{error::Result, MlsClient, MlsClientConfig, ProfileManager, WEBSOCKET_MANAGER};
+use super::{
+    account::Profile, error::Result, notifications::Notification, MlsClient, MlsClientConfig,
+    ProfileManager, WEBSOCKET_MANAGER,
+};
 use anyhow::{bail, Context};
 use mls_rs::{
-    error::MlsError, group::proposal::Proposal, mls_rs_codec::MlsDecode, ExtensionList, Group,
-    MlsMessage,
+    error::MlsError,
+    group::{proposal::Proposal, CommitOutput, ProposalMessageDescription, ProposalSender},
+    mls_rs_codec::MlsDecode,
+    ExtensionList, Group, MlsMessage,
 };
 
 use lib::{
     api::{
-        group::SendMessageRequest,
-        messages::{ChatServiceMessage, Message, UnauthRequest},
+        group::{DeliveryStamp, SendMessageRequest},
+        messages::{ChatServiceMessage, Message, ServiceError, UnauthRequest},
         proto::{self, ApplicationMessage, ProstMessage},
     },
-    crypto::blinded_address::{BlindedAddressPublic, BlindedAddressSecret},
+    crypto::{
+        blinded_address::{BlindedAddressPublic, BlindedAddressSecret},
+        group_link::{GroupLink, GroupLinkSecret},
+    },
     identifiers::{AccountId, GroupIdentifier, LicksIdentifier},
 };
 
@@ -39,7 +53,8 @@ type MlsGroup = Group<MlsClientConfig>;
 /// `GroupManager` is a level of abstraction between the front-end
 /// and the database that keeps track of `GroupIdentifier` <-> `MlsGroup`.
 pub struct GroupManager {
-    mls_client: Arc<MlsClient>,
+    /// Replaced when our signing identity changes, see [`GroupManager::set_mls_client`].
+    mls_client: RwLock<Arc<MlsClient>>,
 }
 
 pub enum ProcessedMessage {
@@ -52,16 +67,43 @@ pub enum ProcessedMessage {
     /// new one and its epoch.
     Commit(u64, BlindedAddressPublic, Vec<ProcessedCommit>),
     ApplicationMessage(MlsApplicationMessage),
+    /// A proposal was received. It is kept in the group state until the next
+    /// commit includes it. This is true if we are the member who should commit
+    /// it (see [`GroupManager::should_commit_proposal`]).
+    Proposal(bool),
+    /// A commit removed us from the group. The group should be deleted
+    /// (see [`ProfileManager::delete_group_locally`]).
+    RemovedFromGroup,
 }
 
 pub enum ProcessedCommit {
     Unknown,
     AddedMember(AccountId),
+    /// One of the leaves of that account was removed. There is one for
+    /// each removed leaf, so the same account may appear several times.
+    RemovedMember(AccountId),
 }
 
 impl GroupManager {
     pub fn init(mls_client: Arc<MlsClient>) -> Result<Self> {
-        Ok(GroupManager { mls_client })
+        Ok(GroupManager {
+            mls_client: RwLock::new(mls_client),
+        })
+    }
+
+    /// The MLS client, which creates and joins groups with our current signing identity.
+    pub fn mls_client(&self) -> Arc<MlsClient> {
+        self.mls_client
+            .read()
+            .expect("RwLock poisoning is safe")
+            .clone()
+    }
+
+    /// Replaces the MLS client after our profile changed (see
+    /// [`ProfileManager::replace_profile`]). Groups we are already in keep
+    /// their own signing key until we commit an Update in them.
+    pub(super) fn set_mls_client(&self, mls_client: Arc<MlsClient>) {
+        *self.mls_client.write().expect("RwLock poisoning is safe") = mls_client;
     }
 
     /// Creates a new group and saves it to the database. If `group_config` is set to None
@@ -75,7 +117,7 @@ impl GroupManager {
         let group_identifier = group_identifier.unwrap_or_else(GroupIdentifier::generate_id);
 
         let mut group = self
-            .mls_client
+            .mls_client()
             .create_group_with_id(group_identifier.to_bytes().to_vec(), extension_list)?;
 
         group.write_to_storage()?;
@@ -90,7 +132,7 @@ impl GroupManager {
         &self,
         welcome: &mls_rs::MlsMessage,
     ) -> Result<(GroupIdentifier, MlsGroup)> {
-        let (mut group, _new_member_info) = self.mls_client.join_group(None, welcome)?;
+        let (mut group, _new_member_info) = self.mls_client().join_group(None, welcome)?;
 
         group.write_to_storage()?;
 
@@ -101,6 +143,22 @@ impl GroupManager {
         Ok((group_identifier, group))
     }
 
+    /// Joins the group of `group_info` by external commit. The group isn't saved
+    /// yet: this should only be done once the returned commit was delivered.
+    pub fn join_group_by_external_commit(
+        &self,
+        group_info: MlsMessage,
+    ) -> Result<(GroupIdentifier, MlsGroup, MlsMessage)> {
+        let (group, commit) = self
+            .mls_client()
+            .external_commit_builder()?
+            .build(group_info)?;
+
+        let group_identifier: GroupIdentifier = group.group_id().try_into()?;
+
+        Ok((group_identifier, group, commit))
+    }
+
     /// Updates the blinded address of a group to that has updated their group secret (like after a commit)
     /// in the database and updates connection listeners.
     /// Returns `Ok(true)` if the blinded address changed and is not in the database.
@@ -124,7 +182,7 @@ impl GroupManager {
     #[inline]
     fn load_mls_rs_group(&self, group_identifier: &GroupIdentifier) -> Result<MlsGroup> {
         Ok(self
-            .mls_client
+            .mls_client()
             .load_group(group_identifier.as_uuid().as_bytes())?)
     }
 
@@ -141,6 +199,10 @@ impl GroupManager {
                 let Ok(mls_message) = mls_rs::MlsMessage::from_bytes(message) else {
                     bail!("Failed to deserialize mls message from bytes");
                 };
+                // Removed members aren't in the roster anymore once
+                // a commit is processed, so we keep track of them now.
+                let previous_members = Self::member_account_ids(&group);
+
                 // Decrypt the message
                 let processed_message_result = group.process_incoming_message(mls_message);
 
@@ -176,7 +238,8 @@ impl GroupManager {
                             );
                         };
 
-                        // the sender_id should match the sender index of the application message
+                        // the sender_id should match the sender index of the application message.
+                        // Edits and deletions rely on this to only apply to messages of their sender.
                         let author_index_member = group
                             .member_at_index(application_message.sender_index)
                             .context("Message sent from a member not in the group.")?;
@@ -197,6 +260,7 @@ impl GroupManager {
                         }
                     }
                     mls_rs::group::ReceivedMessage::Commit(commit) => {
+                        let committer = commit.committer;
                         let processed_commits: Vec<ProcessedCommit> = match commit.effect {
                             mls_rs::group::CommitEffect::NewEpoch(new_epoch) => {
                                 new_epoch
@@ -219,12 +283,39 @@ impl GroupManager {
                                                 ProcessedCommit::Unknown
                                             }
                                             }
+                                            Proposal::Remove(remove_proposal) => previous_members
+                                                .iter()
+                                                .find(|(index, _)| {
+                                                    *index == remove_proposal.to_remove()
+                                                })
+                                                .map_or(
+                                                    ProcessedCommit::Unknown,
+                                                    |(_, account_id)| {
+                                                        ProcessedCommit::RemovedMember(*account_id)
+                                                    },
+                                                ),
+                                            // The committer of an external commit is the new member
+                                            Proposal::ExternalInit(_) => group
+                                                .member_at_index(committer)
+                                                .and_then(|member| {
+                                                    LicksIdentityProvider::resolve_to_licks_credential(
+                                                        &member.signing_identity,
+                                                    )
+                                                })
+                                                .map_or(ProcessedCommit::Unknown, |cred| {
+                                                    ProcessedCommit::AddedMember(
+                                                        *cred.chain.account_id(),
+                                                    )
+                                                }),
                                             _ => ProcessedCommit::Unknown,
                                         }
                                     })
                                     .collect()
                             }
-                            _ => vec![],
+                            mls_rs::group::CommitEffect::Removed { .. } => {
+                                return Ok(ProcessedMessage::RemovedFromGroup);
+                            }
+                            mls_rs::group::CommitEffect::ReInit(_) => vec![],
                         };
                         // Because this is a commit, the epoch has now changed.
                         // We then calculate the new blinded address, update the db tree,
@@ -239,20 +330,37 @@ impl GroupManager {
                     }
                     mls_rs::group::ReceivedMessage::Proposal(proposal) => {
                         log::trace!("Proposal received ({proposal:?})");
-                        todo!();
+
+                        // mls-rs caches the proposal in the group state, which
+                        // we saved above, so the next commit will include it.
+                        Ok(ProcessedMessage::Proposal(Self::should_commit_proposal(
+                            &group, &proposal,
+                        )))
                     }
-                    mls_rs::group::ReceivedMessage::GroupInfo(group_info) => {
-                        log::trace!("Group info received ({group_info:?})");
-                        todo!();
+                    // The messages below aren't processed by the group itself, and
+                    // are never sent to a group: GroupInfos are shared through invite
+                    // links, and KeyPackages through the server.
+                    mls_rs::group::ReceivedMessage::GroupInfo(_) => {
+                        log::warn!(
+                            "Received a GroupInfo message in group {group_identifier}. Rejecting it"
+                        );
+
+                        Ok(ProcessedMessage::Ignore)
                     }
-                    // Do nothing on receiving welcome and keypackage
                     mls_rs::group::ReceivedMessage::Welcome => {
-                        log::trace!("Welcome message received");
-                        todo!();
+                        // Welcomes are sent to the new members directly, not to the group.
+                        log::warn!(
+                            "Received a Welcome message in group {group_identifier}. Ignoring it"
+                        );
+
+                        Ok(ProcessedMessage::Ignore)
                     }
-                    mls_rs::group::ReceivedMessage::KeyPackage(key_package) => {
-                        log::trace!("Key package received ({:?})", key_package);
-                        todo!()
+                    mls_rs::group::ReceivedMessage::KeyPackage(_) => {
+                        log::warn!(
+                            "Received a KeyPackage message in group {group_identifier}. Rejecting it"
+                        );
+
+                        Ok(ProcessedMessage::Ignore)
                     }
                 }
             }
@@ -262,6 +370,67 @@ impl GroupManager {
         }
     }
 
+    /// Returns the leaf index and the [`AccountId`] of every member of the group.
+    fn member_account_ids(group: &MlsGroup) -> Vec<(u32, AccountId)> {
+        group
+            .roster()
+            .members_iter()
+            .filter_map(|member| {
+                LicksIdentityProvider::resolve_to_licks_credential(&member.signing_identity)
+                    .map(|credential| (member.index, *credential.chain.account_id()))
+            })
+            .collect()
+    }
+
+    /// Returns the leaf indexes of `account_id` in the group, except ours.
+    fn leaf_indexes_of(group: &MlsGroup, account_id: &AccountId) -> Vec<u32> {
+        let our_index = group.current_member_index();
+
+        Self::member_account_ids(group)
+            .into_iter()
+            .filter(|(index, member_account_id)| {
+                *index != our_index && member_account_id == account_id
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Deletes the state of the group (with all its epochs) from storage.
+    pub fn delete_group(&self, group_identifier: &GroupIdentifier) -> Result<()> {
+        self.mls_client()
+            .group_state_storage()
+            .delete_group(group_identifier.as_uuid().as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Decides who commits a proposal we received, so that members don't all
+    /// commit it at once: the member with the lowest leaf index, except its
+    /// sender (who would have committed it directly if it could) and the member
+    /// it removes (who can't commit its own removal).
+    ///
+    /// Any other commit made in the meantime also includes it.
+    fn should_commit_proposal(group: &MlsGroup, proposal: &ProposalMessageDescription) -> bool {
+        let sender = match proposal.sender {
+            ProposalSender::Member(index) => Some(index),
+            _ => None,
+        };
+
+        let removed = match &proposal.proposal {
+            Proposal::Remove(remove) => Some(remove.to_remove()),
+            _ => None,
+        };
+
+        let committer = group
+            .roster()
+            .members_iter()
+            .map(|member| member.index)
+            .filter(|index| Some(*index) != sender && Some(*index) != removed)
+            .min();
+
+        committer == Some(group.current_member_index())
+    }
+
     /// Internal function that passes [`Group`] directly so we don't load it
     /// everytime. The public function that takes a [`GroupIdentifier`] instead
     /// is at [`Self::get_blinded_address`].
@@ -280,9 +449,59 @@ impl GroupManager {
         let group = self.load_mls_rs_group(group_identifier)?;
         Self::generate_blinded_address(&group)
     }
+
+    /// Reads the [`GroupMetadata`] stored in the group context. Groups created
+    /// by older clients don't have any.
+    #[inline]
+    fn group_metadata(mls_group: &MlsGroup) -> Option<GroupMetadata> {
+        GroupMetadata::from_extensions(mls_group.context().extensions())
+    }
+
+    /// Returns `true` if we are an admin of the group (see [`GroupRoles`]).
+    fn is_admin(mls_group: &MlsGroup) -> bool {
+        LicksMlsRules::is_admin(
+            &mls_group.roster(),
+            GroupRoles::from_extensions(mls_group.context().extensions()).as_ref(),
+            mls_group.current_member_index(),
+        )
+    }
+
+    /// Returns the roles of the group. Groups created by older clients don't
+    /// have roles, which means every current member is an admin.
+    fn group_roles(mls_group: &MlsGroup) -> GroupRoles {
+        GroupRoles::from_extensions(mls_group.context().extensions()).unwrap_or_else(|| {
+            let mut roles = GroupRoles::default();
+            for (_, account_id) in Self::member_account_ids(mls_group) {
+                roles.promote(account_id);
+            }
+
+            roles
+        })
+    }
 }
 
 impl ClientProfile<'_> {
+    /// Listens to every group we are in, which first catches up on the
+    /// messages we missed while offline.
+    pub async fn listen_to_all_groups(&self) -> Result<()> {
+        for group_id in self.get_all_group_ids()? {
+            if let Err(err) = self
+                .client
+                .listener_manager
+                .listen(
+                    self.profile_manager.clone(),
+                    group_id,
+                    self.client.notification_manager.clone(),
+                )
+                .await
+            {
+                log::warn!("Couldn't listen to group {group_id:?}: {err:?}");
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn create_new_group(
         &self,
         group_name: String,
@@ -319,12 +538,140 @@ impl ClientProfile<'_> {
         Ok(group_ui)
     }
 
+    /// See [`ProfileManager::remove_member_without_updating_listener`].
+    pub async fn remove_member(
+        &self,
+        group_id: GroupIdentifier,
+        account_id: AccountId,
+    ) -> Result<()> {
+        let (new_epoch, new_blinded_address) = self
+            .remove_member_without_updating_listener(&group_id, &account_id)
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_new_epoch(
+                self.profile_manager.clone(),
+                group_id,
+                new_epoch,
+                new_blinded_address.to_public(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// See [`ProfileManager::leave_group_without_updating_listener`]. We keep
+    /// listening to the group until the proposal was delivered, so that nothing
+    /// is missed if it wasn't.
+    pub async fn leave_group(&self, group_id: GroupIdentifier) -> Result<()> {
+        self.leave_group_without_updating_listener(&group_id)
+            .await?;
+
+        // Not listening to that group isn't an issue
+        let _ = self
+            .client
+            .listener_manager
+            .stop(self.profile_manager.clone(), group_id)
+            .await;
+
+        self.delete_group_locally(&group_id)
+    }
+
+    /// See [`ProfileManager::update_group_metadata_without_updating_listener`].
+    pub async fn update_group_metadata(
+        &self,
+        group_id: GroupIdentifier,
+        metadata: GroupMetadata,
+    ) -> Result<GroupUi> {
+        let (group_ui, new_epoch, new_blinded_address) = self
+            .update_group_metadata_without_updating_listener(&group_id, metadata)
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_new_epoch(
+                self.profile_manager.clone(),
+                group_id,
+                new_epoch,
+                new_blinded_address.to_public(),
+            )
+            .await?;
+
+        self.client
+            .notification_manager
+            .send_notification(Notification::GroupUpdated(group_ui.clone()));
+
+        Ok(group_ui)
+    }
+
+    /// See [`ProfileManager::promote_member_without_updating_listener`].
+    pub async fn promote_member(
+        &self,
+        group_id: GroupIdentifier,
+        account_id: AccountId,
+    ) -> Result<()> {
+        let (new_epoch, new_blinded_address) = self
+            .promote_member_without_updating_listener(&group_id, account_id)
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_new_epoch(
+                self.profile_manager.clone(),
+                group_id,
+                new_epoch,
+                new_blinded_address.to_public(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// See [`ProfileManager::demote_member_without_updating_listener`].
+    pub async fn demote_member(
+        &self,
+        group_id: GroupIdentifier,
+        account_id: AccountId,
+    ) -> Result<()> {
+        let (new_epoch, new_blinded_address) = self
+            .demote_member_without_updating_listener(&group_id, &account_id)
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_new_epoch(
+                self.profile_manager.clone(),
+                group_id,
+                new_epoch,
+                new_blinded_address.to_public(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Adds `account_id` to a group and returns the Welcome message they need
+    /// to join it. See [`Self::invite_member`] to send it to them as well.
     pub async fn create_new_welcome(
         &self,
         group_id: GroupIdentifier,
         account_id: AccountId,
     ) -> Result<MlsMessage> {
-        let (welcome_message, new_epoch, new_blinded_address) = self
+        Ok(self
+            .create_welcome_and_listen(group_id, account_id)
+            .await?
+            .0)
+    }
+
+    /// Returns the Welcome message, and the invite inbox of the device
+    /// of `account_id` it was made for.
+    pub(crate) async fn create_welcome_and_listen(
+        &self,
+        group_id: GroupIdentifier,
+        account_id: AccountId,
+    ) -> Result<(MlsMessage, InviteInbox)> {
+        let (welcome_message, invite_inbox, new_epoch, new_blinded_address) = self
             .create_welcome_for_user_without_updating_listener(&group_id, account_id)
             .await?;
 
@@ -338,7 +685,7 @@ impl ClientProfile<'_> {
             )
             .await?;
 
-        Ok(welcome_message)
+        Ok((welcome_message, invite_inbox))
     }
 
     pub async fn join_group_from_welcome_and_listen(
@@ -347,33 +694,63 @@ impl ClientProfile<'_> {
     ) -> Result<GroupIdentifier> {
         let (group_identifier, mls_group) = self.group_manager.join_group_from_welcome(welcome)?;
 
-        let blinded_address = GroupManager::generate_blinded_address(&mls_group)?;
-        let epoch = mls_group.current_epoch();
+        let group_ui = self.save_group_info(&mls_group)?;
+        self.add_members_to_contacts(&mls_group)?;
 
-        // TODO: Issue #78
-        let group_name = "Untitled Group".to_string();
-        let group_description = None;
+        self.client
+            .listener_manager
+            .listen(
+                self.profile_manager.clone(),
+                group_identifier,
+                self.client.notification_manager.clone(),
+            )
+            .await?;
 
-        self.profile_manager.sqlite_database.add_group_info(
-            group_identifier,
-            group_name,
-            group_description,
-            epoch,
-            blinded_address,
-        )?;
+        self.client
+            .notification_manager
+            .send_notification(Notification::GroupUpdated(group_ui));
 
-        for member in mls_group.roster().members_iter() {
-            let member_cred = member.signing_identity;
-            let licks_mls_cred: LicksMlsCredential = member_cred.try_into()?;
+        Ok(group_identifier)
+    }
 
-            self.profile_manager.sqlite_database.add_new_contact(
-                *licks_mls_cred.chain.account_id(),
-                None,
-                None,
-                None,
-            )?;
+    /// Joins a group with one of its invite links (see
+    /// [`ProfileManager::get_group_link_content`]), by external commit.
+    pub async fn join_group_from_link(&self, link: &str) -> Result<GroupIdentifier> {
+        let link = GroupLink::from_link_string(link)?;
+        let (group_info, mut blinded_address) = self.get_group_link_content(&link).await?;
+
+        let (group_identifier, mut mls_group, commit) = self
+            .group_manager
+            .join_group_by_external_commit(group_info)?;
+
+        if self.get_all_group_ids()?.contains(&group_identifier) {
+            bail!("We already are a member of this group");
+        }
+
+        // The commit is sent to the epoch of the GroupInfo, which members are listening to
+        let resp = WEBSOCKET_MANAGER
+            .request_unauth(
+                self.get_server(),
+                UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
+                    blinded_address_proof: blinded_address.create_proof(commit.to_bytes()?),
+                    ephemeral: false,
+                })),
+            )
+            .await?;
+
+        match resp {
+            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(_))) => {}
+            other => {
+                return Err(RequestError::from_response(other))
+                    .context("External commit did not deliver");
+            }
         }
 
+        mls_group.write_to_storage()?;
+
+        let group_ui = self.save_group_info(&mls_group)?;
+        self.add_members_to_contacts(&mls_group)?;
+
         self.client
             .listener_manager
             .listen(
@@ -382,15 +759,69 @@ impl ClientProfile<'_> {
                 self.client.notification_manager.clone(),
             )
             .await?;
+
+        self.client
+            .notification_manager
+            .send_notification(Notification::GroupUpdated(group_ui));
+
         Ok(group_identifier)
     }
+
+    /// Returns the invite link of a group, creating one if it has none that we
+    /// know the secret of. Only admins can create links, and only the one who
+    /// created a link keeps it up to date, see [`ProfileManager::refresh_group_link`].
+    pub async fn create_group_link(&self, group_id: GroupIdentifier) -> Result<GroupLink> {
+        if let Some(link) = self.get_group_link(&group_id)? {
+            return Ok(link);
+        }
+
+        self.rotate_group_link(group_id).await
+    }
+
+    /// See [`ProfileManager::rotate_group_link_without_updating_listener`].
+    pub async fn rotate_group_link(&self, group_id: GroupIdentifier) -> Result<GroupLink> {
+        let (link, new_epoch, new_blinded_address) = self
+            .rotate_group_link_without_updating_listener(&group_id)
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_new_epoch(
+                self.profile_manager.clone(),
+                group_id,
+                new_epoch,
+                new_blinded_address.to_public(),
+            )
+            .await?;
+
+        Ok(link)
+    }
+
+    /// See [`ProfileManager::revoke_group_link_without_updating_listener`].
+    pub async fn revoke_group_link(&self, group_id: GroupIdentifier) -> Result<()> {
+        let (new_epoch, new_blinded_address) = self
+            .revoke_group_link_without_updating_listener(&group_id)
+            .await?;
+
+        self.client
+            .listener_manager
+            .listen_new_epoch(
+                self.profile_manager.clone(),
+                group_id,
+                new_epoch,
+                new_blinded_address.to_public(),
+            )
+            .await?;
+
+        Ok(())
+    }
 }
 
 impl ProfileManager {
     /// Returns the group id of all groups stored inside the MLS client.
     pub fn get_all_group_ids(&self) -> Result<Vec<GroupIdentifier>> {
         Ok(self
-            .mls_client
+            .mls_client()
             .group_state_storage()
             .group_ids()?
             .iter()
@@ -416,88 +847,132 @@ impl ProfileManager {
         group_name: String,
         group_description: Option<String>,
     ) -> Result<GroupUi> {
-        let group_name = Arc::new(group_name);
-        let extensions = ExtensionList::default();
+        let mut extensions = ExtensionList::default();
+        GroupMetadata::new(group_name, group_description).set_in(&mut extensions);
+        GroupRoles::new(self.get_profile().get_account_id()).set_in(&mut extensions);
 
         let group_identifier = self.group_manager.create_group(extensions, None)?;
 
-        let (blinded_address, epoch) = self
-            .group_manager
-            .get_blinded_address_and_epoch(&group_identifier)?;
-
-        self.sqlite_database.add_group_info(
-            group_identifier,
-            group_name.as_str(),
-            group_description,
-            epoch,
-            blinded_address,
-        )?;
-
-        Ok(GroupUi {
-            group_identifier,
-            group_name,
-            last_message: None,
-        })
+        self.refresh_group_info(&group_identifier)
     }
 
     pub fn create_self_group_without_listener(&self) -> Result<GroupUi> {
-        let group_name = Arc::new("Personal Notes".to_string());
+        let group_name = "Personal Notes".to_string();
         let group_description = Some(
             "Your very own little group to store all kinds of notes between all your devices."
                 .to_string(),
         );
 
-        let extensions = ExtensionList::default();
+        let mut extensions = ExtensionList::default();
+        GroupMetadata::new(group_name, group_description).set_in(&mut extensions);
+        GroupRoles::new(self.get_profile().get_account_id()).set_in(&mut extensions);
 
         let group_identifier = self
             .group_manager
             .create_group(extensions, Some(GroupIdentifier::self_id()))?;
 
-        let (blinded_address, epoch) = self
-            .group_manager
-            .get_blinded_address_and_epoch(&group_identifier)?;
+        self.refresh_group_info(&group_identifier)
+    }
+
+    /// Saves the name, description and blinded address of the current epoch of
+    /// `group` into the database. The name and description come from the
+    /// [`GroupMetadata`] of the group, or are kept from the previous epoch if the
+    /// group has none.
+    fn save_group_info(&self, group: &MlsGroup) -> Result<GroupUi> {
+        let group_identifier = GroupIdentifier::try_from(group.group_id())?;
+
+        let metadata = GroupManager::group_metadata(group)
+            .or_else(|| {
+                self.sqlite_database
+                    .get_group_info(group_identifier)
+                    .ok()
+                    .map(|(name, description, _, _)| GroupMetadata::new(name, description))
+            })
+            .unwrap_or_else(|| GroupMetadata::new("Untitled Group".to_string(), None));
 
         self.sqlite_database.add_group_info(
             group_identifier,
-            group_name.as_str(),
-            group_description,
-            epoch,
-            blinded_address,
+            metadata.name.as_str(),
+            metadata.description,
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(group)?,
         )?;
 
         Ok(GroupUi {
             group_identifier,
-            group_name,
+            group_name: Arc::new(metadata.name),
             last_message: None,
         })
     }
 
-    pub async fn create_welcome_for_user_without_updating_listener(
-        &self,
-        group_id: &GroupIdentifier,
-        account_id: AccountId,
-    ) -> Result<(MlsMessage, u64, BlindedAddressSecret)> {
-        // TODO: When we get a ContactManager, we move that logic there
-        // Let the database know about this user
-        self.sqlite_database
-            .add_new_contact(account_id, None, None, None)?;
+    /// Updates the information of `group_id` stored in the database to its
+    /// current epoch. See [`Self::save_group_info`].
+    pub fn refresh_group_info(&self, group_id: &GroupIdentifier) -> Result<GroupUi> {
+        let group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
 
-        let mut group = self
+        self.save_group_info(&group)
+    }
+
+    /// Returns `true` if the name and description of a group are stored in its
+    /// group context (see [`GroupMetadata`]), rather than only in our database.
+    pub fn has_group_metadata(&self, group_id: &GroupIdentifier) -> Result<bool> {
+        let group = self
             .group_manager
             .load_mls_rs_group(group_id)
             .context("Group couldn't be found in database")?;
 
-        let key_package = match WEBSOCKET_MANAGER
-            .request_unauth(
-                self.profile.get_server(),
-                UnauthRequest::GetKeyPackage(account_id),
-            )
-            .await?
-        {
-            Message::Unauth(UnauthRequest::HereIsKeyPackage(key_package)) => {
-                let mut key_package = key_package.as_slice();
-                let key_package = MlsMessage::mls_decode(&mut key_package)
-                    .context("Failed to decode keypackage")?;
+        Ok(GroupManager::group_metadata(&group).is_some())
+    }
+
+    /// Adds every member of a group we just joined to our contacts.
+    fn add_members_to_contacts(&self, group: &MlsGroup) -> Result<()> {
+        for member in group.roster().members_iter() {
+            let member_cred = member.signing_identity;
+            let licks_mls_cred: LicksMlsCredential = member_cred.try_into()?;
+
+            self.sqlite_database.add_new_contact(
+                *licks_mls_cred.chain.account_id(),
+                None,
+                None,
+                None,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a device of `account_id` to a group, and returns the Welcome message
+    /// it needs to join it along with its invite inbox.
+    pub async fn create_welcome_for_user_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+        account_id: AccountId,
+    ) -> Result<(MlsMessage, InviteInbox, u64, BlindedAddressSecret)> {
+        // TODO: When we get a ContactManager, we move that logic there
+        // Let the database know about this user
+        self.sqlite_database
+            .add_new_contact(account_id, None, None, None)?;
+
+        let group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        if !GroupManager::is_admin(&group) {
+            bail!("Only admins can add members");
+        }
+
+        let key_package = match WEBSOCKET_MANAGER
+            .request_unauth(self.get_server(), UnauthRequest::GetKeyPackage(account_id))
+            .await?
+        {
+            Message::Unauth(UnauthRequest::HereIsKeyPackage(key_package)) => {
+                let mut key_package = key_package.as_slice();
+                let key_package = MlsMessage::mls_decode(&mut key_package)
+                    .context("Failed to decode keypackage")?;
 
                 if key_package.wire_format() == mls_rs::WireFormat::KeyPackage {
                     key_package
@@ -509,23 +984,61 @@ impl ProfileManager {
                 bail!("Account has not keypackages");
             }
             other => {
-                bail!("Received unexpected response from the server: {other:?}");
+                return Err(RequestError::from_response(other))
+                    .context("Couldn't retrieve the new member's key package");
             }
         };
-        let add_commit = group.commit_builder().add_member(key_package)?.build()?;
 
-        WEBSOCKET_MANAGER
-            .request_unauth(
-                self.profile.get_server(),
-                UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
-                    blinded_address_proof: GroupManager::generate_blinded_address(&group)?
-                        .create_proof(add_commit.commit_message.to_bytes()?),
-                })),
-            )
+        // Checked before adding the device, which couldn't be invited otherwise
+        let invite_inbox = InviteInbox::from_key_package(&key_package)
+            .context("Key package doesn't have an invite inbox")?;
+
+        let (welcome_message, new_epoch, new_blinded_address) = self
+            .add_key_package_without_updating_listener(group_id, key_package)
             .await?;
 
-        group.apply_pending_commit()?;
-        group.write_to_storage()?;
+        Ok((
+            welcome_message,
+            invite_inbox,
+            new_epoch,
+            new_blinded_address,
+        ))
+    }
+
+    /// Adds the device of `key_package` to a group, and returns the Welcome message
+    /// it needs to join it. Only admins can add members, but anyone can add a device
+    /// of their own account.
+    pub async fn add_key_package_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+        key_package: MlsMessage,
+    ) -> Result<(MlsMessage, u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        // The device whose key package we got, which is the only one able to
+        // read the Welcome message
+        let credential = key_package
+            .clone()
+            .into_key_package()
+            .and_then(|key_package| {
+                LicksIdentityProvider::resolve_to_licks_credential(key_package.signing_identity())
+            })
+            .context("Key package doesn't have a Licks credential")?;
+
+        if !GroupManager::is_admin(&group)
+            && credential.chain.account_id() != &self.get_profile().get_account_id()
+        {
+            bail!("Only admins can add members");
+        }
+
+        let add_commit = group.commit_builder().add_member(key_package)?.build()?;
+
+        self.send_commit(&mut group, &add_commit)
+            .await
+            .context("Add commit did not deliver")?;
 
         let new_blinded_address = GroupManager::generate_blinded_address(&group)?;
 
@@ -540,58 +1053,604 @@ impl ProfileManager {
         ))
     }
 
-    pub async fn send_application_message(
+    /// Commits an Update of our own leaf in `group_id`, replacing our signing
+    /// identity with the one of `profile` (e.g. after rotating our device key).
+    /// Returns the new epoch of the group and its blinded address.
+    pub async fn update_signing_identity_without_updating_listener(
         &self,
         group_id: &GroupIdentifier,
-        content: crate::messages::Content,
-    ) -> Result<()> {
-        // TODO: Add a mechanism where we store "pending" messages that haven't sent yet (or if ever).
+        profile: &Profile,
+    ) -> Result<(u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        let (secret_key, signing_identity) = profile.to_mls_signer();
+        let update_commit = group
+            .commit_builder()
+            .set_new_signing_identity(secret_key.into(), signing_identity)
+            .build()?;
 
-        let message = MlsApplicationMessage::build(content.clone(), &self.profile);
+        self.send_commit(&mut group, &update_commit)
+            .await
+            .context("Update commit did not deliver")?;
 
+        Ok((
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Commits the proposals we received in `group_id` and kept until now (see
+    /// [`ProcessedMessage::Proposal`]). Returns the new epoch of the group and
+    /// its blinded address.
+    pub async fn commit_pending_proposals_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<(u64, BlindedAddressSecret)> {
         let mut group = self
             .group_manager
             .load_mls_rs_group(group_id)
             .context("Group couldn't be found in database")?;
 
-        let application_message = {
-            let bytes = proto::ApplicationMessage::from(message).encode_to_vec();
-            let mls_message = &mut group.encrypt_application_message(&bytes, Vec::default())?;
+        // Pending proposals are included in every commit by default
+        let commit = group.commit(Vec::new())?;
 
-            mls_message.to_bytes()?
+        self.send_commit(&mut group, &commit)
+            .await
+            .context("Proposals commit did not deliver")?;
+
+        Ok((
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Removes every device of `account_id` from the group (every leaf whose
+    /// credential belongs to that account). If `account_id` is our own account,
+    /// our other devices are removed. Only admins can remove other accounts.
+    /// Returns the new epoch of the group and its
+    /// blinded address.
+    pub async fn remove_member_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+        account_id: &AccountId,
+    ) -> Result<(u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        if *account_id != self.get_profile().get_account_id() && !GroupManager::is_admin(&group) {
+            bail!("Only admins can remove other members");
+        }
+
+        let leaf_indexes = GroupManager::leaf_indexes_of(&group, account_id);
+        if leaf_indexes.is_empty() {
+            bail!("This account is not a member of the group");
+        }
+
+        let mut commit_builder = group.commit_builder();
+        for leaf_index in leaf_indexes {
+            commit_builder = commit_builder.remove_member(leaf_index)?;
+        }
+        let remove_commit = commit_builder.build()?;
+
+        self.send_commit(&mut group, &remove_commit)
+            .await
+            .context("Remove commit did not deliver")?;
+
+        Ok((
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Replaces the name, description and avatar of a group by committing a new
+    /// [`GroupMetadata`] in its group context. Only admins can do this. Returns the updated group, its new
+    /// epoch and its blinded address.
+    pub async fn update_group_metadata_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+        metadata: GroupMetadata,
+    ) -> Result<(GroupUi, u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        // Keep the other extensions of the group context
+        let mut extensions = group.context().extensions().clone();
+        metadata.set_in(&mut extensions);
+
+        let group_ui = self
+            .commit_group_context_extensions(&mut group, extensions)
+            .await?;
+
+        Ok((
+            group_ui,
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Makes `account_id` an admin of the group (see [`GroupRoles`]). Only
+    /// admins can promote members. Returns the new epoch of the group and its
+    /// blinded address.
+    pub async fn promote_member_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+        account_id: AccountId,
+    ) -> Result<(u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        if GroupManager::member_account_ids(&group)
+            .iter()
+            .all(|(_, member)| *member != account_id)
+        {
+            bail!("This account is not a member of the group");
+        }
+
+        let mut roles = GroupManager::group_roles(&group);
+        if !roles.promote(account_id) {
+            bail!("This account already is an admin");
+        }
+
+        let mut extensions = group.context().extensions().clone();
+        roles.set_in(&mut extensions);
+
+        self.commit_group_context_extensions(&mut group, extensions)
+            .await?;
+
+        Ok((
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Makes `account_id` a regular member of the group (see [`GroupRoles`]).
+    /// Only admins can demote members, including themselves, as long as the
+    /// group keeps one admin. Returns the new epoch of the group and its
+    /// blinded address.
+    pub async fn demote_member_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+        account_id: &AccountId,
+    ) -> Result<(u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        let mut roles = GroupManager::group_roles(&group);
+        if !roles.demote(account_id) {
+            bail!("This account is not an admin");
+        }
+        if roles.admins.is_empty() {
+            bail!("A group must keep at least one admin");
+        }
+
+        let mut extensions = group.context().extensions().clone();
+        roles.set_in(&mut extensions);
+
+        self.commit_group_context_extensions(&mut group, extensions)
+            .await?;
+
+        Ok((
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Replaces the group context extensions of `group` with `extensions`, which
+    /// only admins can do.
+    async fn commit_group_context_extensions(
+        &self,
+        group: &mut MlsGroup,
+        extensions: ExtensionList,
+    ) -> Result<GroupUi> {
+        if !GroupManager::is_admin(group) {
+            bail!("Only admins can change the group");
+        }
+
+        let commit = group
+            .commit_builder()
+            .set_group_context_ext(extensions)?
+            .build()?;
+
+        self.send_commit(group, &commit)
+            .await
+            .context("Group context extensions commit did not deliver")
+    }
+
+    /// Returns the invite link of a group, if it has one and we created it.
+    pub fn get_group_link(&self, group_id: &GroupIdentifier) -> Result<Option<GroupLink>> {
+        let group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        let active_link = ActiveGroupLink::from_extensions(group.context().extensions());
+
+        Ok(self
+            .sqlite_database
+            .get_group_link_secret(group_id)?
+            .map(|link_secret| link_secret.link())
+            .filter(|link| active_link.is_some_and(|active| active.link_id == link.id)))
+    }
+
+    /// Creates a new invite link for a group, which replaces its previous one (even
+    /// if another admin created it). Only admins can do this. Returns the new link,
+    /// the new epoch of the group and its blinded address.
+    pub async fn rotate_group_link_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<(GroupLink, u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        // Saved first, so that the link is uploaded by [`Self::send_commit`]
+        let link_secret = GroupLinkSecret::generate();
+        self.sqlite_database
+            .set_group_link_secret(group_id, &link_secret)?;
+
+        let mut extensions = group.context().extensions().clone();
+        ActiveGroupLink {
+            link_id: link_secret.link().id,
+        }
+        .set_in(&mut extensions);
+
+        self.commit_group_context_extensions(&mut group, extensions)
+            .await?;
+
+        Ok((
+            link_secret.link(),
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Revokes the invite link of a group, even if another admin created it: new
+    /// members can't join by external commit anymore. Only admins can do this.
+    /// Returns the new epoch of the group and its blinded address.
+    pub async fn revoke_group_link_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<(u64, BlindedAddressSecret)> {
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        if ActiveGroupLink::from_extensions(group.context().extensions()).is_none() {
+            bail!("This group has no invite link");
+        }
+
+        let mut extensions = group.context().extensions().clone();
+        ActiveGroupLink::remove_from(&mut extensions);
+
+        self.commit_group_context_extensions(&mut group, extensions)
+            .await?;
+
+        Ok((
+            group.current_epoch(),
+            GroupManager::generate_blinded_address(&group)?,
+        ))
+    }
+
+    /// Downloads and decrypts the content of an invite link: a GroupInfo of
+    /// the group, and the blinded address of its epoch.
+    pub async fn get_group_link_content(
+        &self,
+        link: &GroupLink,
+    ) -> Result<(MlsMessage, BlindedAddressSecret)> {
+        let encrypted_content = match WEBSOCKET_MANAGER
+            .request_unauth(self.get_server(), UnauthRequest::GetGroupLink(link.id))
+            .await?
+        {
+            Message::Unauth(UnauthRequest::HereIsGroupLink(content)) => content,
+            other => {
+                return Err(RequestError::from_response(other))
+                    .context("Couldn't retrieve the invite link");
+            }
+        };
+
+        let content = proto::GroupLinkContent::decode(link.decrypt(&encrypted_content)?.as_slice())
+            .context("Failed to decode invite link content")?;
+
+        let group_info = MlsMessage::from_bytes(&content.group_info)?;
+        if group_info.wire_format() != mls_rs::WireFormat::GroupInfo {
+            bail!("Invite link does not contain a GroupInfo");
+        }
+
+        let blinded_address = BlindedAddressSecret::from_bytes(
+            content
+                .blinded_address_secret
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid blinded address in invite link"))?,
+        );
+
+        Ok((group_info, blinded_address))
+    }
+
+    /// Updates the invite link of a group to its current epoch, if we created
+    /// it. See [`Self::update_group_link`].
+    pub async fn refresh_group_link(&self, group_id: &GroupIdentifier) -> Result<()> {
+        let group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        self.update_group_link(&group).await
+    }
+
+    /// Uploads again every invite link we created, so that the server doesn't
+    /// expire the links of groups without commits. Groups where it fails are
+    /// only logged.
+    pub async fn refresh_group_links(&self) -> Result<()> {
+        for group_id in self.sqlite_database.get_groups_with_link_secret()? {
+            if let Err(err) = self.refresh_group_link(&group_id).await {
+                log::warn!("Couldn't refresh the invite link of group {group_id:?}: {err:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Uploads a GroupInfo of the current epoch of `group` to its invite link, if
+    /// we created the link: a GroupInfo only allows joining the epoch it was made
+    /// for. If the link was revoked or rotated since, it is deleted instead.
+    ///
+    /// Only the admin who created the link knows its secret, so nobody else can
+    /// refresh it. While they are offline, or once they left the group, the link
+    /// points to an old epoch and can't be used to join until an admin rotates it.
+    async fn update_group_link(&self, group: &MlsGroup) -> Result<()> {
+        let group_id = GroupIdentifier::try_from(group.group_id())?;
+
+        let Some(link_secret) = self.sqlite_database.get_group_link_secret(&group_id)? else {
+            return Ok(());
         };
+        let link = link_secret.link();
+
+        let is_active = ActiveGroupLink::from_extensions(group.context().extensions())
+            .is_some_and(|active| active.link_id == link.id);
+
+        if !is_active {
+            self.sqlite_database.delete_group_link_secret(&group_id)?;
+
+            return match WEBSOCKET_MANAGER
+                .request_unauth(
+                    self.get_server(),
+                    UnauthRequest::DeleteGroupLink(
+                        link_secret.write_key().create_proof(Vec::new()),
+                    ),
+                )
+                .await?
+            {
+                Message::Ok => Ok(()),
+                // The link was never uploaded
+                Message::Error(err) if err.code == ServiceError::NotFound => Ok(()),
+                other => Err(RequestError::from_response(other))
+                    .context("Couldn't delete the invite link"),
+            };
+        }
 
-        let blinded_address_proof =
-            GroupManager::generate_blinded_address(&group)?.create_proof(application_message);
+        let content = proto::GroupLinkContent {
+            group_info: group
+                .group_info_message_allowing_ext_commit(true)?
+                .to_bytes()?,
+            blinded_address_secret: GroupManager::generate_blinded_address(group)?
+                .to_bytes()
+                .to_vec(),
+        };
+
+        match WEBSOCKET_MANAGER
+            .request_unauth(
+                self.get_server(),
+                UnauthRequest::PutGroupLink(
+                    link_secret
+                        .write_key()
+                        .create_proof(link.encrypt(&content.encode_to_vec())),
+                ),
+            )
+            .await?
+        {
+            Message::Ok => Ok(()),
+            other => {
+                Err(RequestError::from_response(other)).context("Couldn't upload the invite link")
+            }
+        }
+    }
+
+    /// Leaves a group: since we can't commit our own removal, we send a proposal
+    /// to remove us, which another member will commit (see
+    /// [`GroupManager::should_commit_proposal`]). Once we stopped listening to
+    /// the group, it has to be deleted with [`Self::delete_group_locally`].
+    pub async fn leave_group_without_updating_listener(
+        &self,
+        group_id: &GroupIdentifier,
+    ) -> Result<()> {
+        if *group_id == GroupIdentifier::self_id() {
+            bail!("Can't leave our own self group");
+        }
+
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        let remove_proposal = group.propose_remove(group.current_member_index(), Vec::new())?;
 
         let resp = WEBSOCKET_MANAGER
             .request_unauth(
-                self.profile.get_server(),
+                self.get_server(),
                 UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
-                    blinded_address_proof,
+                    blinded_address_proof: GroupManager::generate_blinded_address(&group)?
+                        .create_proof(remove_proposal.to_bytes()?),
+                    ephemeral: false,
                 })),
             )
             .await?;
 
         match resp {
-            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(
-                delivery_stamp,
-            ))) => {
-                self.sqlite_database.add_message(
-                    content,
-                    self.profile.get_account_id(),
-                    &delivery_stamp,
-                    group_id,
-                )?;
+            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(_))) => {}
+            other => {
+                return Err(RequestError::from_response(other))
+                    .context("Remove proposal did not deliver");
             }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes everything we know about a group we are not a member of anymore,
+    /// except its messages.
+    pub fn delete_group_locally(&self, group_id: &GroupIdentifier) -> Result<()> {
+        self.group_manager.delete_group(group_id)?;
+        self.sqlite_database.delete_group_info(group_id)?;
+        self.sqlite_database.delete_sync_state(group_id)?;
+        self.sqlite_database.delete_group_link_secret(group_id)?;
+
+        Ok(())
+    }
+
+    /// Sends a commit we just made to the group, then applies and saves it.
+    /// Returns the group as of the new epoch (see [`Self::save_group_info`]).
+    async fn send_commit(&self, group: &mut MlsGroup, commit: &CommitOutput) -> Result<GroupUi> {
+        let resp = WEBSOCKET_MANAGER
+            .request_unauth(
+                self.get_server(),
+                UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
+                    blinded_address_proof: GroupManager::generate_blinded_address(group)?
+                        .create_proof(commit.commit_message.to_bytes()?),
+                    ephemeral: false,
+                })),
+            )
+            .await?;
+
+        match resp {
+            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(_))) => {}
             other => {
-                log::error!("Message did not deliver, received: {other:?}");
-                bail!("Message did not deliver: received: {other:?}");
+                return Err(RequestError::from_response(other).into());
             }
         }
 
+        group.apply_pending_commit()?;
         group.write_to_storage()?;
 
-        Ok(())
+        // The commit was applied, so failing to update the link isn't fatal
+        if let Err(err) = self.update_group_link(group).await {
+            log::warn!("Couldn't update the invite link of the group: {err:?}");
+        }
+
+        self.save_group_info(group)
+    }
+
+    /// Encrypts a message for the group. Returns the `client_stamp` identifying
+    /// it, the encrypted message, and the blinded address to send it to.
+    fn encrypt_application_message(
+        &self,
+        group_id: &GroupIdentifier,
+        content: crate::messages::Content,
+    ) -> Result<(DeliveryStamp, Vec<u8>, BlindedAddressSecret)> {
+        let message = MlsApplicationMessage::build(content, &self.get_profile());
+        let client_stamp = message.client_stamp;
+
+        let mut group = self
+            .group_manager
+            .load_mls_rs_group(group_id)
+            .context("Group couldn't be found in database")?;
+
+        let application_message = {
+            let bytes = proto::ApplicationMessage::from(message).encode_to_vec();
+            let mls_message = &mut group.encrypt_application_message(&bytes, Vec::default())?;
+
+            mls_message.to_bytes()?
+        };
+
+        let blinded_address = GroupManager::generate_blinded_address(&group)?;
+
+        // Saved before sending, so that the keys used by this message are never
+        // used again, even if it takes several attempts to send it.
+        group.write_to_storage()?;
+
+        Ok((client_stamp, application_message, blinded_address))
+    }
+
+    /// Encrypts a message and stores it in the outbox and in our messages, then
+    /// tries sending it (see [`ProfileManager::send_outbox_message`]). Returns the
+    /// `client_stamp` identifying the message, and its status after that first attempt.
+    pub async fn send_application_message(
+        &self,
+        group_id: &GroupIdentifier,
+        content: crate::messages::Content,
+    ) -> Result<(DeliveryStamp, OutboxStatus)> {
+        let (client_stamp, application_message, blinded_address) =
+            self.encrypt_application_message(group_id, content.clone())?;
+        let blinded_address = blinded_address.to_bytes();
+
+        self.sqlite_database.add_outbox_message(
+            &client_stamp,
+            group_id,
+            BlindedAddressSecret::from_bytes(blinded_address),
+            &application_message,
+            content.clone(),
+        )?;
+        // Stored right away so that it's displayed while pending. Its server
+        // stamp is replaced once the server delivered it.
+        self.sqlite_database.add_message(
+            content.clone(),
+            self.get_profile().get_account_id(),
+            Some(&client_stamp),
+            &client_stamp,
+            group_id,
+        )?;
+
+        let status = self
+            .send_outbox_message(OutboxMessage {
+                client_stamp,
+                group_id: *group_id,
+                blinded_address: BlindedAddressSecret::from_bytes(blinded_address),
+                mls_message: application_message,
+                content,
+                status: OutboxStatus::Pending,
+                attempts: 0,
+            })
+            .await?;
+
+        Ok((client_stamp, status))
+    }
+
+    /// Encrypts a message and sends it once, without the outbox. The server
+    /// only broadcasts it to the members listening right now and doesn't
+    /// store it, so it is lost for the others.
+    pub async fn send_ephemeral_message(
+        &self,
+        group_id: &GroupIdentifier,
+        content: crate::messages::Content,
+    ) -> Result<()> {
+        let (_, application_message, mut blinded_address) =
+            self.encrypt_application_message(group_id, content)?;
+
+        let resp = WEBSOCKET_MANAGER
+            .request_unauth(
+                self.get_server(),
+                UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
+                    blinded_address_proof: blinded_address.create_proof(application_message),
+                    ephemeral: true,
+                })),
+            )
+            .await?;
+
+        match resp {
+            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(_))) => Ok(()),
+            other => Err(RequestError::from_response(other).into()),
+        }
     }
 }
@@ -0,0 +1,506 @@
+//! Sending our history to a newly linked device (see [`super::device_link`]).
+//!
+//! A device that joins groups through MLS can't read anything that was sent
+//! before it joined. So once it is linked, the device that linked it sends it
+//! our contacts, the names of our groups, and the messages we have, in batches
+//! of [`HISTORY_SYNC_BATCH_SIZE`] messages. Batches are application messages of
+//! the self group, which only our own devices are members of.
+//!
+//! Each message comes as it was first received, with the edits, deletion,
+//! reactions and receipts it got since. They are applied on top of it the same
+//! way as when they are received from its group.
+//!
+//! The progress of every transfer is saved (see [`crate::database::history_sync`]),
+//! and batches go through the outbox, so an interrupted transfer resumes where it
+//! stopped the next time [`ProfileManager::sync_history`] is called.
+use std::sync::{atomic::Ordering, Arc};
+
+use anyhow::{bail, Context};
+use lib::{
+    api::{
+        group::DeliveryStamp,
+        proto::{
+            self, HistorySyncBatch, HistorySyncContact, HistorySyncGroup, HistorySyncMessage,
+            HistorySyncUpdate, ProstMessage,
+        },
+    },
+    crypto::usernames::Username,
+    identifiers::{AccountId, DeviceId, GroupIdentifier},
+};
+
+use crate::{
+    client::ClientProfile,
+    database::{history_sync::HistorySyncState, messages::DatabaseMessage, outbox::OutboxStatus},
+    messages::{Content, MessageReference},
+};
+
+use super::{
+    error::Result,
+    notifications::{Notification, NotificationSender},
+    ProfileManager,
+};
+
+/// How many messages are sent in each batch.
+pub const HISTORY_SYNC_BATCH_SIZE: usize = 100;
+
+impl ProfileManager {
+    /// Starts sending our history to one of our devices. See [`Self::sync_history`]
+    /// to actually send it.
+    pub fn start_history_sync(&self, device_id: &DeviceId) -> Result<()> {
+        Ok(self.sqlite_database.start_history_sync(device_id)?)
+    }
+
+    /// Sends the next batch of a history transfer. Returns the number of messages
+    /// transferred so far and in total, and whether the transfer is done.
+    async fn send_history_batch(&self, state: &HistorySyncState) -> Result<(u64, u64, bool)> {
+        let database = &self.sqlite_database;
+
+        let messages = database.get_messages_to_sync(
+            state.last_message_id,
+            state.up_to_message_id,
+            HISTORY_SYNC_BATCH_SIZE,
+        )?;
+        let last_message_id = messages
+            .last()
+            .map_or(state.up_to_message_id, |message| message.database_id);
+
+        // Contacts and groups are only sent with the first batch
+        let (contacts, groups) = if state.last_message_id == 0 {
+            (self.contacts_to_sync()?, self.groups_to_sync()?)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        let mut batch_messages = Vec::new();
+        for message in messages {
+            // Until they are sent, our messages have no server stamp. Our other
+            // devices receive them from their group once they are.
+            if matches!(message.outbox_status, None | Some(OutboxStatus::Sent)) {
+                batch_messages.push(self.message_to_sync(message)?);
+            }
+        }
+
+        let batch = HistorySyncBatch {
+            device_id: Some(state.device_id.into()),
+            contacts,
+            groups,
+            messages: batch_messages,
+            synced_messages: database.count_messages_up_to(last_message_id)?,
+            total_messages: database.count_messages_up_to(state.up_to_message_id)?,
+        };
+        let progress = (batch.synced_messages, batch.total_messages);
+
+        let (_, status) = self
+            .send_application_message(
+                &GroupIdentifier::self_id(),
+                Content::HistorySync(batch.encode_to_vec()),
+            )
+            .await?;
+
+        match status {
+            // A pending batch is sent again with the rest of the outbox
+            OutboxStatus::Sent | OutboxStatus::Pending | OutboxStatus::Sending => {}
+            OutboxStatus::Failed => bail!("The history batch was refused by the server"),
+        }
+
+        let done = last_message_id >= state.up_to_message_id;
+        if done {
+            database.delete_history_sync(&state.device_id)?;
+        } else {
+            database.set_history_sync_progress(&state.device_id, last_message_id)?;
+        }
+
+        if status == OutboxStatus::Pending {
+            bail!("The server couldn't be reached");
+        }
+
+        Ok((progress.0, progress.1, done))
+    }
+
+    /// Returns `message` as it was first received, with the contents that
+    /// changed it since.
+    fn message_to_sync(&self, message: DatabaseMessage) -> Result<HistorySyncMessage> {
+        let mut content = message.message;
+        let mut updates = Vec::new();
+
+        if let Some(client_stamp) = message.client_stamp {
+            let database = &self.sqlite_database;
+            let reference = MessageReference {
+                sender_account_id: message.account_id,
+                client_stamp,
+            };
+
+            if message.edited {
+                let original = database
+                    .get_edit_history(message.group_id, &reference)?
+                    .into_iter()
+                    .next();
+
+                if let (Content::BasicText { body } | Content::Reply { body, .. }, Some(original)) =
+                    (&mut content, original)
+                {
+                    *body = original;
+                }
+            }
+
+            // Nothing is left of a deleted message, its deletion makes it a tombstone again
+            if let Content::Delete { .. } = content {
+                content = Content::plain_text(String::new());
+            }
+
+            for (edit_stamp, body) in database.get_message_edits(message.group_id, &reference)? {
+                let edit = match body {
+                    Some(body) => Content::Edit {
+                        target: client_stamp,
+                        body,
+                    },
+                    None => Content::Delete {
+                        target: client_stamp,
+                    },
+                };
+
+                updates.push(HistorySyncUpdate {
+                    sender_account_id: Some(message.account_id.into()),
+                    content: Some(edit.into()),
+                    client_delivery_stamp: edit_stamp.to_vec(),
+                });
+            }
+
+            for (account_id, emoji) in
+                database.get_reactions_by_account(message.group_id, &reference)?
+            {
+                updates.push(HistorySyncUpdate {
+                    sender_account_id: Some(account_id.into()),
+                    content: Some(
+                        Content::Reaction {
+                            target: reference,
+                            emoji,
+                            remove: false,
+                        }
+                        .into(),
+                    ),
+                    client_delivery_stamp: Vec::new(),
+                });
+            }
+
+            for (account_id, kind) in database.get_receipts(message.group_id, &reference)? {
+                updates.push(HistorySyncUpdate {
+                    sender_account_id: Some(account_id.into()),
+                    content: Some(
+                        Content::Receipt {
+                            kind,
+                            messages: vec![reference],
+                        }
+                        .into(),
+                    ),
+                    client_delivery_stamp: Vec::new(),
+                });
+            }
+        }
+
+        Ok(HistorySyncMessage {
+            group_id: Some(message.group_id.into()),
+            sender_account_id: Some(message.account_id.into()),
+            server_delivery_stamp: message.server_delivery_stamp.to_vec(),
+            content: Some(content.into()),
+            client_delivery_stamp: message
+                .client_stamp
+                .map(DeliveryStamp::to_vec)
+                .unwrap_or_default(),
+            updates,
+        })
+    }
+
+    fn contacts_to_sync(&self) -> Result<Vec<HistorySyncContact>> {
+        Ok(self
+            .sqlite_database
+            .get_contacts()?
+            .into_iter()
+            .map(|contact| HistorySyncContact {
+                account_id: Some(contact.account_id.into()),
+                username: contact.username,
+                profile_name: contact.profile_name,
+                profile_description: contact.profile_description,
+            })
+            .collect())
+    }
+
+    fn groups_to_sync(&self) -> Result<Vec<HistorySyncGroup>> {
+        let mut groups = Vec::new();
+
+        for group_id in self.get_all_group_ids()? {
+            if let Ok((name, description, _, _)) = self.sqlite_database.get_group_info(group_id) {
+                groups.push(HistorySyncGroup {
+                    group_id: Some(group_id.into()),
+                    name,
+                    description,
+                });
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Sends our history to our new devices in the background, until every
+    /// transfer is done or one of them fails. The progress is sent as
+    /// notifications.
+    ///
+    /// Does nothing if the history of this profile is already being sent.
+    pub fn sync_history(
+        profile_manager: Arc<ProfileManager>,
+        notification_sender: Arc<NotificationSender>,
+    ) {
+        if profile_manager.syncing_history.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let states = profile_manager
+                .sqlite_database
+                .get_history_syncs()
+                .unwrap_or_default();
+
+            'transfers: for mut state in states {
+                loop {
+                    match profile_manager.send_history_batch(&state).await {
+                        Ok((synced, total, done)) => {
+                            notification_sender
+                                .send_notification(Notification::HistorySync(synced, total));
+
+                            if done {
+                                break;
+                            }
+                        }
+                        Err(err) => {
+                            log::warn!(
+                                "Couldn't send our history to device {:?}: {err:?}",
+                                state.device_id
+                            );
+                            break 'transfers;
+                        }
+                    }
+
+                    match profile_manager.sqlite_database.get_history_syncs() {
+                        Ok(states) => match states
+                            .into_iter()
+                            .find(|next| next.device_id == state.device_id)
+                        {
+                            Some(next) => state = next,
+                            None => break,
+                        },
+                        Err(err) => {
+                            log::warn!("Couldn't load the history transfers: {err:?}");
+                            break 'transfers;
+                        }
+                    }
+                }
+            }
+
+            profile_manager
+                .syncing_history
+                .store(false, Ordering::Release);
+        });
+    }
+
+    /// Applies a batch of history sent in the self group by `sender_account_id`.
+    /// Returns the number of messages transferred so far and in total, or `None`
+    /// if the batch wasn't meant for this device.
+    pub fn receive_history_batch(
+        &self,
+        sender_account_id: &AccountId,
+        batch: &[u8],
+    ) -> Result<Option<(u64, u64)>> {
+        if sender_account_id != &self.get_profile().get_account_id() {
+            bail!("Only our own devices can send us our history");
+        }
+
+        let batch = HistorySyncBatch::decode(batch).context("Failed to decode history batch")?;
+
+        let device_id: DeviceId = batch
+            .device_id
+            .context("History batch has no device")?
+            .try_into()?;
+        if device_id != self.get_profile().get_device_id() {
+            return Ok(None);
+        }
+
+        let database = &self.sqlite_database;
+
+        for contact in batch.contacts {
+            let Some(Ok(account_id)) = contact.account_id.map(AccountId::try_from) else {
+                log::warn!("Ignoring a synced contact without a valid account");
+                continue;
+            };
+
+            database.add_new_contact(
+                account_id,
+                contact
+                    .username
+                    .and_then(|username| Username::new(username).ok()),
+                contact.profile_name.as_deref(),
+                contact.profile_description.as_deref(),
+            )?;
+        }
+
+        for group in batch.groups {
+            let Some(Ok(group_id)) = group.group_id.map(GroupIdentifier::try_from) else {
+                log::warn!("Ignoring a synced group without a valid id");
+                continue;
+            };
+
+            // The group context is more recent than our other device's database
+            if self.has_group_metadata(&group_id).unwrap_or(true) {
+                continue;
+            }
+
+            database.set_group_metadata(group_id, &group.name, group.description.as_deref())?;
+        }
+
+        for mut message in batch.messages {
+            let updates = std::mem::take(&mut message.updates);
+
+            match Self::decode_synced_message(message) {
+                Ok((group_id, sender_account_id, client_stamp, delivery_stamp, content)) => {
+                    let added = database.add_synced_message(
+                        content,
+                        sender_account_id,
+                        client_stamp.as_ref(),
+                        &delivery_stamp,
+                        &group_id,
+                    )?;
+
+                    // If we received the message ourselves, we also received what
+                    // changed it since, which is more recent than this batch
+                    let Some(client_stamp) = client_stamp.filter(|_| added) else {
+                        continue;
+                    };
+                    let reference = MessageReference {
+                        sender_account_id,
+                        client_stamp,
+                    };
+
+                    for update in updates {
+                        if let Err(err) =
+                            self.apply_synced_update(&group_id, &reference, &delivery_stamp, update)
+                        {
+                            log::warn!("Ignoring an update of a synced message: {err:?}");
+                        }
+                    }
+                }
+                Err(err) => log::warn!("Ignoring a synced message: {err:?}"),
+            }
+        }
+
+        Ok(Some((batch.synced_messages, batch.total_messages)))
+    }
+
+    /// Applies an edit, deletion, reaction or receipt of the synced message that
+    /// `target` references. Updates of any other message are refused.
+    fn apply_synced_update(
+        &self,
+        group_id: &GroupIdentifier,
+        target: &MessageReference,
+        delivery_stamp: &DeliveryStamp,
+        update: HistorySyncUpdate,
+    ) -> Result<()> {
+        let sender_account_id: AccountId = update
+            .sender_account_id
+            .context("Synced update has no sender")?
+            .try_into()?;
+        let client_stamp = match update.client_delivery_stamp.as_slice() {
+            [] => None,
+            stamp => Some(
+                DeliveryStamp::try_from(stamp)
+                    .map_err(|()| anyhow::anyhow!("Synced update has an invalid client stamp"))?,
+            ),
+        };
+        let content: Content = update
+            .content
+            .map(proto::Content::try_into)
+            .context("Synced update has no content")??;
+
+        let updates_target = match &content {
+            Content::Edit { target: edited, .. } | Content::Delete { target: edited } => {
+                sender_account_id == target.sender_account_id
+                    && *edited == target.client_stamp
+                    && client_stamp.is_some()
+            }
+            Content::Reaction {
+                target: reacted,
+                remove,
+                ..
+            } => reacted == target && !remove,
+            Content::Receipt { messages, .. } => messages.as_slice() == [*target],
+            _ => false,
+        };
+        if !updates_target {
+            bail!("Synced update doesn't change its message");
+        }
+
+        // The delivery stamp is only used by edits without a client stamp,
+        // which were refused above
+        self.sqlite_database.add_message(
+            content,
+            sender_account_id,
+            client_stamp.as_ref(),
+            delivery_stamp,
+            group_id,
+        )?;
+
+        Ok(())
+    }
+
+    fn decode_synced_message(
+        message: HistorySyncMessage,
+    ) -> Result<(
+        GroupIdentifier,
+        AccountId,
+        Option<DeliveryStamp>,
+        DeliveryStamp,
+        Content,
+    )> {
+        let group_id = message
+            .group_id
+            .context("Synced message has no group")?
+            .try_into()?;
+        let sender_account_id = message
+            .sender_account_id
+            .context("Synced message has no sender")?
+            .try_into()?;
+        let client_stamp = match message.client_delivery_stamp.as_slice() {
+            [] => None,
+            stamp => Some(
+                DeliveryStamp::try_from(stamp)
+                    .map_err(|()| anyhow::anyhow!("Synced message has an invalid client stamp"))?,
+            ),
+        };
+        let delivery_stamp = DeliveryStamp::try_from(message.server_delivery_stamp.as_slice())
+            .map_err(|()| anyhow::anyhow!("Synced message has an invalid delivery stamp"))?;
+        let content: Content = message
+            .content
+            .map(proto::Content::try_into)
+            .context("Synced message has no content")??;
+
+        if let Content::HistorySync(_) | Content::AccountKey(_) = content {
+            bail!("History batches and account keys can't be synced");
+        }
+
+        Ok((
+            group_id,
+            sender_account_id,
+            client_stamp,
+            delivery_stamp,
+            content,
+        ))
+    }
+}
+
+impl ClientProfile<'_> {
+    /// Resumes sending our history to our new devices in the background.
+    pub fn sync_history(&self) {
+        ProfileManager::sync_history(
+            self.profile_manager.clone(),
+            self.client.notification_manager.clone(),
+        );
+    }
+}
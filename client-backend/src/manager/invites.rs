@@ -0,0 +1,242 @@
+//! The invite inbox: a blinded address queue, one per device, to which other
+//! accounts send the Welcome messages of the groups they add us to.
+//!
+//! The address of the inbox is derived from a random secret of the device, which
+//! it publishes in its key packages (see [`InviteInbox`]). So only the inviter,
+//! which added one of them to a group, can send it invites. The server can tell
+//! whose inbox it is, but not which group an invite is for: Welcome messages are
+//! encrypted to the key package they were made for, and they are sent without
+//! authenticating.
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
+use lib::{
+    api::{
+        group::{DeliveryStamp, SendMessageRequest},
+        messages::{ChatServiceMessage, Message, UnauthRequest},
+    },
+    crypto::blinded_address::BlindedAddressPublic,
+    identifiers::{AccountId, GroupIdentifier},
+};
+use mls_rs::{MlsMessage, WireFormat};
+use tokio::{sync::mpsc, time::sleep};
+
+use crate::{
+    client::ClientProfile,
+    database::{invites::InviteStatus, DatabaseError},
+    mls::extensions::InviteInbox,
+    net::RequestError,
+};
+
+use super::{
+    error::Result,
+    listener::{ListenerManager, ListenerMessage},
+    notifications::{Notification, NotificationSender},
+    ProfileManager, WEBSOCKET_MANAGER,
+};
+
+/// The longest we wait between two attempts to subscribe
+/// again to the invite inbox after the connection closed.
+const MAX_INVITE_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+impl ProfileManager {
+    /// Returns the invite inbox of this device.
+    pub fn invite_inbox(&self) -> Result<InviteInbox> {
+        Ok(InviteInbox {
+            secret: self.sqlite_database.get_invite_inbox_secret()?,
+        })
+    }
+
+    /// Sends a Welcome message to the invite inbox of the device
+    /// whose key package it was made for.
+    pub async fn send_invite(&self, inbox: &InviteInbox, welcome: &MlsMessage) -> Result<()> {
+        let resp = WEBSOCKET_MANAGER
+            .request_unauth(
+                self.get_server(),
+                UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
+                    blinded_address_proof: inbox
+                        .blinded_address()
+                        .create_proof(welcome.to_bytes()?),
+                    ephemeral: false,
+                })),
+            )
+            .await?;
+
+        match resp {
+            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(_))) => Ok(()),
+            other => Err(RequestError::from_response(other)).context("Invite did not deliver"),
+        }
+    }
+
+    /// Stores an invite delivered to our inbox. Returns `false` if we already had it.
+    fn receive_invite(&self, invite: &ListenerMessage) -> Result<bool> {
+        let (delivery_stamp, welcome) = invite;
+
+        if MlsMessage::from_bytes(welcome)?.wire_format() != WireFormat::Welcome {
+            bail!("Invite is not a Welcome message");
+        }
+
+        match self.sqlite_database.add_invite(delivery_stamp, welcome) {
+            Ok(()) => Ok(true),
+            Err(DatabaseError::AlreadyExists) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns the invites we didn't accept or decline yet, oldest first.
+    pub fn get_pending_invites(&self) -> Result<Vec<DeliveryStamp>> {
+        Ok(self.sqlite_database.get_pending_invites()?)
+    }
+
+    pub fn decline_invite(&self, delivery_stamp: &DeliveryStamp) -> Result<()> {
+        if !self
+            .sqlite_database
+            .set_invite_status(delivery_stamp, InviteStatus::Declined)?
+        {
+            bail!("This invite is not pending");
+        }
+
+        Ok(())
+    }
+
+    /// Keeps our invite inbox subscribed, subscribing again with
+    /// backoff every time the subscription ends.
+    async fn receive_invites(self: Arc<Self>, notification_sender: Arc<NotificationSender>) {
+        let inbox = match self.invite_inbox() {
+            Ok(inbox) => inbox.blinded_address().to_public(),
+            Err(err) => {
+                log::warn!("Couldn't read our invite inbox: {err:?}");
+                return;
+            }
+        };
+        let mut delay = Duration::from_secs(1);
+
+        loop {
+            match self.subscribe_to_invites(inbox, &notification_sender).await {
+                // We were connected, so try again right away
+                Ok(()) => delay = Duration::from_secs(1),
+                Err(err) => log::debug!("Couldn't subscribe to the invite inbox: {err:?}"),
+            }
+
+            sleep(delay).await;
+            delay = (delay * 2).min(MAX_INVITE_RESUBSCRIBE_DELAY);
+        }
+    }
+
+    /// Subscribes to our invite inbox and retrieves the invites we missed, then
+    /// notifies every new invite. Returns once the subscription ends.
+    async fn subscribe_to_invites(
+        &self,
+        inbox: BlindedAddressPublic,
+        notification_sender: &NotificationSender,
+    ) -> Result<()> {
+        let server = self.get_server();
+        let (live_tx, mut live_rx) = mpsc::channel::<ListenerMessage>(16);
+        let listener_id = WEBSOCKET_MANAGER
+            .start_listen(server, inbox, live_tx)
+            .await?;
+
+        let last_received = self
+            .sqlite_database
+            .get_last_invite_stamp()?
+            .unwrap_or(DeliveryStamp::EARLIEST);
+
+        // Invites don't depend on each other, so unlike group
+        // messages their order doesn't matter
+        let (queue_tx, mut queue_rx) = mpsc::channel::<ListenerMessage>(16);
+        let retrieval = WEBSOCKET_MANAGER.retrieve_queue(server, inbox, last_received, queue_tx);
+        tokio::pin!(retrieval);
+        let mut retrieved = false;
+
+        loop {
+            let invite = tokio::select! {
+                result = &mut retrieval, if !retrieved => {
+                    if let Err(err) = result {
+                        let _ = WEBSOCKET_MANAGER.stop_listen(server, listener_id).await;
+                        return Err(err);
+                    }
+
+                    retrieved = true;
+                    continue;
+                }
+                Some(invite) = queue_rx.recv() => invite,
+                invite = live_rx.recv() => match invite {
+                    Some(invite) => invite,
+                    // The connection closed
+                    None => return Ok(()),
+                },
+            };
+
+            match self.receive_invite(&invite) {
+                Ok(true) => {
+                    notification_sender.send_notification(Notification::Invite(invite.0));
+                }
+                Ok(false) => {}
+                Err(err) => log::warn!("Ignoring invite {:?}: {err:?}", invite.0),
+            }
+        }
+    }
+}
+
+impl ListenerManager {
+    /// Starts listening to the invite inbox of a profile, replacing
+    /// the previous listener if there was one.
+    pub async fn listen_to_invites(
+        &self,
+        profile_manager: Arc<ProfileManager>,
+        notification_sender: Arc<NotificationSender>,
+    ) {
+        let handle = tokio::spawn(profile_manager.clone().receive_invites(notification_sender));
+
+        if let Some((_, old_handle)) = self.invite_listeners.remove_async(&profile_manager).await {
+            old_handle.abort();
+        }
+
+        let _ = self
+            .invite_listeners
+            .insert_async(profile_manager, handle)
+            .await;
+    }
+}
+
+impl ClientProfile<'_> {
+    /// See [`ListenerManager::listen_to_invites`].
+    pub async fn listen_to_invites(&self) {
+        self.client
+            .listener_manager
+            .listen_to_invites(
+                self.profile_manager.clone(),
+                self.client.notification_manager.clone(),
+            )
+            .await;
+    }
+
+    /// Adds `account_id` to a group, and sends them the Welcome
+    /// message through their invite inbox.
+    pub async fn invite_member(
+        &self,
+        group_id: GroupIdentifier,
+        account_id: AccountId,
+    ) -> Result<()> {
+        let (welcome, inbox) = self.create_welcome_and_listen(group_id, account_id).await?;
+
+        self.send_invite(&inbox, &welcome).await
+    }
+
+    /// Joins the group of a pending invite.
+    pub async fn accept_invite(&self, delivery_stamp: &DeliveryStamp) -> Result<GroupIdentifier> {
+        let welcome = MlsMessage::from_bytes(
+            &self
+                .sqlite_database
+                .get_pending_invite(delivery_stamp)
+                .context("This invite is not pending")?,
+        )?;
+
+        let group_id = self.join_group_from_welcome_and_listen(&welcome).await?;
+
+        self.sqlite_database
+            .set_invite_status(delivery_stamp, InviteStatus::Accepted)?;
+
+        Ok(group_id)
+    }
+}
@@ -1,8 +1,10 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use lib::api::messages::AuthRequest;
+use lib::api::messages::{AuthRequest, Message};
 use mls_rs::mls_rs_codec::MlsEncode;
 
+use crate::net::RequestError;
+
 use super::{ProfileManager, WEBSOCKET_MANAGER};
 
 impl ProfileManager {
@@ -10,15 +12,20 @@ impl ProfileManager {
     pub async fn upload_new_key_packages(&self, quantity: usize) -> Result<()> {
         let mut key_packages = Vec::new();
         for _ in 0..quantity {
-            let message = self.mls_client.generate_key_package_message()?;
+            let message = self.mls_client().generate_key_package_message()?;
             key_packages.push(message.mls_encode_to_vec()?);
         }
-        WEBSOCKET_MANAGER
+        match WEBSOCKET_MANAGER
             .request_auth(
                 self.get_profile(),
                 AuthRequest::UploadKeyPackages(key_packages),
             )
-            .await?;
-        Ok(())
+            .await?
+        {
+            Message::Ok | Message::Auth(AuthRequest::KeyPackageAlreadyUploaded) => Ok(()),
+            other => {
+                Err(RequestError::from_response(other)).context("Uploading key packages failed")
+            }
+        }
     }
 }
@@ -1,22 +1,29 @@
 //! An mpsc channel that waits for incoming messages (that are
 //! being listened to/sent by a [`super::net::connection::Connection`])
-use std::{cmp::Reverse, collections::BinaryHeap, sync::Arc};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
 use anyhow::{anyhow, bail};
 use tokio::{
     sync::{mpsc, Mutex},
     task::JoinHandle,
+    time::sleep,
 };
 
 use lib::{
     api::{group::DeliveryStamp, messages::ListenerId},
-    crypto::blinded_address::BlindedAddressPublic,
+    crypto::blinded_address::{BlindedAddressPublic, BlindedAddressSecret},
     identifiers::GroupIdentifier,
 };
 
 use crate::{
+    database::messages::DatabaseMessage,
     manager::groups::ProcessedCommit,
-    messages::Content,
+    messages::{Content, MessageReference},
     ui::{GroupUi, MessageUi},
 };
 
@@ -29,6 +36,20 @@ use super::{
 /// What is sent by the server when listening to a [`BlindedAddress`].
 pub type ListenerMessage = (DeliveryStamp, Vec<u8>);
 
+/// The longest we wait between two attempts to subscribe again
+/// to an epoch after the connection closed.
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+/// What the [`Listener`]'s task receives.
+enum ListenerEvent {
+    /// A message delivered to the blinded address of an epoch, and whether it
+    /// comes from the queue of the epoch, which is in stamp order.
+    Message(u64, ListenerMessage, bool),
+    /// The subscription to the blinded address of an epoch ended without us
+    /// stopping it, which happens when the connection closes.
+    Disconnected(u64, ListenerId, BlindedAddressPublic),
+}
+
 /// The key type for the [`ListenerManager`]. Each Listener is associated
 /// to one group, per profile.
 type ListenerKey = (Arc<ProfileManager>, GroupIdentifier);
@@ -37,12 +58,16 @@ type ListenerKey = (Arc<ProfileManager>, GroupIdentifier);
 /// listened to for a certain profile. One listener per profile, per group.
 pub struct ListenerManager {
     pub listeners: scc::HashMap<ListenerKey, (Arc<Listener>, JoinHandle<()>)>,
+    /// The tasks listening to the invite inbox of each profile
+    /// (see [`super::invites`]).
+    pub invite_listeners: scc::HashMap<Arc<ProfileManager>, JoinHandle<()>>,
 }
 
 impl Default for ListenerManager {
     fn default() -> Self {
         Self {
             listeners: scc::HashMap::new(),
+            invite_listeners: scc::HashMap::new(),
         }
     }
 }
@@ -52,7 +77,7 @@ impl ListenerManager {
     /// This function starts a new listener and removes the old one if there was
     /// already a listener
     pub async fn listen(
-        &self,
+        self: &Arc<Self>,
         profile_manager: Arc<ProfileManager>,
         group_id: GroupIdentifier,
         notification_sender: Arc<NotificationSender>,
@@ -80,6 +105,7 @@ impl ListenerManager {
             epoch,
             blinded_address_secret.to_public(),
             notification_sender,
+            Arc::downgrade(self),
         )
         .await
         .map_err(|()| anyhow!("Starting listener didn't work (request to listen failed)"))?;
@@ -117,6 +143,54 @@ impl ListenerManager {
         }
     }
 
+    /// Listens to the new epoch of groups where we just committed (see for instance
+    /// [`ProfileManager::update_pending_identities_without_updating_listeners`]),
+    /// or starts listening to them if we weren't yet.
+    pub async fn listen_to_updated_groups(
+        self: &Arc<Self>,
+        profile_manager: &Arc<ProfileManager>,
+        updated_groups: Vec<(GroupIdentifier, u64, BlindedAddressSecret)>,
+        notification_sender: &Arc<NotificationSender>,
+    ) -> anyhow::Result<()> {
+        for (group_id, epoch, blinded_address) in updated_groups {
+            if self
+                .listen_new_epoch(
+                    profile_manager.clone(),
+                    group_id,
+                    epoch,
+                    blinded_address.to_public(),
+                )
+                .await
+                .is_err()
+            {
+                // We weren't listening to that group yet
+                self.listen(
+                    profile_manager.clone(),
+                    group_id,
+                    notification_sender.clone(),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// See [`ProfileManager::update_pending_identities_without_updating_listeners`].
+    /// This also listens to the new epoch of every updated group.
+    pub async fn update_pending_identities(
+        self: &Arc<Self>,
+        profile_manager: Arc<ProfileManager>,
+        notification_sender: Arc<NotificationSender>,
+    ) -> anyhow::Result<()> {
+        let updated_groups = profile_manager
+            .update_pending_identities_without_updating_listeners()
+            .await?;
+
+        self.listen_to_updated_groups(&profile_manager, updated_groups, &notification_sender)
+            .await
+    }
+
     /// Stops a listener for a group. Returns [`Ok`] if there was a listener to remove [`Err`] otherwise.
     pub async fn stop(
         &self,
@@ -209,16 +283,25 @@ impl<const N: usize> LastNEpochs<N> {
 }
 
 pub struct Listener {
-    sender: mpsc::Sender<ListenerMessage>,
+    /// Unbounded so that forwarding messages never blocks the connection,
+    /// even while the listener's task waits on a request itself.
+    sender: mpsc::UnboundedSender<ListenerEvent>,
     last_n_epochs: Mutex<LastNEpochs<50>>,
     /// Keeps track of the [`RequestId`]s listening to
     /// a given epoch
     listener_ids: scc::HashMap<u64, ListenerId>,
+    /// The epochs where a queued message couldn't be processed since their queue
+    /// was last retrieved. The next retrieval starts from that message, so the
+    /// stamp from which to retrieve the queue isn't moved past it.
+    stalled_queues: scc::HashSet<u64>,
     /// Contains the [`ProfileManager`] + [`GroupIdentifier`]
     /// the listener is dealing with. This is the same key
     /// used in the [`ListenerManager`] hash map.
     key: ListenerKey,
     notification_sender: Arc<NotificationSender>,
+    /// The manager this listener belongs to, to update the listeners of other
+    /// groups and to remove this one. It is weak since the manager owns the listener.
+    listener_manager: Weak<ListenerManager>,
 }
 
 impl Listener {
@@ -227,41 +310,45 @@ impl Listener {
         start_epoch: u64,
         blinded_address: BlindedAddressPublic,
         notification_sender: Arc<NotificationSender>,
+        listener_manager: Weak<ListenerManager>,
     ) -> Result<(Arc<Self>, JoinHandle<()>), ()> {
-        let (sender, mut rx) = mpsc::channel::<ListenerMessage>(16);
+        let (sender, mut rx) = mpsc::unbounded_channel::<ListenerEvent>();
 
         let listener: Arc<Self> = Arc::new(Self {
             sender,
             last_n_epochs: Mutex::const_new(LastNEpochs::<50>::new(start_epoch)),
             listener_ids: scc::HashMap::default(),
+            stalled_queues: scc::HashSet::default(),
             key,
             notification_sender,
+            listener_manager,
         });
 
         let listener_clone = listener.clone();
 
         let handle = tokio::spawn(async move {
-            while let Some(msg) = rx.recv().await {
-                match listener_clone.on_message_receive(&msg).await {
-                    Ok(Some((new_epoch, new_blinded_address))) => {
-                        listener_clone
-                            .listen_new_epoch(new_epoch, new_blinded_address)
-                            .await
-                            // If this crashes this is fine
-                            .expect("We are already listening so connection must be open");
+            while let Some(event) = rx.recv().await {
+                match event {
+                    ListenerEvent::Message(epoch, msg, queued) => {
+                        listener_clone.handle_message(epoch, &msg, queued).await;
                     }
-                    Ok(None) => {}
-                    Err(err) => {
-                        log::warn!("An error occured when trying to process message with stamp {:?}: {err}. Silently ignoring.", msg.0);
+                    ListenerEvent::Disconnected(epoch, listener_id, blinded_address) => {
+                        listener_clone
+                            .resubscribe(epoch, listener_id, blinded_address)
+                            .await;
                     }
                 }
             }
         });
 
         // And of course, start listener for current epoch
-        let listener_id = listener
+        let Ok(listener_id) = listener
             .listen_new_epoch(start_epoch, blinded_address)
-            .await?;
+            .await
+        else {
+            handle.abort();
+            return Err(());
+        };
 
         let _ = listener
             .listener_ids
@@ -271,7 +358,7 @@ impl Listener {
         Ok((listener, handle))
     }
 
-    pub async fn stop(&mut self) {
+    pub async fn stop(&self) {
         // Tell the server to stop listening to all our epochs... one by one
         // TODO: Maybe not necessary. Where will we call ::stop()? When the client
         // shuts down? In that case the whole connection will shut down too, although
@@ -289,26 +376,331 @@ impl Listener {
         &self,
         epoch: u64,
         blinded_address: BlindedAddressPublic,
+    ) -> Result<ListenerId, ()> {
+        let request_id = self.subscribe(epoch, blinded_address).await?;
+        self.remember_epoch(epoch).await;
+
+        Ok(request_id)
+    }
+
+    /// Adds `epoch` to the epochs we listen to, and stops listening to the
+    /// oldest one if there are too many of them.
+    async fn remember_epoch(&self, epoch: u64) {
+        if let Some(old_epoch_to_remove) = self.last_n_epochs.lock().await.push(epoch) {
+            if let Some((_, old_request_id)) =
+                self.listener_ids.remove_async(&old_epoch_to_remove).await
+            {
+                // The subscription ends with the connection anyway
+                if self.stop_listening(old_request_id).await.is_err() {
+                    log::debug!("Couldn't stop listening to epoch {old_epoch_to_remove}");
+                }
+            }
+        }
+    }
+
+    /// Subscribes to the blinded address of `epoch`, then retrieves the messages that
+    /// were queued on it since the last one we processed. Messages delivered live
+    /// during the retrieval are only processed after the queue (see [`Self::forward`]),
+    /// and the ones we already processed are skipped (see [`Self::handle_message`]),
+    /// so that nothing is missed or processed twice.
+    ///
+    /// Returns Err if we couldn't subscribe or retrieve the queue.
+    async fn subscribe(
+        &self,
+        epoch: u64,
+        blinded_address: BlindedAddressPublic,
     ) -> Result<ListenerId, ()> {
         let server = self.key.0.get_server();
-        let request_id = WEBSOCKET_MANAGER
-            .start_listen(server, blinded_address, self.sender.clone())
+        let (live_tx, live_rx) = mpsc::channel::<ListenerMessage>(16);
+        let listener_id = WEBSOCKET_MANAGER
+            .start_listen(server, blinded_address, live_tx)
             .await
             .map_err(|_| ())?;
 
-        if let Err((_, old)) = self.listener_ids.insert_async(epoch, request_id).await {
-            self.stop_listening(old).await?;
+        let old_listener_id = match self.listener_ids.entry_async(epoch).await {
+            scc::hash_map::Entry::Occupied(mut entry) => Some(entry.insert(listener_id)),
+            scc::hash_map::Entry::Vacant(entry) => {
+                entry.insert_entry(listener_id);
+                None
+            }
+        };
+
+        if let Some(old_listener_id) = old_listener_id {
+            self.stop_listening(old_listener_id).await?;
         }
 
-        if let Some(old_epoch_to_remove) = self.last_n_epochs.lock().await.push(epoch) {
-            if let Some((_, old_request_id)) =
-                self.listener_ids.remove_async(&old_epoch_to_remove).await
+        let (queue_tx, queue_rx) = mpsc::channel::<ListenerMessage>(16);
+        tokio::spawn(Self::forward(
+            epoch,
+            listener_id,
+            blinded_address,
+            queue_rx,
+            live_rx,
+            self.sender.clone(),
+        ));
+
+        let _ = self.stalled_queues.remove_async(&epoch).await;
+        let last_processed = self
+            .key
+            .0
+            .sqlite_database
+            .get_last_delivery_stamp(&self.key.1, epoch)
+            .ok()
+            .flatten()
+            .unwrap_or(DeliveryStamp::EARLIEST);
+
+        if let Err(err) = WEBSOCKET_MANAGER
+            .retrieve_queue(server, blinded_address, last_processed, queue_tx)
+            .await
+        {
+            log::warn!("Couldn't retrieve the queue of epoch {epoch}: {err:?}");
+
+            // Removed first so that the end of this subscription isn't
+            // mistaken for a disconnection
+            self.listener_ids
+                .remove_if_async(&epoch, |current_id| *current_id == listener_id)
+                .await;
+            let _ = self.stop_listening(listener_id).await;
+
+            return Err(());
+        }
+
+        Ok(listener_id)
+    }
+
+    /// Forwards the queued messages of an epoch to the listener's task, then the ones
+    /// delivered live. Live messages received while the queue is retrieved are held
+    /// back until the whole queue was forwarded, so that messages stay in order.
+    ///
+    /// Once the subscription ends, lets the listener's task know so that it can
+    /// subscribe again if we didn't stop it ourselves.
+    async fn forward(
+        epoch: u64,
+        listener_id: ListenerId,
+        blinded_address: BlindedAddressPublic,
+        mut queue_rx: mpsc::Receiver<ListenerMessage>,
+        mut live_rx: mpsc::Receiver<ListenerMessage>,
+        sender: mpsc::UnboundedSender<ListenerEvent>,
+    ) {
+        let mut held_back = Vec::new();
+
+        loop {
+            tokio::select! {
+                biased;
+                queued = queue_rx.recv() => match queued {
+                    Some(msg) => {
+                        let _ = sender.send(ListenerEvent::Message(epoch, msg, true));
+                    }
+                    // The queue was fully retrieved (or the retrieval failed)
+                    None => break,
+                },
+                Some(msg) = live_rx.recv() => held_back.push(msg),
+            }
+        }
+
+        for msg in held_back {
+            let _ = sender.send(ListenerEvent::Message(epoch, msg, false));
+        }
+
+        while let Some(msg) = live_rx.recv().await {
+            if sender
+                .send(ListenerEvent::Message(epoch, msg, false))
+                .is_err()
             {
-                self.stop_listening(old_request_id).await?;
+                // The listener stopped
+                return;
             }
         }
 
-        Ok(request_id)
+        let _ = sender.send(ListenerEvent::Disconnected(
+            epoch,
+            listener_id,
+            blinded_address,
+        ));
+    }
+
+    /// Subscribes again to an epoch whose subscription ended, until it works or we
+    /// stop listening to that epoch. Does nothing if we stopped that subscription
+    /// ourselves (it's not in `listener_ids` anymore).
+    async fn resubscribe(
+        &self,
+        epoch: u64,
+        listener_id: ListenerId,
+        blinded_address: BlindedAddressPublic,
+    ) {
+        if self
+            .listener_ids
+            .read_async(&epoch, |_, current_id| *current_id != listener_id)
+            .await
+            .unwrap_or(true)
+        {
+            return;
+        }
+
+        self.subscribe_with_backoff(epoch, blinded_address).await;
+    }
+
+    /// Subscribes to `epoch`, retrying with an exponential backoff until it works
+    /// or we stop listening to that epoch.
+    async fn subscribe_with_backoff(&self, epoch: u64, blinded_address: BlindedAddressPublic) {
+        let mut delay = Duration::from_secs(1);
+
+        while self
+            .last_n_epochs
+            .lock()
+            .await
+            .buff
+            .iter()
+            .any(|listened_epoch| listened_epoch.0 == epoch)
+        {
+            if self.subscribe(epoch, blinded_address).await.is_ok() {
+                // We're connected again, so the messages that we
+                // couldn't send may go through now
+                ProfileManager::retry_outbox(self.key.0.clone(), self.notification_sender.clone());
+                self.update_pending_identity().await;
+                return;
+            }
+
+            log::debug!("Couldn't subscribe to epoch {epoch}, retrying in {delay:?}");
+            sleep(delay).await;
+            delay = (delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+        }
+    }
+
+    /// Updates our signing identity in every group where it is pending, in the
+    /// background since this listener's group is one of them.
+    fn update_pending_identities(&self) {
+        let Some(listener_manager) = self.listener_manager.upgrade() else {
+            return;
+        };
+        let profile_manager = self.key.0.clone();
+        let notification_sender = self.notification_sender.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = listener_manager
+                .update_pending_identities(profile_manager, notification_sender)
+                .await
+            {
+                log::warn!("Couldn't update our signing identity in our groups: {err:?}");
+            }
+        });
+    }
+
+    /// Stops this listener and removes it from its manager, once we aren't a member
+    /// of the group anymore. This is done in the background, since the manager waits
+    /// for the task of this listener to end.
+    fn remove_from_manager(&self) {
+        let Some(listener_manager) = self.listener_manager.upgrade() else {
+            return;
+        };
+        let (profile_manager, group_id) = self.key.clone();
+
+        tokio::spawn(async move {
+            // It may already have been stopped, e.g. if we left the group
+            let _ = listener_manager.stop(profile_manager, group_id).await;
+        });
+    }
+
+    /// Commits the update of our signing identity in this group if it is still
+    /// pending (see [`ProfileManager::update_pending_identity`]), and listens to
+    /// the new epoch. If it fails, it is tried again after the next reconnection.
+    async fn update_pending_identity(&self) {
+        match self.key.0.update_pending_identity(&self.key.1).await {
+            Ok(Some((epoch, blinded_address))) => {
+                if self
+                    .listen_new_epoch(epoch, blinded_address.to_public())
+                    .await
+                    .is_err()
+                {
+                    log::warn!("Couldn't listen to epoch {epoch} after updating our identity");
+                }
+            }
+            Ok(None) => {}
+            Err(err) => {
+                log::warn!("Couldn't update our signing identity in this group: {err:?}");
+            }
+        }
+    }
+
+    /// Processes a message unless we already did (see [`crate::database::sync`]).
+    ///
+    /// Messages queued while we were subscribing are also received live, and the
+    /// queue is retrieved again after every reconnection, so the same message can
+    /// be received many times. It is only remembered as processed once it was, and
+    /// the ones that couldn't be are retrieved again after the next reconnection.
+    async fn handle_message(&self, epoch: u64, msg: &ListenerMessage, queued: bool) {
+        let database = &self.key.0.sqlite_database;
+        let group_id = self.key.1;
+
+        let processed = match database.is_message_processed(&group_id, epoch, &msg.0) {
+            Ok(processed) => processed,
+            Err(err) => {
+                log::error!(
+                    "Couldn't check if message {:?} was processed: {err:?}",
+                    msg.0
+                );
+                self.retry_later(epoch, &msg.0).await;
+                return;
+            }
+        };
+
+        if !processed {
+            let new_epoch = match self.on_message_receive(msg).await {
+                Ok(new_epoch) => new_epoch,
+                Err(err) => {
+                    log::warn!(
+                        "Couldn't process message with stamp {:?}, retrying after the next reconnection: {err}",
+                        msg.0
+                    );
+                    self.retry_later(epoch, &msg.0).await;
+                    return;
+                }
+            };
+
+            if let Err(err) = database.set_message_processed(&group_id, epoch, &msg.0) {
+                log::error!(
+                    "Couldn't remember that message {:?} was processed: {err:?}",
+                    msg.0
+                );
+            }
+
+            if let Some((new_epoch, new_blinded_address)) = new_epoch {
+                if self
+                    .listen_new_epoch(new_epoch, new_blinded_address)
+                    .await
+                    .is_err()
+                {
+                    log::warn!("Couldn't listen to epoch {new_epoch}, retrying");
+
+                    // Remembered even though we aren't subscribed yet, so that we
+                    // stop retrying once the epoch is too old to be listened to
+                    self.remember_epoch(new_epoch).await;
+                    self.subscribe_with_backoff(new_epoch, new_blinded_address)
+                        .await;
+                }
+            }
+        }
+
+        // The queue is in stamp order, so every message before this one was processed
+        if queued && !self.stalled_queues.contains_async(&epoch).await {
+            if let Err(err) = database.set_last_delivery_stamp(&group_id, epoch, &msg.0) {
+                log::error!("Couldn't save the stamp of the last processed message: {err:?}");
+            }
+        }
+    }
+
+    /// Makes the next retrieval of the queue of `epoch` start from the message with
+    /// `delivery_stamp`, which we couldn't process.
+    async fn retry_later(&self, epoch: u64, delivery_stamp: &DeliveryStamp) {
+        let _ = self.stalled_queues.insert_async(epoch).await;
+
+        if let Err(err) = self.key.0.sqlite_database.rewind_last_delivery_stamp(
+            &self.key.1,
+            epoch,
+            delivery_stamp,
+        ) {
+            log::error!("Couldn't save the stamp of a message to retry: {err:?}");
+        }
     }
 
     /// Returns Err if the connection failed, or if the epoch wasn't being listened to.
@@ -324,7 +716,6 @@ impl Listener {
     ///    listen to the new address
     /// 3) If our epoch counter tells us to remove old epochs, then
     ///    we also do that
-    #[allow(clippy::unused_async)] // async is used in cfg(test)
     async fn on_message_receive(
         &self,
         new_message: &ListenerMessage,
@@ -342,45 +733,216 @@ impl Listener {
         {
             #[allow(unused_variables)]
             Ok(ProcessedMessage::ApplicationMessage(message)) => {
+                // Receipts are sent automatically for every message, so they
+                // aren't logged: tests only see the messages they send
                 #[cfg(test)]
-                {
+                if !matches!(message.content, Content::Receipt { .. }) {
                     let mut message_log = profile_manager.message_log.lock().await;
 
                     message_log.push((group_id, message.clone()));
                 }
 
-                let Content::BasicText { body } = message.content.clone();
+                let database = &profile_manager.sqlite_database;
+                let reference = MessageReference {
+                    sender_account_id: message.sender_account_id,
+                    client_stamp: message.client_stamp,
+                };
+
+                let body = match &message.content {
+                    Content::BasicText { body } | Content::Reply { body, .. } => body.clone(),
+                    Content::HistorySync(batch) => {
+                        if group_id != GroupIdentifier::self_id() {
+                            log::warn!("Ignoring history batch sent outside of the self group");
+                        } else if let Some((synced, total)) = profile_manager
+                            .receive_history_batch(&message.sender_account_id, batch)?
+                        {
+                            self.notification_sender
+                                .send_notification(Notification::HistorySync(synced, total));
+                        }
+
+                        return Ok(None);
+                    }
+                    Content::AccountKey(share) => {
+                        if group_id != GroupIdentifier::self_id() {
+                            log::warn!("Ignoring account key sent outside of the self group");
+                        } else if profile_manager
+                            .receive_account_key(&message.sender_account_id, share)
+                            .await?
+                        {
+                            self.update_pending_identities();
+                        }
+
+                        return Ok(None);
+                    }
+                    // The sender was checked against their credential, and they
+                    // can only edit or delete their own messages
+                    Content::Edit { target, body } => {
+                        let notification = Notification::MessageEdited(
+                            group_id,
+                            MessageReference {
+                                client_stamp: *target,
+                                ..reference
+                            },
+                            body.clone().into(),
+                        );
+                        database.add_message(
+                            message.content,
+                            message.sender_account_id,
+                            Some(&message.client_stamp),
+                            delivery_stamp,
+                            &group_id,
+                        )?;
+                        self.notification_sender.send_notification(notification);
+
+                        return Ok(None);
+                    }
+                    Content::Delete { target } => {
+                        let notification = Notification::MessageDeleted(
+                            group_id,
+                            MessageReference {
+                                client_stamp: *target,
+                                ..reference
+                            },
+                        );
+                        database.add_message(
+                            message.content,
+                            message.sender_account_id,
+                            Some(&message.client_stamp),
+                            delivery_stamp,
+                            &group_id,
+                        )?;
+                        self.notification_sender.send_notification(notification);
+
+                        return Ok(None);
+                    }
+                    // Reactions update the message they react to, instead of
+                    // being new messages
+                    Content::Reaction { target, .. } => {
+                        let target = *target;
+                        database.add_message(
+                            message.content,
+                            message.sender_account_id,
+                            Some(&message.client_stamp),
+                            delivery_stamp,
+                            &group_id,
+                        )?;
+                        self.notification_sender
+                            .send_notification(Notification::Reactions(
+                                group_id,
+                                target,
+                                profile_manager.get_reactions(group_id, &target)?,
+                            ));
+
+                        return Ok(None);
+                    }
+                    // Only the receipts of our own messages are kept
+                    Content::Receipt { kind, messages } => {
+                        let account_id = profile_manager.get_profile().get_account_id();
+                        let messages: Vec<MessageReference> = messages
+                            .iter()
+                            .filter(|target| target.sender_account_id == account_id)
+                            .copied()
+                            .collect();
+
+                        database.add_message(
+                            Content::Receipt {
+                                kind: *kind,
+                                messages: messages.clone(),
+                            },
+                            message.sender_account_id,
+                            Some(&message.client_stamp),
+                            delivery_stamp,
+                            &group_id,
+                        )?;
+                        for target in messages {
+                            self.notification_sender
+                                .send_notification(Notification::Receipts(
+                                    group_id,
+                                    target,
+                                    profile_manager.get_receipts(group_id, &target)?,
+                                ));
+                        }
+
+                        return Ok(None);
+                    }
+                    // Our other devices also receive what we type
+                    Content::Typing(typing) => {
+                        if message.sender_account_id
+                            != profile_manager.get_profile().get_account_id()
+                        {
+                            ProfileManager::receive_typing_indicator(
+                                profile_manager,
+                                self.notification_sender.clone(),
+                                group_id,
+                                message.sender_account_id,
+                                *typing,
+                            );
+                        }
+
+                        return Ok(None);
+                    }
+                };
                 // TODO: Have some kind of ContactManager in the future
                 // Where we'll be able to retrieve the profile name from the given AccountId
-                let message_ui = MessageUi::plain_text(
+                let group_name = database
+                    .get_group_info(group_id)
+                    .map_or_else(|_| "Untitled Group".to_string(), |info| info.0);
+                let mut message_ui = MessageUi::plain_text(
                     "Unknown Contact".to_string(),
                     message.sender_account_id,
                     body,
-                );
+                )
+                .with_reference(reference)
+                // Reactions may have arrived before the message
+                .with_reactions(profile_manager.get_reactions(group_id, &reference)?);
+                if let Content::Reply { parent, .. } = &message.content {
+                    message_ui =
+                        message_ui.with_reply_to(profile_manager.get_quote(group_id, parent)?);
+                }
+
+                database.add_message(
+                    message.content,
+                    message.sender_account_id,
+                    Some(&message.client_stamp),
+                    delivery_stamp,
+                    &group_id,
+                )?;
+                ProfileManager::queue_delivery_receipt(profile_manager, group_id, reference);
+
+                // Edits and deletions may have arrived before the message
+                match database.get_message_by_reference(group_id, &reference)? {
+                    Some(DatabaseMessage {
+                        message: Content::Delete { .. },
+                        ..
+                    }) => return Ok(None),
+                    Some(DatabaseMessage {
+                        message: Content::BasicText { body } | Content::Reply { body, .. },
+                        edited: true,
+                        ..
+                    }) => message_ui.edit(body.into()),
+                    _ => {}
+                }
 
                 self.notification_sender
                     .send_notification(Notification::Message(
                         GroupUi {
                             group_identifier: group_id,
-                            group_name: "idk".to_string().into(),
+                            group_name: group_name.into(),
                             last_message: Some(message_ui.clone()),
                         },
                         message_ui,
                     ));
-
-                profile_manager.sqlite_database.add_message(
-                    message.content,
-                    message.sender_account_id,
-                    delivery_stamp,
-                    &group_id,
-                )?;
             }
             Ok(ProcessedMessage::Commit(new_epoch, new_blinded_address, commits)) => {
                 log::debug!("New epoch. Got blinded address {new_blinded_address:?}");
 
                 for commit in commits {
                     match commit {
-                        ProcessedCommit::Unknown => todo!(),
+                        ProcessedCommit::RemovedMember(account_id) => {
+                            log::debug!("{account_id:?} was removed from group {group_id}");
+                        }
+                        // Nothing to do locally for the other proposals
+                        ProcessedCommit::Unknown => {}
                         ProcessedCommit::AddedMember(account_id) => {
                             // add this new contact to our db
                             profile_manager
@@ -389,12 +951,38 @@ impl Listener {
                         }
                     }
                 }
+                // The name or description may have changed with this commit
+                let group_ui = profile_manager.refresh_group_info(&group_id)?;
+                self.notification_sender
+                    .send_notification(Notification::GroupUpdated(group_ui));
+
+                // The invite link (if we created one) has to point to the new epoch
+                if let Err(err) = profile_manager.refresh_group_link(&group_id).await {
+                    log::warn!("Couldn't update the invite link of group {group_id}: {err:?}");
+                }
+
                 // New epoch/blinded address. Add new listener, get rid of old one if needed
                 return Ok(Some((new_epoch, new_blinded_address)));
             }
+            Ok(ProcessedMessage::Proposal(should_commit)) => {
+                if should_commit {
+                    let (new_epoch, new_blinded_address) = profile_manager
+                        .commit_pending_proposals_without_updating_listener(&group_id)
+                        .await?;
+
+                    return Ok(Some((new_epoch, new_blinded_address.to_public())));
+                }
+            }
+            Ok(ProcessedMessage::RemovedFromGroup) => {
+                log::info!("We were removed from group {group_id}");
+
+                // Nothing else will be sent to us in this group
+                self.remove_from_manager();
+                profile_manager.delete_group_locally(&group_id)?;
+            }
             Ok(ProcessedMessage::Ignore) => {}
             Err(e) => {
-                panic!("{e:?}");
+                return Err(e);
             }
         }
 
@@ -0,0 +1,63 @@
+//! Looking up the messages of our groups, to display how they relate
+//! to each other.
+use lib::identifiers::GroupIdentifier;
+
+use crate::{
+    messages::{Content, MessageReference},
+    ui::{QuoteUi, ReactionUi},
+};
+
+use super::{error::Result, ProfileManager};
+
+impl ProfileManager {
+    /// Returns the quote of `parent` shown above a reply to it. The preview is
+    /// empty if the parent hasn't arrived yet: load the quote again once it has,
+    /// or once a [`Notification::MessageEdited`] is received for it.
+    ///
+    /// [`Notification::MessageEdited`]: super::notifications::Notification::MessageEdited
+    pub fn get_quote(
+        &self,
+        group_id: GroupIdentifier,
+        parent: &MessageReference,
+    ) -> Result<QuoteUi> {
+        let message = self
+            .sqlite_database
+            .get_message_by_reference(group_id, parent)?;
+        let preview = message.as_ref().and_then(|message| match &message.message {
+            Content::BasicText { body } | Content::Reply { body, .. } => Some(body.clone().into()),
+            Content::HistorySync(_)
+            | Content::Edit { .. }
+            | Content::Delete { .. }
+            | Content::Reaction { .. }
+            | Content::Receipt { .. }
+            | Content::Typing(_)
+            | Content::AccountKey(_) => None,
+        });
+
+        Ok(QuoteUi {
+            parent: *parent,
+            preview,
+            // Deleted messages are stored as the deletion that removed them
+            deleted: message
+                .is_some_and(|message| matches!(message.message, Content::Delete { .. })),
+        })
+    }
+
+    /// Returns the reactions to `target`, the most common first.
+    pub fn get_reactions(
+        &self,
+        group_id: GroupIdentifier,
+        target: &MessageReference,
+    ) -> Result<Vec<ReactionUi>> {
+        Ok(self
+            .sqlite_database
+            .get_reactions(group_id, target, self.get_profile().get_account_id())?
+            .into_iter()
+            .map(|reaction| ReactionUi {
+                emoji: reaction.emoji.into(),
+                count: reaction.count,
+                ours: reaction.includes_account,
+            })
+            .collect())
+    }
+}
@@ -1,46 +1,71 @@
 pub mod account;
+pub mod device_link;
 pub mod error;
 pub mod groups;
+pub mod history_sync;
+pub mod invites;
 pub mod key_package;
 pub mod listener;
+pub mod messages;
 pub mod notifications;
+pub mod outbox;
+pub mod receipts;
 pub mod servers;
+pub mod typing;
 
 use std::{
     path::PathBuf,
-    sync::{Arc, LazyLock},
+    sync::{atomic::AtomicBool, Arc, LazyLock, Mutex, RwLock},
 };
 
 use crate::{
-    database::Database, manager::servers::ServerParser, mls::credentials::LicksIdentityProvider,
+    database::Database,
+    manager::servers::ServerParser,
+    mls::{
+        credentials::LicksIdentityProvider,
+        extensions::{InviteInbox, LICKS_EXTENSION_TYPES},
+        rules::LicksMlsRules,
+    },
     net::websocket::WebsocketManager,
 };
 
-use self::{account::Profile, groups::GroupManager};
-use super::account::register::create_account;
-use anyhow::Result;
+use self::{
+    account::Profile,
+    groups::GroupManager,
+    receipts::PendingReceipts,
+    typing::{TypingMembers, TypingSent},
+};
+use super::account::{link::link_device, register::create_account};
+use anyhow::{bail, Result};
 use lib::{
     api::server::Server,
     constants::LOCALHOST_DOMAIN,
     crypto::{
+        device_link::DeviceLinkCode,
         rng::random_bytes,
         usernames::{Username, UsernameHash},
     },
     util::base64::Base64String,
 };
 use mls_rs::{
-    client_builder::{BaseSqlConfig, WithCryptoProvider, WithIdentityProvider},
+    client_builder::{BaseSqlConfig, WithCryptoProvider, WithIdentityProvider, WithMlsRules},
     CipherSuite,
 };
 use mls_rs_crypto_rustcrypto::RustCryptoProvider;
-use mls_rs_provider_sqlite::SqLiteDataStorageEngine;
+use mls_rs_provider_sqlite::{
+    storage::{SqLiteGroupStateStorage, SqLiteKeyPackageStorage, SqLitePreSharedKeyStorage},
+    SqLiteDataStorageEngine,
+};
 use std::fmt::Debug;
 
 pub static WEBSOCKET_MANAGER: LazyLock<WebsocketManager> = LazyLock::new(WebsocketManager::new);
 
-pub type MlsClientConfig = WithIdentityProvider<
-    LicksIdentityProvider,
-    WithCryptoProvider<RustCryptoProvider, BaseSqlConfig>,
+pub type MlsClientConfig = WithMlsRules<
+    LicksMlsRules,
+    WithIdentityProvider<
+        LicksIdentityProvider,
+        WithCryptoProvider<RustCryptoProvider, BaseSqlConfig>,
+    >,
 >;
 type MlsClient = mls_rs::Client<MlsClientConfig>;
 
@@ -49,11 +74,14 @@ pub struct ProfileManager {
     /// The `ClientManager` only deals with one account at a time.
     /// Support for multiple accounts needs to be done at a higher level
     /// (by managing multiple `ClientManager` instances)
-    pub profile: Arc<Profile>,
+    ///
+    /// It is replaced when one of our keys changes, see [`ProfileManager::replace_profile`].
+    profile: RwLock<Arc<Profile>>,
 
-    pub username: (Username, UsernameHash),
+    /// The server of our account, which stays the same when the profile is replaced.
+    server: Server,
 
-    pub mls_client: Arc<MlsClient>,
+    pub username: (Username, UsernameHash),
 
     /// Group manager for storing and loading MLS groups into the db
     pub group_manager: GroupManager,
@@ -69,17 +97,49 @@ pub struct ProfileManager {
     >,
 
     pub sqlite_database: Database,
+
+    /// Makes the server unreachable when sending the outbox, to test sending it again.
+    #[cfg(test)]
+    pub offline: AtomicBool,
+
+    /// Set while the outbox is being sent again in the background,
+    /// see [`ProfileManager::retry_outbox`].
+    retrying_outbox: AtomicBool,
+
+    /// Held while our signing identity is updated in a group, so that the same
+    /// update isn't committed twice, see [`ProfileManager::update_pending_identity`].
+    identity_update_lock: tokio::sync::Mutex<()>,
+
+    /// Set while our history is being sent to our new devices,
+    /// see [`ProfileManager::sync_history`].
+    syncing_history: AtomicBool,
+
+    /// Delivery receipts waiting to be sent together,
+    /// see [`ProfileManager::queue_delivery_receipt`].
+    pending_receipts: Mutex<PendingReceipts>,
+
+    /// Members typing in our groups, see [`ProfileManager::receive_typing_indicator`].
+    typing_members: Mutex<TypingMembers>,
+
+    /// When we last sent a typing indicator to each group we are typing in,
+    /// see [`ProfileManager::set_typing`].
+    typing_sent: Mutex<TypingSent>,
 }
 
+// The profile changes when our keys are rotated, but our account and device stay the same
 impl std::hash::Hash for ProfileManager {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.profile.hash(state);
+        let profile = self.get_profile();
+        profile.get_account_id().hash(state);
+        profile.get_device_id().hash(state);
     }
 }
 
 impl std::cmp::PartialEq for ProfileManager {
     fn eq(&self, other: &Self) -> bool {
-        self.profile == other.profile
+        let (profile, other_profile) = (self.get_profile(), other.get_profile());
+        profile.get_account_id() == other_profile.get_account_id()
+            && profile.get_device_id() == other_profile.get_device_id()
     }
 }
 impl std::cmp::Eq for ProfileManager {}
@@ -99,15 +159,16 @@ impl ProfileManager {
 
         let sqlite_database = Database::new(root_data_folder)?;
 
-        let random_username = Username::new(
-            Base64String::from_bytes(random_bytes::<6>())
-                .inner_str()
-                .to_string(),
-        )
-        .expect("Unpadded base64 always produces a valid username");
+        let random_username = Self::random_username();
         let random_username_hash = random_username.hash();
 
-        let profile = if let Ok(db_profile) = sqlite_database.get_profile() {
+        let profile = if let Ok(mut db_profile) = sqlite_database.get_profile() {
+            if db_profile.renew_expiring_certs() {
+                log::info!("Initializing: Renewed our certificates");
+                sqlite_database.update_profile(&db_profile)?;
+                sqlite_database.set_chain_registration_pending(true)?;
+            }
+
             db_profile
         } else {
             // We don't have a profile, so we automatically register one
@@ -128,51 +189,179 @@ impl ProfileManager {
             profile
         };
 
+        let profile_manager = Self::with_profile(sqlite_database, profile, random_username)?;
+
+        if let Err(err) = profile_manager.register_renewed_chain().await {
+            log::warn!("Initializing: Couldn't register our renewed certificates: {err:?}");
+        }
+
+        if let Err(err) = profile_manager.refresh_group_links().await {
+            log::warn!("Initializing: Couldn't refresh our invite links: {err:?}");
+        }
+
+        Ok(profile_manager)
+    }
+
+    /// Like [`Self::initialize`], but instead of creating a new account, waits for
+    /// an existing device of the account to link us with `code` (see
+    /// [`crate::account::link::link_device`]).
+    ///
+    /// Returns the manager along with the number of key packages the existing
+    /// device expects, which should then be sent with
+    /// [`ClientProfile::finish_device_link`](crate::client::ClientProfile::finish_device_link).
+    pub async fn initialize_from_link(
+        root_data_folder: Option<PathBuf>,
+        code: &DeviceLinkCode,
+    ) -> Result<(Arc<Self>, u32)> {
+        log::info!("Linking: Loading database...");
+
+        let sqlite_database = Database::new(root_data_folder)?;
+
+        if sqlite_database.get_profile().is_ok() {
+            bail!("This device already has a profile");
+        }
+
+        // TODO: Change that when we can finally start having custom server URLs
+        let server_domain = LOCALHOST_DOMAIN.to_string();
+        let our_server: Box<Server> = Server::parse(server_domain)?.into();
+
+        let (profile, key_package_count) = link_device(&our_server, code).await?;
+
+        sqlite_database.set_profile(&profile)?;
+        sqlite_database.add_new_contact(profile.get_account_id(), None, None, None)?;
+
+        let profile_manager =
+            Self::with_profile(sqlite_database, profile, Self::random_username())?;
+
+        Ok((profile_manager, key_package_count))
+    }
+
+    fn random_username() -> Username {
+        Username::new(
+            Base64String::from_bytes(random_bytes::<6>())
+                .inner_str()
+                .to_string(),
+        )
+        .expect("Unpadded base64 always produces a valid username")
+    }
+
+    fn with_profile(
+        sqlite_database: Database,
+        profile: Profile,
+        username: Username,
+    ) -> Result<Arc<Self>> {
+        // Messages we were sending when we stopped are sent again with the outbox
+        sqlite_database.release_outbox_claims()?;
+
         let mls_client = Arc::new(Self::build_mlsrs_client(sqlite_database.clone(), &profile)?);
 
-        let group_manager = GroupManager::init(mls_client.clone())?;
+        let group_manager = GroupManager::init(mls_client)?;
 
+        let username_hash = username.hash();
         let client_manager = Arc::new(Self {
-            profile: Arc::new(profile),
-            username: (random_username, random_username_hash),
-            mls_client,
+            server: profile.get_server().clone(),
+            profile: RwLock::new(Arc::new(profile)),
+            username: (username, username_hash),
             group_manager,
             #[cfg(test)]
             message_log: tokio::sync::Mutex::new(Vec::new()),
             sqlite_database,
+            #[cfg(test)]
+            offline: AtomicBool::new(false),
+            retrying_outbox: AtomicBool::new(false),
+            identity_update_lock: tokio::sync::Mutex::new(()),
+            syncing_history: AtomicBool::new(false),
+            pending_receipts: Mutex::new(PendingReceipts::new()),
+            typing_members: Mutex::new(TypingMembers::new()),
+            typing_sent: Mutex::new(TypingSent::new()),
         });
 
         Ok(client_manager)
     }
 
     pub fn build_mlsrs_client(sqlite_database: Database, profile: &Profile) -> Result<MlsClient> {
-        let (secret_key, signing_identity) = profile.to_mls_signer();
+        let invite_inbox = InviteInbox {
+            secret: sqlite_database.get_invite_inbox_secret()?,
+        };
         let sqlite_engine = SqLiteDataStorageEngine::new(sqlite_database)?;
-        Ok(mls_rs::Client::builder()
+        Ok(Self::build_mlsrs_client_with_storage(
+            profile,
+            invite_inbox,
+            sqlite_engine.pre_shared_key_storage()?,
+            sqlite_engine.key_package_storage()?,
+            sqlite_engine
+                .group_state_storage()?
+                .with_max_epoch_retention(50),
+        ))
+    }
+
+    /// Builds an MLS client that signs with `profile` and publishes `invite_inbox`
+    /// in its key packages, using the storage of an existing client. The storage
+    /// is reused instead of being opened again, since an in-memory database would
+    /// otherwise start empty.
+    fn build_mlsrs_client_with_storage(
+        profile: &Profile,
+        invite_inbox: InviteInbox,
+        psk_store: SqLitePreSharedKeyStorage,
+        key_package_repo: SqLiteKeyPackageStorage,
+        group_state_storage: SqLiteGroupStateStorage,
+    ) -> MlsClient {
+        let (secret_key, signing_identity) = profile.to_mls_signer();
+        mls_rs::Client::builder()
             .crypto_provider(RustCryptoProvider::default())
-            .psk_store(sqlite_engine.pre_shared_key_storage()?)
-            .key_package_repo(sqlite_engine.key_package_storage()?)
-            .group_state_storage(
-                sqlite_engine
-                    .group_state_storage()?
-                    .with_max_epoch_retention(50),
-            )
+            .psk_store(psk_store)
+            .key_package_repo(key_package_repo)
+            .group_state_storage(group_state_storage)
             .signing_identity(
                 signing_identity,
                 secret_key.into(),
                 CipherSuite::CURVE25519_AES128,
             )
             .identity_provider(LicksIdentityProvider)
-            .build())
+            .mls_rules(LicksMlsRules)
+            .extension_types(LICKS_EXTENSION_TYPES)
+            .key_package_extensions(invite_inbox.key_package_extensions())
+            .build()
     }
 
     /// Clones and returns the manager's registered `Profile`.
     pub fn get_profile(&self) -> Arc<Profile> {
-        self.profile.clone()
+        self.profile
+            .read()
+            .expect("RwLock poisoning is safe")
+            .clone()
+    }
+
+    /// Saves `profile` (e.g. after rotating one of our keys) and uses it from now
+    /// on. Our groups keep signing with the previous key until our leaf is updated
+    /// in each of them, so they are all marked as pending (see
+    /// [`ProfileManager::update_pending_identity`]).
+    pub fn replace_profile(&self, profile: Profile) -> Result<()> {
+        let previous_client = self.mls_client();
+        let mls_client = Self::build_mlsrs_client_with_storage(
+            &profile,
+            self.invite_inbox()?,
+            previous_client.secret_store(),
+            previous_client.key_package_store(),
+            previous_client.group_state_storage(),
+        );
+
+        self.sqlite_database
+            .replace_profile(&profile, &self.get_all_group_ids()?)?;
+
+        self.group_manager.set_mls_client(Arc::new(mls_client));
+        *self.profile.write().expect("RwLock poisoning is safe") = Arc::new(profile);
+
+        Ok(())
+    }
+
+    /// The MLS client, see [`GroupManager::mls_client`].
+    pub fn mls_client(&self) -> Arc<MlsClient> {
+        self.group_manager.mls_client()
     }
 
     pub fn get_server(&self) -> &Server {
-        self.profile.get_server()
+        &self.server
     }
 
     pub fn get_username_string(&self) -> &Username {
@@ -1,7 +1,18 @@
 // Dioxus uses that, not tokio's ...
 pub use futures_channel::mpsc::{channel, UnboundedReceiver, UnboundedSender};
 
-use crate::ui::{GroupUi, MessageUi};
+use std::sync::Arc;
+
+use lib::{
+    api::group::DeliveryStamp,
+    identifiers::{AccountId, GroupIdentifier},
+};
+
+use crate::{
+    database::outbox::OutboxStatus,
+    messages::MessageReference,
+    ui::{GroupUi, MessageUi, ReactionUi, ReceiptsUi},
+};
 
 /// Basic notifications.
 /// We use an mpsc channel to add new notifications.
@@ -13,6 +24,34 @@ use crate::ui::{GroupUi, MessageUi};
 pub enum Notification {
     Empty,
     Message(GroupUi, MessageUi),
+    /// The status of a message we sent changed. The message
+    /// is identified by its group and its `client_stamp`.
+    MessageStatus(GroupIdentifier, DeliveryStamp, OutboxStatus),
+    /// The name or description of a group changed, or we joined it.
+    GroupUpdated(GroupUi),
+    /// We received an invite to a group, which can be accepted with
+    /// [`crate::client::ClientProfile::accept_invite`].
+    Invite(DeliveryStamp),
+    /// Progress of a history transfer between our devices: how many
+    /// messages were transferred so far, and in total. This is sent on
+    /// both the sending and the receiving device.
+    HistorySync(u64, u64),
+    /// A message was edited by its sender: its group, the message,
+    /// and its new text. The message may not have arrived yet.
+    MessageEdited(GroupIdentifier, MessageReference, Arc<String>),
+    /// A message was deleted by its sender, see [`MessageUi::delete`].
+    MessageDeleted(GroupIdentifier, MessageReference),
+    /// The reactions to a message changed: its group, the message,
+    /// and all of its reactions. The message may not have arrived yet.
+    Reactions(GroupIdentifier, MessageReference, Vec<ReactionUi>),
+    /// A recipient received or read one of our messages: its group,
+    /// the message, and how many recipients did so far.
+    Receipts(GroupIdentifier, MessageReference, ReceiptsUi),
+    /// A member started or stopped typing in a group. Members who don't
+    /// stop explicitly stop after [`TYPING_INDICATOR_TIMEOUT`].
+    ///
+    /// [`TYPING_INDICATOR_TIMEOUT`]: super::typing::TYPING_INDICATOR_TIMEOUT
+    Typing(GroupIdentifier, AccountId, bool),
 }
 
 pub struct NotificationSender {
@@ -0,0 +1,235 @@
+//! Sending the messages of the outbox (see [`crate::database::outbox`]),
+//! and sending them again with backoff when the server couldn't be reached.
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use lib::{
+    api::{
+        group::{DeliveryStamp, SendMessageRequest},
+        messages::{ChatServiceMessage, Message, UnauthRequest},
+    },
+    crypto::blinded_address::BlindedAddressProof,
+    identifiers::GroupIdentifier,
+};
+use tokio::time::sleep;
+
+use crate::{
+    client::ClientProfile,
+    database::outbox::{OutboxMessage, OutboxStatus},
+    messages::{Content, MessageReference},
+    net::RequestError,
+};
+
+use super::{
+    error::Result,
+    notifications::{Notification, NotificationSender},
+    ProfileManager, WEBSOCKET_MANAGER,
+};
+
+/// We give up on a message (and mark it as failed)
+/// after that many attempts to reach the server.
+const MAX_OUTBOX_ATTEMPTS: u32 = 20;
+
+/// The longest we wait between two attempts to send the outbox.
+const MAX_OUTBOX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A message of the outbox whose status changed:
+/// its group, its `client_stamp`, and its new status.
+pub type OutboxStatusChange = (GroupIdentifier, DeliveryStamp, OutboxStatus);
+
+impl ProfileManager {
+    /// Sends a message of the outbox to the server and updates its status.
+    ///
+    /// The message is claimed first, so that it is never sent twice at once: if
+    /// another attempt is already sending it, this returns [`OutboxStatus::Sending`]
+    /// without sending it. If the server can't be reached, the message is pending
+    /// again until it failed [`MAX_OUTBOX_ATTEMPTS`] times.
+    pub async fn send_outbox_message(&self, message: OutboxMessage) -> Result<OutboxStatus> {
+        let OutboxMessage {
+            client_stamp,
+            group_id,
+            mut blinded_address,
+            mls_message,
+            ..
+        } = message;
+
+        if !self.sqlite_database.claim_outbox_message(&client_stamp)? {
+            return Ok(self.sqlite_database.get_outbox_status(&client_stamp)?);
+        }
+
+        let blinded_address_proof = blinded_address.create_proof(mls_message);
+
+        let resp = self.request_delivery(blinded_address_proof).await;
+
+        match resp {
+            Ok(Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::Delivered(
+                delivery_stamp,
+            )))) => {
+                self.sqlite_database
+                    .set_outbox_status(&client_stamp, OutboxStatus::Sent)?;
+                self.sqlite_database.set_message_server_stamp(
+                    &group_id,
+                    &MessageReference {
+                        sender_account_id: self.get_profile().get_account_id(),
+                        client_stamp,
+                    },
+                    &delivery_stamp,
+                )?;
+
+                Ok(OutboxStatus::Sent)
+            }
+            Ok(other) => {
+                log::warn!(
+                    "Message {client_stamp:?} was refused by the server: {}",
+                    RequestError::from_response(other)
+                );
+
+                self.sqlite_database
+                    .set_outbox_status(&client_stamp, OutboxStatus::Failed)?;
+
+                Ok(OutboxStatus::Failed)
+            }
+            Err(err) => {
+                let attempts = self
+                    .sqlite_database
+                    .increment_outbox_attempts(&client_stamp)?;
+
+                log::debug!("Couldn't send message {client_stamp:?} (attempt {attempts}): {err:?}");
+
+                let status = if attempts >= MAX_OUTBOX_ATTEMPTS {
+                    OutboxStatus::Failed
+                } else {
+                    OutboxStatus::Pending
+                };
+                self.sqlite_database
+                    .set_outbox_status(&client_stamp, status)?;
+
+                Ok(status)
+            }
+        }
+    }
+
+    async fn request_delivery(
+        &self,
+        blinded_address_proof: BlindedAddressProof,
+    ) -> anyhow::Result<Message> {
+        #[cfg(test)]
+        if self.offline.load(Ordering::Acquire) {
+            anyhow::bail!("The server can't be reached");
+        }
+
+        WEBSOCKET_MANAGER
+            .request_unauth(
+                self.get_server(),
+                UnauthRequest::ChatService(ChatServiceMessage::SendMessage(SendMessageRequest {
+                    blinded_address_proof,
+                    ephemeral: false,
+                })),
+            )
+            .await
+    }
+
+    /// Tries sending the pending messages of the outbox, oldest first. Stops at the
+    /// first message that is still pending afterwards, since the server most likely
+    /// can't be reached. Returns the messages whose status changed.
+    pub async fn flush_outbox(&self) -> Result<Vec<OutboxStatusChange>> {
+        let mut changes = Vec::new();
+
+        for message in self.sqlite_database.get_pending_outbox_messages()? {
+            let (group_id, client_stamp) = (message.group_id, message.client_stamp);
+
+            match self.send_outbox_message(message).await? {
+                OutboxStatus::Pending => break,
+                // Whoever is sending it reports its status
+                OutboxStatus::Sending => {}
+                status => changes.push((group_id, client_stamp, status)),
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Flushes the outbox in the background until no message is pending, waiting
+    /// longer after every attempt. Status changes are sent as notifications.
+    ///
+    /// Does nothing if the outbox of this profile is already being retried.
+    pub fn retry_outbox(
+        profile_manager: Arc<ProfileManager>,
+        notification_sender: Arc<NotificationSender>,
+    ) {
+        if profile_manager.retrying_outbox.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut delay = Duration::from_secs(1);
+
+            loop {
+                match profile_manager.flush_outbox().await {
+                    Ok(changes) => {
+                        for (group_id, client_stamp, status) in changes {
+                            notification_sender.send_notification(Notification::MessageStatus(
+                                group_id,
+                                client_stamp,
+                                status,
+                            ));
+                        }
+                    }
+                    Err(err) => {
+                        log::warn!("Couldn't send the outbox: {err:?}");
+                    }
+                }
+
+                match profile_manager
+                    .sqlite_database
+                    .get_pending_outbox_messages()
+                {
+                    Ok(pending) if !pending.is_empty() => {}
+                    _ => break,
+                }
+
+                sleep(delay).await;
+                delay = (delay * 2).min(MAX_OUTBOX_RETRY_DELAY);
+            }
+
+            profile_manager
+                .retrying_outbox
+                .store(false, Ordering::Release);
+        });
+    }
+}
+
+impl ClientProfile<'_> {
+    /// See [`ProfileManager::send_application_message`]. The status of the message
+    /// is also sent as a notification, and if it's still pending, the outbox will
+    /// be sent again in the background.
+    pub async fn send_application_message(
+        &self,
+        group_id: &GroupIdentifier,
+        content: Content,
+    ) -> Result<(DeliveryStamp, OutboxStatus)> {
+        let (client_stamp, status) = self
+            .profile_manager
+            .send_application_message(group_id, content)
+            .await?;
+
+        self.client
+            .send_notification(Notification::MessageStatus(*group_id, client_stamp, status));
+
+        if status == OutboxStatus::Pending {
+            self.retry_outbox();
+        }
+
+        Ok((client_stamp, status))
+    }
+
+    /// See [`ProfileManager::retry_outbox`].
+    pub fn retry_outbox(&self) {
+        ProfileManager::retry_outbox(
+            self.profile_manager.clone(),
+            self.client.notification_manager.clone(),
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Delivery and read receipts, which tell the senders of messages that their
+//! recipients received or read them.
+//!
+//! Delivery receipts are sent automatically for every message we receive,
+//! batched per group over [`RECEIPT_BATCH_DELAY`]. Read receipts are sent by the
+//! frontend with [`ClientProfile::mark_as_read`] when a chat is viewed, unless
+//! they are disabled.
+use std::{collections::HashMap, mem, sync::Arc, time::Duration};
+
+use lib::identifiers::GroupIdentifier;
+use tokio::time::sleep;
+
+use crate::{
+    client::ClientProfile,
+    database::outbox::OutboxStatus,
+    messages::{Content, MessageReference, ReceiptKind},
+    ui::ReceiptsUi,
+};
+
+use super::{error::Result, ProfileManager};
+
+/// How long delivery receipts are collected before being sent together.
+pub const RECEIPT_BATCH_DELAY: Duration = Duration::from_millis(100);
+
+/// Delivery receipts waiting to be sent, per group.
+pub type PendingReceipts = HashMap<GroupIdentifier, Vec<MessageReference>>;
+
+impl ProfileManager {
+    pub fn read_receipts_enabled(&self) -> Result<bool> {
+        Ok(self.sqlite_database.get_read_receipts_enabled()?)
+    }
+
+    /// Disabling read receipts only stops us from sending them.
+    pub fn set_read_receipts_enabled(&self, enabled: bool) -> Result<()> {
+        Ok(self.sqlite_database.set_read_receipts_enabled(enabled)?)
+    }
+
+    /// Returns how many recipients received and read one of our messages.
+    pub fn get_receipts(
+        &self,
+        group_id: GroupIdentifier,
+        message: &MessageReference,
+    ) -> Result<ReceiptsUi> {
+        let (delivered, read) = self.sqlite_database.get_receipt_counts(group_id, message)?;
+
+        Ok(ReceiptsUi { delivered, read })
+    }
+
+    /// Sends a delivery receipt for a message we received, together with the
+    /// other receipts queued in the next [`RECEIPT_BATCH_DELAY`].
+    pub fn queue_delivery_receipt(
+        profile_manager: &Arc<ProfileManager>,
+        group_id: GroupIdentifier,
+        message: MessageReference,
+    ) {
+        let mut pending_receipts = profile_manager
+            .pending_receipts
+            .lock()
+            .expect("Mutex poisoning is safe");
+
+        // The first receipt of a batch schedules it
+        let schedule = pending_receipts.is_empty();
+        pending_receipts.entry(group_id).or_default().push(message);
+
+        if !schedule {
+            return;
+        }
+
+        let profile_manager = profile_manager.clone();
+        tokio::spawn(async move {
+            sleep(RECEIPT_BATCH_DELAY).await;
+
+            let batches = mem::take(
+                &mut *profile_manager
+                    .pending_receipts
+                    .lock()
+                    .expect("Mutex poisoning is safe"),
+            );
+
+            for (group_id, messages) in batches {
+                if let Err(err) = profile_manager
+                    .send_receipts(group_id, ReceiptKind::Delivered, messages)
+                    .await
+                {
+                    log::warn!("Couldn't send delivery receipts to group {group_id}: {err:?}");
+                }
+            }
+        });
+    }
+
+    /// Sends a receipt for the messages of others among `messages`.
+    async fn send_receipts(
+        &self,
+        group_id: GroupIdentifier,
+        kind: ReceiptKind,
+        mut messages: Vec<MessageReference>,
+    ) -> Result<()> {
+        let account_id = self.get_profile().get_account_id();
+        messages.retain(|message| message.sender_account_id != account_id);
+
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let (_, status) = self
+            .send_application_message(&group_id, Content::Receipt { kind, messages })
+            .await?;
+
+        if status == OutboxStatus::Pending {
+            log::debug!("Receipts to group {group_id} will be sent with the outbox");
+        }
+
+        Ok(())
+    }
+}
+
+impl ClientProfile<'_> {
+    /// Tells the senders of `messages` that we read them, unless read receipts
+    /// are disabled. Messages we already marked as read are skipped, so this can
+    /// be called with every message displayed.
+    pub async fn mark_as_read(
+        &self,
+        group_id: GroupIdentifier,
+        messages: Vec<MessageReference>,
+    ) -> Result<()> {
+        if !self.read_receipts_enabled()? {
+            return Ok(());
+        }
+
+        let account_id = self.get_profile().get_account_id();
+        let mut unread = Vec::new();
+        for message in messages {
+            if self
+                .sqlite_database
+                .get_receipt(group_id, &message, account_id)?
+                != Some(ReceiptKind::Read)
+            {
+                unread.push(message);
+            }
+        }
+
+        self.send_receipts(group_id, ReceiptKind::Read, unread)
+            .await
+    }
+}
@@ -0,0 +1,120 @@
+//! Typing indicators, which tell the members of a group that someone is
+//! writing a message.
+//!
+//! They are sent as ephemeral messages (see
+//! [`ProfileManager::send_ephemeral_message`]): the server only broadcasts them
+//! to the members listening right now, and no one stores them. A member is
+//! shown as typing until they stop, or until [`TYPING_INDICATOR_TIMEOUT`] passed
+//! since their last indicator.
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use lib::identifiers::{AccountId, GroupIdentifier};
+use tokio::time::{sleep_until, Instant};
+
+use crate::messages::Content;
+
+use super::{
+    error::Result,
+    notifications::{Notification, NotificationSender},
+    ProfileManager,
+};
+
+/// How long a member is shown as typing after their last indicator.
+pub const TYPING_INDICATOR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// While we type, indicators are sent again at this interval so that
+/// they don't expire for the other members.
+pub const TYPING_INDICATOR_INTERVAL: Duration = Duration::from_secs(3);
+
+/// When the indicator of each member typing in a group expires.
+pub type TypingMembers = HashMap<(GroupIdentifier, AccountId), Instant>;
+
+/// When we last sent a typing indicator to each group we are typing in.
+pub type TypingSent = HashMap<GroupIdentifier, Instant>;
+
+impl ProfileManager {
+    /// Returns the members currently typing in the group.
+    pub fn get_typing_members(&self, group_id: GroupIdentifier) -> Vec<AccountId> {
+        self.typing_members
+            .lock()
+            .expect("Mutex poisoning is safe")
+            .keys()
+            .filter(|(group, _)| *group == group_id)
+            .map(|(_, account_id)| *account_id)
+            .collect()
+    }
+
+    /// Shows a member as typing in the group, or stops showing them. Unless
+    /// they send another indicator, it expires after [`TYPING_INDICATOR_TIMEOUT`].
+    pub fn receive_typing_indicator(
+        profile_manager: &Arc<ProfileManager>,
+        notification_sender: Arc<NotificationSender>,
+        group_id: GroupIdentifier,
+        account_id: AccountId,
+        typing: bool,
+    ) {
+        let key = (group_id, account_id);
+        let mut typing_members = profile_manager
+            .typing_members
+            .lock()
+            .expect("Mutex poisoning is safe");
+
+        if !typing {
+            if typing_members.remove(&key).is_some() {
+                notification_sender
+                    .send_notification(Notification::Typing(group_id, account_id, false));
+            }
+            return;
+        }
+
+        // Repeated indicators only extend the first one
+        let expiry = Instant::now() + TYPING_INDICATOR_TIMEOUT;
+        if typing_members.insert(key, expiry).is_none() {
+            notification_sender.send_notification(Notification::Typing(group_id, account_id, true));
+        }
+        drop(typing_members);
+
+        let profile_manager = profile_manager.clone();
+        tokio::spawn(async move {
+            sleep_until(expiry).await;
+
+            let mut typing_members = profile_manager
+                .typing_members
+                .lock()
+                .expect("Mutex poisoning is safe");
+
+            // Otherwise it was extended or stopped in the meantime
+            if typing_members.get(&key) == Some(&expiry) {
+                typing_members.remove(&key);
+                notification_sender
+                    .send_notification(Notification::Typing(group_id, account_id, false));
+            }
+        });
+    }
+
+    /// Tells the group whether we are typing. While typing, this can be called
+    /// on every keystroke: indicators are only sent every
+    /// [`TYPING_INDICATOR_INTERVAL`].
+    pub async fn set_typing(&self, group_id: GroupIdentifier, typing: bool) -> Result<()> {
+        {
+            let mut typing_sent = self.typing_sent.lock().expect("Mutex poisoning is safe");
+            let now = Instant::now();
+
+            if typing {
+                if typing_sent
+                    .get(&group_id)
+                    .is_some_and(|sent| now.duration_since(*sent) < TYPING_INDICATOR_INTERVAL)
+                {
+                    return Ok(());
+                }
+                typing_sent.insert(group_id, now);
+            } else if typing_sent.remove(&group_id).is_none() {
+                // We weren't typing
+                return Ok(());
+            }
+        }
+
+        self.send_ephemeral_message(&group_id, Content::Typing(typing))
+            .await
+    }
+}
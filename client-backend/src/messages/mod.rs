@@ -94,6 +94,33 @@ impl From<Content> for proto::Content {
     fn from(value: Content) -> Self {
         let inner = match value {
             Content::BasicText { body } => proto::content::Inner::BasicText(body),
+            Content::HistorySync(batch) => proto::content::Inner::HistorySync(batch),
+            Content::Reply { body, parent } => proto::content::Inner::Reply(proto::Reply {
+                body,
+                parent: Some(parent.into()),
+            }),
+            Content::Edit { target, body } => proto::content::Inner::Edit(proto::Edit {
+                target_client_timestamp: target.to_vec(),
+                body,
+            }),
+            Content::Delete { target } => proto::content::Inner::Delete(proto::Delete {
+                target_client_timestamp: target.to_vec(),
+            }),
+            Content::Reaction {
+                target,
+                emoji,
+                remove,
+            } => proto::content::Inner::Reaction(proto::Reaction {
+                target: Some(target.into()),
+                emoji,
+                remove,
+            }),
+            Content::Receipt { kind, messages } => proto::content::Inner::Receipt(proto::Receipt {
+                kind: proto::ReceiptKind::from(kind).into(),
+                messages: messages.into_iter().map(Into::into).collect(),
+            }),
+            Content::Typing(typing) => proto::content::Inner::Typing(typing),
+            Content::AccountKey(share) => proto::content::Inner::AccountKey(share),
         };
         Self { inner: Some(inner) }
     }
@@ -105,6 +132,74 @@ impl TryFrom<proto::Content> for Content {
     fn try_from(value: proto::Content) -> Result<Self, Self::Error> {
         Ok(match value.inner.ok_or(ProtoError)? {
             proto::content::Inner::BasicText(text) => Self::BasicText { body: text },
+            proto::content::Inner::HistorySync(batch) => Self::HistorySync(batch),
+            proto::content::Inner::AccountKey(share) => Self::AccountKey(share),
+            proto::content::Inner::Reply(reply) => Self::Reply {
+                body: reply.body,
+                parent: reply.parent.ok_or(ProtoError)?.try_into()?,
+            },
+            proto::content::Inner::Edit(edit) => Self::Edit {
+                target: edit
+                    .target_client_timestamp
+                    .as_slice()
+                    .try_into()
+                    .map_err(|()| ProtoError)?,
+                body: edit.body,
+            },
+            proto::content::Inner::Delete(delete) => Self::Delete {
+                target: delete
+                    .target_client_timestamp
+                    .as_slice()
+                    .try_into()
+                    .map_err(|()| ProtoError)?,
+            },
+            proto::content::Inner::Reaction(reaction) => Self::Reaction {
+                target: reaction.target.ok_or(ProtoError)?.try_into()?,
+                emoji: reaction.emoji,
+                remove: reaction.remove,
+            },
+            proto::content::Inner::Receipt(receipt) => Self::Receipt {
+                kind: receipt.kind().into(),
+                messages: receipt
+                    .messages
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<Result<_, _>>()?,
+            },
+            proto::content::Inner::Typing(typing) => Self::Typing(typing),
+        })
+    }
+}
+
+/// Identifies a message of a group. The client stamp is generated by the sender
+/// (see [`MlsApplicationMessage::client_stamp`]), so it is known before the
+/// message is even delivered.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageReference {
+    pub sender_account_id: AccountId,
+    pub client_stamp: DeliveryStamp,
+}
+
+impl From<MessageReference> for proto::MessageReference {
+    fn from(value: MessageReference) -> Self {
+        Self {
+            sender_account_id: Some(value.sender_account_id.into()),
+            client_timestamp: value.client_stamp.to_vec(),
+        }
+    }
+}
+
+impl TryFrom<proto::MessageReference> for MessageReference {
+    type Error = ProtoError;
+
+    fn try_from(value: proto::MessageReference) -> Result<Self, Self::Error> {
+        Ok(Self {
+            sender_account_id: value.sender_account_id.ok_or(ProtoError)?.try_into()?,
+            client_stamp: value
+                .client_timestamp
+                .as_slice()
+                .try_into()
+                .map_err(|()| ProtoError)?,
         })
     }
 }
@@ -112,17 +207,113 @@ impl TryFrom<proto::Content> for Content {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum Content {
     // For testing and very basic messages support
-    BasicText { body: String },
+    BasicText {
+        body: String,
+    },
+    /// An encoded [`proto::HistorySyncBatch`], sent to our own devices in
+    /// the self group (see [`crate::manager::history_sync`]). It isn't
+    /// stored or displayed as a message.
+    HistorySync(Vec<u8>),
+    /// A text message quoting `parent`, which may not have arrived yet.
+    Reply {
+        body: String,
+        parent: MessageReference,
+    },
+    /// Replaces the text of `target`, a message with this client stamp sent by
+    /// the same account.
+    Edit {
+        target: DeliveryStamp,
+        body: String,
+    },
+    /// Deletes `target`, a message with this client stamp sent by the same
+    /// account, for every member of the group.
+    Delete {
+        target: DeliveryStamp,
+    },
+    /// Adds or removes the reaction of the sender to `target`. Each account
+    /// has at most one reaction per message: adding one replaces the previous.
+    Reaction {
+        target: MessageReference,
+        emoji: String,
+        remove: bool,
+    },
+    /// Tells the senders of `messages` that we received or read them
+    /// (see [`crate::manager::receipts`]).
+    Receipt {
+        kind: ReceiptKind,
+        messages: Vec<MessageReference>,
+    },
+    /// Whether the sender started or stopped typing. It is sent ephemerally and
+    /// never stored (see [`crate::manager::typing`]).
+    Typing(bool),
+    /// An encoded [`AccountKeyShare`], sent to our own devices in the self
+    /// group after rotating the account key. It isn't stored or displayed as
+    /// a message.
+    ///
+    /// [`AccountKeyShare`]: lib::crypto::certificates::rotation::AccountKeyShare
+    AccountKey(Vec<u8>),
+}
+
+/// How far a message got to one of its recipients. Reading
+/// a message implies it was delivered.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+impl From<ReceiptKind> for proto::ReceiptKind {
+    fn from(value: ReceiptKind) -> Self {
+        match value {
+            ReceiptKind::Delivered => Self::Delivered,
+            ReceiptKind::Read => Self::Read,
+        }
+    }
+}
+
+impl From<proto::ReceiptKind> for ReceiptKind {
+    fn from(value: proto::ReceiptKind) -> Self {
+        match value {
+            proto::ReceiptKind::Delivered => Self::Delivered,
+            proto::ReceiptKind::Read => Self::Read,
+        }
+    }
+}
+
+impl From<ReceiptKind> for u8 {
+    fn from(value: ReceiptKind) -> Self {
+        match value {
+            ReceiptKind::Delivered => 1,
+            ReceiptKind::Read => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for ReceiptKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(ReceiptKind::Delivered),
+            2 => Ok(ReceiptKind::Read),
+            x => Err(x),
+        }
+    }
 }
 
 pub enum MessageKind {
     PlainText,
+    Reply,
+    /// A deleted message, whose content was erased.
+    Deleted,
 }
 
 impl From<MessageKind> for u8 {
     fn from(value: MessageKind) -> Self {
         match value {
             MessageKind::PlainText => 1,
+            MessageKind::Reply => 2,
+            MessageKind::Deleted => 3,
         }
     }
 }
@@ -133,6 +324,8 @@ impl TryFrom<u8> for MessageKind {
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
             1 => Ok(MessageKind::PlainText),
+            2 => Ok(MessageKind::Reply),
+            3 => Ok(MessageKind::Deleted),
             x => Err(x),
         }
     }
@@ -157,4 +350,8 @@ impl Content {
     pub fn plain_text(body: String) -> Self {
         Self::BasicText { body }
     }
+
+    pub fn reply(body: String, parent: MessageReference) -> Self {
+        Self::Reply { body, parent }
+    }
 }
@@ -1,5 +1,8 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use lib::{
-    crypto::certificates::{Certificate, CertificateChain, SerializedChain},
+    api::proto::{self, ProstMessage},
+    crypto::certificates::{rotation, CertificateError, SerializedChain},
     error::ProtoError,
 };
 use mls_rs::{
@@ -15,6 +18,9 @@ pub const LICKS_CREDENTIAL_TYPE: CredentialType = CredentialType::new(0xfefe);
 #[derive(PartialEq)]
 pub struct LicksMlsCredential {
     pub(crate) chain: SerializedChain,
+    /// Signature of the chain's account certificate by the previous account
+    /// key, if the account key was rotated. See [`rotation`].
+    pub(crate) previous_account_signature: Option<Vec<u8>>,
 }
 
 impl From<LicksMlsCredential> for SigningIdentity {
@@ -48,9 +54,14 @@ impl TryFrom<SigningIdentity> for LicksMlsCredential {
 
 impl From<LicksMlsCredential> for CustomCredential {
     fn from(value: LicksMlsCredential) -> Self {
+        let proto_credential = proto::LicksCredential {
+            chain: Some(value.chain.into()),
+            previous_account_signature: value.previous_account_signature.unwrap_or_default(),
+        };
+
         Self {
             credential_type: LICKS_CREDENTIAL_TYPE,
-            data: value.chain.to_bytes(),
+            data: proto_credential.encode_to_vec(),
         }
     }
 }
@@ -60,11 +71,26 @@ impl TryFrom<&CustomCredential> for LicksMlsCredential {
 
     fn try_from(value: &CustomCredential) -> Result<Self, Self::Error> {
         if value.credential_type == LICKS_CREDENTIAL_TYPE {
-            let serialized_chain =
-                SerializedChain::from_bytes(&value.data).map_err(|_| ProtoError)?;
+            // Older credentials only contain the certificate chain
+            let proto_credential = match proto::LicksCredential::decode(value.data.as_slice()) {
+                Ok(proto_credential) if proto_credential.chain.is_some() => proto_credential,
+                _ => proto::LicksCredential {
+                    chain: Some(
+                        proto::CertificateChain::decode(value.data.as_slice())
+                            .map_err(|_| ProtoError)?,
+                    ),
+                    previous_account_signature: Vec::new(),
+                },
+            };
 
             Ok(Self {
-                chain: serialized_chain,
+                chain: proto_credential
+                    .chain
+                    .ok_or(ProtoError)?
+                    .try_into()
+                    .map_err(|_| ProtoError)?,
+                previous_account_signature: Some(proto_credential.previous_account_signature)
+                    .filter(|signature| !signature.is_empty()),
             })
         } else {
             Err(ProtoError)
@@ -94,6 +120,8 @@ pub enum LicksIdentityProviderError {
     InvalidCertificate,
     #[error("The signer is invalid: the public key is not the one stored in the chain")]
     InvalidPublicKey,
+    #[error("The signer is invalid: the certificate chain is expired or not valid yet")]
+    Expired,
 }
 
 impl IntoAnyError for LicksIdentityProviderError {
@@ -114,7 +142,7 @@ impl IdentityProvider for LicksIdentityProvider {
     fn validate_member(
         &self,
         signing_identity: &SigningIdentity,
-        _timestamp: Option<MlsTime>,
+        timestamp: Option<MlsTime>,
         _extensions: Option<&ExtensionList>,
     ) -> Result<(), Self::Error> {
         // IMPORTANT TODO:
@@ -124,12 +152,21 @@ impl IdentityProvider for LicksIdentityProvider {
         let cred = Self::resolve_to_licks_credential(signing_identity)
             .ok_or(LicksIdentityProviderError::Unsupported)?;
 
-        let chain = cred
-            .chain
-            .verify()
-            .map_err(|_| LicksIdentityProviderError::InvalidCertificate)?;
+        // The MLS signature key isn't always the whole device key (e.g. with
+        // hybrid certificates), so get it from the chain before verifying it
+        let pub_key = SignaturePublicKey::new(cred.chain.pub_key_bytes());
+
+        // mls-rs gives us the time the credential has to be valid at
+        // (e.g. the time of a commit), otherwise we check it is valid now
+        let time = timestamp.map_or_else(SystemTime::now, |timestamp| {
+            UNIX_EPOCH + Duration::from_secs(timestamp.seconds_since_epoch())
+        });
+
+        cred.chain.verify_at(time).map_err(|err| match err {
+            CertificateError::OutsideValidityPeriod => LicksIdentityProviderError::Expired,
+            _ => LicksIdentityProviderError::InvalidCertificate,
+        })?;
 
-        let pub_key = SignaturePublicKey::new(chain.device_cert().pub_key_bytes());
         if pub_key == signing_identity.signature_key {
             Ok(())
         } else {
@@ -178,17 +215,34 @@ impl IdentityProvider for LicksIdentityProvider {
     /// new member via external commit. This function determines if a removal
     /// should be allowed by providing the target member to be removed as
     /// `predecessor` and the new member as `successor`.
+    ///
+    /// Only an account can succeed itself: either with the same account key
+    /// (e.g. a renewed device certificate), or with a new account key that
+    /// was cross-signed by the previous one (see [`rotation`]).
     fn valid_successor(
         &self,
-        _predecessor: &SigningIdentity,
-        _successor: &SigningIdentity,
+        predecessor: &SigningIdentity,
+        successor: &SigningIdentity,
         _extensions: &ExtensionList,
     ) -> Result<bool, Self::Error> {
-        // In the future we'll have admin permissions etc
-        // so that people can remove others from groups.
-        // For now we'll just ignore that problem and pretend
-        // anyone can kick anyone else from the group.
-        Ok(true)
+        let predecessor = Self::resolve_to_licks_credential(predecessor)
+            .ok_or(LicksIdentityProviderError::Unsupported)?;
+        let successor = Self::resolve_to_licks_credential(successor)
+            .ok_or(LicksIdentityProviderError::Unsupported)?;
+
+        if predecessor.chain.account_id() != successor.chain.account_id() {
+            return Ok(false);
+        }
+
+        if predecessor.chain.account_pub_key_bytes() == successor.chain.account_pub_key_bytes() {
+            return Ok(true);
+        }
+
+        Ok(successor
+            .previous_account_signature
+            .is_some_and(|signature| {
+                rotation::verify_successor(&predecessor.chain, &successor.chain, &signature).is_ok()
+            }))
     }
 
     /// Credential types that are supported by this provider.
@@ -0,0 +1,416 @@
+//! Custom MLS extensions stored in the group context of Licks groups.
+//!
+//! Extensions in the group context are shared by every member and can only
+//! be changed through a commit, so every member agrees on their value.
+//! [`InviteInbox`] is the exception: it is published in key packages instead.
+use lib::{
+    api::proto::{self, ProstMessage},
+    crypto::blinded_address::{BlindedAddressPublic, BlindedAddressSecret},
+    error::ProtoError,
+    identifiers::AccountId,
+};
+use mls_rs::{Extension, ExtensionList, ExtensionType, MlsMessage};
+
+pub const GROUP_METADATA_EXTENSION_TYPE: ExtensionType = ExtensionType::new(0xfe01);
+pub const GROUP_ROLES_EXTENSION_TYPE: ExtensionType = ExtensionType::new(0xfe02);
+pub const ACTIVE_GROUP_LINK_EXTENSION_TYPE: ExtensionType = ExtensionType::new(0xfe03);
+pub const INVITE_INBOX_EXTENSION_TYPE: ExtensionType = ExtensionType::new(0xfe04);
+
+/// Extension types that our clients support and advertise in their key packages.
+/// Every member must support the extensions of the group context.
+pub const LICKS_EXTENSION_TYPES: [ExtensionType; 3] = [
+    GROUP_METADATA_EXTENSION_TYPE,
+    GROUP_ROLES_EXTENSION_TYPE,
+    ACTIVE_GROUP_LINK_EXTENSION_TYPE,
+];
+
+/// The name, description and avatar of a group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupMetadata {
+    pub name: String,
+    pub description: Option<String>,
+    pub avatar_hash: Option<Vec<u8>>,
+}
+
+impl GroupMetadata {
+    pub fn new(name: String, description: Option<String>) -> Self {
+        Self {
+            name,
+            description,
+            avatar_hash: None,
+        }
+    }
+
+    /// Reads the metadata from the extensions of a group context. Returns
+    /// `None` if the group has none (e.g. it was created by an older client).
+    pub fn from_extensions(extensions: &ExtensionList) -> Option<Self> {
+        extensions
+            .get(GROUP_METADATA_EXTENSION_TYPE)
+            .and_then(|extension| Self::try_from(&extension).ok())
+    }
+
+    /// Adds the metadata to `extensions`, replacing the previous one.
+    pub fn set_in(self, extensions: &mut ExtensionList) {
+        extensions.set(self.into());
+    }
+}
+
+impl From<GroupMetadata> for Extension {
+    fn from(value: GroupMetadata) -> Self {
+        let proto_metadata = proto::GroupMetadata {
+            name: value.name,
+            description: value.description.unwrap_or_default(),
+            avatar_hash: value.avatar_hash.unwrap_or_default(),
+        };
+
+        Extension::new(
+            GROUP_METADATA_EXTENSION_TYPE,
+            proto_metadata.encode_to_vec(),
+        )
+    }
+}
+
+impl TryFrom<&Extension> for GroupMetadata {
+    type Error = ProtoError;
+
+    fn try_from(value: &Extension) -> Result<Self, Self::Error> {
+        if value.extension_type != GROUP_METADATA_EXTENSION_TYPE {
+            return Err(ProtoError);
+        }
+
+        let proto_metadata = proto::GroupMetadata::decode(value.extension_data.as_slice())
+            .map_err(|_| ProtoError)?;
+
+        Ok(Self {
+            name: proto_metadata.name,
+            description: Some(proto_metadata.description).filter(|d| !d.is_empty()),
+            avatar_hash: Some(proto_metadata.avatar_hash).filter(|hash| !hash.is_empty()),
+        })
+    }
+}
+
+/// The accounts that administrate a group. Only admins can add and remove
+/// other members, or change the group context (see [`crate::mls::rules`]).
+///
+/// Roles are given to accounts, so all the devices of an account share them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupRoles {
+    pub admins: Vec<AccountId>,
+}
+
+impl GroupRoles {
+    pub fn new(admin: AccountId) -> Self {
+        Self {
+            admins: vec![admin],
+        }
+    }
+
+    /// Reads the roles from the extensions of a group context. Returns `None`
+    /// if the group has none (e.g. it was created by an older client), in which
+    /// case every member is considered an admin.
+    pub fn from_extensions(extensions: &ExtensionList) -> Option<Self> {
+        extensions
+            .get(GROUP_ROLES_EXTENSION_TYPE)
+            .and_then(|extension| Self::try_from(&extension).ok())
+    }
+
+    /// Adds the roles to `extensions`, replacing the previous ones.
+    pub fn set_in(self, extensions: &mut ExtensionList) {
+        extensions.set(self.into());
+    }
+
+    pub fn is_admin(&self, account_id: &AccountId) -> bool {
+        self.admins.contains(account_id)
+    }
+
+    /// Returns `false` if the account already was an admin.
+    pub fn promote(&mut self, account_id: AccountId) -> bool {
+        if self.is_admin(&account_id) {
+            false
+        } else {
+            self.admins.push(account_id);
+            true
+        }
+    }
+
+    /// Returns `false` if the account wasn't an admin.
+    pub fn demote(&mut self, account_id: &AccountId) -> bool {
+        let admin_count = self.admins.len();
+        self.admins.retain(|admin| admin != account_id);
+
+        admin_count != self.admins.len()
+    }
+}
+
+impl From<GroupRoles> for Extension {
+    fn from(value: GroupRoles) -> Self {
+        let proto_roles = proto::GroupRoles {
+            admins: value.admins.into_iter().map(Into::into).collect(),
+        };
+
+        Extension::new(GROUP_ROLES_EXTENSION_TYPE, proto_roles.encode_to_vec())
+    }
+}
+
+impl TryFrom<&Extension> for GroupRoles {
+    type Error = ProtoError;
+
+    fn try_from(value: &Extension) -> Result<Self, Self::Error> {
+        if value.extension_type != GROUP_ROLES_EXTENSION_TYPE {
+            return Err(ProtoError);
+        }
+
+        let proto_roles =
+            proto::GroupRoles::decode(value.extension_data.as_slice()).map_err(|_| ProtoError)?;
+
+        Ok(Self {
+            admins: proto_roles
+                .admins
+                .into_iter()
+                .map(AccountId::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// The id of the invite link of a group (see [`lib::crypto::group_link`]).
+/// New members can only join by external commit while a link is active, so
+/// removing it revokes the link even for people who already downloaded it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveGroupLink {
+    pub link_id: BlindedAddressPublic,
+}
+
+impl ActiveGroupLink {
+    /// Reads the active link from the extensions of a group context. Returns
+    /// `None` if the group has no invite link.
+    pub fn from_extensions(extensions: &ExtensionList) -> Option<Self> {
+        extensions
+            .get(ACTIVE_GROUP_LINK_EXTENSION_TYPE)
+            .and_then(|extension| Self::try_from(&extension).ok())
+    }
+
+    /// Adds the active link to `extensions`, replacing the previous one.
+    pub fn set_in(self, extensions: &mut ExtensionList) {
+        extensions.set(self.into());
+    }
+
+    /// Removes the active link from `extensions`.
+    pub fn remove_from(extensions: &mut ExtensionList) {
+        extensions.remove(ACTIVE_GROUP_LINK_EXTENSION_TYPE);
+    }
+}
+
+impl From<ActiveGroupLink> for Extension {
+    fn from(value: ActiveGroupLink) -> Self {
+        let proto_link = proto::ActiveGroupLink {
+            link_id: Some(value.link_id.into()),
+        };
+
+        Extension::new(ACTIVE_GROUP_LINK_EXTENSION_TYPE, proto_link.encode_to_vec())
+    }
+}
+
+impl TryFrom<&Extension> for ActiveGroupLink {
+    type Error = ProtoError;
+
+    fn try_from(value: &Extension) -> Result<Self, Self::Error> {
+        if value.extension_type != ACTIVE_GROUP_LINK_EXTENSION_TYPE {
+            return Err(ProtoError);
+        }
+
+        let proto_link = proto::ActiveGroupLink::decode(value.extension_data.as_slice())
+            .map_err(|_| ProtoError)?;
+
+        Ok(Self {
+            link_id: proto_link.link_id.ok_or(ProtoError)?.try_into()?,
+        })
+    }
+}
+
+/// The invite inbox of a device (see [`crate::manager::invites`]), published
+/// in its key packages. Only the server and the groups it is added to see
+/// them, so unlike our ids, it doesn't let anyone send us invites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InviteInbox {
+    pub secret: [u8; 32],
+}
+
+impl InviteInbox {
+    /// Reads the invite inbox of the device that made `key_package`. Returns
+    /// `None` if it has none (e.g. it was made by an older client).
+    pub fn from_key_package(key_package: &MlsMessage) -> Option<Self> {
+        key_package
+            .clone()
+            .into_key_package()?
+            .extensions
+            .get(INVITE_INBOX_EXTENSION_TYPE)
+            .and_then(|extension| Self::try_from(&extension).ok())
+    }
+
+    /// The extensions to publish in our key packages.
+    pub fn key_package_extensions(self) -> ExtensionList {
+        let mut extensions = ExtensionList::default();
+        extensions.set(self.into());
+
+        extensions
+    }
+
+    pub fn blinded_address(&self) -> BlindedAddressSecret {
+        BlindedAddressSecret::from_bytes(self.secret)
+    }
+}
+
+impl From<InviteInbox> for Extension {
+    fn from(value: InviteInbox) -> Self {
+        let proto_inbox = proto::InviteInbox {
+            blinded_address_secret: value.secret.to_vec(),
+        };
+
+        Extension::new(INVITE_INBOX_EXTENSION_TYPE, proto_inbox.encode_to_vec())
+    }
+}
+
+impl TryFrom<&Extension> for InviteInbox {
+    type Error = ProtoError;
+
+    fn try_from(value: &Extension) -> Result<Self, Self::Error> {
+        if value.extension_type != INVITE_INBOX_EXTENSION_TYPE {
+            return Err(ProtoError);
+        }
+
+        let proto_inbox =
+            proto::InviteInbox::decode(value.extension_data.as_slice()).map_err(|_| ProtoError)?;
+
+        Ok(Self {
+            secret: proto_inbox
+                .blinded_address_secret
+                .try_into()
+                .map_err(|_| ProtoError)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::{
+        crypto::{group_link::GroupLinkSecret, rng::random_bytes},
+        identifiers::{AccountId, LicksIdentifier},
+    };
+    use mls_rs::ExtensionList;
+
+    use super::{
+        ActiveGroupLink, GroupMetadata, GroupRoles, InviteInbox, INVITE_INBOX_EXTENSION_TYPE,
+    };
+
+    #[test]
+    fn roundtrip_group_metadata() {
+        let mut extensions = ExtensionList::default();
+
+        assert_eq!(
+            GroupMetadata::from_extensions(&extensions),
+            None,
+            "Empty extension list has no metadata"
+        );
+
+        let metadata = GroupMetadata {
+            name: "Group name".to_string(),
+            description: None,
+            avatar_hash: Some(vec![1, 2, 3]),
+        };
+        metadata.clone().set_in(&mut extensions);
+
+        assert_eq!(
+            GroupMetadata::from_extensions(&extensions),
+            Some(metadata),
+            "Metadata can be read back"
+        );
+
+        let new_metadata =
+            GroupMetadata::new("New name".to_string(), Some("Description".to_string()));
+        new_metadata.clone().set_in(&mut extensions);
+
+        assert_eq!(
+            GroupMetadata::from_extensions(&extensions),
+            Some(new_metadata),
+            "Setting the metadata again replaces it"
+        );
+    }
+
+    #[test]
+    fn roundtrip_group_roles() {
+        let mut extensions = ExtensionList::default();
+
+        assert_eq!(
+            GroupRoles::from_extensions(&extensions),
+            None,
+            "Empty extension list has no roles"
+        );
+
+        let alice = AccountId::generate_id();
+        let bob = AccountId::generate_id();
+
+        let mut roles = GroupRoles::new(alice);
+        assert!(roles.is_admin(&alice), "Alice created the group");
+        assert!(!roles.is_admin(&bob), "Bob is a regular member");
+
+        assert!(roles.promote(bob), "Bob wasn't an admin");
+        assert!(!roles.promote(bob), "Bob already is an admin");
+        assert!(roles.demote(&alice), "Alice was an admin");
+        assert!(!roles.demote(&alice), "Alice isn't an admin anymore");
+
+        roles.clone().set_in(&mut extensions);
+
+        assert_eq!(
+            GroupRoles::from_extensions(&extensions),
+            Some(roles),
+            "Roles can be read back"
+        );
+    }
+
+    #[test]
+    fn roundtrip_active_group_link() {
+        let mut extensions = ExtensionList::default();
+
+        assert_eq!(
+            ActiveGroupLink::from_extensions(&extensions),
+            None,
+            "Empty extension list has no invite link"
+        );
+
+        let active_link = ActiveGroupLink {
+            link_id: GroupLinkSecret::generate().link().id,
+        };
+        active_link.set_in(&mut extensions);
+
+        assert_eq!(
+            ActiveGroupLink::from_extensions(&extensions),
+            Some(active_link),
+            "Active link can be read back"
+        );
+
+        ActiveGroupLink::remove_from(&mut extensions);
+
+        assert_eq!(
+            ActiveGroupLink::from_extensions(&extensions),
+            None,
+            "Active link was removed"
+        );
+    }
+
+    #[test]
+    fn roundtrip_invite_inbox() {
+        let inbox = InviteInbox {
+            secret: random_bytes::<32>(),
+        };
+
+        assert_eq!(
+            inbox
+                .key_package_extensions()
+                .get(INVITE_INBOX_EXTENSION_TYPE)
+                .and_then(|extension| InviteInbox::try_from(&extension).ok()),
+            Some(inbox),
+            "Invite inbox can be read back"
+        );
+    }
+}
@@ -1,3 +1,5 @@
 pub mod credentials;
+pub mod extensions;
 pub mod group;
+pub mod rules;
 pub mod welcome;
@@ -0,0 +1,192 @@
+//! Enforces the roles of a group (see [`GroupRoles`]) on every commit.
+//! New members can only join by external commit while the group has an
+//! invite link (see [`ActiveGroupLink`]).
+//!
+//! Commits we receive are rejected if they contain a proposal their sender
+//! wasn't allowed to make. When we commit ourselves, such proposals (that
+//! other members sent us) are left out of the commit instead.
+use lib::identifiers::AccountId;
+use mls_rs::{
+    error::IntoAnyError,
+    group::{proposal::BorrowedProposal, GroupContext, Roster, Sender},
+    mls_rules::{
+        CommitDirection, CommitOptions, CommitSource, EncryptionOptions, ProposalBundle,
+        ProposalInfo,
+    },
+    MlsRules,
+};
+
+use super::{
+    credentials::LicksIdentityProvider,
+    extensions::{ActiveGroupLink, GroupRoles},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LicksMlsRulesError {
+    #[error("Only admins can add other members")]
+    AddNotAllowed,
+    #[error("Only admins can remove other members")]
+    RemoveNotAllowed,
+    #[error("Only admins can change the group context")]
+    GroupContextNotAllowed,
+    #[error("A group must keep at least one admin")]
+    NoAdmin,
+    #[error("This group has no invite link to join it")]
+    ExternalJoinNotAllowed,
+}
+
+impl IntoAnyError for LicksMlsRulesError {
+    fn into_dyn_error(self) -> Result<Box<dyn std::error::Error + Send + Sync>, Self> {
+        Err(self)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LicksMlsRules;
+
+impl LicksMlsRules {
+    /// Returns the account of the member at `index`.
+    pub fn account_at(roster: &Roster, index: u32) -> Option<AccountId> {
+        roster
+            .members_iter()
+            .find(|member| member.index == index)
+            .and_then(|member| {
+                LicksIdentityProvider::resolve_to_licks_credential(&member.signing_identity)
+            })
+            .map(|credential| *credential.chain.account_id())
+    }
+
+    /// Returns `true` if the member at `index` is an admin. Every member
+    /// is an admin of groups without roles.
+    pub fn is_admin(roster: &Roster, roles: Option<&GroupRoles>, index: u32) -> bool {
+        roles.map_or(true, |roles| {
+            Self::account_at(roster, index).is_some_and(|account_id| roles.is_admin(&account_id))
+        })
+    }
+
+    /// Checks that the sender of `proposal` was allowed to make it.
+    fn check_proposal(
+        roster: &Roster,
+        roles: Option<&GroupRoles>,
+        has_group_link: bool,
+        proposal: &ProposalInfo<BorrowedProposal<'_>>,
+    ) -> Result<(), LicksMlsRulesError> {
+        let sender_is_admin = match proposal.sender {
+            Sender::Member(index) => Self::is_admin(roster, roles, index),
+            _ => false,
+        };
+
+        match proposal.proposal {
+            BorrowedProposal::Add(add) if !sender_is_admin => {
+                // Anyone can add their own new devices (see `ClientProfile::link_new_device`)
+                let added_account = LicksIdentityProvider::resolve_to_licks_credential(
+                    add.key_package().signing_identity(),
+                )
+                .map(|credential| *credential.chain.account_id());
+
+                let allowed = match proposal.sender {
+                    Sender::Member(index) => added_account
+                        .is_some_and(|added| Self::account_at(roster, index) == Some(added)),
+                    _ => false,
+                };
+
+                if allowed {
+                    Ok(())
+                } else {
+                    Err(LicksMlsRulesError::AddNotAllowed)
+                }
+            }
+            BorrowedProposal::Remove(remove) if !sender_is_admin => {
+                let allowed = match proposal.sender {
+                    // Anyone can remove their own devices, which is also how we leave groups
+                    Sender::Member(index) => {
+                        Self::account_at(roster, index).is_some_and(|sender| {
+                            Self::account_at(roster, remove.to_remove()) == Some(sender)
+                        })
+                    }
+                    // The new member replaces one of its own leaves
+                    // (see `LicksIdentityProvider::valid_successor`)
+                    Sender::NewMemberCommit => true,
+                    _ => false,
+                };
+
+                if allowed {
+                    Ok(())
+                } else {
+                    Err(LicksMlsRulesError::RemoveNotAllowed)
+                }
+            }
+            BorrowedProposal::GroupContextExtensions(extensions) => {
+                if !sender_is_admin {
+                    return Err(LicksMlsRulesError::GroupContextNotAllowed);
+                }
+
+                // Removing the roles would make everyone an admin
+                match GroupRoles::from_extensions(extensions) {
+                    Some(new_roles) if !new_roles.admins.is_empty() => Ok(()),
+                    _ if roles.is_none() => Ok(()),
+                    _ => Err(LicksMlsRulesError::NoAdmin),
+                }
+            }
+            BorrowedProposal::ExternalInit(_) if !has_group_link => {
+                Err(LicksMlsRulesError::ExternalJoinNotAllowed)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MlsRules for LicksMlsRules {
+    type Error = LicksMlsRulesError;
+
+    fn filter_proposals(
+        &self,
+        direction: CommitDirection,
+        _source: CommitSource,
+        current_roster: &Roster,
+        current_context: &GroupContext,
+        mut proposals: ProposalBundle,
+    ) -> Result<ProposalBundle, Self::Error> {
+        let roles = GroupRoles::from_extensions(current_context.extensions());
+        let has_group_link =
+            ActiveGroupLink::from_extensions(current_context.extensions()).is_some();
+
+        match direction {
+            CommitDirection::Send => {
+                proposals.retain(|proposal| {
+                    Self::check_proposal(current_roster, roles.as_ref(), has_group_link, proposal)
+                        .is_ok()
+                });
+            }
+            CommitDirection::Receive => {
+                for proposal in proposals.iter_proposals() {
+                    Self::check_proposal(
+                        current_roster,
+                        roles.as_ref(),
+                        has_group_link,
+                        &proposal,
+                    )?;
+                }
+            }
+        }
+
+        Ok(proposals)
+    }
+
+    fn commit_options(
+        &self,
+        _new_roster: &Roster,
+        _new_context: &GroupContext,
+        _proposals: &ProposalBundle,
+    ) -> Result<CommitOptions, Self::Error> {
+        Ok(CommitOptions::default())
+    }
+
+    fn encryption_options(
+        &self,
+        _current_roster: &Roster,
+        _current_context: &GroupContext,
+    ) -> Result<EncryptionOptions, Self::Error> {
+        Ok(EncryptionOptions::default())
+    }
+}
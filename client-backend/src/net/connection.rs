@@ -3,9 +3,12 @@ use std::{fmt::Debug, ops::Deref};
 
 use jenga::{timeout::TimeoutError, Middleware};
 use lib::{
-    api::messages::{
-        ChatServiceMessage, ListenerId, Message, MessageWire, UnauthRequest,
-        MIN_REQUEST_TIMEOUT_SECS,
+    api::{
+        group::GetMessagesRequest,
+        messages::{
+            ChatServiceMessage, ListenerId, Message, MessageWire, UnauthRequest,
+            MIN_REQUEST_TIMEOUT_SECS,
+        },
     },
     crypto::{blinded_address::BlindedAddressPublic, listener::ListenerToken},
 };
@@ -48,6 +51,9 @@ pub enum ConnectionServiceMessage {
     Request(MessageWire),
     Listen(BlindedAddressPublic, mpsc::Sender<ListenerMessage>),
     StopListen(ListenerId),
+    /// Retrieves the messages queued on a blinded address and sends them,
+    /// in order, to the given channel. Completes once the whole queue was sent.
+    RetrieveQueue(GetMessagesRequest, mpsc::Sender<ListenerMessage>),
 }
 
 impl From<MessageWire> for ConnectionServiceMessage {
@@ -102,7 +108,9 @@ impl jenga::Service<ConnectionServiceMessage> for Connection {
                                 .await;
                             Ok(resp)
                         } else {
-                            Err(TimeoutError::ServiceError(RequestError::UnexpectedAnswer))
+                            Err(TimeoutError::ServiceError(RequestError::from_response(
+                                resp,
+                            )))
                         }
                     }
                     Err(e) => Err(e),
@@ -131,6 +139,29 @@ impl jenga::Service<ConnectionServiceMessage> for Connection {
 
                 self.inner.request(message_wire).await
             }
+            ConnectionServiceMessage::RetrieveQueue(get_messages_request, tx) => {
+                let message_wire: MessageWire = Message::Unauth(UnauthRequest::ChatService(
+                    ChatServiceMessage::RetrieveQueue(get_messages_request),
+                ))
+                .into();
+
+                let request_id = message_wire.0;
+                let _ = self.queues.insert_async(request_id, tx).await;
+
+                let resp = self.inner.request(message_wire).await;
+
+                // Already removed if the queue was fully received
+                self.queues.remove_async(&request_id).await;
+
+                match resp? {
+                    resp @ Message::Unauth(UnauthRequest::ChatService(
+                        ChatServiceMessage::QueueDone(_) | ChatServiceMessage::QueueEmpty,
+                    )) => Ok(resp),
+                    other => Err(TimeoutError::ServiceError(RequestError::from_response(
+                        other,
+                    ))),
+                }
+            }
         }
     }
 }
@@ -4,6 +4,7 @@ use anyhow::bail;
 use jenga::Service;
 use lib::{
     api::{
+        group::{DeliveryStamp, GetMessagesRequest},
         messages::{
             AuthRequest, ChatServiceMessage, ListenerId, Message, MessageWire, UnauthRequest,
         },
@@ -117,6 +118,44 @@ impl<C: Connector + Copy + Default> ConnectionManager<C> {
 
         Ok(())
     }
+
+    /// Retrieves the messages queued on `blinded_address` from `from` (included) and
+    /// sends them to `queue_tx` in the order the server received them. Returns the
+    /// number of messages retrieved once the whole queue was sent.
+    pub async fn retrieve_queue(
+        &self,
+        server: &Server,
+        blinded_address: BlindedAddressPublic,
+        from: DeliveryStamp,
+        queue_tx: mpsc::Sender<ListenerMessage>,
+    ) -> anyhow::Result<u64> {
+        let msg = ConnectionServiceMessage::RetrieveQueue(
+            GetMessagesRequest {
+                blinded_address,
+                server_delivery_id: from,
+            },
+            queue_tx,
+        );
+
+        let resp = if let Some(conn) = self.unauth_conns.get_async(server).await {
+            conn.get().request(msg).await?
+        } else {
+            let ws =
+                UnauthConnectionJenga::new(self.connector, server.ws_url_unauth().clone()).await?;
+            let resp = ws.request(msg).await;
+            let _ = self.unauth_conns.insert_async(server.clone(), ws).await;
+
+            resp?
+        };
+
+        match resp {
+            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::QueueDone(count))) => {
+                Ok(count)
+            }
+            Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::QueueEmpty)) => Ok(0),
+            other => bail!("Retrieving queue failed: {other:?}"),
+        }
+    }
 }
 
 #[cfg(test)]
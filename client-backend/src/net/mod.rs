@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use connection::{Connection, ConnectionServiceMessage};
 use jenga::timeout::TimeoutError;
-use lib::api::messages::Message;
+use lib::api::messages::{Message, ServiceErrorDetails};
 
 use crate::manager::account::Profile;
 
@@ -37,6 +37,31 @@ pub enum RequestError {
     Timeout,
     #[error("The request gave an unexpected answer")]
     UnexpectedAnswer,
+    #[error("The server returned an error: {0}")]
+    Service(ServiceErrorDetails),
+}
+
+impl RequestError {
+    /// Turns a response we weren't expecting into an error. If the server answered
+    /// with an error, it is kept as is so that it can be matched on further up
+    /// (e.g. using `anyhow::Error::downcast_ref::<RequestError>()`).
+    pub fn from_response(response: Message) -> Self {
+        match response {
+            Message::Error(err) => Self::Service(err),
+            other => {
+                log::error!("Received unexpected response from the server: {other:?}");
+                Self::UnexpectedAnswer
+            }
+        }
+    }
+
+    /// Returns the error sent back by the server, if there was one.
+    pub fn service_error(&self) -> Option<&ServiceErrorDetails> {
+        match self {
+            Self::Service(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 // Connection + jenga middlewares, notably Restart, which automatically restarts a connection
@@ -1,12 +1,19 @@
 //! A generic connection handler using a stream.
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
 use futures_util::{Sink, SinkExt, Stream, StreamExt};
 use lib::{
     api::messages::{
-        ChatServiceMessage, ClientRequestId, ListenerId, Message, MessageWire, UnauthRequest,
+        ChatServiceMessage, ClientRequestId, ListenerId, Message, MessageWire, ServiceError,
+        ServiceErrorDetails, UnauthRequest,
+    },
+    crypto::{
+        listener::ListenerToken,
+        noise::{ClientHandshake, NoiseError, SupportedHandshakes, MAX_MESSAGE_SIZE},
     },
-    crypto::{listener::ListenerToken, noise::ClientHandshake},
 };
 
 #[cfg(test)]
@@ -27,17 +34,25 @@ type RequestHashmap = Arc<scc::HashMap<ClientRequestId, oneshot::Sender<Message>
 type ListenerHashmap =
     Arc<scc::HashMap<ClientRequestId, (mpsc::Sender<ListenerMessage>, ListenerToken)>>;
 type ListenerIdsHashmap = Arc<scc::HashMap<ListenerId, ClientRequestId>>;
+type QueueHashmap = Arc<scc::HashMap<ClientRequestId, mpsc::Sender<ListenerMessage>>>;
 
 #[derive(Debug)]
 /// A low-level "raw" connection, that just handles bytes in, bytes out.
 /// This struct is not aware of what method is used to connect or
 /// to what server it is connected.
 pub struct RawConnection {
-    pub request_sender: mpsc::Sender<Vec<u8>>,
+    pub request_sender: mpsc::Sender<MessageWire>,
     pub listener_ids: ListenerIdsHashmap,
     pub listening: ListenerHashmap,
+    /// Queue retrievals in progress. The server answers those with one message
+    /// per queued message, followed by [`ChatServiceMessage::QueueDone`] or
+    /// [`ChatServiceMessage::QueueEmpty`] which completes the request.
+    pub queues: QueueHashmap,
     pub requests: RequestHashmap,
     pub cancellation_token: CancellationToken,
+    /// The Noise handshake the connection uses and its hash, set once the
+    /// handshake is complete.
+    handshake: Arc<OnceLock<(SupportedHandshakes, Vec<u8>)>>,
     #[cfg(test)]
     pub(crate) connection_id: Uuid,
 }
@@ -48,7 +63,7 @@ impl RawConnection {
         stream: S,
     ) -> Self {
         let (mut sender, mut receiver) = stream.split();
-        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(16);
+        let (tx, mut rx) = mpsc::channel::<MessageWire>(16);
 
         let cancellation_token = CancellationToken::new();
         let cancellation_token_clone = cancellation_token.clone();
@@ -59,57 +74,113 @@ impl RawConnection {
         let listening: ListenerHashmap = scc::HashMap::new().into();
         let listening_clone = listening.clone();
 
+        let queues: QueueHashmap = scc::HashMap::new().into();
+        let queues_clone = queues.clone();
+
+        let handshake: Arc<OnceLock<(SupportedHandshakes, Vec<u8>)>> = Arc::default();
+        let handshake_clone = handshake.clone();
+
         tokio::task::spawn(async move {
             // Encryption is done at the connection level, not at the request level,
             // so it should be handled here
 
-            // Start encryption
-            let handshake = ClientHandshake::prepare_handshake().expect("todo");
+            // Start encryption. The default handshake is post-quantum, see `SupportedHandshakes`
+            // If it fails, returning drops `rx`, so the connection is seen as closed
+            let client_handshake = match ClientHandshake::prepare_handshake() {
+                Ok(handshake) => handshake,
+                Err(error) => {
+                    log::error!("Couldn't start the Noise handshake: {error}");
+                    return;
+                }
+            };
 
-            if let Err(_) = sender.send(handshake.buffer.read().to_vec()).await {
-                panic!("Connection closed during Noise handshake");
+            if sender
+                .send(client_handshake.buffer.read().to_vec())
+                .await
+                .is_err()
+            {
+                log::warn!("Connection closed during Noise handshake");
+                return;
             }
 
             // Wait for server response
-            let server_response = receiver
-                .next()
-                .await
-                .expect("Server responds to Noise handshake");
+            let Some(server_response) = receiver.next().await else {
+                log::warn!("Connection closed during Noise handshake");
+                return;
+            };
+
+            let (mut transport, buffer) =
+                match client_handshake.complete_handshake(&server_response) {
+                    Ok(completed) => completed,
+                    Err(error) => {
+                        log::warn!("The server's Noise handshake is invalid: {error}");
+                        return;
+                    }
+                };
 
-            let (mut transport, buffer) = handshake
-                .complete_handshake(&server_response)
-                .expect("Noise handshake succeeds");
+            let _ =
+                handshake_clone.set((transport.handshake(), transport.handshake_hash().to_vec()));
 
             // Send our final payload to server
-            if let Err(_) = sender.send(buffer.read().to_vec()).await {
-                panic!("Connection closed during Noise handshake");
+            if sender.send(buffer.read().to_vec()).await.is_err() {
+                log::warn!("Connection closed during Noise handshake");
+                return;
             }
 
-            loop {
+            'connection: loop {
                 tokio::select! {
                     // Received a request from ConnectionManager,
                     // send it to the stream
-                    Some(unencrypted_req) = rx.recv() => {
-                        // Encrypt before sending
-                        let Ok(encrypted_request) = transport.write(&unencrypted_req) else {
-                            rx.close();
-                            return;
+                    Some(request) = rx.recv() => {
+                        let request_id = request.0;
+
+                        // Encrypt before sending. Big requests are split in several frames
+                        let encrypted_frames = match transport.write_message(&request.to_bytes()) {
+                            Ok(encrypted_frames) => encrypted_frames,
+                            Err(NoiseError::MessageTooLarge) => {
+                                // Only this request fails, the connection can still be used
+                                log::warn!("Request {request_id:?} is too large to be sent");
+
+                                listening_clone.remove_async(&request_id).await;
+                                queues_clone.remove_async(&request_id).await;
+                                if let Some((_, tx)) = requests_clone.remove_async(&request_id).await {
+                                    let error = ServiceErrorDetails::new(
+                                        ServiceError::QuotaExceeded,
+                                        "The request is larger than the maximum message size",
+                                    )
+                                    .with_quota(MAX_MESSAGE_SIZE as u64);
+                                    // if request got dropped, ignore result
+                                    let _ = tx.send(Message::Error(error));
+                                }
+                                continue;
+                            }
+                            Err(_) => {
+                                rx.close();
+                                break;
+                            }
                         };
 
-                        if sender.send(encrypted_request.to_vec()).await.is_err() {
-                            log::error!("Connection unexpectedly closed when trying to send request message");
-                            rx.close();
-                            return;
+                        for encrypted_frame in encrypted_frames {
+                            if sender.send(encrypted_frame).await.is_err() {
+                                log::error!("Connection unexpectedly closed when trying to send request message");
+                                rx.close();
+                                break 'connection;
+                            }
                         }
                     },
                     // Received a response from the connection
                     Some(bytes) = receiver.next() => {
-                        let Ok(decrypted_bytes) = transport.read(&bytes) else {
-                            rx.close();
-                            return;
+                        let decrypted_bytes = match transport.read_frame(&bytes) {
+                            Ok(Some(decrypted_bytes)) => decrypted_bytes,
+                            // The rest of the message is in the next frames
+                            Ok(None) => continue,
+                            Err(_) => {
+                                rx.close();
+                                break;
+                            }
                         };
 
-                        if let Ok(msg) = MessageWire::from_bytes(decrypted_bytes) {
+                        if let Ok(msg) = MessageWire::from_bytes(&decrypted_bytes) {
                             let request_id = msg.0;
                             if request_id.is_nil() {
                                 // Not a heartbeat?
@@ -136,6 +207,22 @@ impl RawConnection {
                                         listening_clone.remove_async(&request_id).await;
                                     }
                                 }
+                            } else if let Some(entry) = queues_clone.get_async(&request_id).await {
+                                match msg.1 {
+                                    Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::MlsMessage(timestamp, msg_bytes))) => {
+                                        // if retrieval got dropped, ignore result
+                                        let _ = entry.get().send((timestamp, msg_bytes)).await;
+                                    },
+                                    other => {
+                                        // The queue is done (or the request failed), complete the request
+                                        drop(entry);
+                                        queues_clone.remove_async(&request_id).await;
+
+                                        if let Some((_, tx)) = requests_clone.remove_async(&request_id).await {
+                                            let _ = tx.send(other);
+                                        }
+                                    }
+                                }
                             } else if let Some((_, tx)) = requests_clone.remove_async(&request_id).await {
                                 log::debug!("Received response for request {request_id:?}. Sending back to manager");
                                 // if request got dropped, ignore result
@@ -153,32 +240,41 @@ impl RawConnection {
 
                         let heartbeat = MessageWire(ClientRequestId::generate(), Message::Ping(vec![72, 66])).to_bytes();
 
-                        let Ok(encrypted_heartbeat) = transport.write(&heartbeat) else {
+                        let Ok(encrypted_heartbeat) = transport.write_message(&heartbeat) else {
                             rx.close();
-                            return;
+                            break;
                         };
 
-                        if sender.send(encrypted_heartbeat.to_vec()).await.is_err() {
-                            log::warn!("Connection channel sender errored out, so we're closing it.");
-                            return;
+                        for encrypted_frame in encrypted_heartbeat {
+                            if sender.send(encrypted_frame).await.is_err() {
+                                log::warn!("Connection channel sender errored out, so we're closing it.");
+                                break 'connection;
+                            }
                         }
                     }
                     () = cancellation_token_clone.cancelled() => {
-                        return;
+                        break;
                     },
                     else => {
-                        return;
+                        break;
                     }
                 }
             }
+
+            // Dropping the senders lets listeners know that they won't receive
+            // anything from this connection anymore, so that they can subscribe again.
+            listening_clone.clear_async().await;
+            queues_clone.clear_async().await;
         });
 
         Self {
             request_sender: tx,
             listening,
+            queues,
             listener_ids: scc::HashMap::new().into(),
             requests,
             cancellation_token,
+            handshake,
             #[cfg(test)]
             connection_id: generate_uuid(),
         }
@@ -187,6 +283,18 @@ impl RawConnection {
     pub fn is_open(&self) -> bool {
         !(self.cancellation_token.is_cancelled() || self.request_sender.is_closed())
     }
+
+    /// The Noise handshake used by the connection, if it's done. We always start
+    /// the default one, see [`SupportedHandshakes`].
+    pub fn handshake(&self) -> Option<SupportedHandshakes> {
+        self.handshake.get().map(|(handshake, _)| *handshake)
+    }
+
+    /// The hash of the connection's Noise handshake, if it's done. Requests are only
+    /// sent once it is, so this is always `Some` after receiving a response.
+    pub fn handshake_hash(&self) -> Option<&[u8]> {
+        self.handshake.get().map(|(_, hash)| hash.as_slice())
+    }
 }
 
 impl Drop for RawConnection {
@@ -195,6 +303,40 @@ impl Drop for RawConnection {
     }
 }
 
+/// A request that was sent, but that hasn't been answered yet.
+///
+/// If this gets dropped before the answer came back (which is what happens
+/// when jenga's `Timeout` middleware gives up on the request), we tell the
+/// server to cancel the request so that it stops working on it.
+struct PendingRequest<'a> {
+    connection: &'a RawConnection,
+    request_id: ClientRequestId,
+    answered: bool,
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        if self.answered {
+            return;
+        }
+
+        self.connection.requests.remove(&self.request_id);
+
+        log::debug!(
+            "Request {:?} was dropped before being answered, cancelling it",
+            self.request_id
+        );
+
+        // We can't wait in `drop`, so if the channel is full we just
+        // let the server finish the request and ignore its answer.
+        let cancel = MessageWire(
+            ClientRequestId::generate(),
+            Message::Cancel(self.request_id),
+        );
+        let _ = self.connection.request_sender.try_send(cancel);
+    }
+}
+
 impl jenga::Service<MessageWire> for RawConnection {
     type Response = Message;
     type Error = RequestError;
@@ -206,21 +348,191 @@ impl jenga::Service<MessageWire> for RawConnection {
         let request_id = wire.0;
         let _ = self.requests.insert_async(request_id, tx).await;
 
-        self.request_sender
-            .send(wire.to_bytes())
+        let mut pending = PendingRequest {
+            connection: self,
+            request_id,
+            answered: false,
+        };
+
+        self.request_sender.send(wire).await.map_err(|_| {
+            log::info!("Connection: Tried sending request {request_id:?} but connection was down");
+
+            RequestError::SendConnectionClosed
+        })?;
+
+        let resp = rx.await;
+
+        // Whether we got an answer or the connection closed,
+        // there is nothing left to cancel
+        pending.answered = true;
+
+        resp.map_err(|_| RequestError::ReceiveConnectionClosed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use jenga::Service;
+    use lib::crypto::noise::{NoiseTransport, ServerHandshake};
+
+    use super::*;
+
+    /// A stream whose other end is held by the test.
+    struct TestStream {
+        incoming: mpsc::UnboundedReceiver<Vec<u8>>,
+        outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    }
+
+    impl Stream for TestStream {
+        type Item = Vec<u8>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.incoming.poll_recv(cx)
+        }
+    }
+
+    impl Sink<Vec<u8>> for TestStream {
+        type Error = ();
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, item: Vec<u8>) -> Result<(), ()> {
+            self.outgoing.send(item).map_err(|_| ())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Starts a connection, and plays the server's side of the Noise handshake.
+    /// Returns the connection, the end of the stream receiving what the client
+    /// sends, and the server's transport.
+    async fn connect() -> (
+        RawConnection,
+        mpsc::UnboundedSender<Vec<u8>>,
+        mpsc::UnboundedReceiver<Vec<u8>>,
+        NoiseTransport,
+    ) {
+        let (to_client, incoming) = mpsc::unbounded_channel();
+        let (outgoing, mut from_client) = mpsc::unbounded_channel();
+        let connection = RawConnection::start(TestStream { incoming, outgoing });
+
+        let client_handshake = from_client.recv().await.expect("client starts handshake");
+        let server_handshake =
+            ServerHandshake::respond(&client_handshake).expect("handshake is valid");
+        to_client
+            .send(server_handshake.buffer.read().to_vec())
+            .expect("client is connected");
+        let client_response = from_client.recv().await.expect("client ends handshake");
+        let transport = server_handshake
+            .complete_handshake(&client_response)
+            .expect("handshake is valid");
+
+        (connection, to_client, from_client, transport)
+    }
+
+    #[tokio::test]
+    async fn dropped_requests_are_cancelled() {
+        let (connection, _to_client, mut from_client, mut transport) = connect().await;
+
+        // The server never answers, so the request gets dropped
+        let request_id = ClientRequestId::generate();
+        assert!(
+            tokio::time::timeout(
+                Duration::from_millis(100),
+                connection.request(MessageWire(request_id, Message::Ignore)),
+            )
             .await
-            .map_err(|_| {
-                log::info!(
-                    "Connection: Tried sending request {request_id:?} but connection was down"
-                );
+            .is_err(),
+            "The request should not be answered"
+        );
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let frame = from_client.recv().await.expect("client is connected");
+            if let Some(bytes) = transport.read_frame(&frame).expect("frame is valid") {
+                received.push(MessageWire::from_bytes(&bytes).expect("message is valid").1);
+            }
+        }
 
-                RequestError::SendConnectionClosed
-            })?;
+        assert_eq!(
+            received,
+            vec![Message::Ignore, Message::Cancel(request_id)],
+            "Dropping the request should tell the server to cancel it"
+        );
+        assert!(
+            connection.requests.is_empty(),
+            "The dropped request should be forgotten"
+        );
+    }
 
-        let Ok(resp) = rx.await else {
-            return Err(RequestError::ReceiveConnectionClosed);
-        };
+    #[tokio::test]
+    async fn too_large_requests_fail_alone() {
+        let (connection, to_client, mut from_client, mut transport) = connect().await;
+
+        let answer = connection
+            .request(MessageWire(
+                ClientRequestId::generate(),
+                Message::Ping(vec![0; MAX_MESSAGE_SIZE]),
+            ))
+            .await;
+        assert!(
+            matches!(
+                answer,
+                Ok(Message::Error(ServiceErrorDetails {
+                    code: ServiceError::QuotaExceeded,
+                    ..
+                }))
+            ),
+            "A request too large to be sent should be answered with an error, got {answer:?}"
+        );
+        assert!(
+            connection.is_open(),
+            "A request too large to be sent shouldn't close the connection"
+        );
+
+        // The next request still goes through
+        let request_id = ClientRequestId::generate();
+        let request = tokio::spawn(async move {
+            connection
+                .request(MessageWire(request_id, Message::Ping(vec![1])))
+                .await
+        });
 
-        Ok(resp)
+        let frame = from_client.recv().await.expect("client is connected");
+        let bytes = transport
+            .read_frame(&frame)
+            .expect("frame is valid")
+            .expect("the request fits in one frame");
+        let received = MessageWire::from_bytes(&bytes).expect("message is valid");
+        assert_eq!(
+            (received.0, received.1),
+            (request_id, Message::Ping(vec![1])),
+            "The server should only receive the request that fits"
+        );
+
+        for frame in transport
+            .write_message(&MessageWire(request_id, Message::Pong(vec![1])).to_bytes())
+            .expect("the answer fits")
+        {
+            to_client.send(frame).expect("client is connected");
+        }
+        assert_eq!(
+            request.await.expect("request task doesn't panic").ok(),
+            Some(Message::Pong(vec![1])),
+            "The request should be answered"
+        );
     }
 }
@@ -74,7 +74,12 @@ impl jenga::Service<Arc<Profile>> for WebsocketConnector {
             return Err(ConnectionError::AuthChallengeFailed);
         };
 
-        let challenge_response = msg.get_auth_challenge_response(server_challenge);
+        // Binds our response to this connection
+        let handshake_hash = unauth_conn
+            .handshake_hash()
+            .ok_or(ConnectionError::AuthChallengeFailed)?;
+
+        let challenge_response = msg.get_auth_challenge_response(server_challenge, handshake_hash);
 
         let challenge_2 = unauth_conn
             .request(MessageWire::from(Message::ChallengeResponse(challenge_response)).into())
@@ -91,7 +96,10 @@ impl jenga::Service<Arc<Profile>> for WebsocketConnector {
 #[cfg(test)]
 mod tests {
     use jenga::Service;
-    use lib::{api::server::Server, crypto::usernames::Username};
+    use lib::{
+        api::server::Server,
+        crypto::{noise::SupportedHandshakes, usernames::Username},
+    };
 
     use crate::account::register;
 
@@ -106,6 +114,35 @@ mod tests {
         assert!(conn.is_open());
     }
 
+    #[tokio::test]
+    async fn connections_use_hybrid_handshake() {
+        let url = Server::localhost().ws_url_unauth();
+        let conn = WebsocketConnector
+            .request(url)
+            .await
+            .expect("Connection works");
+
+        // Requests are only sent once the server completed the handshake
+        let response = conn
+            .request(MessageWire::from(Message::Ping(vec![4, 2])).into())
+            .await
+            .expect("Server answers through the hybrid session");
+        assert_eq!(
+            response,
+            Message::Pong(vec![4, 2]),
+            "Server should answer the ping"
+        );
+        assert_eq!(
+            conn.handshake(),
+            Some(SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768),
+            "Connections should complete the post-quantum handshake"
+        );
+        assert!(
+            conn.handshake_hash().is_some(),
+            "Hybrid handshake should be complete"
+        );
+    }
+
     #[tokio::test]
     async fn auth_connector_works() {
         let server = Server::localhost();
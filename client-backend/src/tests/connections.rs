@@ -74,7 +74,8 @@ impl Stream for FakeConnection {
 
         if let Some(req) = wire {
             Poll::Ready(Some(
-                MessageWire(req.0, Message::Error(ServiceError::InvalidOperation)).to_bytes(),
+                MessageWire(req.0, Message::Error(ServiceError::InvalidOperation.into()))
+                    .to_bytes(),
             ))
         } else {
             Poll::Ready(None)
@@ -158,8 +159,9 @@ impl Stream for FakeAuthenticatedConnection {
                 }
                 ChallengeState::Waiting => {
                     if let Message::ChallengeResponse(resp) = req.1 {
+                        // The fake connection has no Noise session to bind the challenge to
                         if resp
-                            .verify(self.2.expect("Challenge was generated"))
+                            .verify(self.2.expect("Challenge was generated"), &[])
                             .is_ok()
                         {
                             self.1 = ChallengeState::Completed;
@@ -174,7 +176,8 @@ impl Stream for FakeAuthenticatedConnection {
                     }
                 }
                 ChallengeState::Completed => Poll::Ready(Some(
-                    MessageWire(req.0, Message::Error(ServiceError::InvalidOperation)).to_bytes(),
+                    MessageWire(req.0, Message::Error(ServiceError::InvalidOperation.into()))
+                        .to_bytes(),
                 )),
                 ChallengeState::Failed => Poll::Ready(None),
             }
@@ -1,9 +1,17 @@
 mod tests {
-    use std::time::Duration;
+    use std::{sync::atomic::Ordering, time::Duration};
 
     use mls_rs::MlsMessage;
 
-    use crate::{client::Client, messages::Content};
+    use lib::{crypto::device_link::DeviceLinkCode, identifiers::LicksIdentifier};
+
+    use crate::{
+        client::{Client, ClientProfile},
+        database::outbox::OutboxStatus,
+        manager::groups::ProcessedMessage,
+        messages::{Content, MessageReference},
+        mls::extensions::GroupMetadata,
+    };
 
     #[tokio::test]
     pub async fn two_person_conversation_test() {
@@ -34,7 +42,7 @@ mod tests {
         let bobs_group_id = bobs_group_ui.group_identifier;
 
         let alices_welcome = bob_manager
-            .create_new_welcome(bobs_group_id, alice_manager.profile.get_account_id())
+            .create_new_welcome(bobs_group_id, alice_manager.get_profile().get_account_id())
             .await
             .expect("welcome should have been created");
 
@@ -149,7 +157,7 @@ mod tests {
         let alice_welcome = bob_manager
             .create_new_welcome(
                 bobs_group_ui.group_identifier,
-                alice_manager.profile.get_account_id(),
+                alice_manager.get_profile().get_account_id(),
             )
             .await
             .expect("welcome should have been created");
@@ -201,7 +209,7 @@ mod tests {
         let charlie_welcome = bob_manager
             .create_new_welcome(
                 bobs_group_ui.group_identifier,
-                charlie_manager.profile.get_account_id(),
+                charlie_manager.get_profile().get_account_id(),
             )
             .await
             .expect("welcome should have been created");
@@ -244,4 +252,1628 @@ mod tests {
             assert_eq!(alice_message_content, Some(charlies_first_message_content));
         }
     }
+
+    #[tokio::test]
+    pub async fn remove_member_and_leave_group() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        alice_manager
+            .upload_new_key_packages(1)
+            .await
+            .expect("keypackage should have uploaded");
+
+        let charlie_manager = client
+            .get_in_memory_profile("charlie")
+            .await
+            .expect("server is open and registration works");
+
+        charlie_manager
+            .upload_new_key_packages(1)
+            .await
+            .expect("keypackage should have uploaded");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let bobs_group_id = bob_manager
+            .create_new_group(String::from("Bob's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        for manager in [&alice_manager, &charlie_manager] {
+            let welcome = bob_manager
+                .create_new_welcome(bobs_group_id, manager.get_profile().get_account_id())
+                .await
+                .expect("welcome should have been created");
+
+            manager
+                .join_group_from_welcome_and_listen(&welcome)
+                .await
+                .expect("group should have been made from welcome");
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+
+        // Bob removes Charlie, who deletes the group once the commit arrives
+        bob_manager
+            .remove_member(
+                bobs_group_id,
+                charlie_manager.get_profile().get_account_id(),
+            )
+            .await
+            .expect("Charlie should have been removed");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(
+            !charlie_manager
+                .get_all_group_ids()
+                .expect("groups can be listed")
+                .contains(&bobs_group_id),
+            "Charlie was removed so the group should have been deleted"
+        );
+        assert!(
+            !client
+                .listener_manager
+                .listeners
+                .contains_async(&(charlie_manager.profile_manager.clone(), bobs_group_id))
+                .await,
+            "Charlie's listener was removed along with the group"
+        );
+
+        assert!(
+            bob_manager
+                .remove_member(
+                    bobs_group_id,
+                    charlie_manager.get_profile().get_account_id()
+                )
+                .await
+                .is_err(),
+            "Charlie isn't a member anymore"
+        );
+
+        // Alice leaves, Bob commits her removal
+        alice_manager
+            .leave_group(bobs_group_id)
+            .await
+            .expect("Alice should have left the group");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(
+            !alice_manager
+                .get_all_group_ids()
+                .expect("groups can be listed")
+                .contains(&bobs_group_id),
+            "Alice left so the group should have been deleted"
+        );
+        assert!(
+            !client
+                .listener_manager
+                .listeners
+                .contains_async(&(alice_manager.profile_manager.clone(), bobs_group_id))
+                .await,
+            "Alice stopped listening to the group she left"
+        );
+
+        assert!(
+            bob_manager
+                .remove_member(bobs_group_id, alice_manager.get_profile().get_account_id())
+                .await
+                .is_err(),
+            "Bob committed Alice's removal, so she isn't a member anymore"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn only_one_member_commits_a_proposal() {
+        let (client, _rx) = Client::new();
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let bobs_group_id = bob_manager
+            .create_new_group(String::from("Bob's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let mut members = Vec::new();
+        for name in ["alice", "charlie", "dave"] {
+            let manager = client
+                .get_in_memory_profile(name)
+                .await
+                .expect("server is open and registration works");
+            manager
+                .upload_new_key_packages(2)
+                .await
+                .expect("keypackage should have uploaded");
+
+            let welcome = bob_manager
+                .create_new_welcome(bobs_group_id, manager.get_profile().get_account_id())
+                .await
+                .expect("welcome should have been created");
+            manager
+                .join_group_from_welcome_and_listen(&welcome)
+                .await
+                .expect("group should have been made from welcome");
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            members.push(manager);
+        }
+        let [alice_manager, charlie_manager, dave_manager] = members
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("Three members joined"));
+
+        let (_, epoch) = bob_manager
+            .group_manager
+            .get_blinded_address_and_epoch(&bobs_group_id)
+            .expect("Bob is in the group");
+
+        // Dave's removal is a standalone proposal, which only Bob commits
+        dave_manager
+            .leave_group(bobs_group_id)
+            .await
+            .expect("Dave should have left the group");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let (blinded_address, new_epoch) = bob_manager
+            .group_manager
+            .get_blinded_address_and_epoch(&bobs_group_id)
+            .expect("Bob is still in the group");
+        assert_eq!(new_epoch, epoch + 1, "Dave's removal was committed once");
+        for manager in [&alice_manager, &charlie_manager] {
+            let (members_blinded_address, members_epoch) = manager
+                .group_manager
+                .get_blinded_address_and_epoch(&bobs_group_id)
+                .expect("The other members are still in the group");
+            assert_eq!(
+                (members_blinded_address.to_bytes(), members_epoch),
+                (blinded_address.to_bytes(), new_epoch),
+                "Every member applied the same commit"
+            );
+        }
+
+        let content = Content::plain_text("Dave left".to_owned());
+        alice_manager
+            .send_application_message(&bobs_group_id, content.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        for manager in [&bob_manager, &charlie_manager] {
+            assert_eq!(
+                manager
+                    .message_log
+                    .lock()
+                    .await
+                    .last()
+                    .map(|(_, message)| &message.content),
+                Some(&content),
+                "Processing the proposal didn't stop any listener"
+            );
+        }
+
+        // The messages below are processed directly
+        for manager in [&alice_manager, &bob_manager, &charlie_manager] {
+            client
+                .listener_manager
+                .stop(manager.profile_manager.clone(), bobs_group_id)
+                .await
+                .expect("Every member was listening to the group");
+        }
+
+        let load_group = |manager: &ClientProfile| {
+            manager
+                .group_manager
+                .mls_client()
+                .load_group(bobs_group_id.as_uuid().as_bytes())
+                .expect("The group can be loaded")
+        };
+        let bobs_index = load_group(&bob_manager).current_member_index();
+        let proposal = load_group(&charlie_manager)
+            .propose_remove(bobs_index, Vec::new())
+            .expect("Charlie can propose to remove Bob")
+            .to_bytes()
+            .expect("The proposal can be serialized");
+
+        // Neither its sender nor the member it removes commit it
+        assert!(
+            matches!(
+                alice_manager
+                    .group_manager
+                    .process_incoming_message(&bobs_group_id, &proposal),
+                Ok(ProcessedMessage::Proposal(true))
+            ),
+            "Alice has the lowest leaf index left, so she commits it"
+        );
+        assert!(
+            matches!(
+                bob_manager
+                    .group_manager
+                    .process_incoming_message(&bobs_group_id, &proposal),
+                Ok(ProcessedMessage::Proposal(false))
+            ),
+            "Bob can't commit his own removal"
+        );
+        assert!(
+            matches!(
+                charlie_manager
+                    .group_manager
+                    .process_incoming_message(&bobs_group_id, &proposal),
+                Ok(ProcessedMessage::Ignore)
+            ),
+            "Charlie ignores his own proposal"
+        );
+
+        let other_group_id = bob_manager
+            .create_new_group(String::from("Bob's other group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+        let welcome = bob_manager
+            .create_new_welcome(other_group_id, dave_manager.get_profile().get_account_id())
+            .await
+            .expect("welcome should have been created")
+            .to_bytes()
+            .expect("The welcome can be serialized");
+        assert!(
+            matches!(
+                alice_manager
+                    .group_manager
+                    .process_incoming_message(&bobs_group_id, &welcome),
+                Ok(ProcessedMessage::Ignore)
+            ),
+            "A Welcome sent to the group is ignored"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn group_metadata_is_shared() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        alice_manager
+            .upload_new_key_packages(1)
+            .await
+            .expect("keypackage should have uploaded");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let bobs_group_id = bob_manager
+            .create_new_group(
+                String::from("Bob's group"),
+                String::from("The place where Bob's friends hang out and talk").into(),
+            )
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let welcome = bob_manager
+            .create_new_welcome(bobs_group_id, alice_manager.get_profile().get_account_id())
+            .await
+            .expect("welcome should have been created");
+
+        alice_manager
+            .join_group_from_welcome_and_listen(&welcome)
+            .await
+            .expect("group should have been made from welcome");
+
+        let (name, description, _, _) = alice_manager
+            .sqlite_database
+            .get_group_info(bobs_group_id)
+            .expect("Alice saved the group info");
+        assert_eq!(name, "Bob's group", "Alice got the name from the welcome");
+        assert_eq!(
+            description.as_deref(),
+            Some("The place where Bob's friends hang out and talk"),
+            "Alice got the description from the welcome"
+        );
+
+        let group_ui = bob_manager
+            .update_group_metadata(
+                bobs_group_id,
+                GroupMetadata::new(String::from("Bob's new group"), None),
+            )
+            .await
+            .expect("metadata should have been updated");
+        assert_eq!(group_ui.name(), "Bob's new group", "Bob renamed the group");
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let (name, description, _, _) = alice_manager
+            .sqlite_database
+            .get_group_info(bobs_group_id)
+            .expect("Alice saved the group info");
+        assert_eq!(name, "Bob's new group", "Alice got the new name");
+        assert_eq!(description, None, "Bob removed the description");
+    }
+
+    #[tokio::test]
+    pub async fn only_admins_can_manage_the_group() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        alice_manager
+            .upload_new_key_packages(1)
+            .await
+            .expect("keypackage should have uploaded");
+
+        let charlie_manager = client
+            .get_in_memory_profile("charlie")
+            .await
+            .expect("server is open and registration works");
+
+        charlie_manager
+            .upload_new_key_packages(1)
+            .await
+            .expect("keypackage should have uploaded");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let bobs_group_id = bob_manager
+            .create_new_group(String::from("Bob's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let welcome = bob_manager
+            .create_new_welcome(bobs_group_id, alice_manager.get_profile().get_account_id())
+            .await
+            .expect("welcome should have been created");
+
+        alice_manager
+            .join_group_from_welcome_and_listen(&welcome)
+            .await
+            .expect("group should have been made from welcome");
+
+        // Alice is a regular member
+        assert!(
+            alice_manager
+                .create_new_welcome(
+                    bobs_group_id,
+                    charlie_manager.get_profile().get_account_id()
+                )
+                .await
+                .is_err(),
+            "Only admins can add members"
+        );
+        assert!(
+            alice_manager
+                .remove_member(bobs_group_id, bob_manager.get_profile().get_account_id())
+                .await
+                .is_err(),
+            "Only admins can remove other members"
+        );
+        assert!(
+            alice_manager
+                .update_group_metadata(
+                    bobs_group_id,
+                    GroupMetadata::new(String::from("Alice's group"), None)
+                )
+                .await
+                .is_err(),
+            "Only admins can rename the group"
+        );
+        assert!(
+            bob_manager
+                .demote_member(bobs_group_id, bob_manager.get_profile().get_account_id())
+                .await
+                .is_err(),
+            "Bob is the only admin"
+        );
+
+        bob_manager
+            .promote_member(bobs_group_id, alice_manager.get_profile().get_account_id())
+            .await
+            .expect("Bob is an admin");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let welcome = alice_manager
+            .create_new_welcome(
+                bobs_group_id,
+                charlie_manager.get_profile().get_account_id(),
+            )
+            .await
+            .expect("Alice is now an admin");
+
+        charlie_manager
+            .join_group_from_welcome_and_listen(&welcome)
+            .await
+            .expect("group should have been made from welcome");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        bob_manager
+            .demote_member(bobs_group_id, bob_manager.get_profile().get_account_id())
+            .await
+            .expect("Alice is still an admin");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(
+            bob_manager
+                .remove_member(
+                    bobs_group_id,
+                    charlie_manager.get_profile().get_account_id()
+                )
+                .await
+                .is_err(),
+            "Bob isn't an admin anymore"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn invite_through_inbox() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        alice_manager
+            .upload_new_key_packages(1)
+            .await
+            .expect("keypackage should have uploaded");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let bobs_group_id = bob_manager
+            .create_new_group(String::from("Bob's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        bob_manager
+            .invite_member(bobs_group_id, alice_manager.get_profile().get_account_id())
+            .await
+            .expect("invite should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let invites = alice_manager
+            .get_pending_invites()
+            .expect("invites can be listed");
+        assert_eq!(invites.len(), 1, "Alice received Bob's invite");
+
+        let group_id = alice_manager
+            .accept_invite(&invites[0])
+            .await
+            .expect("Alice can join the group");
+        assert_eq!(group_id, bobs_group_id, "Alice joined Bob's group");
+
+        assert!(
+            alice_manager
+                .get_pending_invites()
+                .expect("invites can be listed")
+                .is_empty(),
+            "The invite was accepted"
+        );
+        assert!(
+            alice_manager.accept_invite(&invites[0]).await.is_err(),
+            "An invite can only be accepted once"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn join_through_group_link() {
+        let (client, _rx) = Client::new();
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let charlie_manager = client
+            .get_in_memory_profile("charlie")
+            .await
+            .expect("server is open and registration works");
+
+        let bobs_group_id = bob_manager
+            .create_new_group(String::from("Bob's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = bob_manager
+            .create_group_link(bobs_group_id)
+            .await
+            .expect("Bob is an admin")
+            .to_link_string();
+
+        let group_id = alice_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Alice can join with the link");
+        assert_eq!(group_id, bobs_group_id, "Alice joined Bob's group");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(
+            alice_manager
+                .create_group_link(bobs_group_id)
+                .await
+                .is_err(),
+            "Only admins can create links"
+        );
+
+        let new_link = bob_manager
+            .rotate_group_link(bobs_group_id)
+            .await
+            .expect("Bob is an admin")
+            .to_link_string();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(
+            charlie_manager.join_group_from_link(&link).await.is_err(),
+            "The previous link doesn't work anymore"
+        );
+
+        let group_id = charlie_manager
+            .join_group_from_link(&new_link)
+            .await
+            .expect("Charlie can join with the new link, after Alice joined");
+        assert_eq!(group_id, bobs_group_id, "Charlie joined Bob's group");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        bob_manager
+            .revoke_group_link(bobs_group_id)
+            .await
+            .expect("Bob is an admin");
+
+        assert!(
+            bob_manager
+                .get_group_link(&bobs_group_id)
+                .expect("group exists")
+                .is_none(),
+            "The link was revoked"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn receive_missed_messages_once_in_order() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        client
+            .listener_manager
+            .stop(bob_manager.profile_manager.clone(), alices_group_id)
+            .await
+            .expect("Bob was listening to the group");
+        let logged_before = bob_manager.message_log.lock().await.len();
+
+        // The commit in the middle moves the group to a new epoch while Bob is away
+        let first = Content::plain_text("First".to_owned());
+        alice_manager
+            .send_application_message(&alices_group_id, first.clone())
+            .await
+            .expect("application message should have been sent");
+        alice_manager
+            .update_group_metadata(
+                alices_group_id,
+                GroupMetadata::new(String::from("Alice's renamed group"), None),
+            )
+            .await
+            .expect("metadata should have been updated");
+        let second = Content::plain_text("Second".to_owned());
+        alice_manager
+            .send_application_message(&alices_group_id, second.clone())
+            .await
+            .expect("application message should have been sent");
+        let third = Content::plain_text("Third".to_owned());
+        alice_manager
+            .send_application_message(&alices_group_id, third.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(
+            bob_manager.message_log.lock().await.len(),
+            logged_before,
+            "Bob doesn't receive anything while his listener is stopped"
+        );
+
+        client
+            .listener_manager
+            .listen(
+                bob_manager.profile_manager.clone(),
+                alices_group_id,
+                client.notification_manager.clone(),
+            )
+            .await
+            .expect("Bob can listen to the group again");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let received: Vec<Content> = bob_manager.message_log.lock().await[logged_before..]
+            .iter()
+            .filter(|(group_id, _)| *group_id == alices_group_id)
+            .map(|(_, message)| message.content.clone())
+            .collect();
+        assert_eq!(
+            received,
+            vec![first, second, third],
+            "Bob receives every missed message exactly once, in order"
+        );
+
+        let (name, _, _, _) = bob_manager
+            .sqlite_database
+            .get_group_info(alices_group_id)
+            .expect("Bob saved the group info");
+        assert_eq!(
+            name, "Alice's renamed group",
+            "Bob processed the commit he missed"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn link_second_device() {
+        let (client, _rx) = Client::new();
+        let alice_laptop = client
+            .get_in_memory_profile("alice_laptop")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_laptop
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_laptop
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // The new device shows the code, which is typed on the laptop
+        let code = DeviceLinkCode::generate();
+        let (new_device_id, alice_phone) = tokio::join!(
+            alice_laptop.link_new_device(&code.to_code_string()),
+            client.link_in_memory_profile("alice_phone", &code),
+        );
+        let new_device_id = new_device_id.expect("The laptop can link a device");
+        let alice_phone = alice_phone.expect("The phone can be linked");
+
+        assert_eq!(
+            alice_phone.get_profile().get_account_id(),
+            alice_laptop.get_profile().get_account_id(),
+            "Both devices use the same account"
+        );
+        assert_eq!(
+            alice_phone.get_profile().get_device_id(),
+            new_device_id,
+            "The laptop linked the DeviceId the phone generated"
+        );
+        assert_ne!(
+            alice_phone.get_profile().get_device_secret_key(),
+            alice_laptop.get_profile().get_device_secret_key(),
+            "The phone generated its own device key"
+        );
+        assert_eq!(
+            alice_phone.get_profile().share_account_key(),
+            alice_laptop.get_profile().share_account_key(),
+            "The phone received the account key"
+        );
+
+        let mut phone_groups = alice_phone
+            .get_all_group_ids()
+            .expect("groups can be listed");
+        phone_groups.sort();
+        let mut laptop_groups = alice_laptop
+            .get_all_group_ids()
+            .expect("groups can be listed");
+        laptop_groups.sort();
+        assert_eq!(
+            phone_groups, laptop_groups,
+            "The phone was added to all of the laptop's groups, including the self group"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let bobs_message = Content::plain_text("Hi Alice".to_owned());
+        bob_manager
+            .send_application_message(&alices_group_id, bobs_message.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            alice_phone
+                .message_log
+                .lock()
+                .await
+                .last()
+                .map(|(_, message)| &message.content),
+            Some(&bobs_message),
+            "The phone receives the messages of the group"
+        );
+
+        // The linked device has the account key, so it can rotate it too
+        alice_phone
+            .rotate_account_key()
+            .await
+            .expect("The phone can rotate the account key after being linked");
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+
+        assert_eq!(
+            alice_laptop.get_profile().share_account_key(),
+            alice_phone.get_profile().share_account_key(),
+            "The laptop adopted the account key rotated by the phone"
+        );
+
+        let laptops_message = Content::plain_text("Still Alice".to_owned());
+        alice_laptop
+            .send_application_message(&alices_group_id, laptops_message.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            bob_manager
+                .message_log
+                .lock()
+                .await
+                .last()
+                .map(|(_, message)| &message.content),
+            Some(&laptops_message),
+            "Bob accepts the laptop's chain signed by the rotated account key"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn sync_history_to_linked_device() {
+        let (client, _rx) = Client::new();
+        let alice_laptop = client
+            .get_in_memory_profile("alice_laptop")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_laptop
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_laptop
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Sent before the phone is linked, so it can't decrypt it itself
+        let (client_stamp, _) = bob_manager
+            .send_application_message(
+                &alices_group_id,
+                Content::plain_text("Sent before the phne".to_owned()),
+            )
+            .await
+            .expect("application message should have been sent");
+        let target = MessageReference {
+            sender_account_id: bob_manager.get_profile().get_account_id(),
+            client_stamp,
+        };
+        let bobs_message = Content::plain_text("Sent before the phone".to_owned());
+        bob_manager
+            .send_application_message(
+                &alices_group_id,
+                Content::Edit {
+                    target: client_stamp,
+                    body: "Sent before the phone".to_owned(),
+                },
+            )
+            .await
+            .expect("application message should have been sent");
+        alice_laptop
+            .send_application_message(
+                &alices_group_id,
+                Content::Reaction {
+                    target,
+                    emoji: "👍".to_owned(),
+                    remove: false,
+                },
+            )
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let code = DeviceLinkCode::generate();
+        let (new_device_id, alice_phone) = tokio::join!(
+            alice_laptop.link_new_device(&code.to_code_string()),
+            client.link_in_memory_profile("alice_phone", &code),
+        );
+        new_device_id.expect("The laptop can link a device");
+        let alice_phone = alice_phone.expect("The phone can be linked");
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+
+        assert_eq!(
+            alice_phone
+                .sqlite_database
+                .get_last_message(alices_group_id)
+                .expect("The history was synced")
+                .message,
+            bobs_message,
+            "The phone has the messages sent before it was linked"
+        );
+        assert_eq!(
+            alice_phone
+                .sqlite_database
+                .get_edit_history(alices_group_id, &target),
+            Ok(vec![
+                "Sent before the phne".to_owned(),
+                "Sent before the phone".to_owned()
+            ]),
+            "The edits of the messages were synced"
+        );
+        assert_eq!(
+            alice_phone
+                .sqlite_database
+                .get_reactions_by_account(alices_group_id, &target),
+            Ok(vec![(
+                alice_laptop.get_profile().get_account_id(),
+                "👍".to_owned()
+            )]),
+            "The reactions to the messages were synced"
+        );
+        assert!(
+            alice_laptop
+                .sqlite_database
+                .get_history_syncs()
+                .expect("history transfers can be listed")
+                .is_empty(),
+            "The transfer is done"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn reply_to_message() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let (client_stamp, _) = alice_manager
+            .send_application_message(
+                &alices_group_id,
+                Content::plain_text("What's for dinner?".to_owned()),
+            )
+            .await
+            .expect("application message should have been sent");
+        let parent = MessageReference {
+            sender_account_id: alice_manager.get_profile().get_account_id(),
+            client_stamp,
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let bobs_reply = Content::reply("Pasta".to_owned(), parent);
+        bob_manager
+            .send_application_message(&alices_group_id, bobs_reply.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(
+            alice_manager
+                .sqlite_database
+                .get_last_message(alices_group_id)
+                .expect("Alice received the reply")
+                .message,
+            bobs_reply,
+            "The reply references Alice's message"
+        );
+        for manager in [&alice_manager, &bob_manager] {
+            assert_eq!(
+                manager
+                    .get_quote(alices_group_id, &parent)
+                    .expect("quotes can be loaded")
+                    .preview
+                    .as_deref()
+                    .map(String::as_str),
+                Some("What's for dinner?"),
+                "Both the sender and the recipient of a message can quote it"
+            );
+        }
+    }
+
+    #[tokio::test]
+    pub async fn edit_and_delete_message() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let (client_stamp, _) = alice_manager
+            .send_application_message(&alices_group_id, Content::plain_text("Helo".to_owned()))
+            .await
+            .expect("application message should have been sent");
+        let reference = MessageReference {
+            sender_account_id: alice_manager.get_profile().get_account_id(),
+            client_stamp,
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Bob can't edit Alice's message
+        bob_manager
+            .send_application_message(
+                &alices_group_id,
+                Content::Edit {
+                    target: client_stamp,
+                    body: "Bye".to_owned(),
+                },
+            )
+            .await
+            .expect("application message should have been sent");
+        alice_manager
+            .send_application_message(
+                &alices_group_id,
+                Content::Edit {
+                    target: client_stamp,
+                    body: "Hello".to_owned(),
+                },
+            )
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        for manager in [&alice_manager, &bob_manager] {
+            assert_eq!(
+                manager
+                    .sqlite_database
+                    .get_edit_history(alices_group_id, &reference),
+                Ok(vec!["Helo".to_owned(), "Hello".to_owned()]),
+                "Only Alice's edit was applied"
+            );
+        }
+
+        alice_manager
+            .send_application_message(
+                &alices_group_id,
+                Content::Delete {
+                    target: client_stamp,
+                },
+            )
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        for manager in [&alice_manager, &bob_manager] {
+            assert!(
+                manager
+                    .get_quote(alices_group_id, &reference)
+                    .expect("quotes can be loaded")
+                    .deleted,
+                "The message was deleted for everyone"
+            );
+        }
+    }
+
+    #[tokio::test]
+    pub async fn react_to_message() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let alices_message = Content::plain_text("I passed my exam".to_owned());
+        let (client_stamp, _) = alice_manager
+            .send_application_message(&alices_group_id, alices_message.clone())
+            .await
+            .expect("application message should have been sent");
+        let target = MessageReference {
+            sender_account_id: alice_manager.get_profile().get_account_id(),
+            client_stamp,
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        bob_manager
+            .send_application_message(
+                &alices_group_id,
+                Content::Reaction {
+                    target,
+                    emoji: "🎉".to_owned(),
+                    remove: false,
+                },
+            )
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let reactions = alice_manager
+            .get_reactions(alices_group_id, &target)
+            .expect("reactions can be loaded");
+        assert_eq!(reactions.len(), 1, "Alice received Bob's reaction");
+        assert_eq!(
+            (
+                reactions[0].emoji.as_str(),
+                reactions[0].count,
+                reactions[0].ours
+            ),
+            ("🎉", 1, false),
+            "The reaction is Bob's"
+        );
+        assert_eq!(
+            alice_manager
+                .sqlite_database
+                .get_last_message(alices_group_id)
+                .expect("Alice has her message")
+                .message,
+            alices_message,
+            "Reactions aren't the last message of the group"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn delivery_and_read_receipts() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let (client_stamp, _) = alice_manager
+            .send_application_message(&alices_group_id, Content::plain_text("Hi".to_owned()))
+            .await
+            .expect("application message should have been sent");
+        let message = MessageReference {
+            sender_account_id: alice_manager.get_profile().get_account_id(),
+            client_stamp,
+        };
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let receipts = alice_manager
+            .get_receipts(alices_group_id, &message)
+            .expect("receipts can be loaded");
+        assert_eq!(
+            (receipts.delivered, receipts.read),
+            (1, 0),
+            "Bob sent a delivery receipt automatically"
+        );
+
+        bob_manager
+            .mark_as_read(alices_group_id, vec![message])
+            .await
+            .expect("read receipts can be sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let receipts = alice_manager
+            .get_receipts(alices_group_id, &message)
+            .expect("receipts can be loaded");
+        assert_eq!(
+            (receipts.delivered, receipts.read),
+            (1, 1),
+            "Bob read the message"
+        );
+
+        bob_manager
+            .set_read_receipts_enabled(false)
+            .expect("settings can be saved");
+        let (client_stamp, _) = alice_manager
+            .send_application_message(&alices_group_id, Content::plain_text("Hey?".to_owned()))
+            .await
+            .expect("application message should have been sent");
+        let message = MessageReference {
+            sender_account_id: alice_manager.get_profile().get_account_id(),
+            client_stamp,
+        };
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        bob_manager
+            .mark_as_read(alices_group_id, vec![message])
+            .await
+            .expect("marking messages as read works");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let receipts = alice_manager
+            .get_receipts(alices_group_id, &message)
+            .expect("receipts can be loaded");
+        assert_eq!(
+            (receipts.delivered, receipts.read),
+            (1, 0),
+            "Bob disabled read receipts, but still sends delivery receipts"
+        );
+    }
+    #[tokio::test]
+    pub async fn typing_indicators_are_not_stored() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        bob_manager
+            .set_typing(alices_group_id, true)
+            .await
+            .expect("typing indicator should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(
+            alice_manager.get_typing_members(alices_group_id),
+            vec![bob_manager.get_profile().get_account_id()],
+            "Alice sees Bob typing"
+        );
+        assert!(
+            alice_manager
+                .sqlite_database
+                .get_last_message(alices_group_id)
+                .is_err(),
+            "Typing indicators are not stored as messages"
+        );
+
+        bob_manager
+            .set_typing(alices_group_id, false)
+            .await
+            .expect("typing indicator should have been sent");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(
+            alice_manager.get_typing_members(alices_group_id).is_empty(),
+            "Bob stopped typing"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn rotate_device_key_and_keep_messaging() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let previous_profile = bob_manager.get_profile();
+        bob_manager
+            .rotate_device_key()
+            .await
+            .expect("Bob can rotate his device key");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_ne!(
+            bob_manager.get_profile(),
+            previous_profile,
+            "Bob's manager uses the rotated profile right away"
+        );
+        assert_eq!(
+            bob_manager.get_profile().get_device_id(),
+            previous_profile.get_device_id(),
+            "Bob keeps the same device"
+        );
+        assert_eq!(
+            bob_manager.sqlite_database.get_pending_identity_updates(),
+            Ok(vec![]),
+            "Bob's identity was updated in every group"
+        );
+
+        let bobs_content = Content::plain_text("Still me, with a new key".to_owned());
+        bob_manager
+            .send_application_message(&alices_group_id, bobs_content.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            alice_manager
+                .message_log
+                .lock()
+                .await
+                .last()
+                .map(|(_, message)| &message.content),
+            Some(&bobs_content),
+            "Alice receives Bob's message signed with his new key"
+        );
+
+        let alices_content = Content::plain_text("Got it".to_owned());
+        alice_manager
+            .send_application_message(&alices_group_id, alices_content.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            bob_manager
+                .message_log
+                .lock()
+                .await
+                .last()
+                .map(|(_, message)| &message.content),
+            Some(&alices_content),
+            "Bob still receives the messages of the group"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn rotate_account_key_with_two_devices() {
+        let (client, _rx) = Client::new();
+        let alice_laptop = client
+            .get_in_memory_profile("alice_laptop")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_laptop
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_laptop
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let code = DeviceLinkCode::generate();
+        let (new_device_id, alice_phone) = tokio::join!(
+            alice_laptop.link_new_device(&code.to_code_string()),
+            client.link_in_memory_profile("alice_phone", &code),
+        );
+        new_device_id.expect("The laptop can link a device");
+        let alice_phone = alice_phone.expect("The phone can be linked");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let previous_share = alice_laptop.get_profile().share_account_key();
+        alice_laptop
+            .rotate_account_key()
+            .await
+            .expect("The server accepts a rotation re-signing both devices");
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+
+        assert_ne!(
+            alice_laptop.get_profile().share_account_key(),
+            previous_share,
+            "The laptop uses the new account key right away"
+        );
+        assert_eq!(
+            alice_phone.get_profile().share_account_key(),
+            alice_laptop.get_profile().share_account_key(),
+            "The phone adopted the account key shared in the self group"
+        );
+        assert_eq!(
+            alice_phone.sqlite_database.get_pending_identity_updates(),
+            Ok(vec![]),
+            "The phone's identity was updated in every group"
+        );
+
+        let phones_content = Content::plain_text("Sent from the phone".to_owned());
+        alice_phone
+            .send_application_message(&alices_group_id, phones_content.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            bob_manager
+                .message_log
+                .lock()
+                .await
+                .last()
+                .map(|(_, message)| &message.content),
+            Some(&phones_content),
+            "Bob accepts the phone's chain signed by the new account key"
+        );
+
+        let bobs_content = Content::plain_text("Hi Alice".to_owned());
+        bob_manager
+            .send_application_message(&alices_group_id, bobs_content.clone())
+            .await
+            .expect("application message should have been sent");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        for alice in [&alice_laptop, &alice_phone] {
+            assert_eq!(
+                alice
+                    .message_log
+                    .lock()
+                    .await
+                    .last()
+                    .map(|(_, message)| &message.content),
+                Some(&bobs_content),
+                "Both of Alice's devices still receive the messages of the group"
+            );
+        }
+    }
+
+    #[tokio::test]
+    pub async fn send_pending_message_once_back_online() {
+        let (client, _rx) = Client::new();
+        let alice_manager = client
+            .get_in_memory_profile("alice")
+            .await
+            .expect("server is open and registration works");
+
+        let bob_manager = client
+            .get_in_memory_profile("bob")
+            .await
+            .expect("server is open and registration works");
+
+        let alices_group_id = alice_manager
+            .create_new_group(String::from("Alice's group"), None)
+            .await
+            .expect("group creation should have succeeded")
+            .group_identifier;
+
+        let link = alice_manager
+            .create_group_link(alices_group_id)
+            .await
+            .expect("Alice is an admin")
+            .to_link_string();
+        bob_manager
+            .join_group_from_link(&link)
+            .await
+            .expect("Bob can join with the link");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        alice_manager.offline.store(true, Ordering::Release);
+
+        let content = Content::plain_text("Are you there?".to_owned());
+        let (client_stamp, status) = alice_manager
+            .profile_manager
+            .send_application_message(&alices_group_id, content.clone())
+            .await
+            .expect("Messages are queued while offline");
+        assert_eq!(
+            status,
+            OutboxStatus::Pending,
+            "The message stays in the outbox"
+        );
+
+        let reference = MessageReference {
+            sender_account_id: alice_manager.get_profile().get_account_id(),
+            client_stamp,
+        };
+        let message = alice_manager
+            .sqlite_database
+            .get_message_by_reference(alices_group_id, &reference)
+            .expect("Looking up messages works")
+            .expect("The message is stored while pending");
+        assert_eq!(message.message, content, "The pending message is stored");
+        assert_eq!(
+            message.outbox_status,
+            Some(OutboxStatus::Pending),
+            "The stored message is pending"
+        );
+
+        assert!(
+            alice_manager
+                .flush_outbox()
+                .await
+                .expect("The outbox can be flushed")
+                .is_empty(),
+            "Nothing can be sent while offline"
+        );
+
+        alice_manager.offline.store(false, Ordering::Release);
+        alice_manager.retry_outbox();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        let message = alice_manager
+            .sqlite_database
+            .get_message_by_reference(alices_group_id, &reference)
+            .expect("Looking up messages works")
+            .expect("The message is still stored");
+        assert_eq!(
+            message.outbox_status,
+            Some(OutboxStatus::Sent),
+            "The message was sent once back online"
+        );
+        assert_ne!(
+            message.server_delivery_stamp, client_stamp,
+            "The message has the stamp the server delivered it with"
+        );
+
+        let bobs_messages = bob_manager
+            .message_log
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, message)| message.content == content)
+            .count();
+        assert_eq!(bobs_messages, 1, "Bob receives the message once");
+    }
 }
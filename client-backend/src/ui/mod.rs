@@ -2,16 +2,56 @@ use core::fmt::Display;
 use lib::identifiers::{AccountId, GroupIdentifier, LicksIdentifier};
 use std::{hash::Hash, sync::Arc};
 
+use crate::{database::outbox::OutboxStatus, messages::MessageReference};
+
 #[derive(Debug, Clone)]
 pub struct MessageUi {
     pub profile_name: String,
     pub device_id: AccountId,
     message: MessageInner,
+    reference: Option<MessageReference>,
+    reply_to: Option<QuoteUi>,
+    reactions: Vec<ReactionUi>,
+    receipts: Option<ReceiptsUi>,
+    status: Option<OutboxStatus>,
+    edited: bool,
+}
+
+/// How many recipients received and read a message we sent. It is only
+/// sent while both counts are 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReceiptsUi {
+    /// Includes the recipients who read the message.
+    pub delivered: u64,
+    pub read: u64,
+}
+
+/// The reactions to a message with the same emoji.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReactionUi {
+    pub emoji: Arc<String>,
+    pub count: u64,
+    /// `true` if we are one of the reacting accounts, in which
+    /// case the reaction can be removed.
+    pub ours: bool,
+}
+
+/// The message a reply quotes, used to render a preview of it
+/// and jump to it.
+#[derive(Debug, Clone)]
+pub struct QuoteUi {
+    /// Matches the [`MessageUi::reference`] of the quoted message.
+    pub parent: MessageReference,
+    /// The text of the quoted message, or `None` if it hasn't
+    /// arrived yet or was deleted.
+    pub preview: Option<Arc<String>>,
+    pub deleted: bool,
 }
 
 #[derive(Debug, Clone)]
 enum MessageInner {
     PlainText(Arc<String>),
+    Deleted,
 }
 
 impl MessageUi {
@@ -22,13 +62,114 @@ impl MessageUi {
             profile_name,
             device_id,
             message: MessageInner::PlainText(string),
+            reference: None,
+            reply_to: None,
+            reactions: Vec::new(),
+            receipts: None,
+            status: None,
+            edited: false,
         }
     }
 
-    pub fn msg(&self) -> &str {
-        let MessageInner::PlainText(ref string) = self.message;
+    /// Sets the reference replies use to quote this message.
+    pub fn with_reference(mut self, reference: MessageReference) -> Self {
+        self.reference = Some(reference);
+        self
+    }
+
+    /// Marks this message as a reply quoting `quote`.
+    pub fn with_reply_to(mut self, quote: QuoteUi) -> Self {
+        self.reply_to = Some(quote);
+        self
+    }
+
+    /// Returns `None` for messages we haven't sent yet.
+    pub fn reference(&self) -> Option<&MessageReference> {
+        self.reference.as_ref()
+    }
 
-        string
+    pub fn reply_to(&self) -> Option<&QuoteUi> {
+        self.reply_to.as_ref()
+    }
+
+    /// Sets the reactions to this message, the most common first.
+    pub fn with_reactions(mut self, reactions: Vec<ReactionUi>) -> Self {
+        self.reactions = reactions;
+        self
+    }
+
+    /// Replaces the reactions after they changed.
+    pub fn set_reactions(&mut self, reactions: Vec<ReactionUi>) {
+        self.reactions = reactions;
+    }
+
+    pub fn reactions(&self) -> &[ReactionUi] {
+        &self.reactions
+    }
+
+    /// Marks this message as one of ours, whose receipts are displayed.
+    pub fn with_receipts(mut self, receipts: ReceiptsUi) -> Self {
+        self.receipts = Some(receipts);
+        self
+    }
+
+    pub fn set_receipts(&mut self, receipts: ReceiptsUi) {
+        self.receipts = Some(receipts);
+    }
+
+    /// Returns `None` for the messages of others.
+    pub fn receipts(&self) -> Option<&ReceiptsUi> {
+        self.receipts.as_ref()
+    }
+
+    /// Sets the status of a message we sent, see [`crate::database::outbox`].
+    pub fn with_status(mut self, status: OutboxStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn set_status(&mut self, status: OutboxStatus) {
+        self.status = Some(status);
+    }
+
+    /// Returns `None` for the messages of others.
+    pub fn status(&self) -> Option<OutboxStatus> {
+        self.status
+    }
+
+    /// Used to update the quote when the quoted message is edited or deleted.
+    pub fn reply_to_mut(&mut self) -> Option<&mut QuoteUi> {
+        self.reply_to.as_mut()
+    }
+
+    /// Replaces the text of this message after its sender edited it.
+    pub fn edit(&mut self, body: Arc<String>) {
+        if let MessageInner::PlainText(_) = self.message {
+            self.message = MessageInner::PlainText(body);
+            self.edited = true;
+        }
+    }
+
+    /// Erases this message after its sender deleted it.
+    pub fn delete(&mut self) {
+        self.message = MessageInner::Deleted;
+        self.reply_to = None;
+        self.reactions.clear();
+    }
+
+    pub fn is_edited(&self) -> bool {
+        self.edited
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        matches!(self.message, MessageInner::Deleted)
+    }
+
+    pub fn msg(&self) -> &str {
+        match self.message {
+            MessageInner::PlainText(ref string) => string,
+            MessageInner::Deleted => "This message was deleted",
+        }
     }
 }
 
@@ -38,6 +179,9 @@ impl Display for MessageUi {
             MessageInner::PlainText(string) => {
                 write!(f, "{}: {}", self.profile_name, string)
             }
+            MessageInner::Deleted => {
+                write!(f, "{}: {}", self.profile_name, self.msg())
+            }
         }
     }
 }
@@ -67,6 +211,18 @@ impl PartialEq for GroupUi {
 
 impl Eq for GroupUi {}
 
+/// A group without its name or last message, which can be used to look it up
+/// since groups are only compared by their identifier.
+impl From<GroupIdentifier> for GroupUi {
+    fn from(group_identifier: GroupIdentifier) -> Self {
+        Self {
+            group_identifier,
+            group_name: Arc::default(),
+            last_message: None,
+        }
+    }
+}
+
 impl GroupUi {
     pub fn formatted_id(&self) -> String {
         self.group_identifier.as_uuid().to_string()
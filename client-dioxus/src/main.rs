@@ -13,7 +13,7 @@ use dioxus::{
 use dioxus_logger::tracing::{error, info};
 use panels::{
     chat::{ChatPanel, ChatPanelProps},
-    groups::{message_service, GroupsTab, GroupsTabProps},
+    groups::{message_service, GroupsTab, GroupsTabProps, INVITES},
     settings::{SettingsPanel, SettingsTab},
 };
 
@@ -143,6 +143,11 @@ fn App() -> Element {
             .collect()
     });
 
+    use_hook(|| match get_default_profile().get_pending_invites() {
+        Ok(invites) => *INVITES.write() = invites,
+        Err(err) => error!("Couldn't load our pending invites: {err:?}"),
+    });
+
     let selected_group: Signal<GroupUi> = use_signal(|| {
         group_list
             .read()
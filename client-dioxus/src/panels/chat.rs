@@ -1,17 +1,16 @@
 use client_backend::{
+    database::outbox::OutboxStatus,
     manager::account::Username,
-    messages::Content,
-    mls::welcome::welcome_to_base64,
-    ui::{GroupUi, MessageUi},
+    messages::{Content, MessageReference},
+    ui::{GroupUi, MessageUi, ReceiptsUi},
 };
 use dioxus::prelude::*;
-use dioxus_clipboard::hooks::use_clipboard;
 use dioxus_logger::tracing::*;
 
 use crate::{
     components::{icon::ImageIcon, modal::Modal},
     get_default_profile,
-    panels::groups::{LAST_MESSAGE, MESSAGES},
+    panels::groups::{LAST_MESSAGE, MESSAGES, TYPING},
 };
 
 #[component]
@@ -30,22 +29,88 @@ pub fn ChatPanel(selected_group: Signal<GroupUi>) -> Element {
             .map(|message_ui| {
                 let author = &message_ui.profile_name;
                 let message = message_ui.msg();
+                let quote = message_ui.reply_to().map(|quote| match &quote.preview {
+                    Some(preview) => preview.to_string(),
+                    None if quote.deleted => "This message was deleted".to_string(),
+                    None => "Message not received yet".to_string(),
+                });
+                let receipts = match message_ui.status() {
+                    Some(OutboxStatus::Pending | OutboxStatus::Sending) => {
+                        Some("Sending...".to_string())
+                    }
+                    Some(OutboxStatus::Failed) => Some("Not sent".to_string()),
+                    _ => message_ui.receipts().map(|receipts| {
+                        if receipts.read > 0 {
+                            format!("Read by {}", receipts.read)
+                        } else if receipts.delivered > 0 {
+                            format!("Delivered to {}", receipts.delivered)
+                        } else {
+                            "Sent".to_string()
+                        }
+                    }),
+                };
+                let reactions = message_ui
+                    .reactions()
+                    .iter()
+                    .map(|reaction| format!("{} {}", reaction.emoji, reaction.count))
+                    .collect::<Vec<_>>()
+                    .join(" ");
                 rsx! {
                     // TODO: ARIA labels for accessibility
                     div {
                         class: "message",
                         padding: "0 0 1px 4px",
                         width: "inherit",
+                        if let Some(quote) = quote {
+                            p {
+                                class: "message-quote",
+                                overflow: "hidden",
+                                white_space: "nowrap",
+                                text_overflow: "ellipsis",
+                                "{quote}"
+                            }
+                        }
                         p { word_break: "break-all", text_wrap: "stable",
                             b { id: "message-author", "{author}" }
                             "{message}"
                         }
+                        if !reactions.is_empty() {
+                            p { class: "message-reactions", "{reactions}" }
+                        }
+                        if let Some(receipts) = receipts {
+                            p { class: "message-receipts", "{receipts}" }
+                        }
                     }
                 }
             })
             .collect()
     });
 
+    // Tell the senders of the messages of this chat that we read them
+    use_effect(move || {
+        let group = selected_group.read().clone();
+        let messages = MESSAGES
+            .read()
+            .get(&group)
+            .map(|messages| {
+                messages
+                    .iter()
+                    .filter(|message| message.receipts().is_none())
+                    .filter_map(|message| message.reference().copied())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        spawn(async move {
+            if let Err(err) = get_default_profile()
+                .mark_as_read(group.group_identifier, messages)
+                .await
+            {
+                error!("Couldn't send read receipts: {err:?}");
+            }
+        });
+    });
+
     let on_press_send = move || {
         spawn(async move {
             let message = input_message.read().clone();
@@ -57,19 +122,33 @@ pub fn ChatPanel(selected_group: Signal<GroupUi>) -> Element {
             let group_lock = selected_group.read();
             let profile = get_default_profile();
 
-            let account_id = profile.profile.get_account_id();
+            let account_id = profile.get_profile().get_account_id();
             // TODO: Load our actual profile name somewhere idk
-            let message_ui =
+            let mut message_ui =
                 MessageUi::plain_text(profile.username.0 .0.clone(), account_id, message.clone());
 
             let content = Content::plain_text(message);
 
-            // For now, we silently fail.
-            // TODO: If this fails, let the UI know that the message
-            // did not send (for whatever reason).
-            let _ = profile
+            // Messages the server can't receive yet stay in the outbox,
+            // and their status is updated through notifications
+            match profile
                 .send_application_message(&group_lock.group_identifier, content)
-                .await;
+                .await
+            {
+                Ok((client_stamp, status)) => {
+                    message_ui = message_ui
+                        .with_reference(MessageReference {
+                            sender_account_id: account_id,
+                            client_stamp,
+                        })
+                        .with_receipts(ReceiptsUi::default())
+                        .with_status(status);
+                }
+                Err(err) => {
+                    error!("Couldn't send the message: {err:?}");
+                    message_ui = message_ui.with_status(OutboxStatus::Failed);
+                }
+            }
 
             let mut messages_writer_lock = MESSAGES.write();
             if let Some(msg_vec) = messages_writer_lock.get_mut(&group_lock) {
@@ -83,9 +162,37 @@ pub fn ChatPanel(selected_group: Signal<GroupUi>) -> Element {
                 .insert(group_lock.clone(), Some(message_ui));
 
             input_message.write().clear();
+
+            if let Err(err) = profile.set_typing(group_lock.group_identifier, false).await {
+                error!("Couldn't send typing indicator: {err:?}");
+            }
+        });
+    };
+
+    let on_input = move |event: FormEvent| {
+        let typing = !event.value().is_empty();
+        input_message.set(event.value());
+
+        spawn(async move {
+            let group_id = selected_group.read().group_identifier;
+
+            if let Err(err) = get_default_profile().set_typing(group_id, typing).await {
+                error!("Couldn't send typing indicator: {err:?}");
+            }
         });
     };
 
+    let typing_members = TYPING
+        .read()
+        .get(&selected_group.read())
+        .cloned()
+        .unwrap_or_default();
+    let typing = match typing_members.as_slice() {
+        [] => None,
+        [member] => Some(format!("{member} is typing...")),
+        members => Some(format!("{} are typing...", members.join(", "))),
+    };
+
     let group_lock = selected_group.read();
     let group_name = group_lock.group_name.clone();
     let group_color = group_lock.color();
@@ -158,6 +265,9 @@ pub fn ChatPanel(selected_group: Signal<GroupUi>) -> Element {
                     {message}
                 }
             }
+            if let Some(typing) = typing {
+                p { class: "typing-indicator", padding: "0 0 1px 4px", "{typing}" }
+            }
             // Message composer input
             form {
                 display: "flex",
@@ -171,7 +281,7 @@ pub fn ChatPanel(selected_group: Signal<GroupUi>) -> Element {
                     flex_grow: "1",
                     placeholder: "Write a message",
                     value: "{input_message}",
-                    oninput: move |event| input_message.set(event.value()),
+                    oninput: on_input,
                 }
                 input { r#type: "submit", display: "none" }
                 // Send button
@@ -201,7 +311,7 @@ pub fn InviteUsernameToGroupModal(
     selected_group: Signal<GroupUi>,
     mut is_open: Signal<bool>,
 ) -> Element {
-    let create_welcome = move |event: FormEvent| {
+    let send_invite = move |event: FormEvent| {
         spawn(async move {
             let values = event.values();
 
@@ -211,7 +321,7 @@ pub fn InviteUsernameToGroupModal(
                 .first()
                 .expect("username is not None");
 
-            let welcome_string = async {
+            let invite = async {
                 let username = Username::new(username_str.to_string()).map_err(|err| {
                     error!("Username given was invalid: {err:?}");
                 })?;
@@ -226,21 +336,17 @@ pub fn InviteUsernameToGroupModal(
                         error!("Account with username \"{username_str}\" does not exist");
                     })?;
 
-                let welcome = get_default_profile()
-                    .create_new_welcome(selected_group.read().group_identifier, account_id)
+                get_default_profile()
+                    .invite_member(selected_group.read().group_identifier, account_id)
                     .await
                     .map_err(|err| {
-                        error!("Creating welome message failed. Account might not exist: {err:?}")
-                    })?;
-
-                Ok::<_, ()>(welcome_to_base64(&welcome).expect("this is a welcome message"))
+                        error!("Inviting the account failed: {err:?}");
+                    })
             };
 
-            if let Ok(welcome) = welcome_string.await {
-                info!("{welcome}");
-
-                let mut clipboard = use_clipboard();
-                let _ = clipboard.set(welcome.to_string());
+            if invite.await.is_ok() {
+                info!("Invited {username_str}");
+                is_open.set(false);
             }
         });
     };
@@ -248,14 +354,14 @@ pub fn InviteUsernameToGroupModal(
     let child_element = rsx! {
         div { width: "100%", height: "100%",
             h3 { "Invite user to group" }
-            form { class: "invite-input", onsubmit: create_welcome,
+            form { class: "invite-input", onsubmit: send_invite,
                 div {
                     label { r#for: "username", "Username" }
                     input { r#type: "text", autofocus: true, name: "username" }
                 }
                 input {
                     r#type: "submit",
-                    value: "Create and copy invite code to clipboard",
+                    value: "Send invite",
                 }
             }
         }
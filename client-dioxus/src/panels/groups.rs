@@ -8,6 +8,7 @@ use client_backend::{
 use dioxus::prelude::*;
 use dioxus_logger::tracing::{error, info};
 use futures_util::stream::StreamExt;
+use lib::api::group::DeliveryStamp;
 
 use crate::{
     components::{icon::ImageIcon, modal::Modal},
@@ -19,6 +20,11 @@ pub static MESSAGES: GlobalSignal<HashMap<GroupUi, Vec<MessageUi>>> =
 // TODO: Maybe replace this with MESSAGES.get(...).last()?
 pub static LAST_MESSAGE: GlobalSignal<HashMap<GroupUi, Option<MessageUi>>> =
     GlobalSignal::new(HashMap::default);
+/// The members typing in each group.
+pub static TYPING: GlobalSignal<HashMap<GroupUi, Vec<String>>> =
+    GlobalSignal::new(HashMap::default);
+/// The invites we didn't accept or decline yet, oldest first.
+pub static INVITES: GlobalSignal<Vec<DeliveryStamp>> = GlobalSignal::new(Vec::new);
 
 pub async fn message_service(mut rx: UnboundedReceiver<Notification>) {
     while let Some(msg) = rx.next().await {
@@ -37,10 +43,98 @@ pub async fn message_service(mut rx: UnboundedReceiver<Notification>) {
                 let mut last_msg_writer_lock = LAST_MESSAGE.write();
                 let _ = last_msg_writer_lock.insert(group, Some(message));
             }
+            Notification::MessageStatus(group_id, client_stamp, status) => {
+                update_messages(group_id.into(), |message| {
+                    if message
+                        .reference()
+                        .is_some_and(|reference| reference.client_stamp == client_stamp)
+                    {
+                        message.set_status(status);
+                    }
+                });
+            }
+            Notification::GroupUpdated(group) => {
+                // TODO: Update the group list
+                info!(
+                    "Group {} is now named {}",
+                    group.formatted_id(),
+                    group.name()
+                );
+            }
+            Notification::Invite(delivery_stamp) => {
+                let mut invites = INVITES.write();
+                if !invites.contains(&delivery_stamp) {
+                    invites.push(delivery_stamp);
+                }
+            }
+            Notification::HistorySync(synced, total) => {
+                // TODO: Show a progress bar
+                info!("Synced {synced} of {total} messages with our other device");
+            }
+            Notification::MessageEdited(group_id, reference, body) => {
+                update_messages(group_id.into(), |message| {
+                    if message.reference() == Some(&reference) {
+                        message.edit(body.clone());
+                    }
+                    if let Some(quote) = message.reply_to_mut() {
+                        if quote.parent == reference {
+                            quote.preview = Some(body.clone());
+                        }
+                    }
+                });
+            }
+            Notification::MessageDeleted(group_id, reference) => {
+                update_messages(group_id.into(), |message| {
+                    if message.reference() == Some(&reference) {
+                        message.delete();
+                    }
+                    if let Some(quote) = message.reply_to_mut() {
+                        if quote.parent == reference {
+                            quote.preview = None;
+                            quote.deleted = true;
+                        }
+                    }
+                });
+            }
+            Notification::Reactions(group_id, reference, reactions) => {
+                update_messages(group_id.into(), |message| {
+                    if message.reference() == Some(&reference) {
+                        message.set_reactions(reactions.clone());
+                    }
+                });
+            }
+            Notification::Receipts(group_id, reference, receipts) => {
+                update_messages(group_id.into(), |message| {
+                    if message.reference() == Some(&reference) {
+                        message.set_receipts(receipts);
+                    }
+                });
+            }
+            Notification::Typing(group_id, account_id, typing) => {
+                let mut typing_lock = TYPING.write();
+                let members = typing_lock.entry(group_id.into()).or_default();
+                // TODO: Show their profile name
+                let member = account_id.to_string();
+
+                members.retain(|other| *other != member);
+                if typing {
+                    members.push(member);
+                }
+            }
         }
     }
 }
 
+/// Updates the messages of a group we already display, in place.
+fn update_messages(group: GroupUi, mut update: impl FnMut(&mut MessageUi)) {
+    if let Some(messages) = MESSAGES.write().get_mut(&group) {
+        messages.iter_mut().for_each(&mut update);
+    }
+    if let Some(Some(last_message)) = LAST_MESSAGE.write().get_mut(&group) {
+        update(last_message);
+    }
+}
+
 #[component]
 pub fn GroupsTab(
     mut group_list: Signal<Vec<GroupUi>>,
@@ -148,11 +242,72 @@ pub fn GroupsTab(
                     ImageIcon { size: 30, icon_name: "plus.png", button: true }
                 }
             }
+            InviteList { selected_group, group_list }
             {groups_rendered}
         }
     }
 }
 
+/// The invites delivered to our invite inbox, which we can accept or decline.
+#[component]
+pub fn InviteList(
+    selected_group: Signal<GroupUi>,
+    mut group_list: Signal<Vec<GroupUi>>,
+) -> Element {
+    let accept_invite = move |invite: DeliveryStamp| {
+        spawn(async move {
+            let profile = get_default_profile();
+
+            match profile.accept_invite(&invite).await {
+                Ok(group_id) => {
+                    let group = profile.refresh_group_info(&group_id).unwrap_or_else(|err| {
+                        error!("Couldn't load the group we joined: {err:?}");
+                        group_id.into()
+                    });
+
+                    *selected_group.write() = group.clone();
+                    group_list.write().push(group);
+                }
+                Err(err) => {
+                    error!("Accepting the invite failed: {err:?}");
+                    return;
+                }
+            }
+
+            INVITES.write().retain(|other| *other != invite);
+        });
+    };
+
+    let decline_invite = move |invite: DeliveryStamp| {
+        if let Err(err) = get_default_profile().decline_invite(&invite) {
+            error!("Declining the invite failed: {err:?}");
+            return;
+        }
+
+        INVITES.write().retain(|other| *other != invite);
+    };
+
+    let invites = INVITES.read().clone();
+
+    rsx! {
+        for invite in invites {
+            div {
+                class: "invite",
+                display: "flex",
+                margin: "6px",
+                padding: "var(--padding-medium)",
+                align_items: "center",
+                gap: "var(--padding-medium)",
+                background_color: "var(--white-3)",
+                border_radius: "var(--border-radius-md)",
+                p { flex_grow: "1", "You were invited to a group" }
+                button { onclick: move |_| accept_invite(invite), "Accept" }
+                button { onclick: move |_| decline_invite(invite), "Decline" }
+            }
+        }
+    }
+}
+
 #[component]
 pub fn CreateGroupModal(
     selected_group: Signal<GroupUi>,
@@ -1,6 +1,7 @@
 use dioxus::prelude::*;
+use dioxus_logger::tracing::error;
 
-use crate::components::icon::ImageIcon;
+use crate::{components::icon::ImageIcon, PROFILE};
 #[component]
 pub fn SettingsTab() -> Element {
     rsx! {}
@@ -8,6 +9,25 @@ pub fn SettingsTab() -> Element {
 
 #[component]
 pub fn SettingsPanel() -> Element {
+    let mut read_receipts = use_signal(|| {
+        PROFILE
+            .get()
+            .and_then(|profile| profile.read_receipts_enabled().ok())
+            .unwrap_or(true)
+    });
+
+    let on_toggle_read_receipts = move |event: FormEvent| {
+        let Some(profile) = PROFILE.get() else {
+            return;
+        };
+
+        let enabled = event.checked();
+        match profile.set_read_receipts_enabled(enabled) {
+            Ok(()) => read_receipts.set(enabled),
+            Err(err) => error!("Couldn't save the read receipts setting: {err:?}"),
+        }
+    };
+
     rsx! {
         div {
             display: "flex",
@@ -19,6 +39,14 @@ pub fn SettingsPanel() -> Element {
             gap: "10px",
             ImageIcon { size: 80, icon_name: "licker.png" }
             h3 { "Licks Top Secret Dev Version" }
+            label {
+                input {
+                    r#type: "checkbox",
+                    checked: read_receipts(),
+                    onchange: on_toggle_read_receipts,
+                }
+                "Send read receipts"
+            }
         }
     }
 }
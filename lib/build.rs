@@ -20,6 +20,8 @@ fn main() {
             "message_wire",
             "signed_payload",
             "application_message",
+            "group_context",
+            "device_link",
         ]
         .as_slice(),
         "wire",
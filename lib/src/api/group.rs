@@ -32,6 +32,12 @@ use uuid::Uuid;
 pub struct DeliveryStamp(Uuid);
 
 impl DeliveryStamp {
+    /// The smallest possible stamp (a Uuid v7 with a timestamp of 0), which
+    /// is before any stamp the server may generate. Retrieving a queue from
+    /// this stamp returns the whole queue.
+    pub const EARLIEST: Self =
+        DeliveryStamp(Uuid::from_u128(0x0000_0000_0000_7000_8000_0000_0000_0000));
+
     pub fn generate() -> Self {
         DeliveryStamp(generate_uuid_v7())
     }
@@ -82,6 +88,9 @@ impl TryFrom<uuid::Uuid> for DeliveryStamp {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct SendMessageRequest {
     pub blinded_address_proof: BlindedAddressProof,
+    /// Ephemeral messages are only broadcast to the current listeners of the
+    /// address, without being stored in its queue.
+    pub ephemeral: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
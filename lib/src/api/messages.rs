@@ -6,7 +6,8 @@ use uuid::Uuid;
 
 use crate::{
     crypto::{
-        blinded_address::BlindedAddressPublic,
+        blinded_address::{BlindedAddressProof, BlindedAddressPublic},
+        certificates::{rotation::AccountKeyRotation, SerializedChain},
         challenge::{AuthChallenge, AuthChallengeResponse},
         listener::{ListenerCommitment, ListenerToken},
         usernames::UsernameHash,
@@ -36,12 +37,16 @@ pub enum Message {
     Ignore,
     Bye,
     Ok,
-    Error(ServiceError),
+    Error(ServiceErrorDetails),
     Auth(AuthRequest),
     Unauth(UnauthRequest),
     GetChallenge,
     Challenge(AuthChallenge),
     ChallengeResponse(AuthChallengeResponse),
+    /// Sent by the client when it is no longer waiting for the answer
+    /// to a request (for instance, because it timed out). The server
+    /// stops handling that request, and doesn't answer this message.
+    Cancel(ClientRequestId),
 }
 
 #[derive(thiserror::Error, Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -60,18 +65,105 @@ pub enum ServiceError {
     ConnectionIsClosed,
     #[error("Unknown error")]
     UnknownError,
+    #[error("This username is already taken")]
+    UsernameAlreadyTaken,
+    #[error("The requested resource could not be found")]
+    NotFound,
+    #[error("Too many requests were made, try again later")]
+    RateLimited,
+    #[error("The server is overloaded, try again later")]
+    Overloaded,
+    #[error("A quota was exceeded")]
+    QuotaExceeded,
 }
 
-pub type ServiceResult = Result<Message, ServiceError>;
+/// A [`ServiceError`] along with machine-readable context about what went wrong.
+///
+/// `code` is stable across versions and is what clients should match on.
+/// `detail` is a human-readable explanation, meant for logs and UIs; it is
+/// empty if the server had nothing to add to the code itself.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ServiceErrorDetails {
+    pub code: ServiceError,
+    pub detail: String,
+    /// How long the client should wait before retrying the request.
+    pub retry_after: Option<Duration>,
+    /// The limit the request ran into, if it was refused because of a quota.
+    pub quota: Option<u64>,
+    /// The name of the request field that caused the error.
+    pub field: Option<String>,
+}
+
+impl ServiceErrorDetails {
+    pub fn new(code: ServiceError, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            detail: detail.into(),
+            retry_after: None,
+            quota: None,
+            field: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    #[must_use]
+    pub fn with_quota(mut self, quota: u64) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    #[must_use]
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+}
+
+impl From<ServiceError> for ServiceErrorDetails {
+    fn from(value: ServiceError) -> Self {
+        Self::new(value, String::new())
+    }
+}
+
+impl Display for ServiceErrorDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.detail.is_empty() {
+            self.code.fmt(f)
+        } else {
+            write!(f, "{}: {}", self.code, self.detail)
+        }
+    }
+}
+
+impl std::error::Error for ServiceErrorDetails {}
+
+pub type ServiceResult = Result<Message, ServiceErrorDetails>;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AuthRequest {
     SetUsername(UsernameHash),
     RemoveUsername(UsernameHash),
     UsernameIsAlreadyYours,
+    /// Only sent by older servers, which now answer with
+    /// [`ServiceError::UsernameAlreadyTaken`] instead.
     UsernameIsAlreadyTaken,
     UploadKeyPackages(Vec<Vec<u8>>),
     KeyPackageAlreadyUploaded,
+    /// Replaces the account certificate of the authenticated account,
+    /// and the chains of all its devices.
+    RotateAccountKey(AccountKeyRotation),
+    /// Replaces the registered chain of one of the authenticated account's
+    /// devices, e.g. after its device key was rotated.
+    UpdateDeviceChain(SerializedChain),
+    /// Asks for the registered chains of every device of the authenticated
+    /// account, e.g. to sign all of them again when rotating the account key.
+    GetDeviceChains,
+    HereAreDeviceChains(Vec<SerializedChain>),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -84,6 +176,17 @@ pub enum UnauthRequest {
     HereIsAccount(AccountId),
     NoAccount,
     ChatService(ChatServiceMessage),
+    /// Creates or replaces the invite link whose id is the proof's public key.
+    /// The proof's message is the encrypted content of the link.
+    PutGroupLink(BlindedAddressProof),
+    /// Deletes the invite link whose id is the proof's public key.
+    DeleteGroupLink(BlindedAddressProof),
+    GetGroupLink(BlindedAddressPublic),
+    /// The encrypted content of the requested invite link.
+    HereIsGroupLink(Vec<u8>),
+    /// Registers a new device of an existing account. The chain must be
+    /// signed by the account's current certificate.
+    RegisterDevice(SerializedChain),
 }
 
 impl ServiceMessage for AuthRequest {}
@@ -203,3 +306,54 @@ impl TryFrom<Vec<u8>> for ListenerId {
         Ok(Self(Uuid::from_slice(&value).map_err(|_| ())?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_error_details_roundtrip() {
+        let error = ServiceErrorDetails::new(ServiceError::RateLimited, "Slow down")
+            .with_retry_after(Duration::from_secs(30))
+            .with_quota(100)
+            .with_field("username");
+
+        let wire = MessageWire(ClientRequestId::generate(), Message::Error(error.clone()));
+        let roundtrip =
+            MessageWire::from_bytes(&wire.to_bytes()).expect("error message can be decoded");
+
+        assert_eq!(
+            roundtrip.1,
+            Message::Error(error),
+            "All the error fields should survive a serialization roundtrip"
+        );
+
+        let bare: ServiceErrorDetails = ServiceError::InternalError.into();
+        let wire = MessageWire(ClientRequestId::generate(), Message::Error(bare.clone()));
+        let roundtrip =
+            MessageWire::from_bytes(&wire.to_bytes()).expect("error message can be decoded");
+
+        assert_eq!(
+            roundtrip.1,
+            Message::Error(bare),
+            "An error without any details should stay without details"
+        );
+    }
+
+    #[test]
+    fn cancel_roundtrip() {
+        let cancelled_request_id = ClientRequestId::generate();
+        let wire = MessageWire(
+            ClientRequestId::generate(),
+            Message::Cancel(cancelled_request_id),
+        );
+        let roundtrip =
+            MessageWire::from_bytes(&wire.to_bytes()).expect("cancel message can be decoded");
+
+        assert_eq!(
+            roundtrip.1,
+            Message::Cancel(cancelled_request_id),
+            "The cancelled request's id should survive a serialization roundtrip"
+        );
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
     error::ProtoError,
 };
 
-use crate::api::messages::{ClientRequestId, ListenerId, ServiceError};
+use crate::api::messages::{ClientRequestId, ListenerId, ServiceError, ServiceErrorDetails};
 
 pub use prost::Message as ProstMessage;
 
@@ -48,7 +48,7 @@ impl TryFrom<LicksMessageWire> for MessageWire {
 impl From<Message> for licks_message_wire::LicksMessageBody {
     fn from(value: Message) -> Self {
         match value {
-            Message::Error(error) => Self::Error(LicksApiError::from(error) as i32),
+            Message::Error(error) => Self::ErrorDetails(error.into()),
             Message::Ping(bytes) => Self::Ping(bytes),
             Message::Pong(bytes) => Self::Pong(bytes),
             Message::Ignore => Self::Empty(EmptyMessageBody::Ignore.into()),
@@ -61,6 +61,7 @@ impl From<Message> for licks_message_wire::LicksMessageBody {
             Message::ChallengeResponse(challenge_response) => {
                 Self::ChallengeResponse(challenge_response.into())
             }
+            Message::Cancel(request_id) => Self::Cancel(request_id.0.into_bytes().to_vec()),
         }
     }
 }
@@ -88,6 +89,20 @@ impl From<crate::api::messages::AuthRequest> for AuthenticatedChannelMessage {
             crate::api::messages::AuthRequest::KeyPackageAlreadyUploaded => {
                 authenticated_channel_message::Inner::KeyPackageAlreadyUploaded(Empty {})
             }
+            crate::api::messages::AuthRequest::RotateAccountKey(rotation) => {
+                authenticated_channel_message::Inner::RotateAccountKey(rotation.into())
+            }
+            crate::api::messages::AuthRequest::UpdateDeviceChain(chain) => {
+                authenticated_channel_message::Inner::UpdateDeviceChain(chain.into())
+            }
+            crate::api::messages::AuthRequest::GetDeviceChains => {
+                authenticated_channel_message::Inner::GetDeviceChains(Empty {})
+            }
+            crate::api::messages::AuthRequest::HereAreDeviceChains(chains) => {
+                authenticated_channel_message::Inner::HereAreDeviceChains(CertificateChains {
+                    inner: chains.into_iter().map(Into::into).collect(),
+                })
+            }
         };
         Self { inner: Some(inner) }
     }
@@ -116,6 +131,23 @@ impl TryFrom<AuthenticatedChannelMessage> for crate::api::messages::AuthRequest
             authenticated_channel_message::Inner::KeyPackageAlreadyUploaded(_) => {
                 Self::KeyPackageAlreadyUploaded
             }
+            authenticated_channel_message::Inner::RotateAccountKey(rotation) => {
+                Self::RotateAccountKey(rotation.try_into().map_err(|_| ProtoError)?)
+            }
+            authenticated_channel_message::Inner::UpdateDeviceChain(chain) => {
+                Self::UpdateDeviceChain(chain.try_into().map_err(|_| ProtoError)?)
+            }
+            authenticated_channel_message::Inner::GetDeviceChains(_) => Self::GetDeviceChains,
+            authenticated_channel_message::Inner::HereAreDeviceChains(chains) => {
+                Self::HereAreDeviceChains(
+                    chains
+                        .inner
+                        .into_iter()
+                        .map(TryInto::try_into)
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| ProtoError)?,
+                )
+            }
         })
     }
 }
@@ -149,6 +181,21 @@ impl From<crate::api::messages::UnauthRequest> for UnauthenticatedChannelMessage
             crate::api::messages::UnauthRequest::ChatService(msg) => {
                 unauthenticated_channel_message::Inner::ChatService(msg.into())
             }
+            crate::api::messages::UnauthRequest::PutGroupLink(proof) => {
+                unauthenticated_channel_message::Inner::PutGroupLink(proof.into())
+            }
+            crate::api::messages::UnauthRequest::DeleteGroupLink(proof) => {
+                unauthenticated_channel_message::Inner::DeleteGroupLink(proof.into())
+            }
+            crate::api::messages::UnauthRequest::GetGroupLink(link_id) => {
+                unauthenticated_channel_message::Inner::GetGroupLink(link_id.into())
+            }
+            crate::api::messages::UnauthRequest::HereIsGroupLink(content) => {
+                unauthenticated_channel_message::Inner::HereIsGroupLink(content)
+            }
+            crate::api::messages::UnauthRequest::RegisterDevice(chain) => {
+                unauthenticated_channel_message::Inner::RegisterDevice(chain.into())
+            }
         };
         Self { inner: Some(inner) }
     }
@@ -183,6 +230,21 @@ impl TryFrom<UnauthenticatedChannelMessage> for crate::api::messages::UnauthRequ
             unauthenticated_channel_message::Inner::ChatService(msg) => {
                 Self::ChatService(msg.try_into()?)
             }
+            unauthenticated_channel_message::Inner::PutGroupLink(proof) => {
+                Self::PutGroupLink(proof.try_into()?)
+            }
+            unauthenticated_channel_message::Inner::DeleteGroupLink(proof) => {
+                Self::DeleteGroupLink(proof.try_into()?)
+            }
+            unauthenticated_channel_message::Inner::GetGroupLink(link_id) => {
+                Self::GetGroupLink(link_id.try_into()?)
+            }
+            unauthenticated_channel_message::Inner::HereIsGroupLink(content) => {
+                Self::HereIsGroupLink(content)
+            }
+            unauthenticated_channel_message::Inner::RegisterDevice(chain) => {
+                Self::RegisterDevice(chain.try_into().map_err(|_| ProtoError)?)
+            }
         })
     }
 }
@@ -226,8 +288,12 @@ impl TryFrom<licks_message_wire::LicksMessageBody> for Message {
     ) -> Result<Self, <Self as TryFrom<licks_message_wire::LicksMessageBody>>::Error> {
         Ok(match value {
             licks_message_wire::LicksMessageBody::Error(error) => Self::Error(
-                ServiceError::try_from(LicksApiError::try_from(error).map_err(|_| ProtoError)?)?,
+                ServiceError::try_from(LicksApiError::try_from(error).map_err(|_| ProtoError)?)?
+                    .into(),
             ),
+            licks_message_wire::LicksMessageBody::ErrorDetails(details) => {
+                Self::Error(details.try_into()?)
+            }
             licks_message_wire::LicksMessageBody::Authenticated(auth) => {
                 Self::Auth(auth.try_into()?)
             }
@@ -252,6 +318,9 @@ impl TryFrom<licks_message_wire::LicksMessageBody> for Message {
                     EmptyMessageBody::Bye => Self::Bye,
                 }
             }
+            licks_message_wire::LicksMessageBody::Cancel(request_id) => Self::Cancel(
+                ClientRequestId(Uuid::from_slice(&request_id).map_err(|_| ProtoError)?),
+            ),
         })
     }
 }
@@ -291,6 +360,7 @@ impl From<crate::api::messages::ChatServiceMessage> for ChatServiceMessage {
             crate::api::messages::ChatServiceMessage::SendMessage(send_message) => {
                 chat_service_message::Inner::SendMessage(chat_service_message::SendMessageRequest {
                     proof: Some(send_message.blinded_address_proof.into()),
+                    ephemeral: send_message.ephemeral,
                 })
             }
             crate::api::messages::ChatServiceMessage::StopListening(
@@ -343,6 +413,7 @@ impl TryFrom<ChatServiceMessage> for crate::api::messages::ChatServiceMessage {
             chat_service_message::Inner::SendMessage(send_message) => {
                 Self::SendMessage(crate::api::group::SendMessageRequest {
                     blinded_address_proof: send_message.proof.ok_or(ProtoError)?.try_into()?,
+                    ephemeral: send_message.ephemeral,
                 })
             }
             chat_service_message::Inner::StopListening(stop_listening) => Self::StopListening(
@@ -369,6 +440,11 @@ impl From<ServiceError> for LicksApiError {
             ServiceError::InternalError => Self::InternalError,
             ServiceError::ConnectionIsClosed => Self::ConnectionIsClosed,
             ServiceError::UnknownError => Self::UnknownError,
+            ServiceError::UsernameAlreadyTaken => Self::UsernameAlreadyTaken,
+            ServiceError::NotFound => Self::NotFound,
+            ServiceError::RateLimited => Self::RateLimited,
+            ServiceError::Overloaded => Self::Overloaded,
+            ServiceError::QuotaExceeded => Self::QuotaExceeded,
         }
     }
 }
@@ -385,6 +461,44 @@ impl TryFrom<LicksApiError> for ServiceError {
             LicksApiError::InternalError => Ok(Self::InternalError),
             LicksApiError::ConnectionIsClosed => Ok(Self::ConnectionIsClosed),
             LicksApiError::UnknownError => Ok(Self::UnknownError),
+            LicksApiError::UsernameAlreadyTaken => Ok(Self::UsernameAlreadyTaken),
+            LicksApiError::NotFound => Ok(Self::NotFound),
+            LicksApiError::RateLimited => Ok(Self::RateLimited),
+            LicksApiError::Overloaded => Ok(Self::Overloaded),
+            LicksApiError::QuotaExceeded => Ok(Self::QuotaExceeded),
+        }
+    }
+}
+
+impl From<ServiceErrorDetails> for LicksApiErrorDetails {
+    fn from(value: ServiceErrorDetails) -> Self {
+        Self {
+            code: LicksApiError::from(value.code).into(),
+            detail: value.detail,
+            retry_after_secs: value.retry_after.map(|duration| duration.as_secs()),
+            quota: value.quota,
+            field: value.field,
         }
     }
 }
+
+impl TryFrom<LicksApiErrorDetails> for ServiceErrorDetails {
+    type Error = ProtoError;
+
+    fn try_from(value: LicksApiErrorDetails) -> Result<Self, Self::Error> {
+        // Codes we don't know about (i.e. added in a newer version of the server)
+        // are still errors, so we keep the details around instead of failing to decode.
+        let code = match LicksApiError::try_from(value.code) {
+            Ok(code) => ServiceError::try_from(code)?,
+            Err(_) => ServiceError::UnknownError,
+        };
+
+        Ok(Self {
+            code,
+            detail: value.detail,
+            retry_after: value.retry_after_secs.map(std::time::Duration::from_secs),
+            quota: value.quota,
+            field: value.field,
+        })
+    }
+}
@@ -0,0 +1,47 @@
+//! Symmetric encryption of the data we store on the server, such as invite links,
+//! using AES-256-GCM with a random nonce.
+use aes_gcm::{
+    aead::{consts::U12, AeadInPlace},
+    Aes256Gcm, Key as AesKey, KeyInit, Nonce,
+};
+
+use crate::crypto::rng::random_bytes;
+
+pub const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypts `plaintext`, authenticating `associated_data` along with it. The
+/// output is the random nonce followed by the ciphertext.
+pub fn seal(key: &[u8; KEY_LENGTH], associated_data: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let key: &AesKey<Aes256Gcm> = key.into();
+    let nonce_bytes = random_bytes::<NONCE_LENGTH>();
+    let nonce: &Nonce<U12> = (&nonce_bytes).into();
+
+    let mut in_out = plaintext.to_vec();
+    Aes256Gcm::new(key)
+        .encrypt_in_place(nonce, associated_data, &mut in_out)
+        .expect("AES-GCM encryption of an in-memory buffer cannot fail");
+
+    let mut output = nonce_bytes.to_vec();
+    output.append(&mut in_out);
+    output
+}
+
+/// Decrypts the output of [`seal`]. Returns `None` if the key or the
+/// associated data is wrong, or if the ciphertext was tampered with.
+pub fn open(key: &[u8; KEY_LENGTH], associated_data: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    if ciphertext.len() < NONCE_LENGTH {
+        return None;
+    }
+
+    let key: &AesKey<Aes256Gcm> = key.into();
+    let (nonce, ciphertext) = ciphertext.split_at(NONCE_LENGTH);
+    let nonce: &Nonce<U12> = nonce.into();
+
+    let mut in_out = ciphertext.to_vec();
+    Aes256Gcm::new(key)
+        .decrypt_in_place(nonce, associated_data, &mut in_out)
+        .ok()?;
+
+    Some(in_out)
+}
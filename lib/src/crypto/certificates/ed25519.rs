@@ -3,7 +3,7 @@
 use ed25519_dalek::ed25519::signature::Signer;
 use prost::Message;
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, hash::Hash};
+use std::{fmt::Debug, hash::Hash, time::SystemTime};
 
 use crate::{
     api::{proto, server::Server},
@@ -12,7 +12,12 @@ use crate::{
     identifiers::{AccountId, DeviceId, LicksIdentifier},
 };
 
-use super::{Certificate, CertificateChain, CertificateError, SerializedChain};
+use super::{
+    rotation::{self, AccountKeyRotation, AccountKeyShare},
+    Certificate, CertificateChain, CertificateChainSecret, CertificateError,
+    SerializedAccountCertificate, SerializedChain, Validity, ACCOUNT_CERT_VALIDITY,
+    DEVICE_CERT_VALIDITY,
+};
 
 pub(super) type Ed25519Signature = ed25519_dalek::Signature;
 pub(super) type Ed25519SecretKey = ed25519_dalek::SigningKey;
@@ -46,6 +51,9 @@ pub struct Ed25519CertificateChainSecret {
     pub public_chain: Ed25519CertificateChain,
     pub account_secret: Box<Ed25519SecretKey>,
     pub device_secret: Box<Ed25519SecretKey>,
+    /// Signature of our account certificate by the previous account key,
+    /// if the account key was rotated. See [`super::rotation`].
+    pub previous_account_signature: Option<Vec<u8>>,
 }
 
 impl Debug for Ed25519CertificateChainSecret {
@@ -80,7 +88,216 @@ impl Ed25519CertificateChainSecret {
             public_chain,
             account_secret: Box::new(account_secret),
             device_secret: Box::new(device_secret),
+            previous_account_signature: None,
+        }
+    }
+
+    /// Replaces the device certificate with one for the same device and key,
+    /// valid for [`DEVICE_CERT_VALIDITY`] starting now. This should be done
+    /// before the current one expires.
+    pub fn renew_device_cert(&mut self) {
+        let device_cert = Ed25519DeviceCert::complete(
+            self.public_chain.device_cert.device_id,
+            &self.device_secret,
+        );
+
+        self.public_chain.account_to_device_sig =
+            Box::new(self.account_secret.sign(&device_cert.to_bytes()));
+        self.public_chain.device_cert = Box::new(device_cert);
+    }
+
+    /// Replaces the account certificate with one for the same account and key,
+    /// valid for [`ACCOUNT_CERT_VALIDITY`] starting now. The device certificate
+    /// is signed by the same key, so it is kept.
+    ///
+    /// The cross-signature of a previous rotation covered the replaced
+    /// certificate, so it is dropped.
+    pub fn renew_account_cert(&mut self) {
+        let account_cert = Ed25519AccountCert::complete(
+            self.account_secret.verifying_key(),
+            &mut self.account_secret,
+            self.public_chain.account_cert.server.clone(),
+            self.public_chain.account_cert.account_id,
+        );
+
+        self.public_chain.account_cert = Box::new(account_cert);
+        self.previous_account_signature = None;
+    }
+
+    /// Replaces the device key with a new one, for the same device. The
+    /// new chain must then be registered with the server, and used in
+    /// our groups.
+    pub fn rotate_device_key(&self) -> Self {
+        let (device_cert, device_secret) =
+            Ed25519DeviceCert::generate(self.public_chain.device_cert.device_id);
+
+        let mut secret = Self::new(
+            (*self.public_chain.account_cert).clone(),
+            (*self.account_secret).clone(),
+            device_cert,
+            device_secret,
+        );
+        secret
+            .previous_account_signature
+            .clone_from(&self.previous_account_signature);
+
+        secret
+    }
+
+    /// Certifies `device_cert` with our account key, and returns the chain
+    /// of that device. This is how a new device is linked to our account:
+    /// its device secret stays on the new device (see [`Self::from_linked_chain`]).
+    pub fn sign_device_cert(&self, device_cert: Ed25519DeviceCert) -> Ed25519CertificateChain {
+        Ed25519CertificateChain {
+            account_cert: self.public_chain.account_cert.clone(),
+            account_to_device_sig: Box::new(self.account_secret.sign(&device_cert.to_bytes())),
+            device_cert: Box::new(device_cert),
+        }
+    }
+
+    /// Replaces the account key with a new one, keeping the same [`AccountId`]
+    /// and [`Server`], and re-signs our device certificate with it. The new
+    /// account certificate is cross-signed by the previous account key.
+    ///
+    /// The device certificates of the other devices in `registered_chains` (the
+    /// chains registered with the server) are signed again too. Those whose
+    /// device certificate isn't valid anymore are left out.
+    ///
+    /// Returns the rotated secret, and the [`AccountKeyRotation`] to send to
+    /// the server.
+    pub fn rotate_account_key(
+        &self,
+        registered_chains: &[SerializedChain],
+    ) -> (Self, AccountKeyRotation) {
+        let (account_cert, account_secret) = Ed25519AccountCert::generate(
+            self.public_chain.account_cert.server.clone(),
+            self.public_chain.account_cert.account_id,
+        );
+        let previous_account_signature = self
+            .account_secret
+            .sign(&rotation::cross_signed_bytes(&account_cert))
+            .to_vec();
+
+        let mut rotated = Self::new(
+            account_cert.clone(),
+            account_secret,
+            *self.public_chain.device_cert,
+            (*self.device_secret).clone(),
+        );
+        rotated.previous_account_signature = Some(previous_account_signature.clone());
+
+        let mut new_chains = vec![rotated.public_chain.clone().serialize()];
+        for chain in registered_chains {
+            if let SerializedChain::Ed25519Chain(chain) = chain {
+                if chain.account_cert.account_id == self.public_chain.account_cert.account_id
+                    && chain.device_cert.device_id != self.public_chain.device_cert.device_id
+                    && chain.device_cert.verify_self_signature().is_ok()
+                {
+                    new_chains.push(rotated.sign_device_cert(*chain.device_cert).serialize());
+                }
+            }
+        }
+
+        let rotation = AccountKeyRotation {
+            new_account_cert: account_cert.serialize(),
+            previous_account_signature,
+            new_chains,
+        };
+
+        (rotated, rotation)
+    }
+
+    /// Returns our account key, to share it with our other devices after
+    /// rotating it (see [`Self::with_shared_account_key`]), or with a device
+    /// we link (see [`Self::from_linked_chain`]).
+    pub fn share_account_key(&self) -> AccountKeyShare {
+        AccountKeyShare {
+            account_cert: (*self.public_chain.account_cert).clone().serialize(),
+            account_secret: self.account_secret.to_bytes().to_vec(),
+            previous_account_signature: self.previous_account_signature.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Adopts the account key that another device of our account rotated (see
+    /// [`Self::share_account_key`]), and re-signs our device certificate with it.
+    ///
+    /// Fails unless the shared account certificate is valid, matches the shared
+    /// secret, and is cross-signed by our current account key.
+    pub fn with_shared_account_key(
+        &self,
+        share: &AccountKeyShare,
+    ) -> Result<Self, CertificateError> {
+        let (account_cert, account_secret) = Self::shared_account_key(share)?;
+
+        let mut secret = Self::new(
+            account_cert,
+            account_secret,
+            *self.public_chain.device_cert,
+            (*self.device_secret).clone(),
+        );
+        secret.previous_account_signature = Some(share.previous_account_signature.clone());
+
+        let new_chain = secret.serialized();
+        new_chain.clone().verify()?;
+        rotation::verify_successor(
+            &self.serialized(),
+            &new_chain,
+            &share.previous_account_signature,
+        )?;
+
+        Ok(secret)
+    }
+
+    /// Creates the secret of a device we are linking to an account, from its device
+    /// secret and the chain signed by an existing device of the account (see
+    /// [`Self::sign_device_cert`]), which also shared the account key.
+    ///
+    /// Fails unless the chain is valid, certifies `device_secret`, and was signed
+    /// by the shared account key.
+    pub fn from_linked_chain(
+        public_chain: Ed25519CertificateChain,
+        device_secret: Ed25519SecretKey,
+        share: &AccountKeyShare,
+    ) -> Result<Self, CertificateError> {
+        let (account_cert, account_secret) = Self::shared_account_key(share)?;
+
+        public_chain.verify_self()?;
+        if *public_chain.account_cert != account_cert
+            || public_chain.device_cert.pub_key != device_secret.verifying_key()
+        {
+            return Err(CertificateError::InvalidData);
+        }
+
+        Ok(Self {
+            public_chain,
+            account_secret: Box::new(account_secret),
+            device_secret: Box::new(device_secret),
+            previous_account_signature: Some(share.previous_account_signature.clone())
+                .filter(|signature| !signature.is_empty()),
+        })
+    }
+
+    /// Parses the account certificate and secret of `share`, and checks that
+    /// they match.
+    fn shared_account_key(
+        share: &AccountKeyShare,
+    ) -> Result<(Ed25519AccountCert, Ed25519SecretKey), CertificateError> {
+        let SerializedAccountCertificate::Ed25519(account_cert) = &share.account_cert else {
+            return Err(CertificateError::InvalidData);
+        };
+        let account_secret = Ed25519SecretKey::from_bytes(
+            &share
+                .account_secret
+                .as_slice()
+                .try_into()
+                .map_err(|_| CertificateError::CryptoDeserialization)?,
+        );
+
+        if account_secret.verifying_key() != account_cert.pub_key {
+            return Err(CertificateError::InvalidData);
         }
+
+        Ok(((**account_cert).clone(), account_secret))
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
@@ -88,6 +305,7 @@ impl Ed25519CertificateChainSecret {
             public: Some(self.public_chain.clone().serialize().into()),
             account_secret: self.account_secret.to_bytes().to_vec(),
             device_secret: self.device_secret.to_bytes().to_vec(),
+            previous_account_signature: self.previous_account_signature.clone().unwrap_or_default(),
         };
 
         proto.encode_to_vec()
@@ -106,10 +324,9 @@ impl Ed25519CertificateChainSecret {
             .try_into()
             .map_err(|_| ProtoError)?;
 
-        // NOTE: In the future where we have more `SerializedChain` types
-        // we will change this to a `match` and potentially return a ProtoError
-        // if we end up not having an `Ed25519Chain`
-        let SerializedChain::Ed25519Chain(ed25519_public) = public;
+        let SerializedChain::Ed25519Chain(ed25519_public) = public else {
+            return Err(ProtoError);
+        };
 
         Ok(Self {
             public_chain: ed25519_public,
@@ -119,6 +336,8 @@ impl Ed25519CertificateChainSecret {
             device_secret: Box::new(Ed25519SecretKey::from_bytes(
                 &proto.device_secret.try_into().map_err(|_| ProtoError)?,
             )),
+            previous_account_signature: Some(proto.previous_account_signature)
+                .filter(|signature| !signature.is_empty()),
         })
     }
 }
@@ -149,9 +368,9 @@ impl super::CertificateChain for Ed25519CertificateChain {
         &self.device_cert.device_id
     }
 
-    fn verify_self(&self) -> Result<(), CertificateError> {
-        self.account_cert.verify_self_signature()?;
-        self.device_cert.verify_self_signature()?;
+    fn verify_self_at(&self, time: SystemTime) -> Result<(), CertificateError> {
+        self.account_cert.verify_self_signature_at(time)?;
+        self.device_cert.verify_self_signature_at(time)?;
         self.account_cert
             .pub_key
             .verify_strict(&self.device_cert.to_bytes(), &self.account_to_device_sig)
@@ -170,15 +389,29 @@ impl super::CertificateChain for Ed25519CertificateChain {
             .map_err(|_| CertificateError::InvalidSignature)
     }
 
+    fn verify_account_signature(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CertificateError> {
+        let signature =
+            Ed25519Signature::from_slice(signature).map_err(|_| CertificateError::InvalidData)?;
+
+        self.account_cert
+            .pub_key
+            .verify_strict(message, &signature)
+            .map_err(|_| CertificateError::InvalidSignature)
+    }
+
     fn serialize(self) -> super::SerializedChain {
         super::SerializedChain::Ed25519Chain(self)
     }
 
-    fn account_cert(&self) -> &impl Certificate {
+    fn account_cert(&self) -> impl Certificate + '_ {
         &*self.account_cert
     }
 
-    fn device_cert(&self) -> &impl Certificate {
+    fn device_cert(&self) -> impl Certificate + '_ {
         &*self.device_cert
     }
 }
@@ -189,6 +422,7 @@ pub struct Ed25519AccountCert {
     pub(super) account_id: AccountId,
     pub(super) pub_key: Ed25519PublicKey,
     pub(super) self_signature: Ed25519Signature,
+    pub(super) validity: Validity,
 }
 
 impl Ed25519AccountCert {
@@ -212,9 +446,12 @@ impl Ed25519AccountCert {
         server: Server,
         account_id: AccountId,
     ) -> Self {
+        let validity = Validity::starting_now(ACCOUNT_CERT_VALIDITY);
+
         let mut bytes: Vec<u8> = account_id.to_bytes().to_vec();
         bytes.append(&mut server.clone().to_vec());
         bytes.append(&mut pub_key.to_bytes().to_vec());
+        bytes.extend_from_slice(&validity.to_bytes());
 
         let self_signature = secret_key.sign(&bytes);
 
@@ -223,6 +460,7 @@ impl Ed25519AccountCert {
             account_id,
             pub_key,
             self_signature,
+            validity,
         }
     }
 
@@ -239,14 +477,21 @@ impl super::Certificate for Ed25519AccountCert {
         super::SignatureScheme::Ed25519
     }
 
-    fn verify_self_signature(&self) -> Result<(), CertificateError> {
+    fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    fn verify_self_signature_at(&self, time: SystemTime) -> Result<(), CertificateError> {
         let mut bytes: Vec<u8> = self.account_id.to_bytes().to_vec();
         bytes.append(&mut self.server.clone().to_vec());
         bytes.append(&mut self.pub_key.to_bytes().to_vec());
+        bytes.extend_from_slice(&self.validity.to_bytes());
 
         self.pub_key
             .verify_strict(&bytes, &self.self_signature)
-            .map_err(|_| CertificateError::InvalidSignature)
+            .map_err(|_| CertificateError::InvalidSignature)?;
+
+        self.validity.check(time)
     }
 
     fn pub_key_bytes(&self) -> Vec<u8> {
@@ -262,6 +507,8 @@ impl super::Certificate for Ed25519AccountCert {
             public_key: self.pub_key.to_bytes().to_vec(),
             self_signature_of_inner: self.self_signature.to_vec(),
             data,
+            not_before: self.validity.not_before,
+            not_after: self.validity.not_after,
         }
     }
 }
@@ -271,26 +518,36 @@ pub struct Ed25519DeviceCert {
     pub(super) device_id: DeviceId,
     pub(super) pub_key: Ed25519PublicKey,
     pub(super) self_signature: Ed25519Signature,
+    pub(super) validity: Validity,
 }
 
 impl Ed25519DeviceCert {
     pub fn generate(device_id: DeviceId) -> (Self, Ed25519SecretKey) {
         let mut rng = get_rng();
         let secret_key = Ed25519SecretKey::generate(&mut rng);
+        let public_cert = Self::complete(device_id, &secret_key);
+
+        (public_cert, secret_key)
+    }
+
+    /// Creates a certificate for `secret_key`'s public key, valid for
+    /// [`DEVICE_CERT_VALIDITY`] starting now.
+    pub fn complete(device_id: DeviceId, secret_key: &Ed25519SecretKey) -> Self {
         let pub_key = secret_key.verifying_key();
+        let validity = Validity::starting_now(DEVICE_CERT_VALIDITY);
 
         let mut bytes: Vec<u8> = device_id.to_bytes().to_vec();
         bytes.append(&mut pub_key.to_bytes().to_vec());
+        bytes.extend_from_slice(&validity.to_bytes());
 
         let self_signature = secret_key.sign(&bytes);
 
-        let public_cert = Self {
+        Self {
             device_id,
             pub_key,
             self_signature,
-        };
-
-        (public_cert, secret_key)
+            validity,
+        }
     }
 }
 
@@ -299,13 +556,20 @@ impl super::Certificate for Ed25519DeviceCert {
         super::SignatureScheme::Ed25519
     }
 
-    fn verify_self_signature(&self) -> Result<(), CertificateError> {
+    fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    fn verify_self_signature_at(&self, time: SystemTime) -> Result<(), CertificateError> {
         let mut bytes: Vec<u8> = self.device_id.to_bytes().to_vec();
         bytes.append(&mut self.pub_key.to_bytes().to_vec());
+        bytes.extend_from_slice(&self.validity.to_bytes());
 
         self.pub_key
             .verify_strict(&bytes, &self.self_signature)
-            .map_err(|_| CertificateError::InvalidSignature)
+            .map_err(|_| CertificateError::InvalidSignature)?;
+
+        self.validity.check(time)
     }
 
     fn pub_key_bytes(&self) -> Vec<u8> {
@@ -320,6 +584,8 @@ impl super::Certificate for Ed25519DeviceCert {
             public_key: self.pub_key.to_bytes().to_vec(),
             self_signature_of_inner: self.self_signature.to_vec(),
             data,
+            not_before: self.validity.not_before,
+            not_after: self.validity.not_after,
         }
     }
 }
@@ -370,6 +636,389 @@ mod tests {
         assert_eq!(chain.to_bytes(), parsed_chain.to_bytes());
     }
 
+    #[test]
+    pub fn test_ed25519_chain_validity() {
+        use std::time::Duration;
+
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), AccountId::generate_id());
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+
+        let mut chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+
+        let chain = chain_secret.public_chain.clone();
+        let validity = chain.validity();
+        assert_eq!(
+            validity, chain.device_cert.validity,
+            "Device certificates expire before account certificates"
+        );
+
+        let after_expiry = SystemTime::UNIX_EPOCH + Duration::from_secs(validity.not_after + 1);
+        assert_eq!(
+            chain.verify_self_at(after_expiry),
+            Err(CertificateError::OutsideValidityPeriod),
+            "Expired chains should be rejected"
+        );
+        assert_eq!(
+            chain.clone().serialize().verify_at(after_expiry).err(),
+            Some(CertificateError::OutsideValidityPeriod),
+            "Expired serialized chains should be rejected"
+        );
+
+        let before_issuance = SystemTime::UNIX_EPOCH;
+        assert_eq!(
+            chain.verify_self_at(before_issuance),
+            Err(CertificateError::OutsideValidityPeriod),
+            "Chains should be rejected before they're valid"
+        );
+
+        // Tampering with the validity breaks the self-signature
+        let mut extended_device_cert = *chain.device_cert;
+        extended_device_cert.validity.not_after = u64::MAX;
+        assert_eq!(
+            extended_device_cert.verify_self_signature(),
+            Err(CertificateError::InvalidSignature),
+            "The validity period should be covered by the self-signature"
+        );
+
+        // Renewal keeps the device and its key
+        chain_secret.renew_device_cert();
+        let renewed_chain = &chain_secret.public_chain;
+        assert!(
+            renewed_chain.verify_self().is_ok(),
+            "A renewed chain should be valid"
+        );
+        assert_eq!(
+            renewed_chain.device_cert.pub_key,
+            chain_secret.device_secret.verifying_key(),
+            "Renewing should keep the device key"
+        );
+        assert_eq!(
+            renewed_chain.device_id(),
+            chain.device_id(),
+            "Renewing should keep the DeviceId"
+        );
+
+        // So does renewing the account certificate, with the account key
+        let device_cert = renewed_chain.device_cert.clone();
+        chain_secret.renew_account_cert();
+        let renewed_chain = &chain_secret.public_chain;
+        assert!(
+            renewed_chain.verify_self().is_ok(),
+            "A chain with a renewed account certificate should be valid"
+        );
+        assert_eq!(
+            renewed_chain.account_cert.pub_key, chain.account_cert.pub_key,
+            "Renewing should keep the account key"
+        );
+        assert_eq!(
+            renewed_chain.device_cert, device_cert,
+            "Renewing the account certificate should keep the device certificate"
+        );
+    }
+
+    #[test]
+    pub fn test_ed25519_legacy_certificates() {
+        use std::time::Duration;
+
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), AccountId::generate_id());
+
+        // Certificates used to be self-signed without a validity period
+        let device_id = DeviceId::generate_id();
+        let device_secret = Ed25519SecretKey::generate(&mut get_rng());
+        let mut signed_bytes = device_id.to_bytes().to_vec();
+        signed_bytes.extend_from_slice(&device_secret.verifying_key().to_bytes());
+        let legacy_device_cert = Ed25519DeviceCert {
+            device_id,
+            pub_key: device_secret.verifying_key(),
+            self_signature: device_secret.sign(&signed_bytes),
+            validity: Validity::LEGACY,
+        };
+
+        let mut chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            legacy_device_cert,
+            device_secret,
+        );
+
+        // Stored legacy certificates have no validity fields
+        let parsed_chain =
+            SerializedChain::from_bytes(&chain_secret.public_chain.clone().to_bytes())
+                .expect("Serialization round-trip works");
+        let SerializedChain::Ed25519Chain(parsed_chain) = parsed_chain else {
+            panic!("The chain should still use Ed25519");
+        };
+        assert_eq!(
+            parsed_chain.device_cert.validity,
+            Validity::LEGACY,
+            "Legacy certificates should be parsed as such"
+        );
+        assert!(
+            parsed_chain.verify_self().is_ok(),
+            "Legacy certificates should still be valid"
+        );
+        assert_eq!(
+            parsed_chain.validity(),
+            parsed_chain.account_cert.validity,
+            "Legacy certificates shouldn't restrict the validity of a chain"
+        );
+        assert!(
+            parsed_chain
+                .device_cert
+                .validity
+                .expires_within(Duration::ZERO),
+            "Legacy certificates should be renewed as soon as possible"
+        );
+
+        chain_secret.renew_device_cert();
+        assert!(
+            !chain_secret.public_chain.device_cert.validity.is_legacy(),
+            "A renewed certificate should have a validity period"
+        );
+        assert!(
+            chain_secret.public_chain.verify_self().is_ok(),
+            "A renewed legacy chain should be valid"
+        );
+    }
+
+    #[test]
+    pub fn test_ed25519_account_key_rotation() {
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), AccountId::generate_id());
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+
+        let chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+        let chain = chain_secret.public_chain.clone();
+        let (other_device_cert, other_device_key) =
+            Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let other_device_secret = Ed25519CertificateChainSecret::from_linked_chain(
+            chain_secret.sign_device_cert(other_device_cert),
+            other_device_key,
+            &chain_secret.share_account_key(),
+        )
+        .expect("Our other device can be linked");
+
+        let (rotated_secret, rotation) = chain_secret
+            .rotate_account_key(&[chain.clone().serialize(), other_device_secret.serialized()]);
+        let rotated_chain = &rotated_secret.public_chain;
+
+        assert!(
+            rotated_chain.verify_self().is_ok(),
+            "A rotated chain should be valid"
+        );
+        assert_eq!(
+            rotated_chain, &chain,
+            "Rotating should keep the AccountId, Server and DeviceId"
+        );
+        assert_ne!(
+            rotated_chain.account_cert.pub_key, chain.account_cert.pub_key,
+            "Rotating should change the account key"
+        );
+        assert_eq!(
+            rotation.verify(&chain.clone().serialize()),
+            Ok(*chain.account_id()),
+            "The rotation should be cross-signed by the previous account key"
+        );
+
+        // Someone else can't vouch for our new account certificate
+        let (other_account_cert, other_account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), *chain.account_id());
+        let other_chain = Ed25519CertificateChainSecret::new(
+            other_account_cert,
+            other_account_secret,
+            *chain.device_cert,
+            (*chain_secret.device_secret).clone(),
+        )
+        .public_chain;
+        assert_eq!(
+            rotation.verify(&other_chain.serialize()),
+            Err(CertificateError::InvalidSignature),
+            "A rotation should only be valid for the previous account key"
+        );
+
+        // New account certificates must have a validity period
+        let legacy_account_secret = Ed25519SecretKey::generate(&mut get_rng());
+        let mut signed_bytes = chain.account_id().to_bytes().to_vec();
+        signed_bytes.append(&mut Server::localhost().to_vec());
+        signed_bytes.extend_from_slice(&legacy_account_secret.verifying_key().to_bytes());
+        let legacy_account_cert = Ed25519AccountCert {
+            server: Server::localhost(),
+            account_id: *chain.account_id(),
+            pub_key: legacy_account_secret.verifying_key(),
+            self_signature: legacy_account_secret.sign(&signed_bytes),
+            validity: Validity::LEGACY,
+        };
+        let legacy_rotation = AccountKeyRotation {
+            new_account_cert: legacy_account_cert.clone().serialize(),
+            previous_account_signature: chain_secret
+                .account_secret
+                .sign(&rotation::cross_signed_bytes(&legacy_account_cert))
+                .to_vec(),
+            new_chains: vec![Ed25519CertificateChainSecret::new(
+                legacy_account_cert,
+                legacy_account_secret,
+                *chain.device_cert,
+                (*chain_secret.device_secret).clone(),
+            )
+            .serialized()],
+        };
+        assert_eq!(
+            legacy_rotation.verify(&chain.clone().serialize()),
+            Err(CertificateError::InvalidData),
+            "A new account certificate without a validity period should be rejected"
+        );
+
+        // The cross-signature is kept in the secret
+        assert_eq!(
+            Ed25519CertificateChainSecret::from_bytes(&rotated_secret.to_bytes())
+                .expect("Serialization round-trip works")
+                .previous_account_signature,
+            Some(rotation.previous_account_signature),
+            "The cross-signature should survive a serialization round-trip"
+        );
+
+        // Our other device gets a new chain, and adopts the new account key
+        assert_eq!(
+            rotation.new_chains.len(),
+            2,
+            "Every registered device should get a new chain"
+        );
+        let share = AccountKeyShare::from_bytes(&rotated_secret.share_account_key().to_bytes())
+            .expect("Serialization round-trip works");
+        let other_rotated_secret = other_device_secret
+            .with_shared_account_key(&share)
+            .expect("Our other device accepts the new account key");
+        assert_eq!(
+            other_rotated_secret.public_chain.account_cert, rotated_chain.account_cert,
+            "Our other device should use the new account certificate"
+        );
+        assert!(
+            rotation.new_chains.iter().any(|new_chain| {
+                new_chain == &other_rotated_secret.serialized()
+                    && new_chain.pub_key_bytes()
+                        == other_rotated_secret.serialized().pub_key_bytes()
+            }),
+            "The rotation should contain our other device's chain"
+        );
+        assert!(
+            other_rotated_secret
+                .with_shared_account_key(&share)
+                .is_err(),
+            "An account key can't be adopted twice"
+        );
+    }
+
+    #[test]
+    pub fn test_ed25519_device_key_rotation() {
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), AccountId::generate_id());
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+
+        let chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+
+        let rotated_secret = chain_secret.rotate_device_key();
+        let rotated_chain = &rotated_secret.public_chain;
+
+        assert!(
+            rotated_chain.verify_self().is_ok(),
+            "A chain with a rotated device key should be valid"
+        );
+        assert_eq!(
+            rotated_chain, &chain_secret.public_chain,
+            "Rotating should keep the AccountId, Server and DeviceId"
+        );
+        assert_eq!(
+            rotated_chain.account_cert, chain_secret.public_chain.account_cert,
+            "Rotating the device key should keep the account certificate"
+        );
+        assert_ne!(
+            rotated_chain.device_cert.pub_key, chain_secret.public_chain.device_cert.pub_key,
+            "Rotating should change the device key"
+        );
+    }
+
+    #[test]
+    pub fn test_ed25519_link_new_device() {
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), AccountId::generate_id());
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+
+        let chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+
+        // The new device generates its own key, and only sends its certificate
+        let (linked_device_cert, linked_device_key) =
+            Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let linked_secret = Ed25519CertificateChainSecret::from_linked_chain(
+            chain_secret.sign_device_cert(linked_device_cert),
+            linked_device_key.clone(),
+            &AccountKeyShare::from_bytes(&chain_secret.share_account_key().to_bytes())
+                .expect("Serialization round-trip works"),
+        )
+        .expect("The new device accepts the chain signed for it");
+        let linked_chain = &linked_secret.public_chain;
+
+        assert!(
+            linked_chain.verify_self().is_ok(),
+            "The chain of a linked device should be valid"
+        );
+        assert_eq!(
+            linked_chain.account_cert, chain_secret.public_chain.account_cert,
+            "A linked device should keep the account certificate"
+        );
+        assert_ne!(
+            linked_chain.device_cert.device_id, chain_secret.public_chain.device_cert.device_id,
+            "A linked device should have its own DeviceId"
+        );
+        assert_ne!(
+            linked_chain.device_cert.pub_key, chain_secret.public_chain.device_cert.pub_key,
+            "A linked device should have its own device key"
+        );
+
+        let (other_device_cert, _) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        assert_eq!(
+            Ed25519CertificateChainSecret::from_linked_chain(
+                chain_secret.sign_device_cert(other_device_cert),
+                linked_device_key.clone(),
+                &chain_secret.share_account_key(),
+            ),
+            Err(CertificateError::InvalidData),
+            "A chain certifying another device key should be rejected"
+        );
+
+        let (other_account_secret, _) = chain_secret.rotate_account_key(&[]);
+        assert_eq!(
+            Ed25519CertificateChainSecret::from_linked_chain(
+                chain_secret.sign_device_cert(*linked_chain.device_cert),
+                linked_device_key,
+                &other_account_secret.share_account_key(),
+            ),
+            Err(CertificateError::InvalidData),
+            "The shared account key should be the one that signed the chain"
+        );
+    }
+
     #[test]
     pub fn test_fake_ed25519_chain() {
         todo!("Verify that invalid signatures don't get parsed as correct chains");
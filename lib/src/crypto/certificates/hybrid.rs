@@ -0,0 +1,1018 @@
+//! Implementations for account and device certificates using a hybrid signature scheme.
+//!
+//! Every key is made of an Ed25519 key and an ML-DSA-65 (FIPS 204) key, and every
+//! signature of an Ed25519 signature and an ML-DSA-65 signature of the same message.
+//! A signature is only valid if both of them are, so certificates stay secure as
+//! long as one of the two schemes isn't broken (including by a quantum computer).
+//!
+//! The device's MLS signature key is still its Ed25519 key, since MLS doesn't have
+//! cipher suites with post-quantum signatures yet.
+
+use ed25519_dalek::{
+    ed25519::signature::Signer, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH,
+};
+use ml_dsa::{EncodedSignature, EncodedVerifyingKey, KeyGen, MlDsa65, B32};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Debug, hash::Hash, time::SystemTime};
+
+use crate::{
+    api::{proto, server::Server},
+    crypto::rng::{get_rng, random_bytes},
+    error::ProtoError,
+    identifiers::{AccountId, DeviceId, LicksIdentifier},
+};
+
+use super::{
+    ed25519::{Ed25519PublicKey, Ed25519SecretKey, Ed25519Signature},
+    rotation::{self, AccountKeyRotation, AccountKeyShare},
+    Certificate, CertificateChain, CertificateChainSecret, CertificateError,
+    SerializedAccountCertificate, SerializedChain, Validity, ACCOUNT_CERT_VALIDITY,
+    DEVICE_CERT_VALIDITY,
+};
+
+type MlDsaVerifyingKey = ml_dsa::VerifyingKey<MlDsa65>;
+type MlDsaSignature = ml_dsa::Signature<MlDsa65>;
+
+/// Size of an encoded ML-DSA-65 public key.
+const ML_DSA_PUBLIC_KEY_LENGTH: usize = 1952;
+/// Size of an encoded ML-DSA-65 signature.
+const ML_DSA_SIGNATURE_LENGTH: usize = 3309;
+/// Size of the seed ML-DSA-65 key pairs are derived from.
+const ML_DSA_SEED_LENGTH: usize = 32;
+
+/// An Ed25519 public key and an ML-DSA-65 public key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HybridPublicKey {
+    ed25519: Ed25519PublicKey,
+    /// The encoded ML-DSA key. It is only decoded when verifying signatures.
+    ml_dsa: Vec<u8>,
+}
+
+impl HybridPublicKey {
+    pub const LENGTH: usize = PUBLIC_KEY_LENGTH + ML_DSA_PUBLIC_KEY_LENGTH;
+
+    /// The Ed25519 half of the key.
+    pub fn ed25519(&self) -> &Ed25519PublicKey {
+        &self.ed25519
+    }
+
+    /// `[ed25519 public key][ML-DSA public key]`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.ed25519.to_bytes().to_vec();
+        bytes.extend_from_slice(&self.ml_dsa);
+
+        bytes
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, CertificateError> {
+        if bytes.len() != Self::LENGTH {
+            return Err(CertificateError::CryptoDeserialization);
+        }
+
+        let (ed25519, ml_dsa) = bytes.split_at(PUBLIC_KEY_LENGTH);
+
+        Ok(Self {
+            ed25519: Ed25519PublicKey::try_from(ed25519)
+                .map_err(|_| CertificateError::CryptoDeserialization)?,
+            ml_dsa: ml_dsa.to_vec(),
+        })
+    }
+
+    /// Verifies both halves of the signature. Fails if any of them is invalid.
+    pub fn verify(
+        &self,
+        message: &[u8],
+        signature: &HybridSignature,
+    ) -> Result<(), CertificateError> {
+        self.ed25519
+            .verify_strict(message, &signature.ed25519)
+            .map_err(|_| CertificateError::InvalidSignature)?;
+
+        let ml_dsa_key = EncodedVerifyingKey::<MlDsa65>::try_from(self.ml_dsa.as_slice())
+            .map_err(|_| CertificateError::CryptoDeserialization)?;
+        let ml_dsa_signature = signature.ml_dsa()?;
+
+        ml_dsa::signature::Verifier::verify(
+            &MlDsaVerifyingKey::decode(&ml_dsa_key),
+            message,
+            &ml_dsa_signature,
+        )
+        .map_err(|_| CertificateError::InvalidSignature)
+    }
+}
+
+/// An Ed25519 signature and an ML-DSA-65 signature of the same message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HybridSignature {
+    ed25519: Ed25519Signature,
+    /// The encoded ML-DSA signature.
+    ml_dsa: Vec<u8>,
+}
+
+impl HybridSignature {
+    pub const LENGTH: usize = SIGNATURE_LENGTH + ML_DSA_SIGNATURE_LENGTH;
+
+    /// `[ed25519 signature][ML-DSA signature]`
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut bytes = self.ed25519.to_vec();
+        bytes.extend_from_slice(&self.ml_dsa);
+
+        bytes
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, CertificateError> {
+        if bytes.len() != Self::LENGTH {
+            return Err(CertificateError::CryptoDeserialization);
+        }
+
+        let (ed25519, ml_dsa) = bytes.split_at(SIGNATURE_LENGTH);
+
+        let signature = Self {
+            ed25519: Ed25519Signature::from_slice(ed25519)
+                .map_err(|_| CertificateError::CryptoDeserialization)?,
+            ml_dsa: ml_dsa.to_vec(),
+        };
+
+        // Make sure the ML-DSA half is well-formed
+        signature.ml_dsa()?;
+
+        Ok(signature)
+    }
+
+    fn ml_dsa(&self) -> Result<MlDsaSignature, CertificateError> {
+        EncodedSignature::<MlDsa65>::try_from(self.ml_dsa.as_slice())
+            .ok()
+            .and_then(|encoded| MlDsaSignature::decode(&encoded))
+            .ok_or(CertificateError::CryptoDeserialization)
+    }
+}
+
+/// An Ed25519 secret key, and the seed of an ML-DSA-65 key pair.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HybridSecretKey {
+    ed25519: Ed25519SecretKey,
+    ml_dsa_seed: [u8; ML_DSA_SEED_LENGTH],
+}
+
+impl Debug for HybridSecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HybridSecretKey").finish_non_exhaustive()
+    }
+}
+
+impl HybridSecretKey {
+    pub const LENGTH: usize = SECRET_KEY_LENGTH + ML_DSA_SEED_LENGTH;
+
+    pub fn generate() -> Self {
+        let mut rng = get_rng();
+
+        Self {
+            ed25519: Ed25519SecretKey::generate(&mut rng),
+            ml_dsa_seed: random_bytes::<ML_DSA_SEED_LENGTH>(),
+        }
+    }
+
+    /// The Ed25519 half of the key. This is the one used for MLS signatures.
+    pub fn ed25519(&self) -> &Ed25519SecretKey {
+        &self.ed25519
+    }
+
+    fn ml_dsa_key_pair(&self) -> ml_dsa::KeyPair<MlDsa65> {
+        MlDsa65::key_gen_internal(&B32::from(self.ml_dsa_seed))
+    }
+
+    pub fn public_key(&self) -> HybridPublicKey {
+        HybridPublicKey {
+            ed25519: self.ed25519.verifying_key(),
+            ml_dsa: self.ml_dsa_key_pair().verifying_key().encode().to_vec(),
+        }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> HybridSignature {
+        let ml_dsa_signature: MlDsaSignature =
+            ml_dsa::signature::Signer::sign(&self.ml_dsa_key_pair(), message);
+
+        HybridSignature {
+            ed25519: self.ed25519.sign(message),
+            ml_dsa: ml_dsa_signature.encode().to_vec(),
+        }
+    }
+
+    /// `[ed25519 secret key][ML-DSA seed]`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.ed25519.to_bytes().to_vec();
+        bytes.extend_from_slice(&self.ml_dsa_seed);
+
+        bytes
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, CertificateError> {
+        if bytes.len() != Self::LENGTH {
+            return Err(CertificateError::CryptoDeserialization);
+        }
+
+        let (ed25519, ml_dsa_seed) = bytes.split_at(SECRET_KEY_LENGTH);
+
+        Ok(Self {
+            ed25519: Ed25519SecretKey::from_bytes(
+                &ed25519
+                    .try_into()
+                    .map_err(|_| CertificateError::CryptoDeserialization)?,
+            ),
+            ml_dsa_seed: ml_dsa_seed
+                .try_into()
+                .map_err(|_| CertificateError::CryptoDeserialization)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
+pub struct HybridCertificateChain {
+    pub(super) account_cert: Box<HybridAccountCert>,
+    pub(super) account_to_device_sig: Box<HybridSignature>,
+    pub(super) device_cert: Box<HybridDeviceCert>,
+}
+
+impl PartialEq for HybridCertificateChain {
+    fn eq(&self, other: &Self) -> bool {
+        self.account_cert.account_id == other.account_cert.account_id
+            && self.account_cert.server == other.account_cert.server
+            && self.device_cert.device_id == other.device_cert.device_id
+    }
+}
+
+impl Hash for HybridCertificateChain {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.account_cert.account_id.hash(state);
+        self.account_cert.server.hash(state);
+        self.device_cert.device_id.hash(state);
+    }
+}
+
+impl HybridCertificateChain {
+    pub(super) fn from_proto(value: proto::CertificateChain) -> Result<Self, CertificateError> {
+        Ok(Self {
+            account_cert: Box::new(HybridAccountCert::from_proto(
+                value
+                    .account_certificate
+                    .ok_or(CertificateError::InvalidData)?,
+            )?),
+            account_to_device_sig: Box::new(HybridSignature::from_slice(
+                &value.account_to_device_signature,
+            )?),
+            device_cert: Box::new(HybridDeviceCert::from_proto(
+                value
+                    .device_certificate
+                    .ok_or(CertificateError::InvalidData)?,
+            )?),
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HybridCertificateChainSecret {
+    pub public_chain: HybridCertificateChain,
+    pub account_secret: Box<HybridSecretKey>,
+    pub device_secret: Box<HybridSecretKey>,
+    /// Signature of our account certificate by the previous account key,
+    /// if the account key was rotated. See [`super::rotation`].
+    pub previous_account_signature: Option<Vec<u8>>,
+}
+
+impl Debug for HybridCertificateChainSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HybridCertificateChainSecret")
+            .field("public_chain", &self.public_chain)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Hash for HybridCertificateChainSecret {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.public_chain.hash(state);
+    }
+}
+
+impl HybridCertificateChainSecret {
+    pub fn new(
+        account_cert: HybridAccountCert,
+        account_secret: HybridSecretKey,
+        device_cert: HybridDeviceCert,
+        device_secret: HybridSecretKey,
+    ) -> Self {
+        let account_to_device_sig = account_secret.sign(&device_cert.to_bytes());
+        let public_chain = HybridCertificateChain {
+            account_cert: Box::new(account_cert),
+            account_to_device_sig: Box::new(account_to_device_sig),
+            device_cert: Box::new(device_cert),
+        };
+
+        Self {
+            public_chain,
+            account_secret: Box::new(account_secret),
+            device_secret: Box::new(device_secret),
+            previous_account_signature: None,
+        }
+    }
+
+    /// Replaces the device certificate with one for the same device and key,
+    /// valid for [`DEVICE_CERT_VALIDITY`] starting now. This should be done
+    /// before the current one expires.
+    pub fn renew_device_cert(&mut self) {
+        let device_cert = HybridDeviceCert::complete(
+            self.public_chain.device_cert.device_id,
+            &self.device_secret,
+        );
+
+        self.public_chain.account_to_device_sig =
+            Box::new(self.account_secret.sign(&device_cert.to_bytes()));
+        self.public_chain.device_cert = Box::new(device_cert);
+    }
+
+    /// Replaces the account certificate with one for the same account and key,
+    /// valid for [`ACCOUNT_CERT_VALIDITY`] starting now. The device certificate
+    /// is signed by the same key, so it is kept.
+    ///
+    /// The cross-signature of a previous rotation covered the replaced
+    /// certificate, so it is dropped.
+    pub fn renew_account_cert(&mut self) {
+        let account_cert = HybridAccountCert::complete(
+            self.account_secret.public_key(),
+            &self.account_secret,
+            self.public_chain.account_cert.server.clone(),
+            self.public_chain.account_cert.account_id,
+        );
+
+        self.public_chain.account_cert = Box::new(account_cert);
+        self.previous_account_signature = None;
+    }
+
+    /// Replaces the device key with a new one, for the same device. The
+    /// new chain must then be registered with the server, and used in
+    /// our groups.
+    pub fn rotate_device_key(&self) -> Self {
+        let (device_cert, device_secret) =
+            HybridDeviceCert::generate(self.public_chain.device_cert.device_id);
+
+        let mut secret = Self::new(
+            (*self.public_chain.account_cert).clone(),
+            (*self.account_secret).clone(),
+            device_cert,
+            device_secret,
+        );
+        secret
+            .previous_account_signature
+            .clone_from(&self.previous_account_signature);
+
+        secret
+    }
+
+    /// Certifies `device_cert` with our account key, and returns the chain
+    /// of that device. This is how a new device is linked to our account:
+    /// its device secret stays on the new device (see [`Self::from_linked_chain`]).
+    pub fn sign_device_cert(&self, device_cert: HybridDeviceCert) -> HybridCertificateChain {
+        HybridCertificateChain {
+            account_cert: self.public_chain.account_cert.clone(),
+            account_to_device_sig: Box::new(self.account_secret.sign(&device_cert.to_bytes())),
+            device_cert: Box::new(device_cert),
+        }
+    }
+
+    /// Replaces the account key with a new one, keeping the same [`AccountId`]
+    /// and [`Server`], and re-signs our device certificate with it. The new
+    /// account certificate is cross-signed by the previous account key.
+    ///
+    /// The device certificates of the other devices in `registered_chains` (the
+    /// chains registered with the server) are signed again too. Those whose
+    /// device certificate isn't valid anymore are left out.
+    ///
+    /// Returns the rotated secret, and the [`AccountKeyRotation`] to send to
+    /// the server.
+    pub fn rotate_account_key(
+        &self,
+        registered_chains: &[SerializedChain],
+    ) -> (Self, AccountKeyRotation) {
+        let (account_cert, account_secret) = HybridAccountCert::generate(
+            self.public_chain.account_cert.server.clone(),
+            self.public_chain.account_cert.account_id,
+        );
+        let previous_account_signature = self
+            .account_secret
+            .sign(&rotation::cross_signed_bytes(&account_cert))
+            .to_vec();
+
+        let mut rotated = Self::new(
+            account_cert.clone(),
+            account_secret,
+            (*self.public_chain.device_cert).clone(),
+            (*self.device_secret).clone(),
+        );
+        rotated.previous_account_signature = Some(previous_account_signature.clone());
+
+        let mut new_chains = vec![rotated.public_chain.clone().serialize()];
+        for chain in registered_chains {
+            if let SerializedChain::HybridChain(chain) = chain {
+                if chain.account_cert.account_id == self.public_chain.account_cert.account_id
+                    && chain.device_cert.device_id != self.public_chain.device_cert.device_id
+                    && chain.device_cert.verify_self_signature().is_ok()
+                {
+                    new_chains.push(
+                        rotated
+                            .sign_device_cert((*chain.device_cert).clone())
+                            .serialize(),
+                    );
+                }
+            }
+        }
+
+        let rotation = AccountKeyRotation {
+            new_account_cert: account_cert.serialize(),
+            previous_account_signature,
+            new_chains,
+        };
+
+        (rotated, rotation)
+    }
+
+    /// Returns our account key, to share it with our other devices after
+    /// rotating it (see [`Self::with_shared_account_key`]), or with a device
+    /// we link (see [`Self::from_linked_chain`]).
+    pub fn share_account_key(&self) -> AccountKeyShare {
+        AccountKeyShare {
+            account_cert: (*self.public_chain.account_cert).clone().serialize(),
+            account_secret: self.account_secret.to_bytes(),
+            previous_account_signature: self.previous_account_signature.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Adopts the account key that another device of our account rotated (see
+    /// [`Self::share_account_key`]), and re-signs our device certificate with it.
+    ///
+    /// Fails unless the shared account certificate is valid, matches the shared
+    /// secret, and is cross-signed by our current account key.
+    pub fn with_shared_account_key(
+        &self,
+        share: &AccountKeyShare,
+    ) -> Result<Self, CertificateError> {
+        let (account_cert, account_secret) = Self::shared_account_key(share)?;
+
+        let mut secret = Self::new(
+            account_cert,
+            account_secret,
+            (*self.public_chain.device_cert).clone(),
+            (*self.device_secret).clone(),
+        );
+        secret.previous_account_signature = Some(share.previous_account_signature.clone());
+
+        let new_chain = secret.serialized();
+        new_chain.clone().verify()?;
+        rotation::verify_successor(
+            &self.serialized(),
+            &new_chain,
+            &share.previous_account_signature,
+        )?;
+
+        Ok(secret)
+    }
+
+    /// Creates the secret of a device we are linking to an account, from its device
+    /// secret and the chain signed by an existing device of the account (see
+    /// [`Self::sign_device_cert`]), which also shared the account key.
+    ///
+    /// Fails unless the chain is valid, certifies `device_secret`, and was signed
+    /// by the shared account key.
+    pub fn from_linked_chain(
+        public_chain: HybridCertificateChain,
+        device_secret: HybridSecretKey,
+        share: &AccountKeyShare,
+    ) -> Result<Self, CertificateError> {
+        let (account_cert, account_secret) = Self::shared_account_key(share)?;
+
+        public_chain.verify_self()?;
+        if *public_chain.account_cert != account_cert
+            || public_chain.device_cert.pub_key != device_secret.public_key()
+        {
+            return Err(CertificateError::InvalidData);
+        }
+
+        Ok(Self {
+            public_chain,
+            account_secret: Box::new(account_secret),
+            device_secret: Box::new(device_secret),
+            previous_account_signature: Some(share.previous_account_signature.clone())
+                .filter(|signature| !signature.is_empty()),
+        })
+    }
+
+    /// Parses the account certificate and secret of `share`, and checks that
+    /// they match.
+    fn shared_account_key(
+        share: &AccountKeyShare,
+    ) -> Result<(HybridAccountCert, HybridSecretKey), CertificateError> {
+        let SerializedAccountCertificate::Hybrid(account_cert) = &share.account_cert else {
+            return Err(CertificateError::InvalidData);
+        };
+        let account_secret = HybridSecretKey::from_slice(&share.account_secret)?;
+
+        if account_secret.public_key() != account_cert.pub_key {
+            return Err(CertificateError::InvalidData);
+        }
+
+        Ok(((**account_cert).clone(), account_secret))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let proto = proto::HybridCertificateChainSecret {
+            public: Some(self.public_chain.clone().serialize().into()),
+            account_secret: self.account_secret.to_bytes(),
+            device_secret: self.device_secret.to_bytes(),
+            previous_account_signature: self.previous_account_signature.clone().unwrap_or_default(),
+        };
+
+        proto.encode_to_vec()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtoError> {
+        let proto = proto::HybridCertificateChainSecret::decode(bytes).map_err(|_| ProtoError)?;
+
+        let public: SerializedChain = proto
+            .public
+            .ok_or(ProtoError)?
+            .try_into()
+            .map_err(|_| ProtoError)?;
+
+        let SerializedChain::HybridChain(hybrid_public) = public else {
+            return Err(ProtoError);
+        };
+
+        Ok(Self {
+            public_chain: hybrid_public,
+            account_secret: Box::new(
+                HybridSecretKey::from_slice(&proto.account_secret).map_err(|_| ProtoError)?,
+            ),
+            device_secret: Box::new(
+                HybridSecretKey::from_slice(&proto.device_secret).map_err(|_| ProtoError)?,
+            ),
+            previous_account_signature: Some(proto.previous_account_signature)
+                .filter(|signature| !signature.is_empty()),
+        })
+    }
+}
+
+impl super::CertificateChainSecret for HybridCertificateChainSecret {
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.device_secret.sign(message).to_vec()
+    }
+
+    /// Returns a serialized copy of the public certificate chain.
+    ///
+    /// NOTE: This performs a `Clone`.
+    fn serialized(&self) -> super::SerializedChain {
+        self.public_chain.clone().serialize()
+    }
+}
+
+impl super::CertificateChain for HybridCertificateChain {
+    fn get_server(&self) -> &Server {
+        &self.account_cert.server
+    }
+
+    fn account_id(&self) -> &AccountId {
+        &self.account_cert.account_id
+    }
+
+    fn device_id(&self) -> &DeviceId {
+        &self.device_cert.device_id
+    }
+
+    fn verify_self_at(&self, time: SystemTime) -> Result<(), CertificateError> {
+        self.account_cert.verify_self_signature_at(time)?;
+        self.device_cert.verify_self_signature_at(time)?;
+        self.account_cert
+            .pub_key
+            .verify(&self.device_cert.to_bytes(), &self.account_to_device_sig)?;
+
+        Ok(())
+    }
+
+    fn verify_signature(&self, message: &[u8], signature: &[u8]) -> Result<(), CertificateError> {
+        let signature =
+            HybridSignature::from_slice(signature).map_err(|_| CertificateError::InvalidData)?;
+
+        self.device_cert.pub_key.verify(message, &signature)
+    }
+
+    fn verify_account_signature(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CertificateError> {
+        let signature =
+            HybridSignature::from_slice(signature).map_err(|_| CertificateError::InvalidData)?;
+
+        self.account_cert.pub_key.verify(message, &signature)
+    }
+
+    fn serialize(self) -> super::SerializedChain {
+        super::SerializedChain::HybridChain(self)
+    }
+
+    fn account_cert(&self) -> impl Certificate + '_ {
+        &*self.account_cert
+    }
+
+    fn device_cert(&self) -> impl Certificate + '_ {
+        &*self.device_cert
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HybridAccountCert {
+    pub(super) server: Server,
+    pub(super) account_id: AccountId,
+    pub(super) pub_key: HybridPublicKey,
+    pub(super) self_signature: HybridSignature,
+    pub(super) validity: Validity,
+}
+
+impl HybridAccountCert {
+    pub fn serialize(self) -> super::SerializedAccountCertificate {
+        super::SerializedAccountCertificate::Hybrid(Box::new(self))
+    }
+
+    fn signed_bytes(
+        server: &Server,
+        account_id: &AccountId,
+        pub_key: &HybridPublicKey,
+        validity: Validity,
+    ) -> Vec<u8> {
+        let mut bytes: Vec<u8> = account_id.to_bytes().to_vec();
+        bytes.append(&mut server.to_vec());
+        bytes.append(&mut pub_key.to_bytes());
+        bytes.extend_from_slice(&validity.to_bytes());
+
+        bytes
+    }
+
+    /// Parses a certificate that uses [`proto::SignatureScheme::HybridEd25519MlDsa65`].
+    pub(super) fn from_proto(value: proto::Certificate) -> Result<Self, CertificateError> {
+        if value.scheme() != proto::SignatureScheme::HybridEd25519MlDsa65 {
+            return Err(CertificateError::InvalidData);
+        }
+
+        let validity = Validity::from_proto(&value);
+
+        // Data is [account_id][server].
+        let mut account_id = value.data;
+        let server = account_id.split_off(size_of::<AccountId>().min(account_id.len()));
+
+        Ok(Self {
+            server: Server::from_vec(server).map_err(|_| CertificateError::InvalidData)?,
+            account_id: AccountId::try_from(account_id.as_slice())
+                .map_err(|_| CertificateError::InvalidData)?,
+            pub_key: HybridPublicKey::from_slice(&value.public_key)?,
+            self_signature: HybridSignature::from_slice(&value.self_signature_of_inner)?,
+            validity,
+        })
+    }
+
+    pub fn generate_keys() -> (HybridPublicKey, HybridSecretKey) {
+        let secret_key = HybridSecretKey::generate();
+        let pub_key = secret_key.public_key();
+
+        (pub_key, secret_key)
+    }
+
+    pub fn complete(
+        pub_key: HybridPublicKey,
+        secret_key: &HybridSecretKey,
+        server: Server,
+        account_id: AccountId,
+    ) -> Self {
+        let validity = Validity::starting_now(ACCOUNT_CERT_VALIDITY);
+        let self_signature = secret_key.sign(&Self::signed_bytes(
+            &server,
+            &account_id,
+            &pub_key,
+            validity,
+        ));
+
+        Self {
+            server,
+            account_id,
+            pub_key,
+            self_signature,
+            validity,
+        }
+    }
+
+    pub fn generate(server: Server, account_id: AccountId) -> (Self, HybridSecretKey) {
+        let (public_key, secret_key) = Self::generate_keys();
+        let public_cert = Self::complete(public_key, &secret_key, server, account_id);
+
+        (public_cert, secret_key)
+    }
+}
+
+impl super::Certificate for HybridAccountCert {
+    fn get_scheme(&self) -> super::SignatureScheme {
+        super::SignatureScheme::HybridEd25519MlDsa65
+    }
+
+    fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    fn verify_self_signature_at(&self, time: SystemTime) -> Result<(), CertificateError> {
+        self.pub_key.verify(
+            &Self::signed_bytes(&self.server, &self.account_id, &self.pub_key, self.validity),
+            &self.self_signature,
+        )?;
+
+        self.validity.check(time)
+    }
+
+    fn pub_key_bytes(&self) -> Vec<u8> {
+        self.pub_key.to_bytes()
+    }
+
+    fn to_proto(&self) -> proto::Certificate {
+        let mut data: Vec<u8> = self.account_id.to_bytes().to_vec();
+        data.append(&mut self.server.to_vec());
+
+        proto::Certificate {
+            scheme: proto::SignatureScheme::HybridEd25519MlDsa65.into(),
+            public_key: self.pub_key.to_bytes(),
+            self_signature_of_inner: self.self_signature.to_vec(),
+            data,
+            not_before: self.validity.not_before,
+            not_after: self.validity.not_after,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HybridDeviceCert {
+    pub(super) device_id: DeviceId,
+    pub(super) pub_key: HybridPublicKey,
+    pub(super) self_signature: HybridSignature,
+    pub(super) validity: Validity,
+}
+
+impl HybridDeviceCert {
+    fn signed_bytes(
+        device_id: &DeviceId,
+        pub_key: &HybridPublicKey,
+        validity: Validity,
+    ) -> Vec<u8> {
+        let mut bytes: Vec<u8> = device_id.to_bytes().to_vec();
+        bytes.append(&mut pub_key.to_bytes());
+        bytes.extend_from_slice(&validity.to_bytes());
+
+        bytes
+    }
+
+    /// Parses a certificate that uses [`proto::SignatureScheme::HybridEd25519MlDsa65`].
+    pub(super) fn from_proto(value: proto::Certificate) -> Result<Self, CertificateError> {
+        if value.scheme() != proto::SignatureScheme::HybridEd25519MlDsa65 {
+            return Err(CertificateError::InvalidData);
+        }
+
+        Ok(Self {
+            validity: Validity::from_proto(&value),
+            device_id: DeviceId::try_from(value.data.as_slice())
+                .map_err(|_| CertificateError::InvalidData)?,
+            pub_key: HybridPublicKey::from_slice(&value.public_key)?,
+            self_signature: HybridSignature::from_slice(&value.self_signature_of_inner)?,
+        })
+    }
+
+    pub fn generate(device_id: DeviceId) -> (Self, HybridSecretKey) {
+        let secret_key = HybridSecretKey::generate();
+        let public_cert = Self::complete(device_id, &secret_key);
+
+        (public_cert, secret_key)
+    }
+
+    /// Creates a certificate for `secret_key`'s public key, valid for
+    /// [`DEVICE_CERT_VALIDITY`] starting now.
+    pub fn complete(device_id: DeviceId, secret_key: &HybridSecretKey) -> Self {
+        let pub_key = secret_key.public_key();
+        let validity = Validity::starting_now(DEVICE_CERT_VALIDITY);
+
+        let self_signature = secret_key.sign(&Self::signed_bytes(&device_id, &pub_key, validity));
+
+        Self {
+            device_id,
+            pub_key,
+            self_signature,
+            validity,
+        }
+    }
+
+    /// The public key used to sign MLS messages.
+    pub fn mls_pub_key(&self) -> &Ed25519PublicKey {
+        self.pub_key.ed25519()
+    }
+}
+
+impl super::Certificate for HybridDeviceCert {
+    fn get_scheme(&self) -> super::SignatureScheme {
+        super::SignatureScheme::HybridEd25519MlDsa65
+    }
+
+    fn validity(&self) -> Validity {
+        self.validity
+    }
+
+    fn verify_self_signature_at(&self, time: SystemTime) -> Result<(), CertificateError> {
+        self.pub_key.verify(
+            &Self::signed_bytes(&self.device_id, &self.pub_key, self.validity),
+            &self.self_signature,
+        )?;
+
+        self.validity.check(time)
+    }
+
+    fn pub_key_bytes(&self) -> Vec<u8> {
+        self.pub_key.to_bytes()
+    }
+
+    fn to_proto(&self) -> proto::Certificate {
+        let data: Vec<u8> = self.device_id.to_bytes().to_vec();
+
+        proto::Certificate {
+            scheme: proto::SignatureScheme::HybridEd25519MlDsa65.into(),
+            public_key: self.pub_key.to_bytes(),
+            self_signature_of_inner: self.self_signature.to_vec(),
+            data,
+            not_before: self.validity.not_before,
+            not_after: self.validity.not_after,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::certificates::{
+        CertificateChain, CertificateChainSecret, SerializedAccountCertificate, SerializedChain,
+        SerializedDeviceCertificate,
+    };
+
+    use super::*;
+
+    fn generate_chain_secret() -> HybridCertificateChainSecret {
+        let (account_cert, account_secret) =
+            HybridAccountCert::generate(Server::localhost(), AccountId::generate_id());
+        let (device_cert, device_secret) = HybridDeviceCert::generate(DeviceId::generate_id());
+
+        HybridCertificateChainSecret::new(account_cert, account_secret, device_cert, device_secret)
+    }
+
+    #[test]
+    pub fn test_hybrid_chain() {
+        let chain_secret = generate_chain_secret();
+        let chain = chain_secret.public_chain.clone();
+
+        assert!(
+            chain.verify_self().is_ok(),
+            "Correctly generated chain should always be valid"
+        );
+
+        // Serialization round trip
+        let chain_bytes = chain.clone().to_bytes();
+        let serialized_chain =
+            SerializedChain::from_bytes(&chain_bytes).expect("Serialization round-trip works");
+
+        assert_eq!(
+            serialized_chain,
+            chain_secret.serialized(),
+            ".serialized() and .to_bytes() should serialize things the same way"
+        );
+        assert_eq!(
+            serialized_chain.pub_key_bytes(),
+            chain.device_cert.mls_pub_key().to_bytes().to_vec(),
+            "The MLS signature key of a hybrid chain is its Ed25519 device key"
+        );
+
+        let parsed_chain = serialized_chain.verify().expect("our certificate is valid");
+
+        assert_eq!(chain.get_server(), parsed_chain.get_server());
+        assert_eq!(chain.account_id(), parsed_chain.account_id());
+        assert_eq!(chain.device_id(), parsed_chain.device_id());
+        assert_eq!(chain.to_bytes(), parsed_chain.to_bytes());
+
+        // Signatures
+        let signature = chain_secret.sign(b"hello");
+        assert!(
+            parsed_chain.verify_signature(b"hello", &signature).is_ok(),
+            "Signatures made by the chain's device should be valid"
+        );
+        assert_eq!(
+            parsed_chain.verify_signature(b"goodbye", &signature),
+            Err(CertificateError::InvalidSignature),
+            "Signatures of other messages should be invalid"
+        );
+
+        // Secret round trip
+        let chain_secret_bytes = chain_secret.to_bytes();
+        assert_eq!(
+            HybridCertificateChainSecret::from_bytes(&chain_secret_bytes),
+            Ok(chain_secret),
+            "Secret chain serialization round-trip works"
+        );
+    }
+
+    #[test]
+    pub fn test_hybrid_signatures_both_verified() {
+        let chain_secret = generate_chain_secret();
+        let other_secret = HybridSecretKey::generate();
+        let pub_key = chain_secret.device_secret.public_key();
+
+        let signature = chain_secret.device_secret.sign(b"hello");
+        let other_signature = other_secret.sign(b"hello");
+
+        assert!(
+            pub_key.verify(b"hello", &signature).is_ok(),
+            "A correct hybrid signature should be valid"
+        );
+
+        // Only the Ed25519 signature is valid
+        let bad_ml_dsa = HybridSignature {
+            ed25519: signature.ed25519,
+            ml_dsa: other_signature.ml_dsa.clone(),
+        };
+        assert_eq!(
+            pub_key.verify(b"hello", &bad_ml_dsa),
+            Err(CertificateError::InvalidSignature),
+            "An invalid ML-DSA signature should invalidate the hybrid signature"
+        );
+
+        // Only the ML-DSA signature is valid
+        let bad_ed25519 = HybridSignature {
+            ed25519: other_signature.ed25519,
+            ml_dsa: signature.ml_dsa.clone(),
+        };
+        assert_eq!(
+            pub_key.verify(b"hello", &bad_ed25519),
+            Err(CertificateError::InvalidSignature),
+            "An invalid Ed25519 signature should invalidate the hybrid signature"
+        );
+
+        // Tampered account certificate
+        let mut account_cert = *chain_secret.public_chain.account_cert.clone();
+        account_cert.self_signature = bad_ml_dsa;
+        assert_eq!(
+            SerializedAccountCertificate::from_bytes(&account_cert.to_bytes())
+                .expect("the certificate is well-formed")
+                .verify()
+                .err(),
+            Some(CertificateError::InvalidSignature),
+            "An account certificate with an invalid self-signature should be rejected"
+        );
+    }
+
+    #[test]
+    pub fn test_hybrid_link_new_device() {
+        let chain_secret = generate_chain_secret();
+
+        // The new device generates its own key, and only sends its certificate
+        let (device_cert, device_secret) = HybridDeviceCert::generate(DeviceId::generate_id());
+        let device_cert = match SerializedDeviceCertificate::try_from(proto::Certificate::from(
+            SerializedDeviceCertificate::Hybrid(Box::new(device_cert)),
+        )) {
+            Ok(SerializedDeviceCertificate::Hybrid(device_cert)) => *device_cert,
+            other => panic!("The device certificate should round-trip, got {other:?}"),
+        };
+        assert!(
+            device_cert.verify_self_signature().is_ok(),
+            "The device certificate of the new device should be valid"
+        );
+
+        let linked_secret = HybridCertificateChainSecret::from_linked_chain(
+            chain_secret.sign_device_cert(device_cert),
+            device_secret.clone(),
+            &chain_secret.share_account_key(),
+        )
+        .expect("The new device accepts the chain signed for it");
+        assert!(
+            linked_secret.public_chain.verify_self().is_ok(),
+            "The chain of a linked device should be valid"
+        );
+        assert_eq!(
+            linked_secret.public_chain.account_cert, chain_secret.public_chain.account_cert,
+            "A linked device should keep the account certificate"
+        );
+
+        assert_eq!(
+            HybridCertificateChainSecret::from_linked_chain(
+                chain_secret.public_chain.clone(),
+                device_secret,
+                &chain_secret.share_account_key(),
+            ),
+            Err(CertificateError::InvalidData),
+            "A chain certifying another device key should be rejected"
+        );
+    }
+}
@@ -7,7 +7,10 @@
 //! - Device certificates, generated by devices (one per device, 1-to-n devices per user). It is used
 //!   as the MLS credential within groups etc.
 use core::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use ed25519::{Ed25519AccountCert, Ed25519CertificateChain, Ed25519DeviceCert};
+use hybrid::{HybridAccountCert, HybridCertificateChain, HybridDeviceCert};
 use prost::Message;
 use serde::{Deserialize, Serialize};
 
@@ -18,6 +21,8 @@ use crate::{
 };
 
 pub mod ed25519;
+pub mod hybrid;
+pub mod rotation;
 
 #[derive(Debug, thiserror::Error, PartialEq)]
 pub enum CertificateError {
@@ -29,15 +34,118 @@ pub enum CertificateError {
     ProtoDeserialization(#[from] ProtoError),
     #[error("This signature is invalid.")]
     InvalidSignature,
+    #[error("The certificate is expired or not valid yet.")]
+    OutsideValidityPeriod,
+}
+
+/// How long new account certificates are valid for.
+pub const ACCOUNT_CERT_VALIDITY: Duration = Duration::from_secs(2 * 365 * 24 * 60 * 60);
+/// How long new (or renewed) device certificates are valid for.
+pub const DEVICE_CERT_VALIDITY: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// Seconds since the UNIX epoch. Times before it are clamped to 0.
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The period during which a certificate is valid, in seconds since the
+/// UNIX epoch (both ends included). It is covered by the certificate's
+/// self-signature, except for [`Self::LEGACY`] certificates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Validity {
+    pub not_before: u64,
+    pub not_after: u64,
+}
+
+impl Validity {
+    /// The validity of certificates made before they had a validity period,
+    /// whose self-signature doesn't cover one. They are valid at any time,
+    /// and are renewed as soon as possible (see [`Self::expires_within`]).
+    pub const LEGACY: Self = Self {
+        not_before: 0,
+        not_after: 0,
+    };
+
+    pub fn is_legacy(self) -> bool {
+        self == Self::LEGACY
+    }
+
+    /// A validity period starting now and lasting `duration`.
+    pub fn starting_now(duration: Duration) -> Self {
+        let not_before = unix_seconds(SystemTime::now());
+
+        Self {
+            not_before,
+            not_after: not_before.saturating_add(duration.as_secs()),
+        }
+    }
+
+    pub fn contains(&self, time: SystemTime) -> bool {
+        self.is_legacy() || (self.not_before..=self.not_after).contains(&unix_seconds(time))
+    }
+
+    /// Returns `true` if this is no longer valid in `duration` from now.
+    /// Legacy certificates always are, so that they get renewed.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        self.is_legacy()
+            || unix_seconds(SystemTime::now()).saturating_add(duration.as_secs()) > self.not_after
+    }
+
+    /// The period during which both `self` and `other` are valid.
+    #[must_use]
+    pub fn intersection(self, other: Self) -> Self {
+        if self.is_legacy() {
+            return other;
+        }
+        if other.is_legacy() {
+            return self;
+        }
+
+        Self {
+            not_before: self.not_before.max(other.not_before),
+            not_after: self.not_after.min(other.not_after),
+        }
+    }
+
+    /// `[not_before][not_after]`, as big-endian `u64`s. Used in self-signatures.
+    /// Empty for legacy certificates, whose self-signature doesn't cover it.
+    pub fn to_bytes(self) -> Vec<u8> {
+        if self.is_legacy() {
+            return Vec::new();
+        }
+
+        let mut bytes = self.not_before.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.not_after.to_be_bytes());
+
+        bytes
+    }
+
+    pub(crate) fn from_proto(certificate: &proto::Certificate) -> Self {
+        Self {
+            not_before: certificate.not_before,
+            not_after: certificate.not_after,
+        }
+    }
+
+    fn check(self, time: SystemTime) -> Result<(), CertificateError> {
+        if self.contains(time) {
+            Ok(())
+        } else {
+            Err(CertificateError::OutsideValidityPeriod)
+        }
+    }
 }
 
 /// A [`Certificate`] contains:
 ///
-/// - Some data ([`AccountId`]+[`Server`] in the case of
-///   [`Ed25519AccountCert`], and [`DeviceId`] for [`Ed25519DeviceCert`])
+/// - Some data ([`AccountId`]+[`Server`] in the case of account certificates
+///   like [`Ed25519AccountCert`], and [`DeviceId`] for device certificates
+///   like [`Ed25519DeviceCert`])
 /// - A public key defining that certificate
-/// - A signature of the certificate (including the public key),
-///   signed by the public key itself.
+/// - The [`Validity`] period of the certificate
+/// - A signature of the certificate (including the public key
+///   and validity), signed by the public key itself.
 pub trait Certificate {
     fn to_proto(&self) -> proto::Certificate;
     /// Convert to bytes using the protobuf representation
@@ -46,7 +154,12 @@ pub trait Certificate {
     }
     fn pub_key_bytes(&self) -> Vec<u8>;
     fn get_scheme(&self) -> SignatureScheme;
-    fn verify_self_signature(&self) -> Result<(), CertificateError>;
+    fn validity(&self) -> Validity;
+    /// Verifies the self-signature, and that the certificate is valid at `time`.
+    fn verify_self_signature_at(&self, time: SystemTime) -> Result<(), CertificateError>;
+    fn verify_self_signature(&self) -> Result<(), CertificateError> {
+        self.verify_self_signature_at(SystemTime::now())
+    }
 }
 
 /// Traits to handle certificate chains.
@@ -70,10 +183,159 @@ pub trait CertificateChain {
     fn get_server(&self) -> &Server;
     fn account_id(&self) -> &AccountId;
     fn device_id(&self) -> &DeviceId;
-    fn account_cert(&self) -> &impl Certificate;
-    fn device_cert(&self) -> &impl Certificate;
-    fn verify_self(&self) -> Result<(), CertificateError>;
+    fn account_cert(&self) -> impl Certificate + '_;
+    fn device_cert(&self) -> impl Certificate + '_;
+    /// The period during which both certificates of the chain are valid.
+    fn validity(&self) -> Validity {
+        self.account_cert()
+            .validity()
+            .intersection(self.device_cert().validity())
+    }
+    /// Verifies the chain's signatures, and that both certificates are valid at `time`.
+    fn verify_self_at(&self, time: SystemTime) -> Result<(), CertificateError>;
+    fn verify_self(&self) -> Result<(), CertificateError> {
+        self.verify_self_at(SystemTime::now())
+    }
     fn verify_signature(&self, message: &[u8], signature: &[u8]) -> Result<(), CertificateError>;
+    /// Verifies a signature made by the account key (instead of the device key),
+    /// see [`rotation`].
+    fn verify_account_signature(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CertificateError>;
+}
+
+impl<C: Certificate> Certificate for &C {
+    fn to_proto(&self) -> proto::Certificate {
+        C::to_proto(self)
+    }
+    fn pub_key_bytes(&self) -> Vec<u8> {
+        C::pub_key_bytes(self)
+    }
+    fn get_scheme(&self) -> SignatureScheme {
+        C::get_scheme(self)
+    }
+    fn validity(&self) -> Validity {
+        C::validity(self)
+    }
+    fn verify_self_signature_at(&self, time: SystemTime) -> Result<(), CertificateError> {
+        C::verify_self_signature_at(self, time)
+    }
+}
+
+/// A certificate or a certificate chain of any of the [`SignatureScheme`]s,
+/// whose signatures have been verified. Returned by [`SerializedAccountCertificate::verify`]
+/// and [`SerializedChain::verify`].
+enum Verified<E, H> {
+    Ed25519(E),
+    Hybrid(H),
+}
+
+impl<E: Certificate, H: Certificate> Certificate for Verified<E, H> {
+    fn to_proto(&self) -> proto::Certificate {
+        match self {
+            Verified::Ed25519(cert) => cert.to_proto(),
+            Verified::Hybrid(cert) => cert.to_proto(),
+        }
+    }
+
+    fn pub_key_bytes(&self) -> Vec<u8> {
+        match self {
+            Verified::Ed25519(cert) => cert.pub_key_bytes(),
+            Verified::Hybrid(cert) => cert.pub_key_bytes(),
+        }
+    }
+
+    fn get_scheme(&self) -> SignatureScheme {
+        match self {
+            Verified::Ed25519(cert) => cert.get_scheme(),
+            Verified::Hybrid(cert) => cert.get_scheme(),
+        }
+    }
+
+    fn validity(&self) -> Validity {
+        match self {
+            Verified::Ed25519(cert) => cert.validity(),
+            Verified::Hybrid(cert) => cert.validity(),
+        }
+    }
+
+    fn verify_self_signature_at(&self, time: SystemTime) -> Result<(), CertificateError> {
+        match self {
+            Verified::Ed25519(cert) => cert.verify_self_signature_at(time),
+            Verified::Hybrid(cert) => cert.verify_self_signature_at(time),
+        }
+    }
+}
+
+impl<E: CertificateChain, H: CertificateChain> CertificateChain for Verified<E, H> {
+    fn serialize(self) -> SerializedChain {
+        match self {
+            Verified::Ed25519(chain) => chain.serialize(),
+            Verified::Hybrid(chain) => chain.serialize(),
+        }
+    }
+
+    fn get_server(&self) -> &Server {
+        match self {
+            Verified::Ed25519(chain) => chain.get_server(),
+            Verified::Hybrid(chain) => chain.get_server(),
+        }
+    }
+
+    fn account_id(&self) -> &AccountId {
+        match self {
+            Verified::Ed25519(chain) => chain.account_id(),
+            Verified::Hybrid(chain) => chain.account_id(),
+        }
+    }
+
+    fn device_id(&self) -> &DeviceId {
+        match self {
+            Verified::Ed25519(chain) => chain.device_id(),
+            Verified::Hybrid(chain) => chain.device_id(),
+        }
+    }
+
+    fn account_cert(&self) -> impl Certificate + '_ {
+        match self {
+            Verified::Ed25519(chain) => Verified::Ed25519(chain.account_cert()),
+            Verified::Hybrid(chain) => Verified::Hybrid(chain.account_cert()),
+        }
+    }
+
+    fn device_cert(&self) -> impl Certificate + '_ {
+        match self {
+            Verified::Ed25519(chain) => Verified::Ed25519(chain.device_cert()),
+            Verified::Hybrid(chain) => Verified::Hybrid(chain.device_cert()),
+        }
+    }
+
+    fn verify_self_at(&self, time: SystemTime) -> Result<(), CertificateError> {
+        match self {
+            Verified::Ed25519(chain) => chain.verify_self_at(time),
+            Verified::Hybrid(chain) => chain.verify_self_at(time),
+        }
+    }
+
+    fn verify_signature(&self, message: &[u8], signature: &[u8]) -> Result<(), CertificateError> {
+        match self {
+            Verified::Ed25519(chain) => chain.verify_signature(message, signature),
+            Verified::Hybrid(chain) => chain.verify_signature(message, signature),
+        }
+    }
+
+    fn verify_account_signature(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CertificateError> {
+        match self {
+            Verified::Ed25519(chain) => chain.verify_account_signature(message, signature),
+            Verified::Hybrid(chain) => chain.verify_account_signature(message, signature),
+        }
+    }
 }
 
 pub trait CertificateChainSecret {
@@ -89,6 +351,7 @@ pub trait CertificateChainSecret {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum SerializedAccountCertificate {
     Ed25519(Box<Ed25519AccountCert>),
+    Hybrid(Box<HybridAccountCert>),
 }
 
 impl SerializedAccountCertificate {
@@ -101,12 +364,14 @@ impl SerializedAccountCertificate {
     pub fn verify(self) -> Result<(impl Certificate, AccountId), CertificateError> {
         match self {
             SerializedAccountCertificate::Ed25519(ed25519_account_cert) => {
-                if ed25519_account_cert.verify_self_signature().is_ok() {
-                    let account_id = ed25519_account_cert.account_id;
-                    Ok((*ed25519_account_cert, account_id))
-                } else {
-                    Err(CertificateError::InvalidSignature)
-                }
+                ed25519_account_cert.verify_self_signature()?;
+                let account_id = ed25519_account_cert.account_id;
+                Ok((Verified::Ed25519(*ed25519_account_cert), account_id))
+            }
+            SerializedAccountCertificate::Hybrid(hybrid_account_cert) => {
+                hybrid_account_cert.verify_self_signature()?;
+                let account_id = hybrid_account_cert.account_id;
+                Ok((Verified::Hybrid(*hybrid_account_cert), account_id))
             }
         }
     }
@@ -116,6 +381,14 @@ impl TryFrom<proto::Certificate> for SerializedAccountCertificate {
     type Error = CertificateError;
 
     fn try_from(value: proto::Certificate) -> Result<Self, Self::Error> {
+        if value.scheme() == proto::SignatureScheme::HybridEd25519MlDsa65 {
+            return Ok(Self::Hybrid(Box::new(HybridAccountCert::from_proto(
+                value,
+            )?)));
+        }
+
+        let validity = Validity::from_proto(&value);
+
         // Data is [account_id][server].
         let mut account_id = value.data;
         let server = account_id.split_off(size_of::<AccountId>());
@@ -128,6 +401,7 @@ impl TryFrom<proto::Certificate> for SerializedAccountCertificate {
                 .map_err(|_| CertificateError::InvalidData)?,
             self_signature: ed25519::Ed25519Signature::from_slice(&value.self_signature_of_inner)
                 .map_err(|_| CertificateError::InvalidData)?,
+            validity,
         };
 
         Ok(Self::Ed25519(Box::new(ed25519_cert)))
@@ -140,20 +414,96 @@ impl From<SerializedAccountCertificate> for proto::Certificate {
             SerializedAccountCertificate::Ed25519(ed25519_account_cert) => {
                 ed25519_account_cert.to_proto()
             }
+            SerializedAccountCertificate::Hybrid(hybrid_account_cert) => {
+                hybrid_account_cert.to_proto()
+            }
         }
     }
 }
 
+/// A serialized device certificate, sent by a device being linked to an existing
+/// device of the account, which signs it (see [`crate::crypto::device_link`]).
+///
+/// This serialized certificate is untrusted: [`Self::verify`] must be called
+/// before signing it.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-/// A serialized chain is a representation [`Ed25519CertificateChain`] meant
-/// to be stored in MLS credentials or sent to the server. In this state, it
-/// should not be trusted, and [`Self::verify`] must be called before performing
-/// operations on it
+pub enum SerializedDeviceCertificate {
+    Ed25519(Box<Ed25519DeviceCert>),
+    Hybrid(Box<HybridDeviceCert>),
+}
+
+impl SerializedDeviceCertificate {
+    pub fn device_id(&self) -> &DeviceId {
+        match self {
+            SerializedDeviceCertificate::Ed25519(ed25519_device_cert) => {
+                &ed25519_device_cert.device_id
+            }
+            SerializedDeviceCertificate::Hybrid(hybrid_device_cert) => {
+                &hybrid_device_cert.device_id
+            }
+        }
+    }
+
+    pub fn verify(&self) -> Result<(), CertificateError> {
+        match self {
+            SerializedDeviceCertificate::Ed25519(ed25519_device_cert) => {
+                ed25519_device_cert.verify_self_signature()
+            }
+            SerializedDeviceCertificate::Hybrid(hybrid_device_cert) => {
+                hybrid_device_cert.verify_self_signature()
+            }
+        }
+    }
+}
+
+impl TryFrom<proto::Certificate> for SerializedDeviceCertificate {
+    type Error = CertificateError;
+
+    fn try_from(value: proto::Certificate) -> Result<Self, Self::Error> {
+        if value.scheme() == proto::SignatureScheme::HybridEd25519MlDsa65 {
+            return Ok(Self::Hybrid(Box::new(HybridDeviceCert::from_proto(value)?)));
+        }
+
+        let ed25519_cert = Ed25519DeviceCert {
+            device_id: DeviceId::try_from(value.data.as_slice())
+                .map_err(|_| CertificateError::InvalidData)?,
+            pub_key: ed25519::Ed25519PublicKey::try_from(value.public_key.as_slice())
+                .map_err(|_| CertificateError::InvalidData)?,
+            self_signature: ed25519::Ed25519Signature::from_slice(&value.self_signature_of_inner)
+                .map_err(|_| CertificateError::InvalidData)?,
+            validity: Validity::from_proto(&value),
+        };
+
+        Ok(Self::Ed25519(Box::new(ed25519_cert)))
+    }
+}
+
+impl From<SerializedDeviceCertificate> for proto::Certificate {
+    fn from(value: SerializedDeviceCertificate) -> Self {
+        match value {
+            SerializedDeviceCertificate::Ed25519(ed25519_device_cert) => {
+                ed25519_device_cert.to_proto()
+            }
+            SerializedDeviceCertificate::Hybrid(hybrid_device_cert) => {
+                hybrid_device_cert.to_proto()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// A serialized chain is a representation of a certificate chain (such as
+/// [`Ed25519CertificateChain`]) meant to be stored in MLS credentials or sent
+/// to the server. In this state, it should not be trusted, and [`Self::verify`]
+/// must be called before performing operations on it
 pub enum SerializedChain {
     Ed25519Chain(Ed25519CertificateChain),
+    HybridChain(HybridCertificateChain),
 }
 
 impl SerializedChain {
+    /// The public key the device uses to sign MLS messages. For hybrid
+    /// chains, this is only the Ed25519 part of the device's key.
     pub fn pub_key_bytes(&self) -> Vec<u8> {
         match self {
             SerializedChain::Ed25519Chain(ed25519_certificate_chain) => ed25519_certificate_chain
@@ -161,6 +511,51 @@ impl SerializedChain {
                 .pub_key
                 .to_bytes()
                 .to_vec(),
+            SerializedChain::HybridChain(hybrid_certificate_chain) => hybrid_certificate_chain
+                .device_cert
+                .mls_pub_key()
+                .to_bytes()
+                .to_vec(),
+        }
+    }
+
+    pub fn get_server(&self) -> &Server {
+        match self {
+            SerializedChain::Ed25519Chain(ed25519_certificate_chain) => {
+                ed25519_certificate_chain.get_server()
+            }
+            SerializedChain::HybridChain(hybrid_certificate_chain) => {
+                hybrid_certificate_chain.get_server()
+            }
+        }
+    }
+
+    /// Verifies a signature made by the chain's account key. This doesn't
+    /// verify the chain itself, see [`rotation::verify_successor`].
+    pub fn verify_account_signature(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CertificateError> {
+        match self {
+            SerializedChain::Ed25519Chain(ed25519_certificate_chain) => {
+                ed25519_certificate_chain.verify_account_signature(message, signature)
+            }
+            SerializedChain::HybridChain(hybrid_certificate_chain) => {
+                hybrid_certificate_chain.verify_account_signature(message, signature)
+            }
+        }
+    }
+
+    /// The public key of the chain's account certificate.
+    pub fn account_pub_key_bytes(&self) -> Vec<u8> {
+        match self {
+            SerializedChain::Ed25519Chain(ed25519_certificate_chain) => {
+                ed25519_certificate_chain.account_cert.pub_key_bytes()
+            }
+            SerializedChain::HybridChain(hybrid_certificate_chain) => {
+                hybrid_certificate_chain.account_cert.pub_key_bytes()
+            }
         }
     }
 
@@ -169,6 +564,9 @@ impl SerializedChain {
             SerializedChain::Ed25519Chain(ed25519_certificate_chain) => {
                 &ed25519_certificate_chain.account_cert.account_id
             }
+            SerializedChain::HybridChain(hybrid_certificate_chain) => {
+                &hybrid_certificate_chain.account_cert.account_id
+            }
         }
     }
 
@@ -183,13 +581,46 @@ impl SerializedChain {
     }
 
     pub fn verify(self) -> Result<impl CertificateChain, CertificateError> {
+        self.verify_at(SystemTime::now())
+    }
+
+    /// Like [`Self::verify`], but checks that the chain is valid at `time`
+    /// instead of now.
+    pub fn verify_at(self, time: SystemTime) -> Result<impl CertificateChain, CertificateError> {
         match self {
             SerializedChain::Ed25519Chain(ed25519_certificate_chain) => {
-                if ed25519_certificate_chain.verify_self().is_ok() {
-                    Ok(ed25519_certificate_chain)
-                } else {
-                    Err(CertificateError::InvalidSignature)
-                }
+                ed25519_certificate_chain.verify_self_at(time)?;
+                Ok(Verified::Ed25519(ed25519_certificate_chain))
+            }
+            SerializedChain::HybridChain(hybrid_certificate_chain) => {
+                hybrid_certificate_chain.verify_self_at(time)?;
+                Ok(Verified::Hybrid(hybrid_certificate_chain))
+            }
+        }
+    }
+
+    /// The period during which both certificates of the chain are valid.
+    /// This doesn't check any signature.
+    pub fn validity(&self) -> Validity {
+        match self {
+            SerializedChain::Ed25519Chain(ed25519_certificate_chain) => {
+                ed25519_certificate_chain.validity()
+            }
+            SerializedChain::HybridChain(hybrid_certificate_chain) => {
+                hybrid_certificate_chain.validity()
+            }
+        }
+    }
+
+    /// The validity of the chain's device certificate alone. This doesn't
+    /// check any signature.
+    pub fn device_validity(&self) -> Validity {
+        match self {
+            SerializedChain::Ed25519Chain(ed25519_certificate_chain) => {
+                ed25519_certificate_chain.device_cert().validity()
+            }
+            SerializedChain::HybridChain(hybrid_certificate_chain) => {
+                hybrid_certificate_chain.device_cert().validity()
             }
         }
     }
@@ -199,6 +630,18 @@ impl TryFrom<proto::CertificateChain> for SerializedChain {
     type Error = CertificateError;
 
     fn try_from(value: proto::CertificateChain) -> Result<Self, Self::Error> {
+        // Both certificates of a chain always use the same scheme
+        let is_hybrid = value
+            .account_certificate
+            .as_ref()
+            .is_some_and(|cert| cert.scheme() == proto::SignatureScheme::HybridEd25519MlDsa65);
+
+        if is_hybrid {
+            return Ok(Self::HybridChain(HybridCertificateChain::from_proto(
+                value,
+            )?));
+        }
+
         let account_cert_proto = value
             .account_certificate
             .ok_or(CertificateError::InvalidData)?;
@@ -209,6 +652,7 @@ impl TryFrom<proto::CertificateChain> for SerializedChain {
 
         let account_cert = match account_cert_proto.scheme() {
             proto::SignatureScheme::Ed25519 => {
+                let validity = Validity::from_proto(&account_cert_proto);
                 let mut account_id = account_cert_proto.data;
                 let server = account_id.split_off(size_of::<AccountId>());
                 Ed25519AccountCert {
@@ -223,8 +667,12 @@ impl TryFrom<proto::CertificateChain> for SerializedChain {
                         &account_cert_proto.self_signature_of_inner,
                     )
                     .map_err(|_| CertificateError::InvalidData)?,
+                    validity,
                 }
             }
+            proto::SignatureScheme::HybridEd25519MlDsa65 => {
+                return Err(CertificateError::InvalidData);
+            }
         };
 
         let device_cert = match device_cert_proto.scheme() {
@@ -239,7 +687,12 @@ impl TryFrom<proto::CertificateChain> for SerializedChain {
                     &device_cert_proto.self_signature_of_inner,
                 )
                 .map_err(|_| CertificateError::InvalidData)?,
+                validity: Validity::from_proto(&device_cert_proto),
             },
+            // Hybrid device certificates are only valid in hybrid chains
+            proto::SignatureScheme::HybridEd25519MlDsa65 => {
+                return Err(CertificateError::InvalidData);
+            }
         };
 
         Ok(Self::Ed25519Chain(Ed25519CertificateChain {
@@ -263,6 +716,13 @@ impl From<SerializedChain> for proto::CertificateChain {
                     .to_vec(),
                 device_certificate: Some(ed25519_certificate_chain.device_cert.to_proto()),
             },
+            SerializedChain::HybridChain(hybrid_certificate_chain) => Self {
+                account_certificate: Some(hybrid_certificate_chain.account_cert.to_proto()),
+                account_to_device_signature: hybrid_certificate_chain
+                    .account_to_device_sig
+                    .to_vec(),
+                device_certificate: Some(hybrid_certificate_chain.device_cert.to_proto()),
+            },
         }
     }
 }
@@ -272,6 +732,8 @@ impl From<SerializedChain> for proto::CertificateChain {
 /// purposes we'll use i32.
 pub enum SignatureScheme {
     Ed25519,
+    /// Ed25519 and ML-DSA-65, see [`hybrid`].
+    HybridEd25519MlDsa65,
 }
 
 #[cfg(test)]
@@ -0,0 +1,189 @@
+//! Rotation of account keys, e.g. after an account secret leaked.
+//!
+//! The new account certificate keeps the same [`AccountId`] and server,
+//! and the previous account key signs it (a "cross-signature"). This proves to
+//! the server, and to the members of our groups, that whoever owned the previous
+//! account certificate chose the new one as its successor.
+//!
+//! Since device certificates are signed by the account key, every device of
+//! the account gets a new chain during the rotation. The new account key is
+//! then shared with the other devices (see [`AccountKeyShare`]), which need
+//! it to link new devices or rotate it again.
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::{api::proto, error::ProtoError, identifiers::AccountId};
+
+use super::{
+    Certificate, CertificateChain, CertificateError, SerializedAccountCertificate, SerializedChain,
+};
+
+/// Prepended to the new account certificate before it is cross-signed, so that
+/// the signature can't be confused with any other signature of the account key.
+const ROTATION_CONTEXT: &[u8] = b"licks account key rotation";
+
+/// The message signed by the previous account key to vouch for `new_account_cert`.
+pub(super) fn cross_signed_bytes(new_account_cert: &impl Certificate) -> Vec<u8> {
+    let mut bytes = ROTATION_CONTEXT.to_vec();
+    bytes.append(&mut new_account_cert.to_bytes());
+
+    bytes
+}
+
+/// Verifies that `successor` belongs to the same account as `previous`, and that
+/// its account certificate was cross-signed by the account key of `previous`.
+///
+/// Neither chain is verified here: `previous` should be a chain we trusted
+/// before (e.g. when it was registered), and `successor` must be verified
+/// separately.
+pub fn verify_successor(
+    previous: &SerializedChain,
+    successor: &SerializedChain,
+    previous_account_signature: &[u8],
+) -> Result<(), CertificateError> {
+    if previous.account_id() != successor.account_id()
+        || previous.get_server() != successor.get_server()
+    {
+        return Err(CertificateError::InvalidData);
+    }
+
+    let signed_bytes = match successor {
+        SerializedChain::Ed25519Chain(chain) => cross_signed_bytes(&chain.account_cert()),
+        SerializedChain::HybridChain(chain) => cross_signed_bytes(&chain.account_cert()),
+    };
+
+    previous.verify_account_signature(&signed_bytes, previous_account_signature)
+}
+
+/// A request to replace the account certificate of an account (and the chains
+/// of all its devices) by a certificate using a new key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountKeyRotation {
+    pub new_account_cert: SerializedAccountCertificate,
+    /// Signature of the new account certificate by the previous account key.
+    pub previous_account_signature: Vec<u8>,
+    /// The chains of every device of the account, signed by the new account key.
+    pub new_chains: Vec<SerializedChain>,
+}
+
+impl AccountKeyRotation {
+    /// Verifies the new account certificate, its cross-signature by the account
+    /// key of `previous` (see [`verify_successor`]), and that every new chain
+    /// is valid and uses the new account certificate, which must have a validity
+    /// period. Returns the [`AccountId`] of the account.
+    pub fn verify(&self, previous: &SerializedChain) -> Result<AccountId, CertificateError> {
+        let (new_account_cert, account_id) = self.new_account_cert.clone().verify()?;
+        let new_account_cert_bytes = new_account_cert.to_bytes();
+
+        // New certificates always have a validity period
+        if new_account_cert.validity().is_legacy() {
+            return Err(CertificateError::InvalidData);
+        }
+
+        if self.new_chains.is_empty() {
+            return Err(CertificateError::InvalidData);
+        }
+
+        for chain in &self.new_chains {
+            let verified_chain = chain.clone().verify()?;
+
+            if verified_chain.account_cert().to_bytes() != new_account_cert_bytes {
+                return Err(CertificateError::InvalidData);
+            }
+
+            verify_successor(previous, chain, &self.previous_account_signature)?;
+        }
+
+        Ok(account_id)
+    }
+}
+
+impl From<AccountKeyRotation> for proto::AccountKeyRotation {
+    fn from(value: AccountKeyRotation) -> Self {
+        Self {
+            new_account_certificate: Some(value.new_account_cert.into()),
+            previous_account_signature: value.previous_account_signature,
+            new_chains: value.new_chains.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::AccountKeyRotation> for AccountKeyRotation {
+    type Error = CertificateError;
+
+    fn try_from(value: proto::AccountKeyRotation) -> Result<Self, Self::Error> {
+        Ok(Self {
+            new_account_cert: value
+                .new_account_certificate
+                .ok_or(CertificateError::InvalidData)?
+                .try_into()?,
+            previous_account_signature: value.previous_account_signature,
+            new_chains: value
+                .new_chains
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// A rotated account key, shared by the device that rotated it with the other
+/// devices of the account. They verify it against their own account certificate
+/// before adopting it.
+///
+/// It is also sent to new devices along with their chain when linking them (see
+/// [`crate::crypto::device_link`]).
+#[derive(Clone, PartialEq)]
+pub struct AccountKeyShare {
+    pub account_cert: SerializedAccountCertificate,
+    pub account_secret: Vec<u8>,
+    /// Signature of `account_cert` by the previous account key.
+    pub previous_account_signature: Vec<u8>,
+}
+
+impl std::fmt::Debug for AccountKeyShare {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountKeyShare")
+            .field("account_cert", &self.account_cert)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AccountKeyShare {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        proto::AccountKeyShare::from(self.clone()).encode_to_vec()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtoError> {
+        proto::AccountKeyShare::decode(bytes)
+            .map_err(|_| ProtoError)?
+            .try_into()
+    }
+}
+
+impl From<AccountKeyShare> for proto::AccountKeyShare {
+    fn from(value: AccountKeyShare) -> Self {
+        Self {
+            account_certificate: Some(value.account_cert.into()),
+            account_secret: value.account_secret,
+            previous_account_signature: value.previous_account_signature,
+        }
+    }
+}
+
+impl TryFrom<proto::AccountKeyShare> for AccountKeyShare {
+    type Error = ProtoError;
+
+    fn try_from(value: proto::AccountKeyShare) -> Result<Self, Self::Error> {
+        Ok(Self {
+            account_cert: value
+                .account_certificate
+                .ok_or(ProtoError)?
+                .try_into()
+                .map_err(|_| ProtoError)?,
+            account_secret: value.account_secret,
+            previous_account_signature: value.previous_account_signature,
+        })
+    }
+}
@@ -21,10 +21,15 @@ impl AuthChallenge {
         Self(random_bytes::<32>())
     }
 
-    pub fn hash(&self, our_bytes: Self) -> [u8; 32] {
+    /// Hashes the server's bytes, the client's bytes, and the hash of the Noise
+    /// handshake of the connection the challenge is sent over. The latter binds
+    /// the signed challenge to that connection, so that it can't be relayed
+    /// to the server through another one.
+    pub fn hash(&self, our_bytes: Self, handshake_hash: &[u8]) -> [u8; 32] {
         let mut hasher = Sha256::new();
         hasher.update(self.as_bytes());
         hasher.update(our_bytes.as_bytes());
+        hasher.update(handshake_hash);
 
         hasher.finalize().into()
     }
@@ -35,12 +40,19 @@ impl AuthChallenge {
 
     /// Accept the challenge by signing it safely. Used by the client, and
     /// the response is verified by the server.
-    pub fn accept(self, cert_secret: &impl CertificateChainSecret) -> AuthChallengeResponse {
+    ///
+    /// `handshake_hash` is the handshake hash of the Noise session the
+    /// challenge was received on.
+    pub fn accept(
+        self,
+        cert_secret: &impl CertificateChainSecret,
+        handshake_hash: &[u8],
+    ) -> AuthChallengeResponse {
         // Generate our own extra random bytes
         let our_bytes = Self::generate();
 
-        // Hash the two random bytes
-        let hash = self.hash(our_bytes);
+        // Hash the two random bytes, along with the session's handshake hash
+        let hash = self.hash(our_bytes, handshake_hash);
 
         // Sign the hash
         let device_signature_of_hash = cert_secret.sign(&hash);
@@ -65,6 +77,9 @@ impl AuthChallenge {
 /// This is to prevent a dishonest server to send arbitrary bytes (such
 /// as a payload) to the client who would try to sign it no matter what.
 /// See issue #47
+///
+/// The hash also includes the Noise handshake hash of the connection, so
+/// a response is only valid on the connection the challenge was sent over.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AuthChallengeResponse {
     pub chain: SerializedChain,
@@ -75,10 +90,17 @@ pub struct AuthChallengeResponse {
 impl AuthChallengeResponse {
     /// Verifies the challenge response. Returns the verified chain
     /// that signed the challenge bytes.
-    pub fn verify(self, server_bytes: AuthChallenge) -> Result<SerializedChain, CertificateError> {
+    ///
+    /// `handshake_hash` is the handshake hash of the Noise session
+    /// the challenge was sent over.
+    pub fn verify(
+        self,
+        server_bytes: AuthChallenge,
+        handshake_hash: &[u8],
+    ) -> Result<SerializedChain, CertificateError> {
         let verified_chain = self.chain.clone().verify()?;
 
-        let hash = server_bytes.hash(self.client_bytes);
+        let hash = server_bytes.hash(self.client_bytes, handshake_hash);
 
         if verified_chain
             .verify_signature(&hash, &self.device_signature_of_hash)
@@ -126,9 +148,10 @@ mod tests {
     #[test]
     fn test_challenge_ok() {
         let server_challenge = AuthChallenge::generate();
+        let handshake_hash = random_bytes::<32>();
 
         let fake_chain = generate_fake_chain_secret();
-        let client_accepts = server_challenge.accept(&fake_chain);
+        let client_accepts = server_challenge.accept(&fake_chain, &handshake_hash);
 
         let serialized = fake_chain.serialized();
 
@@ -138,9 +161,25 @@ mod tests {
             .expect("Roundtrip serialization works");
 
         let verified_chain = deserialized_response
-            .verify(server_challenge)
+            .verify(server_challenge, &handshake_hash)
             .expect("honest chain is valid");
 
         assert_eq!(verified_chain, serialized);
     }
+
+    #[test]
+    fn test_challenge_other_session() {
+        let server_challenge = AuthChallenge::generate();
+        let handshake_hash = random_bytes::<32>();
+        let other_handshake_hash = random_bytes::<32>();
+
+        let fake_chain = generate_fake_chain_secret();
+        let client_accepts = server_challenge.accept(&fake_chain, &handshake_hash);
+
+        assert_eq!(
+            client_accepts.verify(server_challenge, &other_handshake_hash),
+            Err(CertificateError::InvalidSignature),
+            "A challenge response relayed from another session should not be valid"
+        );
+    }
 }
@@ -0,0 +1,156 @@
+//! Linking a new device to an existing account.
+//!
+//! The new device generates a random [`DeviceLinkCode`] and shows it to the user, who enters
+//! it on a device that is already part of the account. Both devices then talk through blinded
+//! addresses on the server that are derived from the code, one for each [`DeviceLinkStep`] of
+//! the protocol:
+//! 1. the existing device sends the account certificate ([`DeviceLinkStep::Provisioning`]);
+//! 2. the new device generates its device key, and sends its self-signed device certificate
+//!    ([`DeviceLinkStep::DeviceCertificate`]). The device secret never leaves the new device;
+//! 3. the existing device signs it with the account key, and sends back the chain along with
+//!    the account key ([`DeviceLinkStep::Chain`]);
+//! 4. the new device registers itself, and answers with key packages for every group it
+//!    should be added to ([`DeviceLinkStep::KeyPackages`]);
+//! 5. the existing device adds them to its groups and sends back the Welcome messages
+//!    ([`DeviceLinkStep::Welcomes`]).
+//!
+//! Every message is also encrypted with a key derived from the code, so the server never sees
+//! anything but random addresses and encrypted blobs.
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::{
+    crypto::{
+        aead::{self, KEY_LENGTH},
+        blinded_address::BlindedAddressSecret,
+        rng::random_bytes,
+    },
+    util::base64::Base64String,
+};
+
+const CODE_LENGTH: usize = 32;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum DeviceLinkError {
+    #[error("The device link code is malformed")]
+    InvalidCode,
+    #[error("The device link message could not be decrypted")]
+    DecryptionFailed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceLinkStep {
+    Provisioning,
+    DeviceCertificate,
+    Chain,
+    KeyPackages,
+    Welcomes,
+}
+
+impl DeviceLinkStep {
+    fn info(&self) -> &'static [u8] {
+        match self {
+            DeviceLinkStep::Provisioning => b"licks_device_link_provisioning",
+            DeviceLinkStep::DeviceCertificate => b"licks_device_link_device_certificate",
+            DeviceLinkStep::Chain => b"licks_device_link_chain",
+            DeviceLinkStep::KeyPackages => b"licks_device_link_key_packages",
+            DeviceLinkStep::Welcomes => b"licks_device_link_welcomes",
+        }
+    }
+}
+
+/// The one-time code shown by the new device. Anyone who knows it can take
+/// part in the linking, so it should only be shared with the existing device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceLinkCode([u8; CODE_LENGTH]);
+
+impl DeviceLinkCode {
+    pub fn generate() -> Self {
+        Self(random_bytes::<CODE_LENGTH>())
+    }
+
+    fn expand(&self, info: &[u8]) -> [u8; 32] {
+        let hk = Hkdf::<Sha256>::new(None, &self.0);
+        let mut output = [0u8; 32];
+        hk.expand(info, &mut output)
+            .expect("32 is a valid length for Sha256 to output");
+
+        output
+    }
+
+    /// The blinded address where the message of the given step is sent.
+    pub fn address(&self, step: DeviceLinkStep) -> BlindedAddressSecret {
+        BlindedAddressSecret::from_bytes(self.expand(step.info()))
+    }
+
+    fn key(&self) -> [u8; KEY_LENGTH] {
+        self.expand(b"licks_device_link_key")
+    }
+
+    /// Encrypts the message of a step (see [`aead::seal`]). The message is bound
+    /// to its step, so it can't be replayed at another address.
+    pub fn encrypt(&self, step: DeviceLinkStep, plaintext: &[u8]) -> Vec<u8> {
+        aead::seal(&self.key(), &self.address(step).to_public().0, plaintext)
+    }
+
+    pub fn decrypt(
+        &self,
+        step: DeviceLinkStep,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, DeviceLinkError> {
+        aead::open(&self.key(), &self.address(step).to_public().0, ciphertext)
+            .ok_or(DeviceLinkError::DecryptionFailed)
+    }
+
+    /// Encodes the code as an URL-safe string, to be displayed or put in a QR code.
+    pub fn to_code_string(&self) -> String {
+        Base64String::from_bytes(self.0.to_vec()).to_string()
+    }
+
+    pub fn from_code_string(code: &str) -> Result<Self, DeviceLinkError> {
+        let bytes = Base64String::from_base64_str(code.trim())
+            .ok_or(DeviceLinkError::InvalidCode)?
+            .to_vec();
+
+        Ok(Self(
+            bytes.try_into().map_err(|_| DeviceLinkError::InvalidCode)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_link_code_roundtrip() {
+        let code = DeviceLinkCode::generate();
+
+        let parsed = DeviceLinkCode::from_code_string(&code.to_code_string())
+            .expect("The code string should be parsed");
+        assert_eq!(parsed, code, "The parsed code should match the original");
+
+        assert_ne!(
+            code.address(DeviceLinkStep::Provisioning).to_public(),
+            code.address(DeviceLinkStep::KeyPackages).to_public(),
+            "Every step should use its own address"
+        );
+
+        let ciphertext = code.encrypt(DeviceLinkStep::Provisioning, b"profile");
+        assert_eq!(
+            parsed.decrypt(DeviceLinkStep::Provisioning, &ciphertext),
+            Ok(b"profile".to_vec()),
+            "The message should be decrypted with the parsed code"
+        );
+        assert_eq!(
+            parsed.decrypt(DeviceLinkStep::Welcomes, &ciphertext),
+            Err(DeviceLinkError::DecryptionFailed),
+            "A message should not be accepted for another step"
+        );
+        assert_eq!(
+            DeviceLinkCode::generate().decrypt(DeviceLinkStep::Provisioning, &ciphertext),
+            Err(DeviceLinkError::DecryptionFailed),
+            "Another code should not decrypt the message"
+        );
+    }
+}
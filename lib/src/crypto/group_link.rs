@@ -0,0 +1,149 @@
+//! Invite links, which let anyone who knows the link join a group.
+//!
+//! An admin of the group generates a random [`GroupLinkSecret`], from which two keys are derived:
+//! - a write key, which is used as a [`BlindedAddressSecret`]. Its public key is the id of the
+//!   link on the server, and only the holder of the write key can update or delete the link.
+//! - an encryption key, used to encrypt the content of the link (a GroupInfo of the group)
+//!   before it is uploaded to the server.
+//!
+//! The link that is shared ([`GroupLink`]) only contains the id and the encryption key, so
+//! people who received it can read the GroupInfo but can't change the link. The server only
+//! ever sees the link id and an encrypted blob.
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::{
+    crypto::{
+        aead::{self, KEY_LENGTH},
+        blinded_address::{BlindedAddressPublic, BlindedAddressSecret},
+        rng::random_bytes,
+    },
+    util::base64::Base64String,
+};
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum GroupLinkError {
+    #[error("The invite link is malformed")]
+    InvalidLink,
+    #[error("The invite link content could not be decrypted")]
+    DecryptionFailed,
+}
+
+/// The secret kept by the admins of a group, from which the invite link is derived.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GroupLinkSecret([u8; 32]);
+
+impl GroupLinkSecret {
+    pub fn generate() -> Self {
+        Self(random_bytes::<32>())
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    fn expand(&self, info: &[u8]) -> [u8; 32] {
+        let hk = Hkdf::<Sha256>::new(None, &self.0);
+        let mut output = [0u8; 32];
+        hk.expand(info, &mut output)
+            .expect("32 is a valid length for Sha256 to output");
+
+        output
+    }
+
+    /// The key used to prove to the server that we are allowed to update the link.
+    pub fn write_key(&self) -> BlindedAddressSecret {
+        BlindedAddressSecret::from_bytes(self.expand(b"licks_group_link_write"))
+    }
+
+    /// The link that can be shared with people who should be able to join the group.
+    pub fn link(&self) -> GroupLink {
+        GroupLink {
+            id: self.write_key().to_public(),
+            key: self.expand(b"licks_group_link_key"),
+        }
+    }
+}
+
+/// A shareable invite link: the id of the link on the server, and the
+/// key to decrypt its content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupLink {
+    pub id: BlindedAddressPublic,
+    key: [u8; KEY_LENGTH],
+}
+
+impl GroupLink {
+    /// Encrypts the content of the link (see [`aead::seal`]).
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        aead::seal(&self.key, &self.id.0, plaintext)
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, GroupLinkError> {
+        aead::open(&self.key, &self.id.0, ciphertext).ok_or(GroupLinkError::DecryptionFailed)
+    }
+
+    /// Encodes the link as an URL-safe string.
+    pub fn to_link_string(&self) -> String {
+        let mut bytes = self.id.0.to_vec();
+        bytes.extend_from_slice(&self.key);
+
+        Base64String::from_bytes(bytes).to_string()
+    }
+
+    pub fn from_link_string(link: &str) -> Result<Self, GroupLinkError> {
+        let bytes = Base64String::from_base64_str(link.trim())
+            .ok_or(GroupLinkError::InvalidLink)?
+            .to_vec();
+
+        if bytes.len() != 32 + KEY_LENGTH {
+            return Err(GroupLinkError::InvalidLink);
+        }
+
+        let (id, key) = bytes.split_at(32);
+
+        Ok(Self {
+            id: BlindedAddressPublic(id.try_into().map_err(|_| GroupLinkError::InvalidLink)?),
+            key: key.try_into().map_err(|_| GroupLinkError::InvalidLink)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_link_roundtrip() {
+        let secret = GroupLinkSecret::generate();
+        let link = secret.link();
+
+        let parsed = GroupLink::from_link_string(&link.to_link_string())
+            .expect("The link string should be parsed");
+        assert_eq!(parsed, link, "The parsed link should match the original");
+
+        let ciphertext = link.encrypt(b"group info");
+        assert_eq!(
+            parsed.decrypt(&ciphertext),
+            Ok(b"group info".to_vec()),
+            "The link content should be decrypted with the parsed link"
+        );
+
+        let other_link = GroupLinkSecret::generate().link();
+        assert_eq!(
+            other_link.decrypt(&ciphertext),
+            Err(GroupLinkError::DecryptionFailed),
+            "Another link should not decrypt the content"
+        );
+
+        assert_eq!(
+            secret.write_key().to_public(),
+            link.id,
+            "The link id should be the public key of the write key"
+        );
+    }
+}
@@ -1,6 +1,9 @@
+pub mod aead;
 pub mod blinded_address;
 pub mod certificates;
 pub mod challenge;
+pub mod device_link;
+pub mod group_link;
 pub mod listener;
 pub mod noise;
 pub mod rng;
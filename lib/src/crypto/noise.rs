@@ -1,9 +1,77 @@
-#[derive(Default)]
+use std::time::{Duration, Instant};
+
+use ml_kem::{
+    kem::{Decapsulate, Encapsulate},
+    EncodedSizeUser, KemCore, MlKem768,
+};
+
+use super::rng::get_rng;
+
+type KemDecapsulationKey = <MlKem768 as KemCore>::DecapsulationKey;
+type KemEncapsulationKey = <MlKem768 as KemCore>::EncapsulationKey;
+
+/// The maximum size of a message sent over a [`NoiseTransport`], once reassembled.
+/// Anything bigger than this is rejected by both the sender and the receiver.
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// A [`NoiseTransport`] rekeys its outgoing key after sending this many messages...
+pub const REKEY_AFTER_MESSAGES: u64 = 10_000;
+/// ...or after this long, whichever comes first.
+pub const REKEY_AFTER_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Noise frames can't be bigger than 65535 bytes, including the AEAD tag.
+const NOISE_TAG_LENGTH: usize = 16;
+
+/// The first byte of every Noise frame, telling what the rest of the frame is.
+#[repr(u8)]
+enum FrameKind {
+    /// A fragment of a message, see [`FRAGMENT_HEADER_LENGTH`].
+    Fragment = 0,
+    /// The sender switched to a new key for the frames after this one,
+    /// so the receiver must do the same.
+    Rekey = 1,
+}
+
+/// Every fragment starts with a header: the frame kind, followed by the message's
+/// sequence number, the index of the fragment in the message, and the message's
+/// total length. The last three are big-endian `u32`s.
+const FRAGMENT_HEADER_LENGTH: usize = 1 + 3 * size_of::<u32>();
+
+/// How much of a message fits in a single Noise frame.
+const MAX_FRAGMENT_PAYLOAD: usize = u16::MAX as usize - NOISE_TAG_LENGTH - FRAGMENT_HEADER_LENGTH;
+
+#[derive(thiserror::Error, Debug)]
+pub enum NoiseError {
+    #[error("Noise error: {0}")]
+    Snow(#[from] snow::Error),
+    #[error("The message is bigger than the maximum allowed message size")]
+    MessageTooLarge,
+    #[error("Received a fragment that was invalid or out of order")]
+    InvalidFragment,
+}
+
+/// The handshakes a client can pick from. The client's first handshake message
+/// starts with the handshake's byte, and the server simply follows that pattern:
+/// nothing is negotiated, and the server doesn't send the byte back.
+///
+/// Our clients always use the default, hybrid handshake. The server completes
+/// whichever one the client starts, so a client starting the classic one gets a
+/// classic session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
 #[allow(non_camel_case_types)]
-enum SupportedHandshakes {
-    #[default]
+pub enum SupportedHandshakes {
     // temporary until we move onto a KK scheme
-    Noise_XX_25519_AESGCM_SHA256,
+    Noise_XX_25519_AESGCM_SHA256 = 0,
+    /// A hybrid post-quantum handshake: XX, with an ML-KEM-768 key exchange mixed in.
+    ///
+    /// The client sends an encapsulation key as the payload of the first message, and
+    /// the server answers with a secret encapsulated to it in the second one (which
+    /// is already encrypted). That secret is then mixed into the session keys as the
+    /// PSK of the third message, so the session stays secret as long as either X25519
+    /// or ML-KEM isn't broken.
+    #[default]
+    Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768 = 1,
 }
 
 impl SupportedHandshakes {
@@ -12,10 +80,37 @@ impl SupportedHandshakes {
             SupportedHandshakes::Noise_XX_25519_AESGCM_SHA256 => "Noise_XX_25519_AESGCM_SHA256"
                 .parse()
                 .expect("Correct handshake"),
+            SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768 => {
+                "Noise_XXpsk3_25519_AESGCM_SHA256"
+                    .parse()
+                    .expect("Correct handshake")
+            }
+        }
+    }
+
+    fn is_hybrid(self) -> bool {
+        matches!(
+            self,
+            SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768
+        )
+    }
+}
+
+impl TryFrom<u8> for SupportedHandshakes {
+    type Error = snow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SupportedHandshakes::Noise_XX_25519_AESGCM_SHA256),
+            1 => Ok(SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768),
+            _ => Err(snow::Error::Input),
         }
     }
 }
 
+/// Where the ML-KEM shared secret goes in the hybrid handshake (`psk3`).
+const KEM_PSK_LOCATION: u8 = 3;
+
 /// A vector of bytes with a fixed maximum size of 65535.
 #[derive(Debug, Clone)]
 pub struct NoiseMessageBuffer {
@@ -79,24 +174,43 @@ impl AsRef<[u8]> for NoiseMessageBuffer {
 pub struct ClientHandshake {
     pub buffer: NoiseMessageBuffer,
     inner: snow::HandshakeState,
+    handshake: SupportedHandshakes,
+    /// Only used by the hybrid handshake.
+    kem_secret: Option<KemDecapsulationKey>,
 }
 
 impl ClientHandshake {
-    // TODO: Add client hash for auth challenge?
     pub fn prepare_handshake() -> Result<Self, snow::Error> {
-        let builder = snow::Builder::new(SupportedHandshakes::default().into_snow_params());
+        Self::prepare_handshake_with(SupportedHandshakes::default())
+    }
+
+    pub fn prepare_handshake_with(handshake: SupportedHandshakes) -> Result<Self, snow::Error> {
+        let builder = snow::Builder::new(handshake.into_snow_params());
         let keys = builder.generate_keypair()?;
         let builder = builder.local_private_key(&keys.private);
 
+        let (kem_secret, payload) = if handshake.is_hybrid() {
+            let (decapsulation_key, encapsulation_key) = MlKem768::generate(&mut get_rng());
+            (
+                Some(decapsulation_key),
+                encapsulation_key.as_bytes().to_vec(),
+            )
+        } else {
+            (None, Vec::new())
+        };
+
         let mut client = Self {
             buffer: NoiseMessageBuffer::default(),
             inner: builder.build_initiator()?,
+            handshake,
+            kem_secret,
         };
 
-        let new_len = client
-            .inner
-            .write_message(&[], client.buffer.as_mut_unchecked())?;
-        client.buffer.set_len_unchecked(new_len as u16);
+        // The first byte tells the server which handshake we picked
+        let buffer = client.buffer.as_mut_unchecked();
+        buffer[0] = handshake as u8;
+        let new_len = client.inner.write_message(&payload, &mut buffer[1..])?;
+        client.buffer.set_len_unchecked(new_len as u16 + 1);
 
         Ok(client)
     }
@@ -110,6 +224,21 @@ impl ClientHandshake {
             .read_message(&server_response, self.buffer.as_mut_unchecked())?;
         self.buffer.set_len_unchecked(new_len as u16);
 
+        if let Some(kem_secret) = self.kem_secret.take() {
+            // The server's payload is the encapsulated secret
+            let ciphertext: ml_kem::Ciphertext<MlKem768> = self
+                .buffer
+                .read()
+                .try_into()
+                .map_err(|_| snow::Error::Input)?;
+            let shared_secret = kem_secret
+                .decapsulate(&ciphertext)
+                .map_err(|_| snow::Error::Input)?;
+
+            self.inner
+                .set_psk(KEM_PSK_LOCATION as usize, &shared_secret)?;
+        }
+
         let new_len = self
             .inner
             .write_message(&[], self.buffer.as_mut_unchecked())?;
@@ -119,11 +248,15 @@ impl ClientHandshake {
         // "Empty" buffer to reuse it for Transport mode
         self.buffer.set_len_unchecked(0);
 
+        let handshake_hash = self.inner.get_handshake_hash().to_vec();
+
         Ok((
-            NoiseTransport {
-                buffer: self.buffer,
-                inner: self.inner.into_transport_mode()?,
-            },
+            NoiseTransport::new(
+                self.buffer,
+                self.inner.into_transport_mode()?,
+                self.handshake,
+                handshake_hash,
+            ),
             handshake_response,
         ))
     }
@@ -132,33 +265,85 @@ impl ClientHandshake {
 pub struct ServerHandshake {
     pub buffer: NoiseMessageBuffer,
     inner: snow::HandshakeState,
+    /// The handshake picked by the client.
+    handshake: SupportedHandshakes,
 }
 
+/// An encrypted Noise channel, once the handshake is done.
+///
+/// Noise frames are limited to 65535 bytes, so messages are split into
+/// fragments on [`Self::write_message`] and put back together on [`Self::read_frame`].
+/// Frames must be read in the same order they were written.
 #[derive(Debug)]
 pub struct NoiseTransport {
     buffer: NoiseMessageBuffer,
     inner: snow::TransportState,
+    /// The handshake the session was established with.
+    handshake: SupportedHandshakes,
+    /// Uniquely identifies the session. Both sides have the same hash.
+    handshake_hash: Vec<u8>,
+    /// Sequence number of the next message we write.
+    outgoing_sequence: u32,
+    /// Sequence number of the next message we expect to read.
+    incoming_sequence: u32,
+    /// The fragments of the message we're currently reading.
+    incoming_message: Vec<u8>,
+    /// Index of the next fragment we expect to read.
+    incoming_fragment: u32,
+    /// Messages written since we last rekeyed.
+    messages_since_rekey: u64,
+    /// When we last rekeyed (or when the handshake completed).
+    last_rekey: Instant,
+    rekey_after_messages: u64,
+    rekey_after_duration: Duration,
 }
 
 impl ServerHandshake {
     pub fn respond(client_initiation: &[u8]) -> Result<Self, snow::Error> {
-        let builder = snow::Builder::new(SupportedHandshakes::default().into_snow_params());
+        let (&handshake, client_initiation) =
+            client_initiation.split_first().ok_or(snow::Error::Input)?;
+        let handshake = SupportedHandshakes::try_from(handshake)?;
+
+        let builder = snow::Builder::new(handshake.into_snow_params());
         let keys = builder.generate_keypair()?;
         let builder = builder.local_private_key(&keys.private);
 
         let mut server = Self {
             buffer: NoiseMessageBuffer::default(),
             inner: builder.build_responder()?,
+            handshake,
         };
 
-        let _new_len = server
+        let new_len = server
             .inner
             .read_message(&client_initiation, server.buffer.as_mut_unchecked())?;
-        // server.buffer.set_len_unchecked(new_len as u16);
+        server.buffer.set_len_unchecked(new_len as u16);
+
+        let payload = if handshake.is_hybrid() {
+            // The client's payload is its encapsulation key
+            let encapsulation_key = KemEncapsulationKey::from_bytes(
+                &server
+                    .buffer
+                    .read()
+                    .try_into()
+                    .map_err(|_| snow::Error::Input)?,
+            );
+            let (ciphertext, shared_secret) = encapsulation_key
+                .encapsulate(&mut get_rng())
+                .map_err(|_| snow::Error::Input)?;
+
+            server
+                .inner
+                .set_psk(KEM_PSK_LOCATION as usize, &shared_secret)?;
+
+            ciphertext.to_vec()
+        } else {
+            Vec::new()
+        };
 
         let new_len = server
             .inner
-            .write_message(&[], server.buffer.as_mut_unchecked())?;
+            .write_message(&payload, server.buffer.as_mut_unchecked())?;
         server.buffer.set_len_unchecked(new_len as u16);
 
         Ok(server)
@@ -175,15 +360,193 @@ impl ServerHandshake {
         // "Empty" buffer to reuse it for Transport mode
         self.buffer.set_len_unchecked(0);
 
-        Ok(NoiseTransport {
-            buffer: self.buffer,
-            inner: self.inner.into_transport_mode()?,
-        })
+        let handshake_hash = self.inner.get_handshake_hash().to_vec();
+
+        Ok(NoiseTransport::new(
+            self.buffer,
+            self.inner.into_transport_mode()?,
+            self.handshake,
+            handshake_hash,
+        ))
     }
 }
 
 impl NoiseTransport {
-    pub fn write(&mut self, bytes: &[u8]) -> Result<&[u8], snow::Error> {
+    fn new(
+        buffer: NoiseMessageBuffer,
+        inner: snow::TransportState,
+        handshake: SupportedHandshakes,
+        handshake_hash: Vec<u8>,
+    ) -> Self {
+        Self {
+            buffer,
+            inner,
+            handshake,
+            handshake_hash,
+            outgoing_sequence: 0,
+            incoming_sequence: 0,
+            incoming_message: Vec::new(),
+            incoming_fragment: 0,
+            messages_since_rekey: 0,
+            last_rekey: Instant::now(),
+            rekey_after_messages: REKEY_AFTER_MESSAGES,
+            rekey_after_duration: REKEY_AFTER_DURATION,
+        }
+    }
+
+    /// Changes how often the outgoing key is rotated. Both sides of the connection
+    /// can use different values, since each side only rekeys what it sends.
+    #[must_use]
+    pub fn with_rekey_interval(mut self, messages: u64, duration: Duration) -> Self {
+        self.rekey_after_messages = messages;
+        self.rekey_after_duration = duration;
+        self
+    }
+
+    /// The handshake the session was established with, which is the one the
+    /// client picked.
+    pub fn handshake(&self) -> SupportedHandshakes {
+        self.handshake
+    }
+
+    /// The hash of the handshake, which is the same on both sides of the session
+    /// and unique to it. Used to bind the authentication challenge to the session.
+    pub fn handshake_hash(&self) -> &[u8] {
+        &self.handshake_hash
+    }
+
+    fn should_rekey(&self) -> bool {
+        self.messages_since_rekey >= self.rekey_after_messages
+            || self.last_rekey.elapsed() >= self.rekey_after_duration
+    }
+
+    /// Encrypts `bytes`, splitting it into as many Noise frames as needed.
+    /// Each frame must be sent as-is, in order, to the other side.
+    ///
+    /// If it is time to rekey, the first frame tells the other side that the
+    /// frames after it use a new key.
+    pub fn write_message(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, NoiseError> {
+        if bytes.len() > MAX_MESSAGE_SIZE {
+            return Err(NoiseError::MessageTooLarge);
+        }
+
+        // `MAX_MESSAGE_SIZE` fits in a u32, so these can't truncate
+        #[allow(clippy::cast_possible_truncation)]
+        let total_length = bytes.len() as u32;
+        let sequence = self.outgoing_sequence;
+
+        // An empty message still needs one (empty) fragment
+        let fragments: Vec<&[u8]> = if bytes.is_empty() {
+            vec![bytes]
+        } else {
+            bytes.chunks(MAX_FRAGMENT_PAYLOAD).collect()
+        };
+
+        let mut frames = Vec::with_capacity(fragments.len() + 1);
+
+        if self.should_rekey() {
+            // The rekey frame is encrypted with the old key, so the
+            // other side knows to rekey right after reading it
+            frames.push(self.write(&[FrameKind::Rekey as u8])?.to_vec());
+            self.inner.rekey_outgoing();
+            self.messages_since_rekey = 0;
+            self.last_rekey = Instant::now();
+        }
+
+        let mut plaintext = Vec::with_capacity(FRAGMENT_HEADER_LENGTH + MAX_FRAGMENT_PAYLOAD);
+
+        #[allow(clippy::cast_possible_truncation)]
+        for (index, fragment) in fragments.into_iter().enumerate() {
+            plaintext.clear();
+            plaintext.push(FrameKind::Fragment as u8);
+            plaintext.extend_from_slice(&sequence.to_be_bytes());
+            plaintext.extend_from_slice(&(index as u32).to_be_bytes());
+            plaintext.extend_from_slice(&total_length.to_be_bytes());
+            plaintext.extend_from_slice(fragment);
+
+            frames.push(self.write(&plaintext)?.to_vec());
+        }
+
+        self.outgoing_sequence = self.outgoing_sequence.wrapping_add(1);
+        self.messages_since_rekey += 1;
+
+        Ok(frames)
+    }
+
+    /// Decrypts a Noise frame written by [`Self::write_message`].
+    ///
+    /// Returns the message once its last fragment was read, and `None`
+    /// while we're still waiting on fragments.
+    pub fn read_frame(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>, NoiseError> {
+        // Decrypting inline, because borrowing all of `self` in a helper
+        // would prevent us from updating the reassembly state below
+        let new_len = self
+            .inner
+            .read_message(frame, self.buffer.as_mut_unchecked())? as u16;
+        self.buffer.set_len_unchecked(new_len);
+        let plaintext = self.buffer.read();
+
+        if plaintext.first() == Some(&(FrameKind::Rekey as u8)) {
+            // Rekeying in the middle of a message isn't something we do
+            if plaintext.len() != 1 || self.incoming_fragment != 0 {
+                return Err(NoiseError::InvalidFragment);
+            }
+
+            self.inner.rekey_incoming();
+            return Ok(None);
+        }
+
+        let (header, fragment) = plaintext
+            .split_at_checked(FRAGMENT_HEADER_LENGTH)
+            .ok_or(NoiseError::InvalidFragment)?;
+
+        let read_u32 = |offset: usize| {
+            u32::from_be_bytes(
+                header[offset..offset + size_of::<u32>()]
+                    .try_into()
+                    .expect("header is long enough for three u32s"),
+            )
+        };
+
+        let kind = header[0];
+        let sequence = read_u32(1);
+        let index = read_u32(5);
+        let total_length = read_u32(9) as usize;
+
+        if kind != FrameKind::Fragment as u8
+            || sequence != self.incoming_sequence
+            || index != self.incoming_fragment
+        {
+            return Err(NoiseError::InvalidFragment);
+        }
+
+        if total_length > MAX_MESSAGE_SIZE {
+            return Err(NoiseError::MessageTooLarge);
+        }
+
+        if index == 0 {
+            self.incoming_message = Vec::with_capacity(total_length);
+        }
+
+        if self.incoming_message.len() + fragment.len() > total_length {
+            return Err(NoiseError::InvalidFragment);
+        }
+
+        self.incoming_message.extend_from_slice(fragment);
+
+        if self.incoming_message.len() == total_length {
+            self.incoming_sequence = self.incoming_sequence.wrapping_add(1);
+            self.incoming_fragment = 0;
+
+            Ok(Some(std::mem::take(&mut self.incoming_message)))
+        } else {
+            self.incoming_fragment += 1;
+
+            Ok(None)
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<&[u8], snow::Error> {
         let new_len = self
             .inner
             .write_message(bytes, self.buffer.as_mut_unchecked())? as u16;
@@ -192,7 +555,10 @@ impl NoiseTransport {
         Ok(self.buffer.read())
     }
 
-    pub fn read(&mut self, bytes: &[u8]) -> Result<&[u8], snow::Error> {
+    /// Decrypts a single Noise frame, without fragmentation. Only the tests
+    /// use it, to check the raw transport.
+    #[cfg(test)]
+    fn read(&mut self, bytes: &[u8]) -> Result<&[u8], snow::Error> {
         let new_len = self
             .inner
             .read_message(bytes, self.buffer.as_mut_unchecked())? as u16;
@@ -208,7 +574,88 @@ mod tests {
 
     #[test]
     fn basic_handshake() {
+        basic_handshake_with(SupportedHandshakes::Noise_XX_25519_AESGCM_SHA256);
+    }
+
+    #[test]
+    fn hybrid_handshake() {
+        basic_handshake_with(SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768);
+    }
+
+    #[test]
+    fn handshake_negotiation() {
+        let client = ClientHandshake::prepare_handshake_with(
+            SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768,
+        )
+        .expect("client handshake works");
+
+        let mut unknown_handshake = client.buffer.read().to_vec();
+        unknown_handshake[0] = u8::MAX;
+        assert!(
+            ServerHandshake::respond(&unknown_handshake).is_err(),
+            "The server should refuse handshakes it doesn't know"
+        );
+
+        let mut wrong_handshake = client.buffer.read().to_vec();
+        wrong_handshake[0] = SupportedHandshakes::Noise_XX_25519_AESGCM_SHA256 as u8;
+        let server = ServerHandshake::respond(&wrong_handshake)
+            .expect("the first message of XX accepts any payload");
+        assert!(
+            client.complete_handshake(server.buffer.as_ref()).is_err(),
+            "The client should notice the server didn't use the handshake it picked"
+        );
+
+        assert!(
+            ServerHandshake::respond(&[]).is_err(),
+            "An empty handshake should be refused"
+        );
+    }
+
+    #[test]
+    fn server_follows_the_client_handshake() {
+        for handshake in [
+            SupportedHandshakes::Noise_XX_25519_AESGCM_SHA256,
+            SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768,
+        ] {
+            let client =
+                ClientHandshake::prepare_handshake_with(handshake).expect("client handshake works");
+            let server = ServerHandshake::respond(client.buffer.as_ref())
+                .expect("server handshake response works");
+            let (client_transport, client_response) = client
+                .complete_handshake(server.buffer.as_ref())
+                .expect("client completes handshake successfully");
+            let server_transport = server
+                .complete_handshake(client_response.as_ref())
+                .expect("server completes handshake");
+
+            assert_eq!(
+                client_transport.handshake(),
+                handshake,
+                "The client should use the handshake it picked"
+            );
+            assert_eq!(
+                server_transport.handshake(),
+                handshake,
+                "The server should use the handshake the client picked"
+            );
+            assert_eq!(
+                client_transport.handshake_hash(),
+                server_transport.handshake_hash(),
+                "Both sides should end up in the same session"
+            );
+        }
+
         let client = ClientHandshake::prepare_handshake().expect("client handshake works");
+        assert_eq!(
+            client.handshake,
+            SupportedHandshakes::Noise_XXpsk3_25519_AESGCM_SHA256_MLKEM768,
+            "Clients should use the hybrid handshake by default"
+        );
+    }
+
+    fn basic_handshake_with(handshake: SupportedHandshakes) {
+        let client =
+            ClientHandshake::prepare_handshake_with(handshake).expect("client handshake works");
 
         let server = ServerHandshake::respond(client.buffer.as_ref())
             .expect("server handshake response works");
@@ -235,6 +682,12 @@ mod tests {
             client_transport.inner.receiving_nonce()
         );
 
+        assert_eq!(
+            client_transport.handshake_hash(),
+            server_transport.handshake_hash(),
+            "Both sides of the session should have the same handshake hash"
+        );
+
         let wawa = b"wawa";
         let client_wawa = client_transport
             .write(wawa)
@@ -258,4 +711,199 @@ mod tests {
         assert_ne!(wewe, server_wewe);
         assert_eq!(wewe, client_wewe);
     }
+
+    fn transports() -> (NoiseTransport, NoiseTransport) {
+        let client = ClientHandshake::prepare_handshake().expect("client handshake works");
+        let server = ServerHandshake::respond(client.buffer.as_ref())
+            .expect("server handshake response works");
+        let (client_transport, client_response) = client
+            .complete_handshake(server.buffer.as_ref())
+            .expect("client completes handshake successfully");
+        let server_transport = server
+            .complete_handshake(client_response.as_ref())
+            .expect("server completes handshake");
+
+        (client_transport, server_transport)
+    }
+
+    fn send(from: &mut NoiseTransport, to: &mut NoiseTransport, message: &[u8]) -> Vec<u8> {
+        let frames = from.write_message(message).expect("encryption works");
+
+        let mut received = None;
+        for frame in frames {
+            assert!(
+                frame.len() <= u16::MAX as usize,
+                "Frames should fit in a single Noise message"
+            );
+            assert!(
+                received.is_none(),
+                "The message should only be complete after the last frame"
+            );
+            received = to.read_frame(&frame).expect("decryption works");
+        }
+
+        received.expect("The message should be complete after the last frame")
+    }
+
+    #[test]
+    fn fragmented_messages() {
+        use crate::api::messages::{ClientRequestId, Message, MessageWire};
+
+        let (mut client_transport, mut server_transport) = transports();
+
+        // A few megabytes, which doesn't divide evenly in fragments
+        let big_wire = MessageWire(
+            ClientRequestId::generate(),
+            Message::Ping(crate::crypto::rng::random_bytes_vec(3 * 1024 * 1024 + 17)),
+        );
+        let big_bytes = big_wire.clone().to_bytes();
+
+        let received = send(&mut client_transport, &mut server_transport, &big_bytes);
+        let received_wire = MessageWire::from_bytes(&received).expect("message wire decodes");
+        assert_eq!(
+            received_wire.1, big_wire.1,
+            "A multi-megabyte message should arrive intact"
+        );
+
+        let received = send(&mut server_transport, &mut client_transport, &big_bytes);
+        assert_eq!(
+            received, big_bytes,
+            "Fragmentation should work in both directions"
+        );
+
+        // Messages right at the edge of a fragment, and small/empty messages in between
+        let exact = vec![7u8; MAX_FRAGMENT_PAYLOAD * 2];
+        for message in [&b""[..], &b"wawa"[..], &exact] {
+            assert_eq!(
+                send(&mut client_transport, &mut server_transport, message),
+                message,
+                "Messages of any size should arrive intact"
+            );
+        }
+    }
+
+    #[test]
+    fn fragment_limits() {
+        let (mut client_transport, mut server_transport) = transports();
+
+        assert!(
+            matches!(
+                client_transport.write_message(&vec![0u8; MAX_MESSAGE_SIZE + 1]),
+                Err(NoiseError::MessageTooLarge)
+            ),
+            "Messages over the maximum size should not be sent"
+        );
+
+        // Forge frames with a bogus header
+        let forge = |transport: &mut NoiseTransport, sequence: u32, index: u32, length: u32| {
+            let mut plaintext = vec![FrameKind::Fragment as u8];
+            plaintext.extend_from_slice(&sequence.to_be_bytes());
+            plaintext.extend_from_slice(&index.to_be_bytes());
+            plaintext.extend_from_slice(&length.to_be_bytes());
+            plaintext.extend_from_slice(b"wawa");
+
+            transport
+                .write(&plaintext)
+                .expect("encryption works")
+                .to_vec()
+        };
+
+        let too_large = forge(&mut client_transport, 0, 0, u32::MAX);
+        assert!(
+            matches!(
+                server_transport.read_frame(&too_large),
+                Err(NoiseError::MessageTooLarge)
+            ),
+            "Messages over the maximum size should not be received"
+        );
+
+        let (mut client_transport, mut server_transport) = transports();
+
+        let out_of_order = forge(&mut client_transport, 0, 1, 8);
+        assert!(
+            matches!(
+                server_transport.read_frame(&out_of_order),
+                Err(NoiseError::InvalidFragment)
+            ),
+            "Fragments should be received in order"
+        );
+    }
+
+    #[test]
+    fn periodic_rekeying() {
+        let (client_transport, server_transport) = transports();
+        let mut client_transport = client_transport.with_rekey_interval(10, REKEY_AFTER_DURATION);
+        let mut server_transport = server_transport.with_rekey_interval(25, Duration::from_secs(0));
+
+        let mut client_rekeys = 0;
+        for i in 0..100u32 {
+            let frames = client_transport
+                .write_message(&i.to_be_bytes())
+                .expect("encryption works");
+
+            // One frame for the message, and one more if we rekeyed
+            if frames.len() == 2 {
+                client_rekeys += 1;
+            }
+
+            let mut received = None;
+            for frame in frames {
+                received = server_transport
+                    .read_frame(&frame)
+                    .expect("decryption works");
+            }
+            assert_eq!(
+                received.expect("message is complete"),
+                i.to_be_bytes(),
+                "Messages should still arrive intact after rekeying"
+            );
+        }
+
+        assert_eq!(
+            client_rekeys, 9,
+            "The client should rekey every 10 messages"
+        );
+
+        // The server rekeys every time because of its duration
+        for _ in 0..5 {
+            let frames = server_transport
+                .write_message(b"wawa")
+                .expect("encryption works");
+            assert_eq!(
+                frames.len(),
+                2,
+                "The server should rekey before every message"
+            );
+
+            let mut received = None;
+            for frame in frames {
+                received = client_transport
+                    .read_frame(&frame)
+                    .expect("decryption works");
+            }
+            assert_eq!(
+                received.expect("message is complete"),
+                b"wawa",
+                "Messages should still arrive intact after rekeying"
+            );
+        }
+
+        // Frames encrypted before a rekey can't be read with the new key
+        let (client_transport, mut server_transport) = transports();
+        let mut client_transport = client_transport.with_rekey_interval(1, REKEY_AFTER_DURATION);
+        let first = client_transport
+            .write_message(b"wawa")
+            .expect("encryption works");
+        let second = client_transport
+            .write_message(b"wewe")
+            .expect("encryption works");
+
+        server_transport
+            .read_frame(&first[0])
+            .expect("decryption works");
+        assert!(
+            server_transport.read_frame(&second[1]).is_err(),
+            "Skipping the rekey frame should make decryption fail"
+        );
+    }
 }
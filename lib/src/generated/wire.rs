@@ -25,6 +25,11 @@ pub struct Certificate {
     pub self_signature_of_inner: ::prost::alloc::vec::Vec<u8>,
     #[prost(bytes = "vec", tag = "4")]
     pub data: ::prost::alloc::vec::Vec<u8>,
+    /// Validity period, in seconds since the UNIX epoch (both included)
+    #[prost(uint64, tag = "5")]
+    pub not_before: u64,
+    #[prost(uint64, tag = "6")]
+    pub not_after: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CertificateChain {
@@ -52,11 +57,56 @@ pub struct Ed25519CertificateChainSecret {
     pub account_secret: ::prost::alloc::vec::Vec<u8>,
     #[prost(bytes = "vec", tag = "3")]
     pub device_secret: ::prost::alloc::vec::Vec<u8>,
+    /// Empty unless the account key was rotated
+    #[prost(bytes = "vec", tag = "4")]
+    pub previous_account_signature: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HybridCertificateChainSecret {
+    #[prost(message, optional, tag = "1")]
+    pub public: ::core::option::Option<CertificateChain>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub account_secret: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub device_secret: ::prost::alloc::vec::Vec<u8>,
+    /// Empty unless the account key was rotated
+    #[prost(bytes = "vec", tag = "4")]
+    pub previous_account_signature: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AccountKeyRotation {
+    #[prost(message, optional, tag = "1")]
+    pub new_account_certificate: ::core::option::Option<Certificate>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub previous_account_signature: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, repeated, tag = "3")]
+    pub new_chains: ::prost::alloc::vec::Vec<CertificateChain>,
+}
+/// An account key, shared with the other devices of the account after rotating
+/// it, or with a new device when linking it
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AccountKeyShare {
+    #[prost(message, optional, tag = "1")]
+    pub account_certificate: ::core::option::Option<Certificate>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub account_secret: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub previous_account_signature: ::prost::alloc::vec::Vec<u8>,
+}
+/// The contents of a Licks MLS credential
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LicksCredential {
+    #[prost(message, optional, tag = "1")]
+    pub chain: ::core::option::Option<CertificateChain>,
+    /// Empty unless the account key was rotated
+    #[prost(bytes = "vec", tag = "2")]
+    pub previous_account_signature: ::prost::alloc::vec::Vec<u8>,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum SignatureScheme {
     Ed25519 = 0,
+    HybridEd25519MlDsa65 = 1,
 }
 impl SignatureScheme {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -66,12 +116,14 @@ impl SignatureScheme {
     pub fn as_str_name(&self) -> &'static str {
         match self {
             Self::Ed25519 => "ED25519",
+            Self::HybridEd25519MlDsa65 => "HYBRID_ED25519_ML_DSA_65",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
     pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
         match value {
             "ED25519" => Some(Self::Ed25519),
+            "HYBRID_ED25519_ML_DSA_65" => Some(Self::HybridEd25519MlDsa65),
             _ => None,
         }
     }
@@ -230,6 +282,9 @@ pub mod chat_service_message {
     pub struct SendMessageRequest {
         #[prost(message, optional, tag = "1")]
         pub proof: ::core::option::Option<super::BlindedAddressProof>,
+        /// Only broadcast to the current listeners, without being queued
+        #[prost(bool, tag = "2")]
+        pub ephemeral: bool,
     }
     #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct StartListeningRequest {
@@ -268,13 +323,34 @@ pub mod chat_service_message {
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LicksApiErrorDetails {
+    #[prost(enumeration = "LicksApiError", tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub detail: ::prost::alloc::string::String,
+    #[prost(uint64, optional, tag = "3")]
+    pub retry_after_secs: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "4")]
+    pub quota: ::core::option::Option<u64>,
+    #[prost(string, optional, tag = "5")]
+    pub field: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct KeyPackages {
     #[prost(bytes = "vec", repeated, tag = "1")]
     pub inner: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CertificateChains {
+    #[prost(message, repeated, tag = "1")]
+    pub inner: ::prost::alloc::vec::Vec<CertificateChain>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AuthenticatedChannelMessage {
-    #[prost(oneof = "authenticated_channel_message::Inner", tags = "1, 2, 3, 4, 5, 6")]
+    #[prost(
+        oneof = "authenticated_channel_message::Inner",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10"
+    )]
     pub inner: ::core::option::Option<authenticated_channel_message::Inner>,
 }
 /// Nested message and enum types in `AuthenticatedChannelMessage`.
@@ -293,13 +369,21 @@ pub mod authenticated_channel_message {
         UploadKeyPackages(super::KeyPackages),
         #[prost(message, tag = "6")]
         KeyPackageAlreadyUploaded(super::Empty),
+        #[prost(message, tag = "7")]
+        RotateAccountKey(super::AccountKeyRotation),
+        #[prost(message, tag = "8")]
+        UpdateDeviceChain(super::CertificateChain),
+        #[prost(message, tag = "9")]
+        GetDeviceChains(super::Empty),
+        #[prost(message, tag = "10")]
+        HereAreDeviceChains(super::CertificateChains),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UnauthenticatedChannelMessage {
     #[prost(
         oneof = "unauthenticated_channel_message::Inner",
-        tags = "1, 2, 3, 4, 5, 6, 7, 8"
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13"
     )]
     pub inner: ::core::option::Option<unauthenticated_channel_message::Inner>,
 }
@@ -323,6 +407,16 @@ pub mod unauthenticated_channel_message {
         ChatService(super::ChatServiceMessage),
         #[prost(message, tag = "8")]
         NoAccount(super::Empty),
+        #[prost(message, tag = "9")]
+        PutGroupLink(super::BlindedAddressProof),
+        #[prost(message, tag = "10")]
+        DeleteGroupLink(super::BlindedAddressProof),
+        #[prost(message, tag = "11")]
+        GetGroupLink(super::BlindedAddressPublic),
+        #[prost(bytes, tag = "12")]
+        HereIsGroupLink(::prost::alloc::vec::Vec<u8>),
+        #[prost(message, tag = "13")]
+        RegisterDevice(super::CertificateChain),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -331,7 +425,7 @@ pub struct LicksMessageWire {
     pub request_id: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
     #[prost(
         oneof = "licks_message_wire::LicksMessageBody",
-        tags = "2, 4, 5, 6, 7, 8, 9, 10"
+        tags = "2, 3, 4, 5, 6, 7, 8, 9, 10, 11"
     )]
     pub licks_message_body: ::core::option::Option<licks_message_wire::LicksMessageBody>,
 }
@@ -341,6 +435,8 @@ pub mod licks_message_wire {
     pub enum LicksMessageBody {
         #[prost(enumeration = "super::LicksApiError", tag = "2")]
         Error(i32),
+        #[prost(message, tag = "3")]
+        ErrorDetails(super::LicksApiErrorDetails),
         #[prost(message, tag = "4")]
         Authenticated(super::AuthenticatedChannelMessage),
         #[prost(message, tag = "5")]
@@ -355,6 +451,8 @@ pub mod licks_message_wire {
         Pong(::prost::alloc::vec::Vec<u8>),
         #[prost(enumeration = "super::EmptyMessageBody", tag = "10")]
         Empty(i32),
+        #[prost(bytes, tag = "11")]
+        Cancel(::prost::alloc::vec::Vec<u8>),
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
@@ -368,6 +466,11 @@ pub enum LicksApiError {
     ConnectionIsClosed = 5,
     UnknownError = 6,
     InvalidRequest = 7,
+    UsernameAlreadyTaken = 8,
+    NotFound = 9,
+    RateLimited = 10,
+    Overloaded = 11,
+    QuotaExceeded = 12,
 }
 impl LicksApiError {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -384,6 +487,11 @@ impl LicksApiError {
             Self::ConnectionIsClosed => "CONNECTION_IS_CLOSED",
             Self::UnknownError => "UNKNOWN_ERROR",
             Self::InvalidRequest => "INVALID_REQUEST",
+            Self::UsernameAlreadyTaken => "USERNAME_ALREADY_TAKEN",
+            Self::NotFound => "NOT_FOUND",
+            Self::RateLimited => "RATE_LIMITED",
+            Self::Overloaded => "OVERLOADED",
+            Self::QuotaExceeded => "QUOTA_EXCEEDED",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -397,6 +505,11 @@ impl LicksApiError {
             "CONNECTION_IS_CLOSED" => Some(Self::ConnectionIsClosed),
             "UNKNOWN_ERROR" => Some(Self::UnknownError),
             "INVALID_REQUEST" => Some(Self::InvalidRequest),
+            "USERNAME_ALREADY_TAKEN" => Some(Self::UsernameAlreadyTaken),
+            "NOT_FOUND" => Some(Self::NotFound),
+            "RATE_LIMITED" => Some(Self::RateLimited),
+            "OVERLOADED" => Some(Self::Overloaded),
+            "QUOTA_EXCEEDED" => Some(Self::QuotaExceeded),
             _ => None,
         }
     }
@@ -435,7 +548,7 @@ impl EmptyMessageBody {
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Content {
-    #[prost(oneof = "content::Inner", tags = "1")]
+    #[prost(oneof = "content::Inner", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9")]
     pub inner: ::core::option::Option<content::Inner>,
 }
 /// Nested message and enum types in `Content`.
@@ -444,8 +557,75 @@ pub mod content {
     pub enum Inner {
         #[prost(string, tag = "1")]
         BasicText(::prost::alloc::string::String),
+        /// An encoded HistorySyncBatch, only sent in the self group
+        #[prost(bytes, tag = "2")]
+        HistorySync(::prost::alloc::vec::Vec<u8>),
+        #[prost(message, tag = "3")]
+        Reply(super::Reply),
+        #[prost(message, tag = "4")]
+        Edit(super::Edit),
+        #[prost(message, tag = "5")]
+        Delete(super::Delete),
+        #[prost(message, tag = "6")]
+        Reaction(super::Reaction),
+        #[prost(message, tag = "7")]
+        Receipt(super::Receipt),
+        /// Whether the sender is typing, sent ephemerally: it is never stored
+        #[prost(bool, tag = "8")]
+        Typing(bool),
+        /// An encoded AccountKeyShare, only sent in the self group
+        #[prost(bytes, tag = "9")]
+        AccountKey(::prost::alloc::vec::Vec<u8>),
     }
 }
+/// Identifies a message of a group by its sender and the client timestamp
+/// they generated for it.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MessageReference {
+    #[prost(message, optional, tag = "1")]
+    pub sender_account_id: ::core::option::Option<AccountId>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub client_timestamp: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Reply {
+    #[prost(string, tag = "1")]
+    pub body: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub parent: ::core::option::Option<MessageReference>,
+}
+/// Edits and deletions can only target messages of their own sender,
+/// so only the client timestamp of the target is sent.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Edit {
+    #[prost(bytes = "vec", tag = "1")]
+    pub target_client_timestamp: ::prost::alloc::vec::Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub body: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Delete {
+    #[prost(bytes = "vec", tag = "1")]
+    pub target_client_timestamp: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Reaction {
+    #[prost(message, optional, tag = "1")]
+    pub target: ::core::option::Option<MessageReference>,
+    #[prost(string, tag = "2")]
+    pub emoji: ::prost::alloc::string::String,
+    /// Removes the reaction instead of adding it
+    #[prost(bool, tag = "3")]
+    pub remove: bool,
+}
+/// Tells the senders of many messages that we received or read them
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Receipt {
+    #[prost(enumeration = "ReceiptKind", tag = "1")]
+    pub kind: i32,
+    #[prost(message, repeated, tag = "2")]
+    pub messages: ::prost::alloc::vec::Vec<MessageReference>,
+}
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ApplicationMessage {
     #[prost(bytes = "vec", tag = "1")]
@@ -459,3 +639,181 @@ pub struct ApplicationMessage {
     #[prost(message, optional, tag = "5")]
     pub content: ::core::option::Option<Content>,
 }
+/// Part of the history of an account, sent by one of its devices to a newly
+/// linked one through the self group.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySyncBatch {
+    /// The device this history is for
+    #[prost(message, optional, tag = "1")]
+    pub device_id: ::core::option::Option<DeviceId>,
+    #[prost(message, repeated, tag = "2")]
+    pub contacts: ::prost::alloc::vec::Vec<HistorySyncContact>,
+    #[prost(message, repeated, tag = "3")]
+    pub groups: ::prost::alloc::vec::Vec<HistorySyncGroup>,
+    #[prost(message, repeated, tag = "4")]
+    pub messages: ::prost::alloc::vec::Vec<HistorySyncMessage>,
+    /// How many messages were sent so far, including this batch
+    #[prost(uint64, tag = "5")]
+    pub synced_messages: u64,
+    #[prost(uint64, tag = "6")]
+    pub total_messages: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySyncContact {
+    #[prost(message, optional, tag = "1")]
+    pub account_id: ::core::option::Option<AccountId>,
+    #[prost(string, optional, tag = "2")]
+    pub username: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "3")]
+    pub profile_name: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "4")]
+    pub profile_description: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySyncGroup {
+    #[prost(message, optional, tag = "1")]
+    pub group_id: ::core::option::Option<GroupId>,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "3")]
+    pub description: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySyncMessage {
+    #[prost(message, optional, tag = "1")]
+    pub group_id: ::core::option::Option<GroupId>,
+    #[prost(message, optional, tag = "2")]
+    pub sender_account_id: ::core::option::Option<AccountId>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub server_delivery_stamp: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, optional, tag = "4")]
+    pub content: ::core::option::Option<Content>,
+    /// Empty for messages stored before client timestamps were
+    #[prost(bytes = "vec", tag = "5")]
+    pub client_delivery_stamp: ::prost::alloc::vec::Vec<u8>,
+    /// The edits, deletion, reactions and receipts of the message, applied
+    /// after it. Messages without a client timestamp have none.
+    #[prost(message, repeated, tag = "6")]
+    pub updates: ::prost::alloc::vec::Vec<HistorySyncUpdate>,
+}
+/// A change to a synced message, as the content that made it
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySyncUpdate {
+    #[prost(message, optional, tag = "1")]
+    pub sender_account_id: ::core::option::Option<AccountId>,
+    #[prost(message, optional, tag = "2")]
+    pub content: ::core::option::Option<Content>,
+    /// The client timestamp of edits and deletions, empty otherwise
+    #[prost(bytes = "vec", tag = "3")]
+    pub client_delivery_stamp: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ReceiptKind {
+    Delivered = 0,
+    Read = 1,
+}
+impl ReceiptKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Delivered => "DELIVERED",
+            Self::Read => "READ",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DELIVERED" => Some(Self::Delivered),
+            "READ" => Some(Self::Read),
+            _ => None,
+        }
+    }
+}
+/// Stored in the group context of every group, as a custom MLS extension.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GroupMetadata {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// Empty if the group has no description
+    #[prost(string, tag = "2")]
+    pub description: ::prost::alloc::string::String,
+    /// Empty if the group has no avatar
+    #[prost(bytes = "vec", tag = "3")]
+    pub avatar_hash: ::prost::alloc::vec::Vec<u8>,
+}
+/// Stored in the group context of every group, as a custom MLS extension.
+/// Members that aren't admins are regular members.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GroupRoles {
+    #[prost(message, repeated, tag = "1")]
+    pub admins: ::prost::alloc::vec::Vec<AccountId>,
+}
+/// Stored in the group context of groups that have an invite link.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActiveGroupLink {
+    #[prost(message, optional, tag = "1")]
+    pub link_id: ::core::option::Option<BlindedAddressPublic>,
+}
+/// Content of an invite link, stored encrypted on the server.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GroupLinkContent {
+    /// A GroupInfo MLS message that allows external commits
+    #[prost(bytes = "vec", tag = "1")]
+    pub group_info: ::prost::alloc::vec::Vec<u8>,
+    /// Secret of the blinded address of the GroupInfo's epoch, where
+    /// the external commit should be sent
+    #[prost(bytes = "vec", tag = "2")]
+    pub blinded_address_secret: ::prost::alloc::vec::Vec<u8>,
+}
+/// Published in the key packages of a device, as a custom MLS extension,
+/// so that only who adds one of them to a group can invite the device.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InviteInbox {
+    /// Secret of the blinded address of the device's invite inbox
+    #[prost(bytes = "vec", tag = "1")]
+    pub blinded_address_secret: ::prost::alloc::vec::Vec<u8>,
+}
+/// Sent by the existing device to the device being linked.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeviceLinkProvisioning {
+    /// How many key packages the new device should send back, one for each
+    /// group it will be added to
+    #[prost(uint32, tag = "2")]
+    pub key_package_count: u32,
+    /// The certificate of the account, whose signature scheme the device
+    /// certificate of the new device must use
+    #[prost(message, optional, tag = "3")]
+    pub account_certificate: ::core::option::Option<Certificate>,
+}
+/// Sent by the new device: its self-signed device certificate, to be signed
+/// by the account key.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeviceLinkDeviceCertificate {
+    #[prost(message, optional, tag = "1")]
+    pub device_certificate: ::core::option::Option<Certificate>,
+}
+/// Sent by the existing device: the chain of the new device, and the account
+/// key.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeviceLinkChain {
+    #[prost(message, optional, tag = "1")]
+    pub chain: ::core::option::Option<CertificateChain>,
+    #[prost(message, optional, tag = "2")]
+    pub account_key: ::core::option::Option<AccountKeyShare>,
+}
+/// Sent by the new device once it is registered.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeviceLinkKeyPackages {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub key_packages: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
+/// Sent by the existing device after adding the new device to its groups.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeviceLinkWelcomes {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub welcomes: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
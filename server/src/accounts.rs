@@ -1,8 +1,9 @@
-use std::sync::LazyLock;
+use std::{sync::LazyLock, time::SystemTime};
 
 use lib::{
+    api::messages::{AuthRequest, Message, ServiceError, ServiceErrorDetails, ServiceResult},
     crypto::{
-        certificates::{CertificateChain, SerializedChain},
+        certificates::{rotation::AccountKeyRotation, SerializedChain},
         usernames::UsernameHash,
     },
     identifiers::{AccountId, LicksIdentifier},
@@ -65,12 +66,24 @@ impl AccountService {
 
     /// Returns `true` if `chain` is one that is valid and registered to the server.
     /// This is `O(n)` with `n` the number of devices linked to the user.
+    ///
+    /// Expired chains are never valid. A renewed device certificate (same
+    /// account, device and device key) is accepted in place of the registered
+    /// one, but the account and device keys must be the registered ones: chains
+    /// carrying a key that was rotated away are rejected.
     pub fn is_chain_valid(chain: &SerializedChain) -> Result<bool, Error> {
+        if !chain.validity().contains(SystemTime::now()) {
+            return Ok(false);
+        }
+
         let account_id = chain.account_id();
         if let Ok(Some(account_info)) = Self::get_account_info(account_id) {
             let db_chains = account_info.certificates;
             for db_chain in db_chains {
-                if db_chain.eq(chain) {
+                if db_chain.eq(chain)
+                    && db_chain.account_pub_key_bytes() == chain.account_pub_key_bytes()
+                    && db_chain.pub_key_bytes() == chain.pub_key_bytes()
+                {
                     return Ok(true);
                 }
             }
@@ -78,9 +91,425 @@ impl AccountService {
         Ok(false)
     }
 
+    /// Returns the registered chains of every device of `verified_account_id`.
+    pub fn get_device_chains(verified_account_id: &AccountId) -> ServiceResult {
+        let Some(account_info) =
+            Self::get_account_info(verified_account_id).map_err(|_| ServiceError::InternalError)?
+        else {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::NotFound,
+                "This account isn't registered",
+            ));
+        };
+
+        Ok(Message::Auth(AuthRequest::HereAreDeviceChains(
+            account_info.certificates,
+        )))
+    }
+
+    /// Replaces the account certificate of `verified_account_id` by the one in
+    /// `rotation`, along with the chains of all its devices.
+    ///
+    /// The new account certificate must be cross-signed by the registered account
+    /// key, and `rotation` must contain a new chain for every registered device
+    /// whose chain hasn't expired. Expired chains can't authenticate anymore, so
+    /// they are dropped.
+    pub fn rotate_account_key(
+        verified_account_id: &AccountId,
+        rotation: AccountKeyRotation,
+    ) -> ServiceResult {
+        let Some(mut account_info) =
+            Self::get_account_info(verified_account_id).map_err(|_| ServiceError::InternalError)?
+        else {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::NotFound,
+                "This account isn't registered",
+            ));
+        };
+
+        // All the registered chains share the same account certificate
+        let previous_chain = account_info
+            .certificates
+            .first()
+            .ok_or(ServiceError::InternalError)?
+            .clone();
+
+        match rotation.verify(&previous_chain) {
+            Ok(account_id) if &account_id == verified_account_id => (),
+            _ => {
+                return Err(ServiceErrorDetails::new(
+                    ServiceError::InvalidCredentials,
+                    "The new account certificate isn't a valid successor of the registered one",
+                ));
+            }
+        }
+
+        let now = SystemTime::now();
+        account_info
+            .certificates
+            .retain(|registered_chain| registered_chain.validity().contains(now));
+
+        let every_device_renewed = account_info.certificates.iter().all(|registered_chain| {
+            rotation
+                .new_chains
+                .iter()
+                .any(|new_chain| new_chain == registered_chain)
+        });
+
+        if rotation.new_chains.len() != account_info.certificates.len() || !every_device_renewed {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidRequest,
+                "The rotation must contain exactly one new chain per registered device",
+            )
+            .with_field("new_chains"));
+        }
+
+        account_info.certificates = rotation.new_chains;
+
+        let serialized_account_info =
+            serialize_bytes(account_info).map_err(|_| ServiceError::InternalError)?;
+        REGISTERED_ACCOUNTS
+            .insert(verified_account_id.to_bytes(), serialized_account_info)
+            .map_err(|_| ServiceError::InternalError)?;
+
+        Ok(Message::Ok)
+    }
+
+    /// Replaces the registered chain of one of `verified_account_id`'s devices
+    /// by `chain`, e.g. after the device rotated its key. The chain must be
+    /// valid, signed by the registered account key, and its device certificate
+    /// must have a validity period (see [`Self::add_new_device`]).
+    pub fn update_device_chain(
+        verified_account_id: &AccountId,
+        chain: SerializedChain,
+    ) -> ServiceResult {
+        if chain.account_id() != verified_account_id || chain.clone().verify().is_err() {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidCredentials,
+                "The chain is invalid or belongs to another account",
+            ));
+        }
+
+        if chain.device_validity().is_legacy() {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidCredentials,
+                "New device certificates must have a validity period",
+            ));
+        }
+
+        let Some(mut account_info) =
+            Self::get_account_info(verified_account_id).map_err(|_| ServiceError::InternalError)?
+        else {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::NotFound,
+                "This account isn't registered",
+            ));
+        };
+
+        let Some(registered_chain) = account_info.certificates.iter_mut().find(|db_chain| {
+            **db_chain == chain && db_chain.account_pub_key_bytes() == chain.account_pub_key_bytes()
+        }) else {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidRequest,
+                "This device isn't registered with the current account key",
+            ));
+        };
+
+        *registered_chain = chain;
+
+        let serialized_account_info =
+            serialize_bytes(account_info).map_err(|_| ServiceError::InternalError)?;
+        REGISTERED_ACCOUNTS
+            .insert(verified_account_id.to_bytes(), serialized_account_info)
+            .map_err(|_| ServiceError::InternalError)?;
+
+        Ok(Message::Ok)
+    }
+
+    /// Registers the chain of a new device of an existing account. The chain must be
+    /// valid and signed by the registered account key, which only the devices of the
+    /// account know.
+    ///
+    /// The device certificate must have a validity period: legacy certificates
+    /// (see [`lib::crypto::certificates::Validity::LEGACY`]) are only accepted
+    /// in chains that were registered before they had one.
+    ///
     /// This only works for registered accounts. If you want to register the certificate
     /// for a brand new account, then use [`Self::register_account`]
-    pub fn add_new_device(_device_certificate_chain: &impl CertificateChain) -> Result<(), Error> {
-        todo!("Only accept chains signed by another valid account chain")
+    pub fn add_new_device(chain: SerializedChain) -> ServiceResult {
+        if chain.clone().verify().is_err() {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidCredentials,
+                "The chain is invalid",
+            ));
+        }
+
+        if chain.device_validity().is_legacy() {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidCredentials,
+                "New device certificates must have a validity period",
+            ));
+        }
+
+        let account_id = *chain.account_id();
+        let Some(mut account_info) =
+            Self::get_account_info(&account_id).map_err(|_| ServiceError::InternalError)?
+        else {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::NotFound,
+                "This account isn't registered",
+            ));
+        };
+
+        // All the registered chains share the same account certificate
+        let registered_chain = account_info
+            .certificates
+            .first()
+            .ok_or(ServiceError::InternalError)?;
+
+        if registered_chain.account_pub_key_bytes() != chain.account_pub_key_bytes() {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidCredentials,
+                "The chain isn't signed by the current account key",
+            ));
+        }
+
+        if account_info.certificates.contains(&chain) {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidRequest,
+                "This device is already registered",
+            ));
+        }
+
+        account_info.certificates.push(chain);
+
+        let serialized_account_info =
+            serialize_bytes(account_info).map_err(|_| ServiceError::InternalError)?;
+        REGISTERED_ACCOUNTS
+            .insert(account_id.to_bytes(), serialized_account_info)
+            .map_err(|_| ServiceError::InternalError)?;
+
+        Ok(Message::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::{
+        api::server::Server,
+        crypto::{
+            certificates::{
+                ed25519::{Ed25519AccountCert, Ed25519CertificateChainSecret, Ed25519DeviceCert},
+                CertificateChain, CertificateChainSecret,
+            },
+            usernames::Username,
+        },
+        identifiers::DeviceId,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_update_device_chain() {
+        let account_id = AccountId::generate_id();
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), account_id);
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+
+        AccountService::register_account(
+            chain_secret.serialized(),
+            Username::new("device_rotation_test".to_string())
+                .expect("username is valid")
+                .hash(),
+        )
+        .expect("registration works");
+
+        let mut rotated_secret = chain_secret.rotate_device_key();
+
+        assert!(
+            AccountService::update_device_chain(
+                &AccountId::generate_id(),
+                rotated_secret.serialized()
+            )
+            .is_err(),
+            "Accounts can't update the chains of another account"
+        );
+        assert_eq!(
+            AccountService::update_device_chain(&account_id, rotated_secret.serialized()),
+            Ok(Message::Ok),
+            "A device should be able to register its new device key"
+        );
+        assert_eq!(
+            AccountService::is_chain_valid(&rotated_secret.serialized()).ok(),
+            Some(true),
+            "The chain with the new device key should authenticate"
+        );
+        assert_eq!(
+            AccountService::is_chain_valid(&chain_secret.serialized()).ok(),
+            Some(false),
+            "The chain with the replaced device key shouldn't authenticate anymore"
+        );
+        rotated_secret.renew_device_cert();
+        assert_eq!(
+            AccountService::is_chain_valid(&rotated_secret.serialized()).ok(),
+            Some(true),
+            "A renewed certificate for the registered device key should authenticate"
+        );
+
+        // Chains signed by another account key are rejected
+        let (other_secret, _) = chain_secret.rotate_account_key(&[]);
+        assert!(
+            AccountService::update_device_chain(&account_id, other_secret.serialized()).is_err(),
+            "Chains must be signed by the registered account key"
+        );
+    }
+
+    #[test]
+    fn test_add_new_device() {
+        let account_id = AccountId::generate_id();
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), account_id);
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+
+        let (new_device_cert, _) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let new_device_chain = chain_secret.sign_device_cert(new_device_cert).serialize();
+        assert!(
+            AccountService::add_new_device(new_device_chain.clone()).is_err(),
+            "Devices can't be added to an account that isn't registered"
+        );
+
+        AccountService::register_account(
+            chain_secret.serialized(),
+            Username::new("device_link_test".to_string())
+                .expect("username is valid")
+                .hash(),
+        )
+        .expect("registration works");
+
+        assert_eq!(
+            AccountService::add_new_device(new_device_chain.clone()),
+            Ok(Message::Ok),
+            "A device signed by the account key should be added"
+        );
+        assert_eq!(
+            AccountService::is_chain_valid(&new_device_chain).ok(),
+            Some(true),
+            "The new device should be able to authenticate"
+        );
+        assert!(
+            AccountService::add_new_device(new_device_chain).is_err(),
+            "A device can't be registered twice"
+        );
+
+        let (other_account_secret, _) = chain_secret.rotate_account_key(&[]);
+        let (other_device_cert, _) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        assert!(
+            AccountService::add_new_device(
+                other_account_secret
+                    .sign_device_cert(other_device_cert)
+                    .serialize()
+            )
+            .is_err(),
+            "Devices must be signed by the registered account key"
+        );
+    }
+
+    #[test]
+    fn test_account_key_rotation() {
+        let account_id = AccountId::generate_id();
+        let (account_cert, account_secret) =
+            Ed25519AccountCert::generate(Server::localhost(), account_id);
+        let (device_cert, device_secret) = Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let chain_secret = Ed25519CertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+
+        AccountService::register_account(
+            chain_secret.serialized(),
+            Username::new("rotation_test".to_string())
+                .expect("username is valid")
+                .hash(),
+        )
+        .expect("registration works");
+
+        let (other_device_cert, other_device_key) =
+            Ed25519DeviceCert::generate(DeviceId::generate_id());
+        let other_device_secret = Ed25519CertificateChainSecret::from_linked_chain(
+            chain_secret.sign_device_cert(other_device_cert),
+            other_device_key,
+            &chain_secret.share_account_key(),
+        )
+        .expect("The other device can be linked");
+        AccountService::add_new_device(other_device_secret.serialized())
+            .expect("adding a device works");
+
+        let (_, incomplete_rotation) = chain_secret.rotate_account_key(&[]);
+        assert!(
+            AccountService::rotate_account_key(&account_id, incomplete_rotation).is_err(),
+            "A rotation must renew every registered device"
+        );
+
+        let Ok(Message::Auth(AuthRequest::HereAreDeviceChains(registered_chains))) =
+            AccountService::get_device_chains(&account_id)
+        else {
+            panic!("The registered chains should be returned");
+        };
+        assert_eq!(
+            registered_chains.len(),
+            2,
+            "Both devices should be registered"
+        );
+
+        let (rotated_secret, rotation) = chain_secret.rotate_account_key(&registered_chains);
+
+        assert!(
+            AccountService::rotate_account_key(&AccountId::generate_id(), rotation.clone())
+                .is_err(),
+            "Accounts can't rotate the key of another account"
+        );
+        assert_eq!(
+            AccountService::rotate_account_key(&account_id, rotation.clone()),
+            Ok(Message::Ok),
+            "A cross-signed rotation should be accepted"
+        );
+
+        assert_eq!(
+            AccountService::is_chain_valid(&rotated_secret.serialized()).ok(),
+            Some(true),
+            "Chains signed by the new account key should be valid"
+        );
+        assert_eq!(
+            AccountService::is_chain_valid(&chain_secret.serialized()).ok(),
+            Some(false),
+            "Chains signed by the previous account key should no longer be valid"
+        );
+        let other_rotated_secret = other_device_secret
+            .with_shared_account_key(&rotated_secret.share_account_key())
+            .expect("The other device adopts the new account key");
+        assert_eq!(
+            AccountService::is_chain_valid(&other_rotated_secret.serialized()).ok(),
+            Some(true),
+            "The other device's chain should be renewed too"
+        );
+
+        assert!(
+            AccountService::rotate_account_key(&account_id, rotation).is_err(),
+            "A rotation can't be replayed once the previous key was replaced"
+        );
     }
 }
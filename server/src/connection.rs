@@ -1,22 +1,26 @@
 //! Useful traits and types for handling connections and the API
 
 use crate::{
+    accounts::AccountService,
     error::Error,
     services::{
-        chat::ChatService, key_packages::KeyPackageService, register::RegistrationService,
+        chat::ChatService,
+        group_links::{GroupLinkQuota, GroupLinkService},
+        key_packages::KeyPackageService,
+        register::RegistrationService,
         usernames::UsernameService,
     },
 };
 use lib::{
     api::messages::{
         AuthRequest, ClientRequestId, Message, MessageWire, ServiceError as SocketError,
-        ServiceMessage, ServiceResult, UnauthRequest,
+        ServiceErrorDetails, ServiceMessage, ServiceResult, UnauthRequest,
     },
     crypto::certificates::SerializedChain,
     identifiers::AccountId,
 };
 use std::{future::Future, sync::Arc};
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, task::JoinHandle};
 use tracing::{debug_span, instrument, Span};
 
 /// Each socket waits for requests (in the form of `MessageWire`)
@@ -46,8 +50,16 @@ impl Request {
         }
     }
 
+    /// Spawns a task handling `message`. The returned handle can be used
+    /// to abort the task if the client cancels the request.
+    ///
+    /// `group_link_quota` is shared by all the requests of the connection.
     #[instrument(skip_all)]
-    pub fn handle(mut self, message: Message) {
+    pub fn handle(
+        mut self,
+        group_link_quota: Arc<GroupLinkQuota>,
+        message: Message,
+    ) -> JoinHandle<()> {
         tokio::task::spawn(async move {
             // Since we don't have an handle for this function, we don't care about its return value.
             // Panicking is therefore completely fine here. The use of `expect` allows us to add more
@@ -55,9 +67,13 @@ impl Request {
             // This task will panic is a user abruptly closes the connection before a request gets finished.
             match message {
                 Message::Unauth(as_msg) => {
-                    UnauthenticatedChannelService::handle_request(&mut self, as_msg)
-                        .await
-                        .expect("Couldn't handle unauthenticated channel service request");
+                    UnauthenticatedChannelService::handle_request(
+                        &mut self,
+                        &group_link_quota,
+                        as_msg,
+                    )
+                    .await
+                    .expect("Couldn't handle unauthenticated channel service request");
                 }
                 Message::Ignore => {}
                 Message::Ping(b) => self
@@ -69,11 +85,16 @@ impl Request {
                     .await
                     .expect("User requested an invalid operation, but couldn't send error back"),
             }
-        });
+        })
     }
 
+    /// Same as [`Request::handle`], but for authenticated connections.
     #[instrument(skip_all)]
-    pub fn handle_authenticated(mut self, chain: Arc<SerializedChain>, message: Message) {
+    pub fn handle_authenticated(
+        mut self,
+        chain: Arc<SerializedChain>,
+        message: Message,
+    ) -> JoinHandle<()> {
         tokio::task::spawn(async move {
             match message {
                 Message::Auth(as_msg) => {
@@ -95,7 +116,7 @@ impl Request {
                     .await
                     .expect("User requested an invalid operation, but couldn't send error back"),
             }
-        });
+        })
     }
 }
 
@@ -107,11 +128,12 @@ pub trait RequestHandler: Clone + Send + 'static {
     /// Keep track of the tracing span to log things related to the request we're handling
     fn span(&self) -> &Span;
 
-    /// Send back an error to the user.
+    /// Send back an error to the user. Either a bare [`SocketError`] code,
+    /// or a [`ServiceErrorDetails`] if there's more to say about it.
     #[instrument(skip_all, parent = self.span())]
     #[inline]
-    async fn error(&mut self, err: SocketError) -> Result<(), Error> {
-        self.message(Message::Error(err)).await
+    async fn error(&mut self, err: impl Into<ServiceErrorDetails> + Send) -> Result<(), Error> {
+        self.message(Message::Error(err.into())).await
     }
 
     /// Send a socket message to the user.
@@ -189,9 +211,12 @@ pub trait AuthenticatedConnectionService<M: ServiceMessage> {
 #[derive(Default)]
 pub struct UnauthenticatedChannelService;
 
-impl ConnectionService<UnauthRequest> for UnauthenticatedChannelService {
-    async fn handle_request(
+impl UnauthenticatedChannelService {
+    /// Handle a request on an unauthenticated connection. Invite links it
+    /// uploads are counted against `group_link_quota`.
+    pub async fn handle_request(
         request: &mut impl RequestHandler,
+        group_link_quota: &GroupLinkQuota,
         msg: UnauthRequest,
     ) -> Result<(), Error> {
         match msg {
@@ -209,6 +234,29 @@ impl ConnectionService<UnauthRequest> for UnauthenticatedChannelService {
                     .map_service_result(UsernameService::find_account_id, username_hash)
                     .await
             }
+            UnauthRequest::PutGroupLink(proof) => {
+                request
+                    .map_service_result(
+                        |proof| GroupLinkService::put_group_link(group_link_quota, proof),
+                        proof,
+                    )
+                    .await
+            }
+            UnauthRequest::DeleteGroupLink(proof) => {
+                request
+                    .map_service_result(GroupLinkService::delete_group_link, proof)
+                    .await
+            }
+            UnauthRequest::GetGroupLink(link_id) => {
+                request
+                    .map_service_result(GroupLinkService::get_group_link, link_id)
+                    .await
+            }
+            UnauthRequest::RegisterDevice(chain) => {
+                request
+                    .map_service_result(AccountService::add_new_device, chain)
+                    .await
+            }
             _ => request.error(SocketError::InvalidOperation).await,
         }
     }
@@ -252,6 +300,33 @@ impl AuthenticatedConnectionService<AuthRequest> for AuthenticatedService {
                     )
                     .await
             }
+            AuthRequest::RotateAccountKey(rotation) => {
+                request
+                    .map_authenticated_service_result(
+                        AccountService::rotate_account_key,
+                        rotation,
+                        verified_account_id,
+                    )
+                    .await
+            }
+            AuthRequest::UpdateDeviceChain(chain) => {
+                request
+                    .map_authenticated_service_result(
+                        AccountService::update_device_chain,
+                        chain,
+                        verified_account_id,
+                    )
+                    .await
+            }
+            AuthRequest::GetDeviceChains => {
+                request
+                    .map_authenticated_service_result(
+                        |account_id, ()| AccountService::get_device_chains(account_id),
+                        (),
+                        verified_account_id,
+                    )
+                    .await
+            }
             _ => request.error(SocketError::InvalidOperation).await,
         }
     }
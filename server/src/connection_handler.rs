@@ -13,18 +13,111 @@
 //! [`handle_authenticated_connection`] first prompts the user to respond to a challenge
 //! (to authenticate them), then also redirects to [`handle_connection_socket`] but with
 //! an authenticated request handler.
+//!
+//! Every request is handled in its own task. The client can abort one of them by
+//! sending [`Message::Cancel`] with the request's [`ClientRequestId`].
+//!
+//! A connection can't have more than [`MAX_CONNECTION_REQUESTS`] requests being
+//! handled at once, and the whole server no more than [`MAX_SERVER_REQUESTS`].
+//! Requests over these limits are refused with [`ServiceError::RateLimited`] and
+//! [`ServiceError::Overloaded`] respectively. Unauthenticated connections can
+//! also only upload so many invite links, see [`GroupLinkQuota`].
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use futures_util::{SinkExt, Stream, StreamExt};
 use lib::{
-    api::messages::{Message, MessageWire, MAX_CONNECTION_TIMEOUT_SECS},
+    api::messages::{
+        ClientRequestId, Message, MessageWire, ServiceError, ServiceErrorDetails,
+        MAX_CONNECTION_TIMEOUT_SECS,
+    },
     crypto::challenge::AuthChallenge,
 };
-use tokio::{sync::mpsc, time::timeout};
+use tokio::{sync::mpsc, task::JoinHandle, time::timeout};
 use tracing::{event, Level};
 
-use crate::{accounts::AccountService, connection::Request};
+use crate::{accounts::AccountService, connection::Request, services::group_links::GroupLinkQuota};
+
+/// How many requests a single connection can have handled at the same time.
+pub const MAX_CONNECTION_REQUESTS: usize = 64;
+
+/// How many requests can be handled at the same time across all connections.
+pub const MAX_SERVER_REQUESTS: usize = 16_384;
+
+/// How long clients are told to wait before retrying a refused request.
+pub const REQUEST_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Requests currently handled by all the connections, see [`RequestTasks`].
+static SERVER_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The requests a connection is handling, so that the client can cancel them.
+/// They are counted in [`SERVER_REQUESTS`], and aborted when the connection
+/// closes.
+#[derive(Default)]
+struct RequestTasks(HashMap<ClientRequestId, JoinHandle<()>>);
+
+impl RequestTasks {
+    /// Refuses a new request if it would go over one of the limits.
+    fn check_limits(&self) -> Result<(), ServiceErrorDetails> {
+        if self.0.len() >= MAX_CONNECTION_REQUESTS {
+            Err(ServiceErrorDetails::new(
+                ServiceError::RateLimited,
+                "Too many requests are being handled for this connection",
+            )
+            .with_retry_after(REQUEST_RETRY_AFTER)
+            .with_quota(MAX_CONNECTION_REQUESTS as u64))
+        } else if SERVER_REQUESTS.load(Ordering::Relaxed) >= MAX_SERVER_REQUESTS {
+            Err(ServiceErrorDetails::new(
+                ServiceError::Overloaded,
+                "The server is handling too many requests",
+            )
+            .with_retry_after(REQUEST_RETRY_AFTER))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn insert(&mut self, request_id: ClientRequestId, task: JoinHandle<()>) {
+        // If the client reused a request id, the previous task is left running
+        // but can't be cancelled anymore, and only the new one is counted
+        if self.0.insert(request_id, task).is_none() {
+            SERVER_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn remove(&mut self, request_id: &ClientRequestId) -> Option<JoinHandle<()>> {
+        let task = self.0.remove(request_id);
+        if task.is_some() {
+            SERVER_REQUESTS.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        task
+    }
+
+    /// Forgets the requests that were fully handled.
+    fn remove_finished(&mut self) {
+        let count = self.0.len();
+        self.0.retain(|_, task| !task.is_finished());
+        SERVER_REQUESTS.fetch_sub(count - self.0.len(), Ordering::Relaxed);
+    }
+}
+
+impl Drop for RequestTasks {
+    fn drop(&mut self) {
+        // nobody is going to receive the answers anymore
+        for task in self.0.values() {
+            task.abort();
+        }
+        SERVER_REQUESTS.fetch_sub(self.0.len(), Ordering::Relaxed);
+    }
+}
 
 pub async fn handle_unauthenticated_connection<
     Socket: Stream<Item = Result<MessageWire, ()>> + SinkExt<MessageWire> + Send + 'static,
@@ -33,17 +126,21 @@ pub async fn handle_unauthenticated_connection<
 ) where
     <Socket as futures_util::Sink<MessageWire>>::Error: std::marker::Send,
 {
-    let req_handler = |req: Request, msg: Message| {
-        Request::handle(req, msg);
-    };
+    // the invite links uploaded by this connection
+    let group_link_quota = Arc::new(GroupLinkQuota::default());
+    let req_handler =
+        move |req: Request, msg: Message| Request::handle(req, group_link_quota.clone(), msg);
 
     handle_connection_socket(socket, req_handler).await;
 }
 
+/// `handshake_hash` is the hash of the socket's Noise handshake, which the client's
+/// response to the authentication challenge must be bound to.
 pub async fn handle_authenticated_connection<
     Socket: Stream<Item = Result<MessageWire, ()>> + SinkExt<MessageWire> + Send + Unpin + 'static,
 >(
     mut socket: Socket,
+    handshake_hash: Vec<u8>,
 ) where
     <Socket as futures_util::Sink<MessageWire>>::Error: std::marker::Send,
 {
@@ -74,7 +171,9 @@ pub async fn handle_authenticated_connection<
         if let Some(Ok(MessageWire(req_id, Message::ChallengeResponse(challenge_response)))) =
             socket.next().await
         {
-            let Ok(verified_chain) = challenge_response.verify(our_challenge_bytes) else {
+            let Ok(verified_chain) =
+                challenge_response.verify(our_challenge_bytes, &handshake_hash)
+            else {
                 // Chain self signatures failed.
                 event!(Level::DEBUG, "The client's certificate chain was invalid.");
                 let _ = socket.close().await;
@@ -103,7 +202,7 @@ pub async fn handle_authenticated_connection<
     .await
     {
         let req_handler = move |req: Request, msg: Message| {
-            Request::handle_authenticated(req, chain.clone(), msg);
+            Request::handle_authenticated(req, chain.clone(), msg)
         };
 
         handle_connection_socket(socket, req_handler).await;
@@ -120,11 +219,14 @@ pub async fn handle_authenticated_connection<
 /// That function is what will handle the request.
 /// For unauthenticated requests ([`handle_unauthenticated_connection`]), that's [`Request::handle`].
 /// For authenticated requests ([`handle_authenticated_connection`]), that's [`Request::handle_authenticated`].
+///
+/// The function returns the handle of the task handling the request, which gets aborted
+/// if the client cancels the request or if the connection closes.
 pub async fn handle_connection_socket<
     Socket: Stream<Item = Result<MessageWire, ()>> + SinkExt<MessageWire> + Send + 'static,
 >(
     socket: Socket,
-    req_handler: impl Fn(Request, Message) + Send + 'static,
+    req_handler: impl Fn(Request, Message) -> JoinHandle<()> + Send + 'static,
 ) where
     <Socket as futures_util::Sink<MessageWire>>::Error: std::marker::Send,
 {
@@ -135,11 +237,30 @@ pub async fn handle_connection_socket<
     // create an mpsc receiver. the senders will be cloned and sent to each request the user is making.
     // the receiver will just loop and send back whatever to the socket
     let (req_sender, mut req_receiver) = mpsc::unbounded_channel::<MessageWire>();
+
+    // the requests currently being handled, so that the client can cancel them
+    let mut tasks = RequestTasks::default();
+
     loop {
         tokio::select! {
             // client requested something, we handle it
             Some(Ok(msg)) = receiver.next() => {
-                req_handler(Request::make(req_sender.clone(), msg.0, &span), msg.1);
+                if let Message::Cancel(request_id) = msg.1 {
+                    // client wants us to stop working on a request
+                    if let Some(task) = tasks.remove(&request_id) {
+                        event!(Level::DEBUG, "Request {request_id} was cancelled by the user");
+                        task.abort();
+                    }
+                } else {
+                    tasks.remove_finished();
+                    if let Err(err) = tasks.check_limits() {
+                        event!(Level::DEBUG, "Request {} was refused: {err}", msg.0);
+                        let _ = req_sender.send(MessageWire(msg.0, Message::Error(err)));
+                    } else {
+                        let task = req_handler(Request::make(req_sender.clone(), msg.0, &span), msg.1);
+                        tasks.insert(msg.0, task);
+                    }
+                }
             },
             // we finished handling a request. we try to
             // send it back to the client
@@ -164,3 +285,162 @@ pub async fn handle_connection_socket<
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures_util::Sink;
+
+    use crate::connection::RequestHandler;
+
+    use super::*;
+
+    /// A socket whose other end is held by the test.
+    struct TestSocket {
+        incoming: mpsc::UnboundedReceiver<MessageWire>,
+        outgoing: mpsc::UnboundedSender<MessageWire>,
+    }
+
+    impl TestSocket {
+        /// Returns the socket, a sender of requests to it, and a receiver
+        /// of its responses.
+        fn make() -> (
+            Self,
+            mpsc::UnboundedSender<MessageWire>,
+            mpsc::UnboundedReceiver<MessageWire>,
+        ) {
+            let (requests, incoming) = mpsc::unbounded_channel();
+            let (outgoing, responses) = mpsc::unbounded_channel();
+
+            (Self { incoming, outgoing }, requests, responses)
+        }
+    }
+
+    impl Stream for TestSocket {
+        type Item = Result<MessageWire, ()>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.incoming.poll_recv(cx).map(|msg| msg.map(Ok))
+        }
+    }
+
+    impl Sink<MessageWire> for TestSocket {
+        type Error = ();
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, item: MessageWire) -> Result<(), ()> {
+            self.outgoing.send(item).map_err(|_| ())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Tells the test that the task handling a request was dropped,
+    /// whether it finished or was aborted.
+    struct DropGuard(mpsc::UnboundedSender<&'static str>);
+
+    impl Drop for DropGuard {
+        fn drop(&mut self) {
+            let _ = self.0.send("dropped");
+        }
+    }
+
+    #[tokio::test]
+    async fn cancelled_requests_are_aborted() {
+        let (socket, requests, mut responses) = TestSocket::make();
+        let (events_sender, mut events) = mpsc::unbounded_channel();
+
+        // Requests are answered after a while, unless they are cancelled
+        tokio::spawn(handle_connection_socket(socket, move |mut request, _| {
+            let guard = DropGuard(events_sender.clone());
+            tokio::spawn(async move {
+                let _ = guard.0.send("started");
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                let _ = guard.0.send("finished");
+                let _ = request.message(Message::Ok).await;
+            })
+        }));
+
+        let request_id = ClientRequestId::generate();
+        requests
+            .send(MessageWire(request_id, Message::Ignore))
+            .expect("connection is open");
+        assert_eq!(
+            events.recv().await,
+            Some("started"),
+            "The request should be handled"
+        );
+
+        requests
+            .send(MessageWire(
+                ClientRequestId::generate(),
+                Message::Cancel(request_id),
+            ))
+            .expect("connection is open");
+        assert_eq!(
+            events.recv().await,
+            Some("dropped"),
+            "The request should be aborted before it finishes"
+        );
+
+        assert!(
+            timeout(Duration::from_secs(1), responses.recv())
+                .await
+                .is_err(),
+            "A cancelled request should not be answered"
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_limit_are_refused() {
+        let (socket, requests, mut responses) = TestSocket::make();
+
+        // Requests are never answered
+        tokio::spawn(handle_connection_socket(socket, |_, _| {
+            tokio::spawn(std::future::pending::<()>())
+        }));
+
+        for _ in 0..MAX_CONNECTION_REQUESTS {
+            requests
+                .send(MessageWire(ClientRequestId::generate(), Message::Ignore))
+                .expect("connection is open");
+        }
+        let refused_id = ClientRequestId::generate();
+        requests
+            .send(MessageWire(refused_id, Message::Ignore))
+            .expect("connection is open");
+
+        match responses.recv().await.expect("connection is open") {
+            MessageWire(request_id, Message::Error(err)) => {
+                assert_eq!(
+                    request_id, refused_id,
+                    "Only the request over the limit should be refused"
+                );
+                assert_eq!(
+                    err.code,
+                    ServiceError::RateLimited,
+                    "The connection made too many requests"
+                );
+                assert_eq!(
+                    err.quota,
+                    Some(MAX_CONNECTION_REQUESTS as u64),
+                    "The error should tell the client about the limit"
+                );
+            }
+            other => panic!("Unexpected response, got {other:?}"),
+        }
+    }
+}
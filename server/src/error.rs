@@ -1,4 +1,4 @@
-use lib::api::messages::ServiceError;
+use lib::api::messages::{ServiceError, ServiceErrorDetails};
 use sled::transaction::TransactionError;
 
 use crate::{authenticator::AuthenticationError, services::register::RegistrationError};
@@ -31,10 +31,11 @@ impl From<TransactionError<()>> for Error {
     }
 }
 
-impl From<Error> for ServiceError {
+impl From<Error> for ServiceErrorDetails {
     fn from(value: Error) -> Self {
         tracing::error!("Internal server error while processing WS request: {value}");
-        ServiceError::InternalError
+        // The internal error is only logged, clients don't need to know what went wrong.
+        ServiceError::InternalError.into()
     }
 }
 
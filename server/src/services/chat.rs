@@ -171,20 +171,25 @@ impl ConnectionService<ChatServiceMessage> for ChatService {
 }
 
 impl ChatService {
+    /// Stores a message in the queue of its blinded address and broadcasts it
+    /// to the listeners of the address. Ephemeral messages are only broadcast,
+    /// so they are lost for clients that aren't listening right now.
     pub fn send_message(request: SendMessageRequest) -> ServiceResult {
         let (verified_blinded_address, verified_message) =
             verify_blinded_address(request.blinded_address_proof)
                 .map_err(|_| ServiceError::InvalidCredentials)?;
 
-        let queue_tree = Self::open_message_queue(&verified_blinded_address)
-            .map_err(|_| ServiceError::InternalError)?;
-
         // DeliveryId is guaranteed to generate a unique database key
         let delivery_stamp = DeliveryStamp::generate();
 
-        queue_tree
-            .insert(delivery_stamp.as_bytes(), verified_message.clone())
-            .map_err(Error::from)?;
+        if !request.ephemeral {
+            let queue_tree = Self::open_message_queue(&verified_blinded_address)
+                .map_err(|_| ServiceError::InternalError)?;
+
+            queue_tree
+                .insert(delivery_stamp.as_bytes(), verified_message.clone())
+                .map_err(Error::from)?;
+        }
 
         // Broadcast message to all the listeners
         tokio::spawn(async move {
@@ -226,7 +231,9 @@ mod tests {
             group::GetMessagesRequest,
             messages::{ClientRequestId, MessageWire},
         },
-        crypto::{blinded_address::BlindedAddressSecret, rng::random_bytes},
+        crypto::{
+            blinded_address::BlindedAddressSecret, listener::ListenerToken, rng::random_bytes,
+        },
     };
     use tokio::sync::mpsc;
     use tracing::Span;
@@ -259,9 +266,10 @@ mod tests {
         // Send fake message fails
         assert_eq!(
             ChatService::send_message(SendMessageRequest {
-                blinded_address_proof: invalid_blinded_proof
+                blinded_address_proof: invalid_blinded_proof,
+                ephemeral: false,
             }),
-            Err(ServiceError::InvalidCredentials),
+            Err(ServiceError::InvalidCredentials.into()),
             "Sending a message with an invalid blinded address should not work"
         );
 
@@ -278,7 +286,8 @@ mod tests {
         assert!(
             matches!(
                 ChatService::send_message(SendMessageRequest {
-                    blinded_address_proof: valid_proof(a.clone())
+                    blinded_address_proof: valid_proof(a.clone()),
+                    ephemeral: false,
                 }),
                 Ok(Message::Unauth(UnauthRequest::ChatService(
                     ChatServiceMessage::Delivered(_)
@@ -295,7 +304,8 @@ mod tests {
         assert!(
             matches!(
                 ChatService::send_message(SendMessageRequest {
-                    blinded_address_proof: valid_proof(b.clone())
+                    blinded_address_proof: valid_proof(b.clone()),
+                    ephemeral: false,
                 }),
                 Ok(Message::Unauth(UnauthRequest::ChatService(
                     ChatServiceMessage::Delivered(_)
@@ -312,7 +322,8 @@ mod tests {
         assert!(
             matches!(
                 ChatService::send_message(SendMessageRequest {
-                    blinded_address_proof: valid_proof(c.clone())
+                    blinded_address_proof: valid_proof(c.clone()),
+                    ephemeral: false,
                 }),
                 Ok(Message::Unauth(UnauthRequest::ChatService(
                     ChatServiceMessage::Delivered(_)
@@ -452,4 +463,57 @@ mod tests {
             }
         };
     }
+
+    #[tokio::test]
+    async fn ephemeral_messages_are_not_queued() {
+        let mut ba_secret = BlindedAddressSecret::from_group_secret(&random_bytes::<16>());
+        let ba_public = ba_secret.to_public();
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let request_handler = Request::make(sender, ClientRequestId::default(), &Span::none());
+
+        add_listener(
+            ba_public,
+            ListenerId::generate(),
+            ListenerToken::default().commitment(),
+            request_handler,
+        )
+        .await
+        .expect("listener is added");
+
+        let typing = vec![4, 2];
+        assert!(
+            matches!(
+                ChatService::send_message(SendMessageRequest {
+                    blinded_address_proof: ba_secret.create_proof(typing.clone()),
+                    ephemeral: true,
+                }),
+                Ok(Message::Unauth(UnauthRequest::ChatService(
+                    ChatServiceMessage::Delivered(_)
+                )))
+            ),
+            "Sending an ephemeral message should work"
+        );
+
+        match receiver.recv().await.expect("valid response") {
+            MessageWire(
+                _,
+                Message::Unauth(UnauthRequest::ChatService(ChatServiceMessage::MlsMessage(
+                    _,
+                    recv,
+                ))),
+            ) => {
+                assert_eq!(recv, typing, "Listeners should receive ephemeral messages");
+            }
+            other => {
+                panic!("Unexpected response, got {other:?}");
+            }
+        };
+
+        let tree = ChatService::open_message_queue(&ba_public).expect("tree opens");
+        assert!(
+            tree.is_empty(),
+            "Ephemeral messages should not be stored in the queue"
+        );
+    }
 }
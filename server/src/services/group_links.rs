@@ -0,0 +1,357 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    authenticator::verify_blinded_address,
+    db::{deserialize_bytes, serialize_bytes, DB},
+    error::Error,
+};
+use lib::{
+    api::messages::{Message, ServiceError, ServiceErrorDetails, ServiceResult, UnauthRequest},
+    crypto::blinded_address::{BlindedAddressProof, BlindedAddressPublic},
+};
+use serde::{Deserialize, Serialize};
+use sled::Tree;
+
+/// Invite link id -> [`GroupLinkEntry`].
+static GROUP_LINK_TREE: LazyLock<Tree> = LazyLock::new(|| {
+    DB.open_tree(b"grouplinks")
+        .expect("we expect sled to be able to open trees")
+});
+
+/// Upload time (big-endian Unix seconds, so that the oldest links come first)
+/// followed by the invite link id -> nothing. Used to find expired links.
+static GROUP_LINK_EXPIRY_TREE: LazyLock<Tree> = LazyLock::new(|| {
+    DB.open_tree(b"grouplinks/expiry")
+        .expect("we expect sled to be able to open trees")
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GroupLinkEntry {
+    uploaded_at: SystemTime,
+    content: Vec<u8>,
+}
+
+impl GroupLinkEntry {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        now.duration_since(self.uploaded_at)
+            .is_ok_and(|age| age >= GROUP_LINK_LIFETIME)
+    }
+}
+
+fn expiry_key(uploaded_at: SystemTime, link_id: &[u8]) -> Vec<u8> {
+    let mut key = unix_seconds(uploaded_at).to_be_bytes().to_vec();
+    key.extend_from_slice(link_id);
+    key
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The maximum size of the encrypted content of an invite link. A GroupInfo
+/// contains the whole ratchet tree, so this has to allow for big groups.
+pub const MAX_GROUP_LINK_SIZE: usize = 1 << 20;
+
+/// How many bytes of invite links a connection can upload per
+/// [`GROUP_LINK_QUOTA_PERIOD`]. Uploads are unauthenticated, so this keeps a
+/// single connection from filling the database.
+pub const GROUP_LINK_QUOTA: usize = 4 * MAX_GROUP_LINK_SIZE;
+
+/// The period over which [`GROUP_LINK_QUOTA`] applies.
+pub const GROUP_LINK_QUOTA_PERIOD: Duration = Duration::from_secs(60);
+
+/// Invite links that weren't uploaded again for this long expire, so that
+/// abandoned links don't fill the database. Their creator uploads them after
+/// every commit, and when it starts.
+pub const GROUP_LINK_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The invite links a connection uploaded during the current period, as the
+/// start of the period and the bytes uploaded since.
+#[derive(Default)]
+pub struct GroupLinkQuota(Mutex<Option<(Instant, usize)>>);
+
+impl GroupLinkQuota {
+    /// Counts `size` more bytes against the quota, unless it would go over it.
+    fn consume(&self, size: usize) -> Result<(), ServiceErrorDetails> {
+        let mut usage = self.0.lock().expect("no poison");
+        let now = Instant::now();
+
+        let (period_start, used) = match *usage {
+            Some((start, used)) if now.duration_since(start) < GROUP_LINK_QUOTA_PERIOD => {
+                (start, used)
+            }
+            _ => (now, 0),
+        };
+
+        if used + size > GROUP_LINK_QUOTA {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::RateLimited,
+                "Too many invite links were uploaded by this connection",
+            )
+            .with_retry_after(GROUP_LINK_QUOTA_PERIOD - now.duration_since(period_start))
+            .with_quota(GROUP_LINK_QUOTA as u64));
+        }
+
+        *usage = Some((period_start, used + size));
+
+        Ok(())
+    }
+}
+
+/// Stores the encrypted content of invite links. The server can't read it,
+/// and only the holder of the link's write key can change or delete it.
+///
+/// Uploads are limited per connection (see [`GroupLinkQuota`]), and links
+/// expire after [`GROUP_LINK_LIFETIME`], which bounds the storage they use.
+pub struct GroupLinkService;
+
+impl GroupLinkService {
+    pub fn put_group_link(quota: &GroupLinkQuota, proof: BlindedAddressProof) -> ServiceResult {
+        let (link_id, content) =
+            verify_blinded_address(proof).map_err(|_| ServiceError::InvalidCredentials)?;
+
+        if content.len() > MAX_GROUP_LINK_SIZE {
+            return Err(ServiceErrorDetails::new(
+                ServiceError::QuotaExceeded,
+                "The invite link content is too big",
+            ));
+        }
+
+        quota.consume(content.len())?;
+
+        let now = SystemTime::now();
+        Self::store_group_link(&link_id, content, now).map_err(|_| ServiceError::InternalError)?;
+        Self::remove_expired_group_links(now).map_err(|_| ServiceError::InternalError)?;
+
+        Ok(Message::Ok)
+    }
+
+    /// Stores the content of a link, replacing the previous one along with
+    /// its expiry.
+    fn store_group_link(
+        link_id: &BlindedAddressPublic,
+        content: Vec<u8>,
+        uploaded_at: SystemTime,
+    ) -> Result<(), Error> {
+        let entry = serialize_bytes(GroupLinkEntry {
+            uploaded_at,
+            content,
+        })?;
+
+        if let Some(previous) = GROUP_LINK_TREE.insert(link_id.0, entry)? {
+            let previous: GroupLinkEntry = deserialize_bytes(previous)?;
+            GROUP_LINK_EXPIRY_TREE.remove(expiry_key(previous.uploaded_at, &link_id.0))?;
+        }
+        GROUP_LINK_EXPIRY_TREE.insert(expiry_key(uploaded_at, &link_id.0), Vec::new())?;
+
+        Ok(())
+    }
+
+    /// Removes the links that expired at `now`.
+    fn remove_expired_group_links(now: SystemTime) -> Result<(), Error> {
+        let Some(cutoff) = now.checked_sub(GROUP_LINK_LIFETIME) else {
+            return Ok(());
+        };
+
+        for key in GROUP_LINK_EXPIRY_TREE.range(..unix_seconds(cutoff).to_be_bytes()) {
+            let (key, _) = key?;
+            let link_id = &key[8..];
+
+            // The link may have been uploaded again in the meantime
+            if let Some(entry) = GROUP_LINK_TREE.get(link_id)? {
+                let entry: GroupLinkEntry = deserialize_bytes(entry)?;
+                if entry.is_expired(now) {
+                    GROUP_LINK_TREE.remove(link_id)?;
+                }
+            }
+            GROUP_LINK_EXPIRY_TREE.remove(key)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_group_link(proof: BlindedAddressProof) -> ServiceResult {
+        let (link_id, _) =
+            verify_blinded_address(proof).map_err(|_| ServiceError::InvalidCredentials)?;
+
+        let Some(entry) = GROUP_LINK_TREE
+            .remove(link_id.0)
+            .map_err(|_| ServiceError::InternalError)?
+        else {
+            return Err(ServiceError::NotFound.into());
+        };
+
+        let entry: GroupLinkEntry =
+            deserialize_bytes(entry).map_err(|_| ServiceError::InternalError)?;
+        GROUP_LINK_EXPIRY_TREE
+            .remove(expiry_key(entry.uploaded_at, &link_id.0))
+            .map_err(|_| ServiceError::InternalError)?;
+
+        Ok(Message::Ok)
+    }
+
+    pub fn get_group_link(link_id: BlindedAddressPublic) -> ServiceResult {
+        let Some(entry) = GROUP_LINK_TREE
+            .get(link_id.0)
+            .map_err(|_| ServiceError::InternalError)?
+        else {
+            return Err(ServiceError::NotFound.into());
+        };
+
+        let entry: GroupLinkEntry =
+            deserialize_bytes(entry).map_err(|_| ServiceError::InternalError)?;
+        if entry.is_expired(SystemTime::now()) {
+            return Err(ServiceError::NotFound.into());
+        }
+
+        Ok(Message::Unauth(UnauthRequest::HereIsGroupLink(
+            entry.content,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::crypto::{blinded_address::BlindedAddressSecret, rng::random_bytes};
+
+    #[test]
+    fn test_group_links() {
+        let quota = GroupLinkQuota::default();
+        let mut write_key = BlindedAddressSecret::from_bytes(random_bytes::<32>());
+        let link_id = write_key.to_public();
+
+        assert_eq!(
+            GroupLinkService::get_group_link(link_id).map_err(|err| err.code),
+            Err(ServiceError::NotFound),
+            "A link that was never uploaded should not be found"
+        );
+
+        assert_eq!(
+            GroupLinkService::put_group_link(&quota, write_key.create_proof(b"first".to_vec())),
+            Ok(Message::Ok),
+            "The link should be uploaded"
+        );
+
+        assert_eq!(
+            GroupLinkService::put_group_link(&quota, write_key.create_proof(b"second".to_vec())),
+            Ok(Message::Ok),
+            "The link should be replaced"
+        );
+
+        assert_eq!(
+            GroupLinkService::get_group_link(link_id),
+            Ok(Message::Unauth(UnauthRequest::HereIsGroupLink(
+                b"second".to_vec()
+            ))),
+            "The latest content of the link should be returned"
+        );
+
+        let mut forged_proof = write_key.create_proof(b"forged".to_vec());
+        forged_proof.message = b"other".to_vec();
+        assert_eq!(
+            GroupLinkService::put_group_link(&quota, forged_proof).map_err(|err| err.code),
+            Err(ServiceError::InvalidCredentials),
+            "A link can't be changed without its write key"
+        );
+
+        assert_eq!(
+            GroupLinkService::delete_group_link(write_key.create_proof(Vec::new())),
+            Ok(Message::Ok),
+            "The link should be deleted"
+        );
+
+        assert_eq!(
+            GroupLinkService::get_group_link(link_id).map_err(|err| err.code),
+            Err(ServiceError::NotFound),
+            "A deleted link should not be found"
+        );
+    }
+
+    #[test]
+    fn group_link_uploads_are_rate_limited() {
+        let quota = GroupLinkQuota::default();
+        let mut write_key = BlindedAddressSecret::from_bytes(random_bytes::<32>());
+        let content = vec![0; MAX_GROUP_LINK_SIZE];
+
+        for _ in 0..GROUP_LINK_QUOTA / MAX_GROUP_LINK_SIZE {
+            assert_eq!(
+                GroupLinkService::put_group_link(&quota, write_key.create_proof(content.clone())),
+                Ok(Message::Ok),
+                "Uploads within the quota should be accepted"
+            );
+        }
+
+        let err = GroupLinkService::put_group_link(&quota, write_key.create_proof(content))
+            .expect_err("Uploads over the quota should be refused");
+        assert_eq!(
+            err.code,
+            ServiceError::RateLimited,
+            "The connection uploaded too much"
+        );
+        assert_eq!(
+            err.quota,
+            Some(GROUP_LINK_QUOTA as u64),
+            "The error should tell the client about the limit"
+        );
+
+        assert_eq!(
+            GroupLinkService::put_group_link(
+                &GroupLinkQuota::default(),
+                write_key.create_proof(Vec::new())
+            ),
+            Ok(Message::Ok),
+            "Other connections have their own quota"
+        );
+    }
+
+    #[test]
+    fn group_links_expire() {
+        let mut write_key = BlindedAddressSecret::from_bytes(random_bytes::<32>());
+        let link_id = write_key.to_public();
+        let now = SystemTime::now();
+
+        GroupLinkService::store_group_link(
+            &link_id,
+            b"old".to_vec(),
+            now - GROUP_LINK_LIFETIME - Duration::from_secs(1),
+        )
+        .expect("Storing the link works");
+        assert_eq!(
+            GroupLinkService::get_group_link(link_id).map_err(|err| err.code),
+            Err(ServiceError::NotFound),
+            "An expired link should not be found"
+        );
+
+        GroupLinkService::remove_expired_group_links(now).expect("Removing expired links works");
+        assert!(
+            GROUP_LINK_TREE
+                .get(link_id.0)
+                .expect("sled works")
+                .is_none(),
+            "An expired link should be removed"
+        );
+
+        assert_eq!(
+            GroupLinkService::put_group_link(
+                &GroupLinkQuota::default(),
+                write_key.create_proof(b"new".to_vec())
+            ),
+            Ok(Message::Ok),
+            "The link should be uploaded again"
+        );
+        GroupLinkService::remove_expired_group_links(now).expect("Removing expired links works");
+        assert_eq!(
+            GroupLinkService::get_group_link(link_id),
+            Ok(Message::Unauth(UnauthRequest::HereIsGroupLink(
+                b"new".to_vec()
+            ))),
+            "Uploading a link again should renew it"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub mod chat;
+pub mod group_links;
 pub mod key_packages;
 pub mod register;
 pub mod usernames;
@@ -2,7 +2,7 @@ use std::{sync::LazyLock, time::SystemTime};
 
 use lib::{
     api::{
-        messages::{Message, ServiceError, ServiceResult, UnauthRequest},
+        messages::{Message, ServiceError, ServiceErrorDetails, ServiceResult, UnauthRequest},
         registration::{self, Stage1Message, Stage3Message},
     },
     crypto::certificates::{Certificate, CertificateChain, SerializedAccountCertificate},
@@ -127,6 +127,13 @@ impl RegistrationService {
                     deserialize_bytes(unverified_account_entry)?;
                 let account_pub_key = unverified_account_entry.account_pub_key;
 
+                if account_certificate.validity().is_legacy() {
+                    return Err(ServiceErrorDetails::new(
+                        ServiceError::InvalidCredentials,
+                        "New account certificates must have a validity period",
+                    ));
+                }
+
                 if account_certificate.pub_key_bytes() == account_pub_key {
                     // Check 1 and 2 is OK
 
@@ -138,10 +145,16 @@ impl RegistrationService {
 
                     Ok(Message::Ok)
                 } else {
-                    Err(ServiceError::InvalidCredentials)
+                    Err(ServiceErrorDetails::new(
+                        ServiceError::InvalidCredentials,
+                        "The account certificate's public key doesn't match the one sent in stage 1",
+                    ))
                 }
             }
-            None => Err(ServiceError::InvalidRequest),
+            None => Err(ServiceErrorDetails::new(
+                ServiceError::NotFound,
+                "No pending registration for this AccountId, stage 1 must be completed first",
+            )),
         }
     }
 
@@ -171,6 +184,13 @@ impl RegistrationService {
                     .verify()
                     .map_err(|_| ServiceError::InvalidCredentials)?;
 
+                if chain.device_cert().validity().is_legacy() {
+                    return Err(ServiceErrorDetails::new(
+                        ServiceError::InvalidCredentials,
+                        "New device certificates must have a validity period",
+                    ));
+                }
+
                 if chain
                     .account_cert()
                     .to_bytes()
@@ -181,10 +201,16 @@ impl RegistrationService {
 
                     Ok(Message::Ok)
                 } else {
-                    Err(ServiceError::InvalidCredentials)
+                    Err(ServiceErrorDetails::new(
+                        ServiceError::InvalidCredentials,
+                        "The certificate chain doesn't contain the account certificate sent in stage 2",
+                    ))
                 }
             }
-            None => Err(ServiceError::InvalidOperation),
+            None => Err(ServiceErrorDetails::new(
+                ServiceError::InvalidOperation,
+                "No pending registration for this AccountId, stage 2 must be completed first",
+            )),
         }
     }
 }
@@ -196,6 +222,7 @@ mod tests {
         crypto::{
             certificates::{
                 ed25519::{Ed25519AccountCert, Ed25519CertificateChainSecret, Ed25519DeviceCert},
+                hybrid::{HybridAccountCert, HybridCertificateChainSecret, HybridDeviceCert},
                 CertificateChainSecret,
             },
             usernames::Username,
@@ -264,6 +291,55 @@ mod tests {
         assert_eq!(res, Message::Ok);
     }
 
+    #[test]
+    fn test_full_hybrid_registration() {
+        let (account_pub_key, account_secret) = HybridAccountCert::generate_keys();
+
+        // Stage 1
+        let res = RegistrationService::stage_1(account_pub_key.to_bytes()).expect("Stage 1 is ok");
+
+        let Message::Unauth(UnauthRequest::Registration(
+            registration::RegistrationService::Stage1(Stage1Message::HereIsYourAccountId(
+                account_id,
+            )),
+        )) = res
+        else {
+            panic!("Unexpected response from server")
+        };
+
+        // Stage 2
+        let account_cert = HybridAccountCert::complete(
+            account_pub_key,
+            &account_secret,
+            Server::localhost(),
+            account_id,
+        );
+
+        let res =
+            RegistrationService::stage_2(account_cert.clone().serialize()).expect("Stage 2 is ok");
+
+        assert_eq!(res, Message::Ok);
+
+        // Stage 3
+        let (device_cert, device_secret) = HybridDeviceCert::generate(DeviceId::generate_id());
+        let cert_chain_secret = HybridCertificateChainSecret::new(
+            account_cert,
+            account_secret,
+            device_cert,
+            device_secret,
+        );
+
+        let res = RegistrationService::stage_3(Stage3Message {
+            certificate: cert_chain_secret.serialized(),
+            username_hash: Username::new("test_hybrid".to_string())
+                .expect("username is valid")
+                .hash(),
+        })
+        .expect("Stage 3 is ok");
+
+        assert_eq!(res, Message::Ok);
+    }
+
     #[test]
     fn test_bad_registrations() {
         todo!("Rewrite bad registration test with newer certs");
@@ -2,7 +2,9 @@ use std::sync::LazyLock;
 
 use crate::db::DB;
 use lib::{
-    api::messages::{AuthRequest, Message, ServiceError, ServiceResult, UnauthRequest},
+    api::messages::{
+        AuthRequest, Message, ServiceError, ServiceErrorDetails, ServiceResult, UnauthRequest,
+    },
     crypto::usernames::UsernameHash,
     identifiers::{AccountId, LicksIdentifier},
 };
@@ -44,7 +46,11 @@ impl UsernameService {
                 Ok(Message::Auth(AuthRequest::UsernameIsAlreadyYours))
             } else {
                 // There's already a username and it's not ours
-                Ok(Message::Auth(AuthRequest::UsernameIsAlreadyTaken))
+                Err(ServiceErrorDetails::new(
+                    ServiceError::UsernameAlreadyTaken,
+                    "This username belongs to another account",
+                )
+                .with_field("username"))
             }
         } else {
             // No one has that username, go with it
@@ -63,7 +69,11 @@ impl UsernameService {
             .get(username)
             .map_err(|_| ServiceError::InternalError)?
         else {
-            return Err(ServiceError::InvalidRequest);
+            return Err(ServiceErrorDetails::new(
+                ServiceError::InvalidRequest,
+                "This username isn't set",
+            )
+            .with_field("username"));
         };
 
         // Can't remove other people's usernames, can we
@@ -74,7 +84,11 @@ impl UsernameService {
 
             Ok(Message::Ok)
         } else {
-            Err(ServiceError::InvalidCredentials)
+            Err(ServiceErrorDetails::new(
+                ServiceError::InvalidCredentials,
+                "This username belongs to another account",
+            )
+            .with_field("username"))
         }
     }
 }
@@ -112,13 +126,13 @@ mod tests {
             .expect("username is valid")
             .hash();
         assert_eq!(
-            UsernameService::remove_username(&bob_id, alice_username),
+            UsernameService::remove_username(&bob_id, alice_username).map_err(|err| err.code),
             Err(ServiceError::InvalidCredentials)
         );
 
         assert_eq!(
-            UsernameService::set_username(&bob_id, alice_username),
-            Ok(Message::Auth(AuthRequest::UsernameIsAlreadyTaken,))
+            UsernameService::set_username(&bob_id, alice_username).map_err(|err| err.code),
+            Err(ServiceError::UsernameAlreadyTaken)
         );
 
         assert_eq!(
@@ -3,7 +3,10 @@ use std::sync::{
     Arc,
 };
 
-use lib::{api::messages::MessageWire, crypto::noise::ServerHandshake};
+use lib::{
+    api::messages::{Message, MessageWire, ServiceError, ServiceErrorDetails},
+    crypto::noise::{NoiseError, NoiseTransport, ServerHandshake, MAX_MESSAGE_SIZE},
+};
 use std::sync::Mutex;
 use tracing::{instrument, span, Instrument, Level};
 
@@ -18,6 +21,29 @@ use futures_util::{SinkExt, StreamExt};
 
 pub static ACTIVE_WS_CONNECTIONS_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+/// Encrypts a message into Noise frames. Messages that are too large to be sent
+/// are replaced by an error, so that the client isn't left waiting for them.
+fn encrypt_message(
+    transport: &mut NoiseTransport,
+    msg: MessageWire,
+) -> Result<Vec<Vec<u8>>, NoiseError> {
+    let request_id = msg.0;
+
+    match transport.write_message(&msg.to_bytes()) {
+        Err(NoiseError::MessageTooLarge) => {
+            tracing::warn!("The response to request {request_id} is too large to be sent");
+
+            let error = ServiceErrorDetails::new(
+                ServiceError::QuotaExceeded,
+                "The response is larger than the maximum message size",
+            )
+            .with_quota(MAX_MESSAGE_SIZE as u64);
+            transport.write_message(&MessageWire(request_id, Message::Error(error)).to_bytes())
+        }
+        result => result,
+    }
+}
+
 /// HTTP request that we will upgrade into a `WebSocket` connection
 pub async fn unauthenticated_ws_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
     ws_handler(ws, false)
@@ -51,9 +77,16 @@ pub fn ws_handler(ws: WebSocketUpgrade, authenticated: bool) -> impl IntoRespons
             return;
         };
 
-        let server_handshake = ServerHandshake::respond(&client_handshake).expect("todo");
+        let server_handshake = match ServerHandshake::respond(&client_handshake) {
+            Ok(server_handshake) => server_handshake,
+            Err(error) => {
+                tracing::warn!("Invalid Noise handshake from client: {error}");
+                let _ = socket.close().await;
+                return;
+            }
+        };
         let Ok(()) = socket.send(server_handshake.buffer.read().to_vec()).await else {
-            panic!();
+            return;
         };
 
         let Some(Ok(client_response)) = socket.next().await else {
@@ -61,32 +94,47 @@ pub fn ws_handler(ws: WebSocketUpgrade, authenticated: bool) -> impl IntoRespons
             return;
         };
 
-        let server_transport = Arc::new(Mutex::new(
-            server_handshake
-                .complete_handshake(&client_response)
-                .expect("todo"),
-        ));
+        let server_transport = match server_handshake.complete_handshake(&client_response) {
+            Ok(server_transport) => server_transport,
+            Err(error) => {
+                tracing::warn!("Client failed to complete the Noise handshake: {error}");
+                let _ = socket.close().await;
+                return;
+            }
+        };
+        let handshake_hash = server_transport.handshake_hash().to_vec();
+        let server_transport = Arc::new(Mutex::new(server_transport));
 
         // Convert Sink<Vec<u8>> into a Sink<MessageWire>.
+        // A message can be split into several Noise frames, which are sent one after the other.
         let server_transport_with = server_transport.clone();
-        let socket = socket.with::<MessageWire, _, _, _>(move |msg: MessageWire| {
-            let server_transport_with = server_transport_with.clone();
-            async move {
-                let mut lock = server_transport_with.lock().expect("no poison");
-                let enc = lock.write(&msg.to_bytes()).expect("todo");
-                Ok::<_, axum::Error>(enc.to_vec())
-            }
+        let socket = socket.with_flat_map(move |msg: MessageWire| {
+            let mut lock = server_transport_with.lock().expect("no poison");
+            let frames: Vec<Result<_, axum::Error>> = match encrypt_message(&mut lock, msg) {
+                Ok(frames) => frames.into_iter().map(Ok).collect(),
+                // Failing to send closes the connection
+                Err(err) => vec![Err(axum::Error::new(err))],
+            };
+            futures_util::stream::iter(frames)
         });
 
         // Convert Stream<Item = Result<Option<Vec<u8>>, _> into Stream<Item = MessageWire>
+        // Frames that don't complete a message are skipped until the last one arrives.
         let server_transport_map = server_transport.clone();
-        let socket = socket.map(move |ws_m: Result<axum::body::Bytes, _>| match ws_m {
-            Ok(bytes) => {
-                let mut lock = server_transport_map.lock().expect("no poison");
-                let dec = lock.read(&bytes).map_err(|_| ())?;
-                Ok(MessageWire::from_bytes(dec).map_err(|_| ())?)
-            }
-            _ => Err(()),
+        let socket = socket.filter_map(move |ws_m: Result<axum::body::Bytes, _>| {
+            let msg = match ws_m {
+                Ok(bytes) => {
+                    let mut lock = server_transport_map.lock().expect("no poison");
+                    match lock.read_frame(&bytes) {
+                        Ok(Some(dec)) => Some(MessageWire::from_bytes(&dec).map_err(|_| ())),
+                        Ok(None) => None,
+                        Err(_) => Some(Err(())),
+                    }
+                }
+                _ => Some(Err(())),
+            };
+
+            std::future::ready(msg)
         });
 
         ACTIVE_WS_CONNECTIONS_COUNTER.fetch_add(1, Ordering::Relaxed);
@@ -100,7 +148,7 @@ pub fn ws_handler(ws: WebSocketUpgrade, authenticated: bool) -> impl IntoRespons
         if authenticated {
             let socket = Box::pin(socket);
 
-            handle_authenticated_connection(socket)
+            handle_authenticated_connection(socket, handshake_hash)
                 .instrument(ws_span)
                 .await;
         } else {
@@ -117,3 +165,54 @@ pub fn ws_handler(ws: WebSocketUpgrade, authenticated: bool) -> impl IntoRespons
         );
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use lib::{api::messages::ClientRequestId, crypto::noise::ClientHandshake};
+
+    use super::*;
+
+    #[test]
+    fn responses_too_large_are_replaced_by_an_error() {
+        let client = ClientHandshake::prepare_handshake().expect("client handshake works");
+        let server = ServerHandshake::respond(client.buffer.as_ref())
+            .expect("server handshake response works");
+        let (mut client_transport, client_response) = client
+            .complete_handshake(server.buffer.as_ref())
+            .expect("client completes handshake successfully");
+        let mut server_transport = server
+            .complete_handshake(client_response.as_ref())
+            .expect("server completes handshake");
+
+        let request_id = ClientRequestId::generate();
+        let frames = encrypt_message(
+            &mut server_transport,
+            MessageWire(request_id, Message::Pong(vec![0; MAX_MESSAGE_SIZE])),
+        )
+        .expect("the error can be sent instead");
+
+        let mut received = None;
+        for frame in frames {
+            received = client_transport
+                .read_frame(&frame)
+                .expect("decryption works");
+        }
+        let received = MessageWire::from_bytes(&received.expect("the message is complete"))
+            .expect("message wire decodes");
+
+        assert_eq!(
+            received.0, request_id,
+            "The error should answer the same request"
+        );
+        assert!(
+            matches!(
+                received.1,
+                Message::Error(ServiceErrorDetails {
+                    code: ServiceError::QuotaExceeded,
+                    ..
+                })
+            ),
+            "The client should be told that the response was too large"
+        );
+    }
+}